@@ -0,0 +1,230 @@
+//! Markdown lint rules
+//!
+//! Each rule looks at the parsed `MarkdownAst` or at the raw lines and reports
+//! `LintIssue`s with 1-based positions. Lines inside code blocks are never
+//! checked for whitespace problems.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+use super::line_column;
+use crate::format::code_block_ranges;
+use crate::markdown_ast::{MarkdownAst, NodeKind};
+use crate::markdown_parser::MarkdownParser;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintIssue {
+    /// Short rule name, e.g. `heading-increment`
+    pub rule: &'static str,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}: {}", self.line, self.column, self.rule, self.message)
+    }
+}
+
+/// Run every rule over `markdown`, returning issues in document order
+pub fn lint(markdown: &str) -> Vec<LintIssue> {
+    let ast = MarkdownParser::new().parse_ast(markdown);
+    let mut issues = Vec::new();
+    check_headings(&ast, markdown, &mut issues);
+    check_links_and_images(&ast, markdown, &mut issues);
+    check_footnotes(&ast, markdown, &mut issues);
+    check_whitespace(markdown, &mut issues);
+    issues.sort_by_key(|issue| (issue.line, issue.column));
+    issues
+}
+
+fn issue(source: &str, offset: usize, rule: &'static str, message: impl Into<String>) -> LintIssue {
+    let (line, column) = line_column(source, offset);
+    LintIssue { rule, message: message.into(), line, column }
+}
+
+/// Headings should go down one level at a time and have some text
+fn check_headings(ast: &MarkdownAst, source: &str, issues: &mut Vec<LintIssue>) {
+    let mut previous_level = None;
+    for id in ast.descendants(ast.root()) {
+        let level = match ast.node(id).kind {
+            NodeKind::Heading(level) => level,
+            _ => continue,
+        };
+        let start = ast.node(id).range.start;
+        if ast.text(id).trim().is_empty() {
+            issues.push(issue(source, start, "empty-heading", "Heading has no text"));
+        }
+        if let Some(previous) = previous_level {
+            if level > previous + 1 {
+                issues.push(issue(
+                    source,
+                    start,
+                    "heading-increment",
+                    format!("Heading level jumps from {} to {}", previous, level),
+                ));
+            }
+        }
+        previous_level = Some(level);
+    }
+}
+
+fn check_links_and_images(ast: &MarkdownAst, source: &str, issues: &mut Vec<LintIssue>) {
+    for id in ast.descendants(ast.root()) {
+        let start = ast.node(id).range.start;
+        match &ast.node(id).kind {
+            NodeKind::Link { url, .. } if url.trim().is_empty() => {
+                issues.push(issue(source, start, "empty-link", "Link has no destination"));
+            }
+            NodeKind::Image { .. } if ast.text(id).trim().is_empty() => {
+                issues.push(issue(source, start, "missing-alt-text", "Image has no alt text"));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Every footnote reference needs a definition and every definition a reference.
+/// References to undefined footnotes are left as plain text by the parser, so
+/// they're found in the source instead.
+fn check_footnotes(ast: &MarkdownAst, source: &str, issues: &mut Vec<LintIssue>) {
+    let mut referenced = HashSet::new();
+    let mut defined = HashSet::new();
+    for node in ast.nodes() {
+        match &node.kind {
+            NodeKind::FootnoteReference(label) => {
+                referenced.insert(label.clone());
+            }
+            NodeKind::FootnoteDefinition(label) => {
+                defined.insert(label.clone());
+            }
+            _ => {}
+        }
+    }
+
+    for node in ast.nodes() {
+        if let NodeKind::FootnoteDefinition(label) = &node.kind {
+            if !referenced.contains(label) {
+                issues.push(issue(
+                    source,
+                    node.range.start,
+                    "unused-footnote",
+                    format!("Footnote [^{}] is never referenced", label),
+                ));
+            }
+        }
+    }
+
+    for found in footnote_reference_regex().captures_iter(source) {
+        let (whole, label) = (found.get(0).unwrap(), &found[1]);
+        let is_definition = source[whole.end()..].starts_with(':');
+        let in_code = ast
+            .path_at(whole.start())
+            .iter()
+            .any(|&id| matches!(ast.node(id).kind, NodeKind::Code(_) | NodeKind::CodeBlock(_)));
+        if !is_definition && !in_code && !defined.contains(label) {
+            issues.push(issue(
+                source,
+                whole.start(),
+                "undefined-footnote",
+                format!("Footnote [^{}] is never defined", label),
+            ));
+        }
+    }
+}
+
+fn footnote_reference_regex() -> &'static Regex {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    REFERENCE.get_or_init(|| Regex::new(r"\[\^([^\]\s]+)\]").unwrap())
+}
+
+/// Trailing whitespace, repeated blank lines and a missing final newline
+fn check_whitespace(source: &str, issues: &mut Vec<LintIssue>) {
+    let code_blocks = code_block_ranges(source);
+    let mut offset = 0;
+    let mut blank_run = 0;
+    for line in source.split('\n') {
+        let line_start = offset;
+        offset += line.len() + 1;
+        let in_code = code_blocks
+            .iter()
+            .any(|range| range.start < line_start + line.len() && line_start < range.end);
+        if in_code {
+            blank_run = 0;
+            continue;
+        }
+
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            blank_run += 1;
+            if blank_run == 2 && offset <= source.len() {
+                issues.push(issue(source, line_start, "multiple-blank-lines", "More than one blank line in a row"));
+            }
+            continue;
+        }
+        blank_run = 0;
+
+        // Exactly two trailing spaces are a deliberate hard line break
+        if trimmed.len() != line.len() && &line[trimmed.len()..] != "  " {
+            issues.push(issue(source, line_start + trimmed.len(), "trailing-whitespace", "Line ends with whitespace"));
+        }
+    }
+
+    if !source.is_empty() && !source.ends_with('\n') {
+        issues.push(issue(source, source.len(), "final-newline", "File doesn't end with a newline"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(markdown: &str) -> Vec<&'static str> {
+        lint(markdown).into_iter().map(|issue| issue.rule).collect()
+    }
+
+    #[test]
+    fn test_clean_document_has_no_issues() {
+        let markdown = "# Title\n\n## Section\n\nText with a [link](https://example.com)[^1].  \nNext line.\n\n\
+                        ```\ncode   \n\n\n```\n\n[^1]: A note.\n";
+        assert_eq!(lint(markdown), Vec::new());
+    }
+
+    #[test]
+    fn test_heading_rules() {
+        let issues = lint("# Title\n\n### Skipped\n\n##\n");
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].rule, "heading-increment");
+        assert_eq!((issues[0].line, issues[0].column), (3, 1));
+        assert_eq!(issues[0].message, "Heading level jumps from 1 to 3");
+        assert_eq!(issues[1].rule, "empty-heading");
+    }
+
+    #[test]
+    fn test_link_image_and_footnote_rules() {
+        assert_eq!(
+            rules("A [link]() and ![](cat.png) and [^missing].\n\n[^unused]: Never used.\n"),
+            vec!["empty-link", "missing-alt-text", "undefined-footnote", "unused-footnote"]
+        );
+    }
+
+    #[test]
+    fn test_whitespace_rules() {
+        let issues = lint("Title \n\n\n\nBody\t\nEnd");
+        assert_eq!(
+            issues.iter().map(|issue| (issue.rule, issue.line, issue.column)).collect::<Vec<_>>(),
+            vec![
+                ("trailing-whitespace", 1, 6),
+                ("multiple-blank-lines", 3, 1),
+                ("trailing-whitespace", 5, 5),
+                ("final-newline", 6, 4),
+            ]
+        );
+        assert_eq!(issues[0].to_string(), "1:6: trailing-whitespace: Line ends with whitespace");
+    }
+}
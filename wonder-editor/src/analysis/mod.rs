@@ -0,0 +1,29 @@
+// Analysis module - Checks and statistics over whole documents, shared by
+// the editor and the `wonder` command-line tool
+
+pub mod lint;
+pub mod stats;
+
+pub use lint::{lint, LintIssue};
+pub use stats::{document_stats, DocumentStats};
+
+/// 1-based line and column (in chars) of a byte offset in `source`
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_column() {
+        let source = "# Title\n\nçafé here";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 8), (2, 1));
+        assert_eq!(line_column(source, source.find("here").unwrap()), (3, 6));
+    }
+}
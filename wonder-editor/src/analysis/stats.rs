@@ -0,0 +1,87 @@
+//! Document statistics: counts of words, blocks and tasks
+
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::markdown_ast::NodeKind;
+use crate::markdown_parser::MarkdownParser;
+
+/// Words per minute used for the reading time estimate
+const READING_WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DocumentStats {
+    pub characters: usize,
+    pub lines: usize,
+    /// Words of rendered text, so markup and URLs aren't counted
+    pub words: usize,
+    pub headings: usize,
+    pub paragraphs: usize,
+    pub links: usize,
+    pub images: usize,
+    pub code_blocks: usize,
+    pub tables: usize,
+    pub tasks: usize,
+    pub tasks_done: usize,
+    pub reading_minutes: usize,
+}
+
+pub fn document_stats(markdown: &str) -> DocumentStats {
+    let ast = MarkdownParser::new().parse_ast(markdown);
+    let mut stats = DocumentStats {
+        characters: markdown.chars().count(),
+        lines: markdown.lines().count(),
+        ..DocumentStats::default()
+    };
+
+    for node in ast.nodes() {
+        match &node.kind {
+            NodeKind::Text(text) | NodeKind::Code(text) => stats.words += text.unicode_words().count(),
+            NodeKind::Heading(_) => stats.headings += 1,
+            NodeKind::Paragraph => stats.paragraphs += 1,
+            NodeKind::Link { .. } => stats.links += 1,
+            NodeKind::Image { .. } => stats.images += 1,
+            NodeKind::CodeBlock(_) => stats.code_blocks += 1,
+            NodeKind::Table(_) => stats.tables += 1,
+            NodeKind::TaskListMarker(checked) => {
+                stats.tasks += 1;
+                if *checked {
+                    stats.tasks_done += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    stats.reading_minutes = stats.words.div_ceil(READING_WORDS_PER_MINUTE);
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_stats() {
+        let markdown = "# Trip plan\n\nPack **light** and see [the map](https://example.com/a-long-url).\n\n\
+                        - [x] Book flights\n- [ ] Pack\n\n```\nlet bags = 1;\n```\n";
+        let stats = document_stats(markdown);
+        assert_eq!(
+            stats,
+            DocumentStats {
+                characters: markdown.chars().count(),
+                lines: 10,
+                words: 14,
+                headings: 1,
+                paragraphs: 1,
+                links: 1,
+                images: 0,
+                code_blocks: 1,
+                tables: 0,
+                tasks: 2,
+                tasks_done: 1,
+                reading_minutes: 1,
+            }
+        );
+        assert_eq!(document_stats(""), DocumentStats::default());
+    }
+}
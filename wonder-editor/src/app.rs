@@ -1,29 +1,67 @@
 use gpui::{
-    div, prelude::*, rgb, Context, Render,
+    div, prelude::*, rgb, Context, Render, Window,
 };
 
 use crate::editor::MarkdownEditor;
+use crate::file::UnsavedChanges;
 
 pub struct WonderApp {
     editor: gpui::Entity<MarkdownEditor>,
 }
 
 impl WonderApp {
-    pub fn new(cx: &mut Context<Self>) -> Self {
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
         let editor = cx.new(|cx| MarkdownEditor::new(cx));
         
-        Self { editor }
+        Self::with_editor(editor, window, cx)
     }
     
-    pub fn new_with_content(content: String, cx: &mut Context<Self>) -> Self {
+    pub fn new_with_content(content: String, window: &mut Window, cx: &mut Context<Self>) -> Self {
         let editor = cx.new(|cx| MarkdownEditor::new_with_content(content, cx));
         
+        Self::with_editor(editor, window, cx)
+    }
+
+    /// Start with `path` open, or an empty document if it can't be read.
+    /// Offers to recover edits if the last session crashed with this file open.
+    pub fn new_with_file(path: std::path::PathBuf, window: &mut Window, cx: &mut Context<Self>) -> Self {
+        let editor = cx.new(|cx| {
+            let mut editor = MarkdownEditor::new(cx);
+            if let Err(error) = editor.open_file(&path, UnsavedChanges::Discard) {
+                eprintln!("Could not open {}: {}", path.display(), error);
+            }
+            editor
+        });
+        editor.update(cx, |editor, cx| editor.offer_recovery(window, cx));
+        
+        Self::with_editor(editor, window, cx)
+    }
+
+    fn with_editor(editor: gpui::Entity<MarkdownEditor>, window: &mut Window, cx: &mut Context<Self>) -> Self {
+        // Re-render the title bar when the document's name or dirty state changes
+        cx.observe(&editor, |_, _, cx| cx.notify()).detach();
+        editor.update(cx, |editor, cx| {
+            editor.watch_for_external_changes(window, cx);
+            editor.flush_journal_periodically(window, cx);
+        });
         Self { editor }
     }
+
+    /// Title bar text: the file name, marked when there are unsaved changes
+    fn title(&self, cx: &gpui::App) -> String {
+        let editor = self.editor.read(cx);
+        let dirty_marker = if editor.is_dirty() { " •" } else { "" };
+        format!("{}{} - Wonder", editor.file_session().display_name(), dirty_marker)
+    }
 }
 
 impl Render for WonderApp {
-    fn render(&mut self, _window: &mut gpui::Window, _cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, _window: &mut gpui::Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let title = self.title(cx);
+        let pending_keys = self.editor.read(cx).pending_keys_text();
+        let vim_mode = self.editor.read(cx).vim_mode_label();
+        let search_prompt = self.editor.read(cx).incremental_search_prompt();
+        let recording_macro = self.editor.read(cx).is_recording_macro();
         div()
             .flex()
             .flex_col()
@@ -41,7 +79,35 @@ impl Render for WonderApp {
                         div()
                             .text_lg()
                             .text_color(rgb(0xcdd6f4))
-                            .child("Wonder - Markdown Editor")
+                            .child(title)
+                    )
+                    .child(
+                        div()
+                            .ml_auto()
+                            .flex()
+                            .gap_4()
+                            .text_sm()
+                            // The start of a chord or vim command, waiting for its next key
+                            .children(pending_keys.map(|keys| {
+                                div()
+                                    .text_color(rgb(0xa6adc8))
+                                    .child(format!("{} …", keys))
+                            }))
+                            .children(search_prompt.map(|prompt| {
+                                div()
+                                    .text_color(rgb(0xcdd6f4))
+                                    .child(prompt)
+                            }))
+                            .children(recording_macro.then(|| {
+                                div()
+                                    .text_color(rgb(0xf38ba8))
+                                    .child("● Recording macro")
+                            }))
+                            .children(vim_mode.map(|mode| {
+                                div()
+                                    .text_color(rgb(0xf9e2af))
+                                    .child(format!("-- {} --", mode))
+                            }))
                     )
             )
             .child(
@@ -0,0 +1,234 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::{bail, Context, Result};
+use wonder_editor::analysis::{document_stats, lint};
+use wonder_editor::export::{export_text, HtmlExporter};
+use wonder_editor::format::format_markdown;
+use wonder_editor::markdown_parser::MarkdownParser;
+use wonder_editor::rendering::StyleContext;
+
+// Headless front end to the editor's parser and document tools, for scripts
+// and CI. Never opens a window.
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("parse") => run_parse(&args[1..]),
+        Some("export") => run_export(&args[1..]),
+        Some("fmt") => run_fmt(&args[1..]),
+        Some("lint") => run_lint(&args[1..]),
+        Some("stats") => run_stats(&args[1..]),
+        Some("--help") | Some("-h") | Some("help") | None => {
+            print_help();
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(command) => Err(anyhow::anyhow!("unknown command '{}', see 'wonder --help'", command)),
+    };
+
+    result.unwrap_or_else(|error| {
+        eprintln!("wonder: {:#}", error);
+        ExitCode::from(2)
+    })
+}
+
+fn print_help() {
+    println!("Wonder - Markdown tools using the editor's parser");
+    println!();
+    println!("Usage: wonder <COMMAND> [OPTIONS] [FILE]");
+    println!();
+    println!("FILE may be '-' or left out to read from stdin.");
+    println!();
+    println!("Commands:");
+    println!("  parse [FILE]                       Print the parsed tokens as JSON");
+    println!("  export [FILE] [--format html|text] [-o OUTPUT] [--title TITLE]");
+    println!("                                     Export as a standalone HTML page (default) or plain text");
+    println!("  fmt [FILES...] [--check|--write]   Rewrite as canonical Markdown; prints the result unless --write is given.");
+    println!("                                     --check exits with 1 if any file would change");
+    println!("  lint [FILES...]                    Report problems; exits with 1 if any are found");
+    println!("  stats [FILE] [--json]              Count words, blocks and tasks");
+}
+
+/// Command-line arguments split into flags (with their values) and files
+struct Args {
+    files: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// `with_value` lists the options that take a value
+    fn parse(args: &[String], known: &[&str], with_value: &[&str]) -> Result<Self> {
+        let mut files = Vec::new();
+        let mut options = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-" || !arg.starts_with('-') {
+                files.push(arg.clone());
+            } else if with_value.contains(&arg.as_str()) {
+                let value = args.next().with_context(|| format!("{} needs a value", arg))?;
+                options.push((arg.clone(), Some(value.clone())));
+            } else if known.contains(&arg.as_str()) {
+                options.push((arg.clone(), None));
+            } else {
+                bail!("unknown option '{}'", arg);
+            }
+        }
+        Ok(Self { files, options })
+    }
+
+    fn flag(&self, names: &[&str]) -> bool {
+        self.options.iter().any(|(name, _)| names.contains(&name.as_str()))
+    }
+
+    fn value(&self, names: &[&str]) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// The single input file, or stdin
+    fn single_file(&self) -> Result<Option<&str>> {
+        match self.files.as_slice() {
+            [] => Ok(None),
+            [file] => Ok(Some(file)),
+            _ => bail!("expected one input file, got {}", self.files.len()),
+        }
+    }
+}
+
+/// Read a file, or stdin for `None` and `-`
+fn read_input(file: Option<&str>) -> Result<String> {
+    match file {
+        None | Some("-") => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).context("could not read stdin")?;
+            Ok(input)
+        }
+        Some(path) => fs::read_to_string(path).with_context(|| format!("could not read {}", path)),
+    }
+}
+
+fn display_name(file: Option<&str>) -> &str {
+    match file {
+        None | Some("-") => "<stdin>",
+        Some(path) => path,
+    }
+}
+
+/// `wonder parse notes.md`
+fn run_parse(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &[], &[])?;
+    let markdown = read_input(args.single_file()?)?;
+    let tokens = MarkdownParser::new().parse_with_positions(&markdown);
+    println!("{}", serde_json::to_string_pretty(&tokens)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `wonder export notes.md -o notes.html`
+fn run_export(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &[], &["-o", "--output", "--title", "--format"])?;
+    let markdown = read_input(args.single_file()?)?;
+
+    let exported = match args.value(&["--format"]).unwrap_or("html") {
+        "html" => {
+            let mut exporter = HtmlExporter::new(StyleContext::default());
+            if let Some(title) = args.value(&["--title"]) {
+                exporter = exporter.with_title(title);
+            }
+            exporter.export(&markdown)
+        }
+        "text" => export_text(&markdown),
+        format => bail!("unknown export format '{}' (expected html or text)", format),
+    };
+
+    match args.value(&["-o", "--output"]) {
+        Some(path) => fs::write(path, exported).with_context(|| format!("could not write {}", path))?,
+        None => print!("{}", exported),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// `wonder fmt --check docs/*.md`
+fn run_fmt(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--check", "--write"], &[])?;
+    let (check, write) = (args.flag(&["--check"]), args.flag(&["--write"]));
+    if check && write {
+        bail!("--check and --write can't be used together");
+    }
+
+    let files: Vec<Option<&str>> = if args.files.is_empty() {
+        vec![None]
+    } else {
+        args.files.iter().map(|file| Some(file.as_str())).collect()
+    };
+    if write && files.iter().any(|file| matches!(file, None | Some("-"))) {
+        bail!("--write needs files to write to");
+    }
+
+    let mut unformatted = false;
+    for file in files {
+        let markdown = read_input(file)?;
+        let formatted = format_markdown(&markdown);
+        if check {
+            if formatted != markdown {
+                println!("{} is not formatted", display_name(file));
+                unformatted = true;
+            }
+        } else if write {
+            if formatted != markdown {
+                let path = PathBuf::from(display_name(file));
+                fs::write(&path, formatted).with_context(|| format!("could not write {}", path.display()))?;
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    Ok(if unformatted { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// `wonder lint notes.md` prints `notes.md:3:1: heading-increment: ...`
+fn run_lint(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &[], &[])?;
+    let files: Vec<Option<&str>> = if args.files.is_empty() {
+        vec![None]
+    } else {
+        args.files.iter().map(|file| Some(file.as_str())).collect()
+    };
+
+    let mut found = false;
+    for file in files {
+        let markdown = read_input(file)?;
+        for issue in lint(&markdown) {
+            println!("{}:{}", display_name(file), issue);
+            found = true;
+        }
+    }
+    Ok(if found { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// `wonder stats notes.md --json`
+fn run_stats(args: &[String]) -> Result<ExitCode> {
+    let args = Args::parse(args, &["--json"], &[])?;
+    let stats = document_stats(&read_input(args.single_file()?)?);
+    if args.flag(&["--json"]) {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("Characters:    {}", stats.characters);
+    println!("Lines:         {}", stats.lines);
+    println!("Words:         {}", stats.words);
+    println!("Headings:      {}", stats.headings);
+    println!("Paragraphs:    {}", stats.paragraphs);
+    println!("Links:         {}", stats.links);
+    println!("Images:        {}", stats.images);
+    println!("Code blocks:   {}", stats.code_blocks);
+    println!("Tables:        {}", stats.tables);
+    println!("Tasks:         {}/{} done", stats.tasks_done, stats.tasks);
+    println!("Reading time:  {} min", stats.reading_minutes);
+    Ok(ExitCode::SUCCESS)
+}
@@ -1,15 +1,20 @@
 //! Command History Manager for Undo/Redo Operations
 //!
-//! This module implements a stack-based command history system that enables
-//! undo/redo functionality with transaction grouping and memory management.
+//! This module implements a command history system that enables undo/redo
+//! functionality with transaction grouping and memory management. Finished
+//! transactions are kept in an `UndoTree`, so undone steps stay reachable
+//! after new edits.
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::{Duration, Instant};
-use std::collections::VecDeque;
-use crate::core::commands::UndoableCommand;
+use crate::core::commands::{CommandRecord, UndoableCommand};
+use crate::core::selection::SelectionSnapshot;
+use crate::core::undo_tree::{UndoBranch, UndoMove, UndoTree};
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
-/// Maximum number of commands to keep in history
-const DEFAULT_MAX_HISTORY_SIZE: usize = 1000;
+/// Maximum number of undo steps to keep in history
+const DEFAULT_MAX_HISTORY_SIZE: usize = 200;
 
 /// Default transaction timeout in milliseconds
 const DEFAULT_TRANSACTION_TIMEOUT_MS: u64 = 500;
@@ -17,12 +22,80 @@ const DEFAULT_TRANSACTION_TIMEOUT_MS: u64 = 500;
 /// Maximum memory usage for command history (in bytes)
 const DEFAULT_MAX_MEMORY_BYTES: usize = 10 * 1024 * 1024; // 10MB
 
+/// How commands added one after another are grouped into undo steps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupingPolicy {
+    /// Commands join the open step until it's older than the transaction
+    /// timeout
+    Timeout,
+    /// Typing and deleting are grouped by word: a step ends before
+    /// whitespace or punctuation that follows a word, when the next edit
+    /// isn't where the last one left the cursor, and when typing turns into
+    /// deleting or back. Other edits are steps of their own.
+    #[default]
+    WordBoundary,
+}
+
+impl GroupingPolicy {
+    /// Whether `next` starts a new undo step rather than joining the one
+    /// `previous` is in
+    fn starts_new_step(self, previous: &dyn UndoableCommand, next: &dyn UndoableCommand) -> bool {
+        match self {
+            GroupingPolicy::Timeout => false,
+            GroupingPolicy::WordBoundary => is_word_boundary(&previous.to_record(), &next.to_record()),
+        }
+    }
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Whether the edit `next` can't carry on the same word as `previous`
+fn is_word_boundary(previous: &CommandRecord, next: &CommandRecord) -> bool {
+    // The chars either side of where one edit ends and the next begins
+    let (last, first) = match (previous, next) {
+        (
+            CommandRecord::Insert { position, text },
+            CommandRecord::Insert { position: next_position, text: next_text },
+        ) => {
+            if *next_position != position + text.chars().count() {
+                return true;
+            }
+            (text.chars().last(), next_text.chars().next())
+        }
+        (
+            CommandRecord::Delete { start, deleted_text, .. },
+            CommandRecord::Delete { start: next_start, end: next_end, deleted_text: next_deleted },
+        ) => {
+            if next_end == start {
+                // Backspacing: the next deletion is just before this one
+                (deleted_text.chars().next(), next_deleted.chars().last())
+            } else if next_start == start {
+                // Deleting forwards: the text after slides into place
+                (deleted_text.chars().last(), next_deleted.chars().next())
+            } else {
+                return true;
+            }
+        }
+        _ => return true,
+    };
+    match (last, first) {
+        (Some(last), Some(first)) => is_word_char(last) && !is_word_char(first),
+        _ => true,
+    }
+}
+
 /// A group of commands that should be undone/redone together
 #[derive(Debug)]
 pub struct CommandTransaction {
     commands: Vec<Box<dyn UndoableCommand>>,
     timestamp: Instant,
     description: String,
+    /// Cursors and selections before the first command, restored by undo
+    selections_before: Option<SelectionSnapshot>,
+    /// Cursors and selections after the last command, restored by redo
+    selections_after: Option<SelectionSnapshot>,
 }
 
 impl CommandTransaction {
@@ -31,6 +104,8 @@ impl CommandTransaction {
             commands: Vec::new(),
             timestamp: Instant::now(),
             description,
+            selections_before: None,
+            selections_after: None,
         }
     }
 
@@ -54,6 +129,22 @@ impl CommandTransaction {
         &self.commands
     }
 
+    pub fn selections_before(&self) -> Option<&SelectionSnapshot> {
+        self.selections_before.as_ref()
+    }
+
+    pub fn selections_after(&self) -> Option<&SelectionSnapshot> {
+        self.selections_after.as_ref()
+    }
+
+    pub fn set_selections_before(&mut self, selections: SelectionSnapshot) {
+        self.selections_before = Some(selections);
+    }
+
+    pub fn set_selections_after(&mut self, selections: SelectionSnapshot) {
+        self.selections_after = Some(selections);
+    }
+
     /// Execute all commands in the transaction
     pub fn execute(&self, rope: &Rope) -> Rope {
         let mut current_rope = rope.clone();
@@ -87,16 +178,109 @@ impl Clone for CommandTransaction {
             commands: self.commands.iter().map(|cmd| cmd.clone_command()).collect(),
             timestamp: self.timestamp,
             description: self.description.clone(),
+            selections_before: self.selections_before.clone(),
+            selections_after: self.selections_after.clone(),
+        }
+    }
+}
+
+/// Serializable form of one undo step
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub id: usize,
+    /// The step this one was made after, or the root
+    pub parent: usize,
+    pub description: String,
+    pub commands: Vec<CommandRecord>,
+}
+
+impl StepRecord {
+    fn new(id: usize, parent: usize, transaction: &CommandTransaction) -> Self {
+        Self {
+            id,
+            parent,
+            description: transaction.description.clone(),
+            commands: transaction.commands.iter().map(|command| command.to_record()).collect(),
+        }
+    }
+
+    fn into_transaction(self) -> CommandTransaction {
+        let mut transaction = CommandTransaction::new(self.description);
+        for command in self.commands {
+            transaction.add_command(command.into_command());
+        }
+        transaction
+    }
+}
+
+/// Serializable form of a history: every step in its undo tree, and the
+/// step the document is at. Cursors and selections aren't kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// Oldest first
+    pub steps: Vec<StepRecord>,
+    pub current: usize,
+}
+
+impl HistoryRecord {
+    /// Whether every step applies cleanly, walking the tree outwards from
+    /// the current step with `rope` as its content
+    fn fits(&self, rope: &Rope) -> bool {
+        let steps: BTreeMap<usize, &StepRecord> = self.steps.iter().map(|step| (step.id, step)).collect();
+        let mut neighbours: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for step in &self.steps {
+            if step.parent >= step.id {
+                return false;
+            }
+            neighbours.entry(step.id).or_default().push(step.parent);
+            neighbours.entry(step.parent).or_default().push(step.id);
+        }
+
+        let mut visited = BTreeSet::from([self.current]);
+        let mut pending = vec![(self.current, rope.clone())];
+        while let Some((node, rope)) = pending.pop() {
+            for &next in neighbours.get(&node).into_iter().flatten() {
+                if !visited.insert(next) {
+                    continue;
+                }
+                // Children always have higher ids than their parent
+                let moved = if next > node {
+                    steps.get(&next).and_then(|step| apply_records(step.commands.iter().cloned(), rope.clone()))
+                } else {
+                    steps.get(&node).and_then(|step| {
+                        apply_records(step.commands.iter().rev().map(|command| command.inverse()), rope.clone())
+                    })
+                };
+                match moved {
+                    Some(state) => pending.push((next, state)),
+                    None => return false,
+                }
+            }
+        }
+        visited.len() == steps.len() + 1
+    }
+}
+
+/// Apply `records` in order, or `None` if one doesn't fit the text
+fn apply_records(records: impl Iterator<Item = CommandRecord>, mut rope: Rope) -> Option<Rope> {
+    for record in records {
+        if !record.applies_to(&rope) {
+            return None;
         }
+        rope = record.into_command().execute(&rope);
     }
+    Some(rope)
 }
 
 /// Command History Manager with undo/redo capabilities
 #[derive(Debug)]
 pub struct CommandHistory {
-    undo_stack: VecDeque<CommandTransaction>,
-    redo_stack: VecDeque<CommandTransaction>,
+    tree: UndoTree,
     current_transaction: Option<CommandTransaction>,
+    /// Whether the current transaction came from `start_transaction`, which
+    /// keeps every command until it's finished together
+    current_is_explicit: bool,
+    grouping_policy: GroupingPolicy,
     max_history_size: usize,
     max_memory_bytes: usize,
     transaction_timeout: Duration,
@@ -105,9 +289,10 @@ pub struct CommandHistory {
 impl CommandHistory {
     pub fn new() -> Self {
         Self {
-            undo_stack: VecDeque::new(),
-            redo_stack: VecDeque::new(),
+            tree: UndoTree::new(),
             current_transaction: None,
+            current_is_explicit: false,
+            grouping_policy: GroupingPolicy::default(),
             max_history_size: DEFAULT_MAX_HISTORY_SIZE,
             max_memory_bytes: DEFAULT_MAX_MEMORY_BYTES,
             transaction_timeout: Duration::from_millis(DEFAULT_TRANSACTION_TIMEOUT_MS),
@@ -116,42 +301,96 @@ impl CommandHistory {
 
     pub fn with_limits(max_history_size: usize, max_memory_bytes: usize) -> Self {
         Self {
-            undo_stack: VecDeque::new(),
-            redo_stack: VecDeque::new(),
+            tree: UndoTree::new(),
             current_transaction: None,
+            current_is_explicit: false,
+            grouping_policy: GroupingPolicy::default(),
             max_history_size,
             max_memory_bytes,
             transaction_timeout: Duration::from_millis(DEFAULT_TRANSACTION_TIMEOUT_MS),
         }
     }
 
-    /// Start a new transaction group
+    pub fn grouping_policy(&self) -> GroupingPolicy {
+        self.grouping_policy
+    }
+
+    pub fn set_grouping_policy(&mut self, policy: GroupingPolicy) {
+        self.finish_current_transaction();
+        self.grouping_policy = policy;
+    }
+
+    /// Start a new transaction group, which keeps every command added until
+    /// it's finished
     pub fn start_transaction(&mut self, description: String) {
         self.finish_current_transaction();
         self.current_transaction = Some(CommandTransaction::new(description));
+        self.current_is_explicit = true;
     }
 
-    /// Add a command to the current transaction or create a new single-command transaction
+    /// Add a command to the current transaction, or to a new one if the
+    /// grouping policy ends the current one before it
     pub fn add_command(&mut self, command: Box<dyn UndoableCommand>) {
-        // If no current transaction, create a new one
-        if self.current_transaction.is_none() {
-            self.start_transaction(command.description().to_string());
+        self.finish_step_before(command.as_ref());
+        self.add_command_to_current(command);
+    }
+
+    /// Add a command that carries on the same edit as the last one, so it
+    /// joins its transaction whatever the grouping policy
+    pub fn add_command_to_current(&mut self, command: Box<dyn UndoableCommand>) {
+        self.add_commands_to_current(vec![command]);
+    }
+
+    /// Add commands that must stay together in the current transaction,
+    /// such as one edit made at every cursor. The grouping policy compares
+    /// the last of them with the last command before them, which for
+    /// multi-cursor edits are both made at the first cursor.
+    pub fn add_commands(&mut self, commands: Vec<Box<dyn UndoableCommand>>) {
+        if let Some(last) = commands.last() {
+            self.finish_step_before(last.as_ref());
+        }
+        self.add_commands_to_current(commands);
+    }
+
+    /// Finish the current transaction if the grouping policy starts a new
+    /// step at `next`
+    fn finish_step_before(&mut self, next: &dyn UndoableCommand) {
+        let Some(transaction) = &self.current_transaction else {
+            return;
+        };
+        let starts_new_step = match transaction.commands.last() {
+            Some(previous) => !self.current_is_explicit && self.grouping_policy.starts_new_step(previous.as_ref(), next),
+            None => false,
+        };
+        if starts_new_step {
+            self.finish_current_transaction();
         }
+    }
 
-        if let Some(transaction) = &mut self.current_transaction {
+    /// Add commands to the current transaction, starting one if needed,
+    /// whatever the grouping policy. The timeout is only checked once all of
+    /// them have been added.
+    pub fn add_commands_to_current(&mut self, commands: Vec<Box<dyn UndoableCommand>>) {
+        if commands.is_empty() {
+            return;
+        }
+        let transaction = self.current_transaction.get_or_insert_with(|| {
+            CommandTransaction::new(commands[0].description().to_string())
+        });
+        for command in commands {
             transaction.add_command(command);
         }
 
         // Auto-finish transaction if it's getting old
-        if let Some(transaction) = &self.current_transaction {
-            if transaction.timestamp.elapsed() > self.transaction_timeout {
-                self.finish_current_transaction();
-            }
+        let timed_out = transaction.timestamp.elapsed() > self.transaction_timeout;
+        if self.grouping_policy == GroupingPolicy::Timeout && !self.current_is_explicit && timed_out {
+            self.finish_current_transaction();
         }
     }
 
     /// Finish the current transaction and add it to history
     pub fn finish_current_transaction(&mut self) {
+        self.current_is_explicit = false;
         if let Some(transaction) = self.current_transaction.take() {
             if !transaction.is_empty() {
                 self.add_transaction_to_history(transaction);
@@ -159,40 +398,67 @@ impl CommandHistory {
         }
     }
 
-    /// Add a completed transaction to the undo stack
-    fn add_transaction_to_history(&mut self, transaction: CommandTransaction) {
-        // Clear redo stack when adding new commands
-        self.redo_stack.clear();
+    /// Record an already-built transaction as a single undo step.
+    /// Unlike `add_command`, the transaction is never split by the timeout.
+    pub fn record_transaction(&mut self, transaction: CommandTransaction) {
+        self.finish_current_transaction();
+        if !transaction.is_empty() {
+            self.add_transaction_to_history(transaction);
+        }
+    }
+
+    /// The transaction the latest command went into, still open or not
+    fn newest_transaction_mut(&mut self) -> Option<&mut CommandTransaction> {
+        match &mut self.current_transaction {
+            Some(transaction) => Some(transaction),
+            None => {
+                let current = self.tree.current();
+                self.tree.transaction_mut(current)
+            }
+        }
+    }
+
+    /// Keep the cursors and selections from before the newest transaction,
+    /// unless it already has some from an earlier command in it
+    pub fn set_selections_before(&mut self, selections: SelectionSnapshot) {
+        if let Some(transaction) = self.newest_transaction_mut() {
+            if transaction.selections_before.is_none() {
+                transaction.selections_before = Some(selections);
+            }
+        }
+    }
+
+    /// Keep the cursors and selections left by the newest transaction's
+    /// latest edit
+    pub fn set_selections_after(&mut self, selections: SelectionSnapshot) {
+        if let Some(transaction) = self.newest_transaction_mut() {
+            transaction.selections_after = Some(selections);
+        }
+    }
 
-        // Add to undo stack
-        self.undo_stack.push_back(transaction);
+    /// Add a completed transaction to the tree, after the current state
+    fn add_transaction_to_history(&mut self, transaction: CommandTransaction) {
+        self.tree.push(transaction);
 
         // Enforce memory and size limits
         self.enforce_limits();
     }
 
-    /// Enforce history size and memory limits
+    /// Enforce history size and memory limits, pruning the oldest branches
+    /// first
     fn enforce_limits(&mut self) {
-        // Enforce size limit
-        while self.undo_stack.len() > self.max_history_size {
-            self.undo_stack.pop_front();
-        }
+        while self.tree.len() > self.max_history_size && self.tree.prune_oldest() {}
 
-        // Enforce memory limit using LRU eviction
-        while self.estimated_memory_usage() > self.max_memory_bytes && !self.undo_stack.is_empty() {
-            self.undo_stack.pop_front();
-        }
+        while self.estimated_memory_usage() > self.max_memory_bytes && self.tree.prune_oldest() {}
     }
 
     /// Estimate total memory usage
     fn estimated_memory_usage(&self) -> usize {
-        let undo_size: usize = self.undo_stack.iter().map(|t| t.estimated_memory_usage()).sum();
-        let redo_size: usize = self.redo_stack.iter().map(|t| t.estimated_memory_usage()).sum();
         let current_size = self.current_transaction.as_ref()
             .map(|t| t.estimated_memory_usage())
             .unwrap_or(0);
-        
-        undo_size + redo_size + current_size
+
+        self.tree.estimated_memory_usage() + current_size
     }
 
     /// Undo the last transaction
@@ -200,35 +466,80 @@ impl CommandHistory {
         // Finish any current transaction first
         self.finish_current_transaction();
 
-        if let Some(transaction) = self.undo_stack.pop_back() {
-            let result_rope = transaction.undo(rope);
-            self.redo_stack.push_back(transaction);
-            Some(result_rope)
-        } else {
-            None
-        }
+        self.tree.undo().map(|transaction| transaction.undo(rope))
     }
 
-    /// Redo the last undone transaction
+    /// Redo the last undone transaction, or the newest one made after it
     pub fn redo(&mut self, rope: &Rope) -> Option<Rope> {
-        if let Some(transaction) = self.redo_stack.pop_back() {
-            let result_rope = transaction.execute(rope);
-            self.undo_stack.push_back(transaction);
-            Some(result_rope)
-        } else {
-            None
-        }
+        self.finish_current_transaction();
+        let child = self.redo_node()?;
+        self.redo_branch(child, rope)
+    }
+
+    /// Redo the transaction of node `child`, one of the current node's
+    /// children
+    pub fn redo_branch(&mut self, child: usize, rope: &Rope) -> Option<Rope> {
+        self.finish_current_transaction();
+        self.tree.redo(child).map(|transaction| transaction.execute(rope))
+    }
+
+    /// The transaction the last `undo` reverted
+    pub fn last_undone(&self) -> Option<&CommandTransaction> {
+        self.tree.redo_child().and_then(|child| self.tree.transaction(child))
+    }
+
+    /// The most recent finished transaction, e.g. the one the last `redo` re-applied
+    pub fn last_done(&self) -> Option<&CommandTransaction> {
+        self.tree.transaction(self.tree.current())
+    }
+
+    /// The node of the undo tree the document is at
+    pub fn current_node(&self) -> usize {
+        self.tree.current()
+    }
+
+    /// The node redo goes to: the last one undone, or the newest one made
+    /// after the current one
+    pub fn redo_node(&self) -> Option<usize> {
+        self.tree.redo_child()
+    }
+
+    /// The node made just before the current one, on any branch
+    pub fn older_node(&self) -> Option<usize> {
+        self.tree.older()
+    }
+
+    /// The node made just after the current one, on any branch
+    pub fn newer_node(&self) -> Option<usize> {
+        self.tree.newer()
+    }
+
+    /// The undos and redos from the current node to node `id`
+    pub fn path_to(&self, id: usize) -> Option<Vec<UndoMove>> {
+        self.tree.path_to(id)
+    }
+
+    /// Every branch of the undo tree, oldest first
+    pub fn branches(&self) -> Vec<UndoBranch> {
+        self.tree.branches()
+    }
+
+    /// The transaction leading to node `id`
+    pub fn transaction(&self, id: usize) -> Option<&CommandTransaction> {
+        self.tree.transaction(id)
     }
 
     /// Check if undo is available
     pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty() || 
-        self.current_transaction.as_ref().map_or(false, |t| !t.is_empty())
+        self.tree.undo_depth() > 0 ||
+        self.current_transaction.as_ref().is_some_and(|t| !t.is_empty())
     }
 
     /// Check if redo is available
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        // Edits still being grouped will start a new branch once finished
+        self.current_transaction.as_ref().is_none_or(|t| t.is_empty()) &&
+        self.tree.redo_child().is_some()
     }
 
     /// Get description of the next undo operation
@@ -238,26 +549,80 @@ impl CommandHistory {
                 return Some(transaction.description());
             }
         }
-        self.undo_stack.back().map(|t| t.description())
+        self.last_done().map(|t| t.description())
     }
 
     /// Get description of the next redo operation
     pub fn redo_description(&self) -> Option<&str> {
-        self.redo_stack.back().map(|t| t.description())
+        self.last_undone().map(|t| t.description())
+    }
+
+    /// Plain-data copy of the finished steps, for saving the history. The
+    /// oldest steps are left out until the record's JSON fits in
+    /// `max_bytes`.
+    pub fn to_record(&self, max_bytes: usize) -> HistoryRecord {
+        let mut tree = self.tree.clone();
+        let mut sizes: BTreeMap<usize, usize> = tree
+            .steps()
+            .map(|(id, parent, transaction)| {
+                let json = serde_json::to_string(&StepRecord::new(id, parent, transaction));
+                (id, json.map_or(0, |json| json.len()))
+            })
+            .collect();
+        // The steps, the commas between them and the record around them
+        let record_size = |tree: &UndoTree, sizes: &BTreeMap<usize, usize>| {
+            let empty = HistoryRecord { steps: Vec::new(), current: tree.current() };
+            let wrapper = serde_json::to_string(&empty).map_or(0, |json| json.len());
+            wrapper + sizes.values().sum::<usize>() + sizes.len().saturating_sub(1)
+        };
+        while record_size(&tree, &sizes) > max_bytes && tree.prune_oldest() {
+            sizes.retain(|id, _| tree.contains(*id));
+        }
+
+        HistoryRecord {
+            steps: tree
+                .steps()
+                .map(|(id, parent, transaction)| StepRecord::new(id, parent, transaction))
+                .collect(),
+            current: tree.current(),
+        }
+    }
+
+    /// Replace the history with a saved one made when the document's content
+    /// was `rope`. Returns false, leaving the history as it was, unless the
+    /// record is a valid tree whose every step can be undone and redone from
+    /// there.
+    pub fn restore(&mut self, record: HistoryRecord, rope: &Rope) -> bool {
+        if !record.fits(rope) {
+            return false;
+        }
+        let steps = record
+            .steps
+            .into_iter()
+            .map(|step| (step.id, step.parent, step.into_transaction()))
+            .collect();
+        let Some(tree) = UndoTree::from_steps(steps, record.current) else {
+            return false;
+        };
+        self.clear();
+        self.tree = tree;
+        self.enforce_limits();
+        true
     }
 
     /// Clear all history
     pub fn clear(&mut self) {
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        self.tree = UndoTree::new();
         self.current_transaction = None;
+        self.current_is_explicit = false;
     }
 
     /// Get current history statistics
     pub fn stats(&self) -> HistoryStats {
         HistoryStats {
-            undo_count: self.undo_stack.len(),
-            redo_count: self.redo_stack.len(),
+            undo_count: self.tree.undo_depth(),
+            redo_count: self.tree.redo_depth(),
+            node_count: self.tree.len(),
             memory_usage: self.estimated_memory_usage(),
             has_current_transaction: self.current_transaction.is_some(),
         }
@@ -274,6 +639,8 @@ impl Default for CommandHistory {
 pub struct HistoryStats {
     pub undo_count: usize,
     pub redo_count: usize,
+    /// Undo steps on every branch
+    pub node_count: usize,
     pub memory_usage: usize,
     pub has_current_transaction: bool,
 }
@@ -281,7 +648,8 @@ pub struct HistoryStats {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::commands::InsertCommand;
+    use crate::core::commands::{DeleteCommand, InsertCommand, ReplaceCommand};
+    use crate::core::undo_tree::UndoMove;
 
     #[test]
     fn test_command_history_creation() {
@@ -364,6 +732,22 @@ mod tests {
         assert_eq!(undo_rope.to_string(), "Hello");
     }
 
+    #[test]
+    fn test_add_commands_stay_in_one_transaction() {
+        let mut history = CommandHistory::new();
+        let rope = Rope::from_str("-a\n-b");
+
+        history.add_commands(vec![
+            Box::new(InsertCommand::new(2, "-".to_string())),
+            Box::new(InsertCommand::new(0, "-".to_string())),
+        ]);
+        history.finish_current_transaction();
+
+        assert_eq!(history.stats().undo_count, 1);
+        let undo_rope = history.undo(&rope).unwrap();
+        assert_eq!(undo_rope.to_string(), "a\nb");
+    }
+
     #[test]
     fn test_memory_limits() {
         // Create history with very small memory limit
@@ -438,4 +822,196 @@ mod tests {
         let stats = history.stats();
         assert_eq!(stats.undo_count, 2); // Should be 2 separate transactions
     }
-}
\ No newline at end of file
+
+    fn type_text(history: &mut CommandHistory, position: usize, text: &str) {
+        for (offset, ch) in text.chars().enumerate() {
+            history.add_command(Box::new(InsertCommand::new(position + offset, ch.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_words_are_undo_steps() {
+        let mut history = CommandHistory::new();
+        let mut rope = Rope::from_str("hi there, you");
+        type_text(&mut history, 0, "hi there, you");
+        history.finish_current_transaction();
+
+        // Each step starts at the whitespace or punctuation after a word
+        assert_eq!(history.stats().undo_count, 3);
+        for expected in ["hi there", "hi", ""] {
+            rope = history.undo(&rope).unwrap();
+            assert_eq!(rope.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cursor_jumps_and_kind_changes_end_steps() {
+        let mut history = CommandHistory::new();
+        type_text(&mut history, 0, "ab");
+        // Typing somewhere else
+        type_text(&mut history, 0, "cd");
+        // Backspacing a run of chars is one step
+        history.add_command(Box::new(DeleteCommand::new(1, 2, "d".to_string())));
+        history.add_command(Box::new(DeleteCommand::new(0, 1, "c".to_string())));
+        // Deleting forwards from where that left the cursor carries on
+        history.add_command(Box::new(DeleteCommand::new(0, 1, "a".to_string())));
+        history.add_command(Box::new(DeleteCommand::new(0, 1, "b".to_string())));
+        // Typing again after deleting
+        type_text(&mut history, 0, "e");
+        history.finish_current_transaction();
+
+        assert_eq!(history.stats().undo_count, 4);
+    }
+
+    #[test]
+    fn test_replacements_are_steps_of_their_own() {
+        let mut history = CommandHistory::new();
+        type_text(&mut history, 0, "word");
+        history.add_command(Box::new(ReplaceCommand::new(0, 4, "word".to_string(), "**word**".to_string())));
+        type_text(&mut history, 8, "s");
+        history.finish_current_transaction();
+
+        assert_eq!(history.stats().undo_count, 3);
+    }
+
+    #[test]
+    fn test_explicit_transactions_and_timeout_policy_ignore_words() {
+        let mut history = CommandHistory::new();
+        history.start_transaction("Type phrase".to_string());
+        type_text(&mut history, 0, "a b");
+        history.finish_current_transaction();
+        assert_eq!(history.stats().undo_count, 1);
+
+        let mut history = CommandHistory::new();
+        history.set_grouping_policy(GroupingPolicy::Timeout);
+        type_text(&mut history, 0, "a b");
+        type_text(&mut history, 10, "c");
+        history.finish_current_transaction();
+        assert_eq!(history.stats().undo_count, 1);
+    }
+
+    #[test]
+    fn test_keeps_two_hundred_steps() {
+        let mut history = CommandHistory::new();
+        for i in 0..250 {
+            history.add_command(Box::new(InsertCommand::new(0, format!("{} ", i))));
+            history.finish_current_transaction();
+        }
+        assert_eq!(history.stats().undo_count, 200);
+    }
+
+    #[test]
+    fn test_undone_steps_stay_in_a_branch() {
+        let mut history = CommandHistory::new();
+        let mut rope = Rope::from_str("");
+        for text in ["a", "b"] {
+            let command = InsertCommand::new(rope.len_chars(), text.to_string());
+            rope = command.execute(&rope);
+            history.add_command(Box::new(command));
+            history.finish_current_transaction();
+        }
+        rope = history.undo(&rope).unwrap();
+        let command = InsertCommand::new(1, "c".to_string());
+        rope = command.execute(&rope);
+        history.add_command(Box::new(command));
+        history.finish_current_transaction();
+        assert_eq!(rope.to_string(), "ac");
+        assert!(!history.can_redo());
+
+        let branches = history.branches();
+        assert_eq!(branches.len(), 2);
+        assert_eq!(history.stats().node_count, 3);
+
+        // Back to "ab" through the state both branches share
+        let moves = history.path_to(branches[0].tip()).unwrap();
+        assert_eq!(moves.len(), 2);
+        for step in moves {
+            rope = match step {
+                UndoMove::Undo => history.undo(&rope),
+                UndoMove::Redo(child) => history.redo_branch(child, &rope),
+            }
+            .unwrap();
+        }
+        assert_eq!(rope.to_string(), "ab");
+        assert_eq!(history.older_node(), Some(1));
+        assert_eq!(history.newer_node(), Some(3));
+    }
+
+    #[test]
+    fn test_limits_prune_abandoned_branches_first() {
+        let mut history = CommandHistory::with_limits(3, 10 * 1024 * 1024);
+        let mut rope = Rope::from_str("");
+        for text in ["a", "b", "c"] {
+            let command = InsertCommand::new(rope.len_chars(), text.to_string());
+            rope = command.execute(&rope);
+            history.add_command(Box::new(command));
+            history.finish_current_transaction();
+        }
+        rope = history.undo(&rope).unwrap();
+        history.undo(&rope).unwrap();
+
+        // A fourth step goes over the limit, so the undone "b" and "c" go
+        history.add_command(Box::new(InsertCommand::new(1, "d".to_string())));
+        history.finish_current_transaction();
+        assert_eq!(history.stats().node_count, 3);
+        assert_eq!(history.branches().len(), 2);
+        history.add_command(Box::new(InsertCommand::new(2, " e".to_string())));
+        history.finish_current_transaction();
+        assert_eq!(history.branches().len(), 1);
+        assert_eq!(history.stats().undo_count, 3);
+    }
+
+    #[test]
+    fn test_record_restores_every_branch() {
+        let mut history = CommandHistory::new();
+        let mut rope = Rope::from_str("");
+        for (position, text) in [(0, "a"), (1, "b")] {
+            let command = InsertCommand::new(position, text.to_string());
+            rope = command.execute(&rope);
+            history.add_command(Box::new(command));
+            history.finish_current_transaction();
+        }
+        rope = history.undo(&rope).unwrap();
+        let command = InsertCommand::new(1, "c".to_string());
+        rope = command.execute(&rope);
+        history.add_command(Box::new(command));
+
+        // The open step is left out: only finished steps are saved
+        let record = history.to_record(usize::MAX);
+        assert_eq!(record.steps.len(), 2);
+        history.finish_current_transaction();
+        let record = history.to_record(usize::MAX);
+        assert_eq!(record.steps.len(), 3);
+
+        let mut restored = CommandHistory::new();
+        assert!(!restored.restore(record.clone(), &Rope::from_str("ab")));
+        assert!(!restored.can_undo());
+        assert!(restored.restore(record, &rope));
+        assert_eq!(restored.branches(), history.branches());
+        rope = restored.undo(&rope).unwrap();
+        assert_eq!(rope.to_string(), "a");
+        rope = restored.redo_branch(2, &rope).unwrap();
+        assert_eq!(rope.to_string(), "ab");
+    }
+
+    #[test]
+    fn test_record_fits_in_max_bytes() {
+        let mut history = CommandHistory::new();
+        let mut rope = Rope::from_str("");
+        for text in ["one", " two", " three", " four"] {
+            let command = InsertCommand::new(rope.len_chars(), text.to_string());
+            rope = command.execute(&rope);
+            history.add_command(Box::new(command));
+            history.finish_current_transaction();
+        }
+
+        let full = serde_json::to_string(&history.to_record(usize::MAX)).unwrap().len();
+        // Below the size of a record with no steps, no steps is all it can do
+        for max_bytes in 0..=full {
+            let record = history.to_record(max_bytes);
+            assert!(record.steps.is_empty() || serde_json::to_string(&record).unwrap().len() <= max_bytes);
+        }
+        assert_eq!(history.to_record(full).steps.len(), 4);
+        assert!(history.to_record(full - 1).steps.len() < 4);
+    }
+}
@@ -5,6 +5,7 @@
 //! will be converted to commands that can be executed, undone, and redone.
 
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
 /// Trait for commands that can be executed, undone, and redone
 /// Commands must be cloneable and debuggable for use in the command history system
@@ -20,6 +21,97 @@ pub trait UndoableCommand: std::fmt::Debug + Send + Sync {
     
     /// Clone this command
     fn clone_command(&self) -> Box<dyn UndoableCommand>;
+
+    /// The region of text this command changes when executed
+    fn edit_range(&self) -> EditRange;
+
+    /// Plain-data copy of this command, for journals and saved history
+    fn to_record(&self) -> CommandRecord;
+}
+
+/// Serializable form of the built-in commands
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommandRecord {
+    Insert { position: usize, text: String },
+    Delete { start: usize, end: usize, deleted_text: String },
+    Replace { start: usize, end: usize, old_text: String, new_text: String },
+}
+
+impl CommandRecord {
+    /// Rebuild the command this record was made from
+    pub fn into_command(self) -> Box<dyn UndoableCommand> {
+        match self {
+            CommandRecord::Insert { position, text } => Box::new(InsertCommand::new(position, text)),
+            CommandRecord::Delete { start, end, deleted_text } => {
+                Box::new(DeleteCommand::new(start, end, deleted_text))
+            }
+            CommandRecord::Replace { start, end, old_text, new_text } => {
+                Box::new(ReplaceCommand::new(start, end, old_text, new_text))
+            }
+        }
+    }
+
+    /// The record that reverses this one
+    pub fn inverse(&self) -> CommandRecord {
+        match self {
+            CommandRecord::Insert { position, text } => CommandRecord::Delete {
+                start: *position,
+                end: position + text.chars().count(),
+                deleted_text: text.clone(),
+            },
+            CommandRecord::Delete { start, deleted_text, .. } => CommandRecord::Insert {
+                position: *start,
+                text: deleted_text.clone(),
+            },
+            CommandRecord::Replace { start, old_text, new_text, .. } => CommandRecord::Replace {
+                start: *start,
+                end: start + new_text.chars().count(),
+                old_text: new_text.clone(),
+                new_text: old_text.clone(),
+            },
+        }
+    }
+
+    /// Whether this record can be applied to `rope` without going out of bounds
+    /// or removing text other than what it expects
+    pub fn applies_to(&self, rope: &Rope) -> bool {
+        let len = rope.len_chars();
+        match self {
+            CommandRecord::Insert { position, .. } => *position <= len,
+            CommandRecord::Delete { start, end, deleted_text } => {
+                start <= end && *end <= len && rope.slice(*start..*end) == deleted_text.as_str()
+            }
+            CommandRecord::Replace { start, end, old_text, .. } => {
+                start <= end && *end <= len && rope.slice(*start..*end) == old_text.as_str()
+            }
+        }
+    }
+}
+
+/// The region of text changed by a command, in char positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditRange {
+    /// Position where the change starts
+    pub start: usize,
+    /// Number of chars removed at `start`
+    pub removed: usize,
+    /// Number of chars inserted at `start`
+    pub inserted: usize,
+}
+
+impl EditRange {
+    /// Map a position in the text before the edit to the same place after it.
+    /// Positions inside the removed text end up after the inserted text.
+    pub fn map_position(&self, position: usize) -> usize {
+        if position <= self.start {
+            position
+        } else if position >= self.start + self.removed {
+            position - self.removed + self.inserted
+        } else {
+            self.start + self.inserted
+        }
+    }
 }
 
 /// Insert text at a specific position
@@ -56,6 +148,21 @@ impl UndoableCommand for InsertCommand {
     fn clone_command(&self) -> Box<dyn UndoableCommand> {
         Box::new(self.clone())
     }
+
+    fn edit_range(&self) -> EditRange {
+        EditRange {
+            start: self.position,
+            removed: 0,
+            inserted: self.text.chars().count(),
+        }
+    }
+
+    fn to_record(&self) -> CommandRecord {
+        CommandRecord::Insert {
+            position: self.position,
+            text: self.text.clone(),
+        }
+    }
 }
 
 /// Delete text within a specific range
@@ -92,6 +199,22 @@ impl UndoableCommand for DeleteCommand {
     fn clone_command(&self) -> Box<dyn UndoableCommand> {
         Box::new(self.clone())
     }
+
+    fn edit_range(&self) -> EditRange {
+        EditRange {
+            start: self.start,
+            removed: self.end - self.start,
+            inserted: 0,
+        }
+    }
+
+    fn to_record(&self) -> CommandRecord {
+        CommandRecord::Delete {
+            start: self.start,
+            end: self.end,
+            deleted_text: self.deleted_text.clone(),
+        }
+    }
 }
 
 /// Replace text within a specific range
@@ -132,6 +255,23 @@ impl UndoableCommand for ReplaceCommand {
     fn clone_command(&self) -> Box<dyn UndoableCommand> {
         Box::new(self.clone())
     }
+
+    fn edit_range(&self) -> EditRange {
+        EditRange {
+            start: self.start,
+            removed: self.end - self.start,
+            inserted: self.new_text.chars().count(),
+        }
+    }
+
+    fn to_record(&self) -> CommandRecord {
+        CommandRecord::Replace {
+            start: self.start,
+            end: self.end,
+            old_text: self.old_text.clone(),
+            new_text: self.new_text.clone(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -203,4 +343,57 @@ mod tests {
         assert_eq!(delete_cmd.description(), "Delete text");
         assert_eq!(replace_cmd.description(), "Replace text");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_edit_ranges_map_positions() {
+        let insert = InsertCommand::new(2, "abc".to_string()).edit_range();
+        assert_eq!(insert.map_position(1), 1);
+        assert_eq!(insert.map_position(2), 2);
+        assert_eq!(insert.map_position(5), 8);
+
+        let delete = DeleteCommand::new(2, 6, "text".to_string()).edit_range();
+        assert_eq!(delete.map_position(4), 2);
+        assert_eq!(delete.map_position(6), 2);
+        assert_eq!(delete.map_position(9), 5);
+
+        let replace = ReplaceCommand::new(2, 4, "ab".to_string(), "wxyz".to_string()).edit_range();
+        assert_eq!(replace.map_position(3), 6);
+        assert_eq!(replace.map_position(4), 6);
+        assert_eq!(replace.map_position(10), 12);
+    }
+
+    #[test]
+    fn test_command_records_round_trip() {
+        let rope = Rope::from_str("Hello World");
+        let commands: Vec<Box<dyn UndoableCommand>> = vec![
+            Box::new(InsertCommand::new(5, ",".to_string())),
+            Box::new(DeleteCommand::new(0, 1, "H".to_string())),
+            Box::new(ReplaceCommand::new(6, 11, "World".to_string(), "Rust".to_string())),
+        ];
+
+        for command in commands {
+            let record = command.to_record();
+            let json = serde_json::to_string(&record).unwrap();
+            let parsed: CommandRecord = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, record);
+            assert_eq!(parsed.into_command().execute(&rope), command.execute(&rope));
+        }
+    }
+
+    #[test]
+    fn test_command_record_inverse() {
+        let rope = Rope::from_str("Hello World");
+        let record = CommandRecord::Replace {
+            start: 6,
+            end: 11,
+            old_text: "World".to_string(),
+            new_text: "Rust".to_string(),
+        };
+        assert!(record.applies_to(&rope));
+
+        let changed = record.clone().into_command().execute(&rope);
+        assert!(!record.applies_to(&changed));
+        assert!(record.inverse().applies_to(&changed));
+        assert_eq!(record.inverse().into_command().execute(&changed), rope);
+    }
+}
@@ -0,0 +1,438 @@
+//! Line diffs and three-way merges between versions of a document
+//!
+//! Used when the file behind the document changes on disk: `map_position`
+//! keeps the cursor on the same text across a reload, and `merge3` combines
+//! unsaved edits with the changes made on disk since the last save. The
+//! formatter uses `map_position_in_rewrite` to keep cursors in place.
+//! `PositionMap` does either for many positions with a single diff.
+//! Positions are char positions, the same units as the cursor.
+
+use std::ops::Range;
+
+/// Beyond this many line comparisons the changed middle is treated as one hunk
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// A run of lines that differs between two texts, in line indices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub old_lines: Range<usize>,
+    pub new_lines: Range<usize>,
+}
+
+/// Result of a three-way merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub text: String,
+    /// Number of regions marked with conflict markers
+    pub conflicts: usize,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+/// Split into lines, keeping line endings so the lines join back into `text`
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Lines for mapping positions: like `lines`, plus the empty line after a
+/// trailing newline so the end of the text has a line of its own
+fn position_lines(text: &str) -> Vec<&str> {
+    let mut lines = lines(text);
+    if text.is_empty() || text.ends_with('\n') {
+        lines.push("");
+    }
+    lines
+}
+
+/// The hunks that turn `old` into `new`. Hunks are in order and separated by
+/// at least one unchanged line.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffHunk> {
+    diff_line_slices(&lines(old), &lines(new))
+}
+
+fn diff_line_slices(old: &[&str], new: &[&str]) -> Vec<DiffHunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+    if old_middle.is_empty()
+        || new_middle.is_empty()
+        || old_middle.len() * new_middle.len() > MAX_DIFF_CELLS
+    {
+        return vec![DiffHunk {
+            old_lines: prefix..old.len() - suffix,
+            new_lines: prefix..new.len() - suffix,
+        }];
+    }
+
+    // Longest common subsequence table over the changed middle
+    let (rows, columns) = (old_middle.len(), new_middle.len());
+    let mut lcs = vec![0u32; (rows + 1) * (columns + 1)];
+    for i in (0..rows).rev() {
+        for j in (0..columns).rev() {
+            lcs[i * (columns + 1) + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * (columns + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (columns + 1) + j].max(lcs[i * (columns + 1) + j + 1])
+            };
+        }
+    }
+
+    // Walk the table, collecting runs of unmatched lines into hunks
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut hunk_start: Option<(usize, usize)> = None;
+    while i < rows || j < columns {
+        if i < rows && j < columns && old_middle[i] == new_middle[j] {
+            if let Some((old_start, new_start)) = hunk_start.take() {
+                hunks.push(DiffHunk {
+                    old_lines: prefix + old_start..prefix + i,
+                    new_lines: prefix + new_start..prefix + j,
+                });
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        hunk_start.get_or_insert((i, j));
+        if j == columns || (i < rows && lcs[(i + 1) * (columns + 1) + j] >= lcs[i * (columns + 1) + j + 1]) {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    if let Some((old_start, new_start)) = hunk_start {
+        hunks.push(DiffHunk {
+            old_lines: prefix + old_start..prefix + rows,
+            new_lines: prefix + new_start..prefix + columns,
+        });
+    }
+    hunks
+}
+
+/// Map a char position in `old` to the matching position in `new`. Positions
+/// on unchanged lines keep their line and column; positions on changed lines
+/// move to the same column of the replacement, clamped to its first line.
+pub fn map_position(old: &str, new: &str, position: usize) -> usize {
+    PositionMap::new(old, new).map(position)
+}
+
+/// Like `map_position`, but a position on a changed line follows the
+/// characters around it into the replacement. Suited to rewrites that keep
+/// the text and change its markup, such as formatting, where `Title\n===`
+/// becomes `# Title` and the cursor should stay inside the same word.
+pub fn map_position_in_rewrite(old: &str, new: &str, position: usize) -> usize {
+    PositionMap::in_rewrite(old, new).map(position)
+}
+
+/// Maps positions from one text to another, diffing the texts once however
+/// many positions go through it
+#[derive(Debug, Clone)]
+pub struct PositionMap<'a> {
+    old_lines: Vec<&'a str>,
+    new_lines: Vec<&'a str>,
+    /// Char position each line starts at, plus the end of the text
+    old_starts: Vec<usize>,
+    new_starts: Vec<usize>,
+    hunks: Vec<DiffHunk>,
+    follow_characters: bool,
+}
+
+impl<'a> PositionMap<'a> {
+    /// Map the way `map_position` does
+    pub fn new(old: &'a str, new: &'a str) -> Self {
+        Self::with_mode(old, new, false)
+    }
+
+    /// Map the way `map_position_in_rewrite` does
+    pub fn in_rewrite(old: &'a str, new: &'a str) -> Self {
+        Self::with_mode(old, new, true)
+    }
+
+    fn with_mode(old: &'a str, new: &'a str, follow_characters: bool) -> Self {
+        let old_lines = position_lines(old);
+        let new_lines = position_lines(new);
+        let hunks = diff_line_slices(&old_lines, &new_lines);
+        Self {
+            old_starts: line_starts(&old_lines),
+            new_starts: line_starts(&new_lines),
+            old_lines,
+            new_lines,
+            hunks,
+            follow_characters,
+        }
+    }
+
+    pub fn map(&self, position: usize) -> usize {
+        // The line `position` is on; the last line takes anything past the end
+        let line = self.old_starts[1..self.old_lines.len()].partition_point(|&start| start <= position);
+        let column = position - self.old_starts[line];
+
+        let mut new_line = line;
+        for hunk in &self.hunks {
+            if line < hunk.old_lines.start {
+                break;
+            }
+            if line < hunk.old_lines.end {
+                if self.follow_characters {
+                    let old_chars: Vec<char> = self.old_lines[hunk.old_lines.clone()].concat().chars().collect();
+                    let new_chars: Vec<char> = self.new_lines[hunk.new_lines.clone()].concat().chars().collect();
+                    let offset = position - self.old_starts[hunk.old_lines.start];
+                    if let Some(mapped) = map_within(&old_chars, &new_chars, offset) {
+                        return self.new_starts[hunk.new_lines.start] + mapped;
+                    }
+                }
+                new_line = hunk.new_lines.start;
+                break;
+            }
+            new_line = line - hunk.old_lines.end + hunk.new_lines.end;
+        }
+
+        let line_length = self
+            .new_lines
+            .get(new_line)
+            .map(|line| line.trim_end_matches('\n').chars().count())
+            .unwrap_or(0);
+        self.new_starts[new_line] + column.min(line_length)
+    }
+}
+
+/// Char position each line starts at, plus the end of the text
+fn line_starts(lines: &[&str]) -> Vec<usize> {
+    let mut starts = Vec::with_capacity(lines.len() + 1);
+    let mut start = 0;
+    starts.push(start);
+    for line in lines {
+        start += line.chars().count();
+        starts.push(start);
+    }
+    starts
+}
+
+/// Map `offset` in `old` to `new` by matching their characters: the position
+/// goes in front of the first character at or after it that both texts share,
+/// or after the last shared one before it. `None` if nothing is shared.
+fn map_within(old: &[char], new: &[char], offset: usize) -> Option<usize> {
+    let (rows, columns) = (old.len(), new.len());
+    if rows * columns > MAX_DIFF_CELLS {
+        return None;
+    }
+    let mut lcs = vec![0u32; (rows + 1) * (columns + 1)];
+    for i in (0..rows).rev() {
+        for j in (0..columns).rev() {
+            lcs[i * (columns + 1) + j] = if old[i] == new[j] {
+                lcs[(i + 1) * (columns + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (columns + 1) + j].max(lcs[i * (columns + 1) + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut last_match = None;
+    while i < rows && j < columns {
+        if old[i] == new[j] {
+            if i >= offset {
+                return Some(j);
+            }
+            last_match = Some(j + 1);
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * (columns + 1) + j] >= lcs[i * (columns + 1) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    last_match
+}
+
+/// Merge the changes from `base` to `ours` with the changes from `base` to
+/// `theirs`. Where both sides changed the same lines differently, both
+/// versions are kept between `<<<<<<<`, `=======` and `>>>>>>>` markers.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let base_lines = lines(base);
+    let our_lines = lines(ours);
+    let their_lines = lines(theirs);
+    let our_hunks = diff_line_slices(&base_lines, &our_lines);
+    let their_hunks = diff_line_slices(&base_lines, &their_lines);
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+    let mut base_position = 0;
+    // How far each side's line numbers have drifted from the base so far
+    let (mut our_offset, mut their_offset) = (0isize, 0isize);
+    let (mut next_ours, mut next_theirs) = (0, 0);
+
+    loop {
+        // Start a region at whichever side's next hunk comes first
+        let start = match (our_hunks.get(next_ours), their_hunks.get(next_theirs)) {
+            (Some(a), Some(b)) => a.old_lines.start.min(b.old_lines.start),
+            (Some(a), None) => a.old_lines.start,
+            (None, Some(b)) => b.old_lines.start,
+            (None, None) => break,
+        };
+        let mut end = start;
+        let (first_ours, first_theirs) = (next_ours, next_theirs);
+
+        // Grow it until no hunk on either side touches it
+        loop {
+            if let Some(hunk) = our_hunks.get(next_ours).filter(|hunk| hunk.old_lines.start <= end) {
+                end = end.max(hunk.old_lines.end);
+                next_ours += 1;
+            } else if let Some(hunk) = their_hunks.get(next_theirs).filter(|hunk| hunk.old_lines.start <= end) {
+                end = end.max(hunk.old_lines.end);
+                next_theirs += 1;
+            } else {
+                break;
+            }
+        }
+
+        text.extend(base_lines[base_position..start].iter().copied());
+        base_position = end;
+
+        let our_text = side_text(&our_lines, &our_hunks[first_ours..next_ours], start..end, &mut our_offset);
+        let their_text = side_text(&their_lines, &their_hunks[first_theirs..next_theirs], start..end, &mut their_offset);
+        match (our_text, their_text) {
+            (Some(ours), None) => text.push_str(&ours),
+            (None, Some(theirs)) => text.push_str(&theirs),
+            (Some(ours), Some(theirs)) if ours == theirs => text.push_str(&ours),
+            (Some(ours), Some(theirs)) => {
+                conflicts += 1;
+                text.push_str("<<<<<<< yours\n");
+                push_with_newline(&mut text, &ours);
+                text.push_str("=======\n");
+                push_with_newline(&mut text, &theirs);
+                text.push_str(">>>>>>> on disk\n");
+            }
+            (None, None) => {}
+        }
+    }
+    text.extend(base_lines[base_position..].iter().copied());
+
+    MergeResult { text, conflicts }
+}
+
+/// The lines one side has in place of the base region `base_range`, or `None`
+/// if that side didn't change it. Updates the side's running line offset.
+fn side_text(
+    side_lines: &[&str],
+    hunks: &[DiffHunk],
+    base_range: Range<usize>,
+    offset: &mut isize,
+) -> Option<String> {
+    if hunks.is_empty() {
+        return None;
+    }
+    let start = (base_range.start as isize + *offset) as usize;
+    for hunk in hunks {
+        *offset += hunk.new_lines.len() as isize - hunk.old_lines.len() as isize;
+    }
+    let end = (base_range.end as isize + *offset) as usize;
+    Some(side_lines[start..end].concat())
+}
+
+fn push_with_newline(text: &mut String, lines: &str) {
+    text.push_str(lines);
+    if !lines.is_empty() && !lines.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines_finds_changed_runs() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "a\nB\nc\nd\nx\ne\n";
+        assert_eq!(
+            diff_lines(old, new),
+            vec![
+                DiffHunk { old_lines: 1..2, new_lines: 1..2 },
+                DiffHunk { old_lines: 4..4, new_lines: 4..5 },
+            ]
+        );
+        assert!(diff_lines(old, old).is_empty());
+    }
+
+    #[test]
+    fn test_map_position_follows_unchanged_lines() {
+        let old = "# Title\n\nSome text here\n";
+        let new = "# Title\n\nA new paragraph\n\nSome text here\n";
+        // Cursor after "Some " stays after "Some "
+        let position = old.find("text").unwrap();
+        let mapped = map_position(old, new, position);
+        assert_eq!(&new[mapped..mapped + 4], "text");
+
+        // On a replaced line the column is kept, clamped to the new line
+        assert_eq!(map_position("abcdef\n", "xy\n", 4), 2);
+        assert_eq!(map_position("one", "one two", 3), 3);
+        // Lines inserted at the cursor's line push it down
+        assert_eq!(map_position("a\nb\n", "a\nb\nc\n", 4), 6);
+        assert_eq!(map_position("", "new", 0), 0);
+    }
+
+    #[test]
+    fn test_map_position_in_rewrite_follows_characters() {
+        let old = "Intro\n\nTitle\n=====\n\nSome __bold__ text\n";
+        let new = "Intro\n\n# Title\n\nSome **bold** text\n";
+        let after_ti = old.find("tle").unwrap();
+        assert_eq!(&new[map_position_in_rewrite(old, new, after_ti)..], &new[new.find("tle").unwrap()..]);
+        let in_bold = old.find("old").unwrap();
+        assert_eq!(&new[map_position_in_rewrite(old, new, in_bold)..][..3], "old");
+        // Unchanged lines map as they do for `map_position`
+        assert_eq!(map_position_in_rewrite(old, new, 2), 2);
+    }
+
+    #[test]
+    fn test_position_map_maps_many_positions_like_single_calls() {
+        let old = "# Title\n\nfirst line\nsecond line\n\nlast\n";
+        let new = "# Title\n\nintro\n\nfirst line\nchanged\n\nlast\n";
+        let positions = PositionMap::new(old, new);
+        for position in 0..=old.chars().count() + 1 {
+            assert_eq!(positions.map(position), map_position(old, new, position), "at {}", position);
+        }
+    }
+
+    #[test]
+    fn test_merge3_combines_separate_changes() {
+        let base = "# Notes\n\nfirst\n\nsecond\n\nthird\n";
+        let ours = "# Notes\n\nfirst, edited here\n\nsecond\n\nthird\n";
+        let theirs = "# Notes\n\nfirst\n\nsecond\n\nthird, edited on disk\n";
+        let merged = merge3(base, ours, theirs);
+        assert!(merged.is_clean());
+        assert_eq!(merged.text, "# Notes\n\nfirst, edited here\n\nsecond\n\nthird, edited on disk\n");
+
+        // The same change on both sides isn't a conflict
+        assert_eq!(merge3(base, ours, ours).text, ours);
+    }
+
+    #[test]
+    fn test_merge3_marks_conflicts() {
+        let base = "title\nbody\n";
+        let ours = "title\nmy body\n";
+        let theirs = "title\ntheir body";
+        let merged = merge3(base, ours, theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.text,
+            "title\n<<<<<<< yours\nmy body\n=======\ntheir body\n>>>>>>> on disk\n"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Markdown formatting markup in source text
+//!
+//! Inline markers (`**`, `*`, `` ` ``, `~~`, `==`) are found by pairing runs
+//! of marker characters on the lines around the selection, so a toggle can
+//! tell whether the selection or cursor is already inside a formatted span.
+//! Line formats (headings, lists, quotes) are rewritten through `LinePrefix`.
+//! Offsets are char offsets into the text passed in.
+
+use std::ops::Range;
+
+use super::lists::{self, LinePrefix, ListMarker, MarkerKind};
+
+/// An opening and closing inline marker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkerPair {
+    pub open: Range<usize>,
+    pub close: Range<usize>,
+}
+
+/// The pair of `marker`s in `text` that formats `start..end`: one containing
+/// the selection, markers included, or around the text a cursor is in
+pub fn enclosing_pair(text: &str, marker: &str, start: usize, end: usize) -> Option<MarkerPair> {
+    let chars: Vec<char> = text.chars().collect();
+    let marker_char = marker.chars().next()?;
+    let length = marker.chars().count();
+    let code_spans = if marker_char == '`' { Vec::new() } else { code_spans(&chars) };
+    // `_` works the same as `*` for emphasis
+    let alternatives: &[char] = if marker_char == '*' { &['*', '_'] } else { &[marker_char] };
+
+    for &ch in alternatives {
+        let mut open: Option<Range<usize>> = None;
+        for run in runs(&chars, ch) {
+            let escaped = run.start > 0 && chars[run.start - 1] == '\\';
+            let in_code = code_spans.iter().any(|span| span.contains(&run.start));
+            // `***` is both bold and italic
+            let matches = run.len() == length || (ch != '`' && run.len() == 3 && length < 3);
+            if escaped || in_code || !matches {
+                continue;
+            }
+
+            let Some(opening) = open.take() else {
+                // `_` inside a word, as in snake_case, isn't emphasis
+                if ch == '_' && run.start > 0 && chars[run.start - 1].is_alphanumeric() {
+                    continue;
+                }
+                open = Some(run.end - length..run.end);
+                continue;
+            };
+            let pair = MarkerPair { open: opening, close: run.start..run.start + length };
+            let encloses = if start == end {
+                pair.open.end <= start && start <= pair.close.start
+            } else {
+                pair.open.start <= start && end <= pair.close.end
+            };
+            if encloses {
+                return Some(pair);
+            }
+        }
+    }
+    None
+}
+
+/// Runs of `ch` in `chars`
+fn runs(chars: &[char], ch: char) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index] != ch {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < chars.len() && chars[index] == ch {
+            index += 1;
+        }
+        runs.push(start..index);
+    }
+    runs
+}
+
+/// Code spans, between backtick runs of the same length
+fn code_spans(chars: &[char]) -> Vec<Range<usize>> {
+    let backticks = runs(chars, '`');
+    let mut spans = Vec::new();
+    let mut index = 0;
+    while index < backticks.len() {
+        let open = &backticks[index];
+        match backticks[index + 1..].iter().position(|close| close.len() == open.len()) {
+            Some(offset) => {
+                spans.push(open.start..backticks[index + 1 + offset].end);
+                index += offset + 2;
+            }
+            None => index += 1,
+        }
+    }
+    spans
+}
+
+/// Level of an ATX heading line and the length of its `#`s and spacing
+fn heading(text: &str) -> Option<(usize, usize)> {
+    let level = text.len() - text.trim_start_matches('#').len();
+    let rest = &text[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, level + rest.len() - rest.trim_start_matches([' ', '\t']).len()))
+}
+
+/// Split a line into its container prefix and the text after it
+fn split(line: &str) -> (LinePrefix, &str) {
+    let prefix = LinePrefix::parse(line);
+    let length = prefix.len();
+    (prefix, &line[length..])
+}
+
+/// Make every non-blank line a heading of `level`, or plain text if they
+/// all are already
+pub fn toggle_heading(lines: &mut [String], level: usize) {
+    let text_lines = || lines.iter().filter(|line| !lists::is_blank(line));
+    let all_set = text_lines().all(|line| heading(split(line).1).is_some_and(|(current, _)| current == level));
+    for line in lines.iter_mut().filter(|line| !lists::is_blank(line)) {
+        let (prefix, text) = split(line);
+        let text = heading(text).map_or(text, |(_, length)| &text[length..]);
+        *line = if all_set {
+            format!("{}{}", prefix, text)
+        } else {
+            format!("{}{} {}", prefix, "#".repeat(level), text)
+        };
+    }
+}
+
+/// Make every non-blank line a bullet item, or take the markers off if they
+/// all are already. Numbered and task items become plain bullets.
+pub fn toggle_bullet_list(lines: &mut [String]) {
+    let is_bullet = |marker: &ListMarker| matches!(marker.kind, MarkerKind::Bullet(_)) && marker.task.is_none();
+    toggle_list(lines, is_bullet, |marker| match marker {
+        Some(marker) if matches!(marker.kind, MarkerKind::Bullet(_)) => ListMarker { task: None, ..marker },
+        _ => bullet(None),
+    });
+}
+
+/// Make every non-blank line a task item, or take the markers off if they
+/// all are already
+pub fn toggle_task_list(lines: &mut [String]) {
+    toggle_list(lines, |marker| marker.task.is_some(), |marker| match marker {
+        Some(marker) if marker.task.is_some() => marker,
+        Some(marker) => ListMarker { task: Some("[ ] ".to_string()), ..marker },
+        None => bullet(Some("[ ] ".to_string())),
+    });
+}
+
+fn bullet(task: Option<String>) -> ListMarker {
+    ListMarker { kind: MarkerKind::Bullet('-'), spacing: " ".to_string(), task }
+}
+
+fn toggle_list(
+    lines: &mut [String],
+    is_set: impl Fn(&ListMarker) -> bool,
+    set: impl Fn(Option<ListMarker>) -> ListMarker,
+) {
+    let all_set = lines
+        .iter()
+        .filter(|line| !lists::is_blank(line))
+        .all(|line| LinePrefix::parse(line).marker.as_ref().is_some_and(&is_set));
+    for line in lines.iter_mut().filter(|line| !lists::is_blank(line)) {
+        let (mut prefix, text) = split(line);
+        let text = text.to_string();
+        prefix.marker = if all_set { None } else { Some(set(prefix.marker.take())) };
+        *line = format!("{}{}", prefix, text);
+    }
+}
+
+/// Quote every line, or take one level of quoting off if every non-blank
+/// line is quoted already
+pub fn toggle_blockquote(lines: &mut [String]) {
+    let all_quoted = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .all(|line| LinePrefix::parse(line).quote_depth() > 0);
+    for line in lines.iter_mut() {
+        *line = if all_quoted {
+            let quote = LinePrefix::parse(line).quote;
+            match quote.find('>') {
+                Some(marker) => {
+                    let after = &line[marker + 1..];
+                    after.strip_prefix(' ').unwrap_or(after).to_string()
+                }
+                None => line.clone(),
+            }
+        } else if line.is_empty() {
+            ">".to_string()
+        } else {
+            format!("> {}", line)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_enclosing_pair() {
+        let text = "a **bold** and *it* `co*de*` ***both***";
+        let pair = |marker: &str, start: usize, end: usize| enclosing_pair(text, marker, start, end);
+
+        // Cursor inside, selection inside, and selection over the markers
+        let bold = Some(MarkerPair { open: 2..4, close: 8..10 });
+        assert_eq!(pair("**", 6, 6), bold);
+        assert_eq!(pair("**", 5, 7), bold);
+        assert_eq!(pair("**", 2, 10), bold);
+        assert_eq!(pair("**", 10, 10), None);
+        assert_eq!(pair("*", 6, 6), None);
+
+        assert_eq!(pair("*", 17, 17), Some(MarkerPair { open: 15..16, close: 18..19 }));
+        // Markers inside code don't count
+        assert_eq!(pair("*", 24, 24), None);
+        assert_eq!(pair("`", 24, 24), Some(MarkerPair { open: 20..21, close: 27..28 }));
+        assert_eq!(pair("*", 33, 33), Some(MarkerPair { open: 31..32, close: 36..37 }));
+        assert_eq!(pair("**", 33, 33), Some(MarkerPair { open: 30..32, close: 36..38 }));
+
+        assert_eq!(enclosing_pair("==mark== snake_case_name", "==", 4, 4), Some(MarkerPair { open: 0..2, close: 6..8 }));
+        assert_eq!(enclosing_pair("snake_case_name", "*", 8, 8), None);
+    }
+
+    #[test]
+    fn test_toggle_heading() {
+        let mut text = lines("Title\n\n## Section");
+        toggle_heading(&mut text, 2);
+        assert_eq!(text, lines("## Title\n\n## Section"));
+        toggle_heading(&mut text, 2);
+        assert_eq!(text, lines("Title\n\nSection"));
+        let mut text = lines("> ### Quoted");
+        toggle_heading(&mut text, 1);
+        assert_eq!(text, lines("> # Quoted"));
+    }
+
+    #[test]
+    fn test_toggle_lists_and_quotes() {
+        let mut text = lines("one\n1. two\n\n  - [x] three");
+        toggle_bullet_list(&mut text);
+        assert_eq!(text, lines("- one\n- two\n\n  - three"));
+        toggle_task_list(&mut text);
+        assert_eq!(text, lines("- [ ] one\n- [ ] two\n\n  - [ ] three"));
+        toggle_task_list(&mut text);
+        assert_eq!(text, lines("one\ntwo\n\n  three"));
+
+        let mut text = lines("quote\n\n> already");
+        toggle_blockquote(&mut text);
+        assert_eq!(text, lines("> quote\n>\n> > already"));
+        toggle_blockquote(&mut text);
+        assert_eq!(text, lines("quote\n\n> already"));
+    }
+}
@@ -0,0 +1,123 @@
+//! Recently cut and copied text, Emacs style
+//!
+//! The ring keeps the latest kills newest first. Pasting (yanking) inserts the
+//! newest entry, and each yank-pop after it moves on to an older one, wrapping
+//! around to the newest again after the oldest.
+
+use std::collections::VecDeque;
+
+/// Kills kept before the oldest are dropped
+const KILL_RING_CAPACITY: usize = 60;
+
+#[derive(Debug, Clone, Default)]
+pub struct KillRing {
+    entries: VecDeque<String>,
+    /// The entry the last yank or yank-pop inserted
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a kill as the newest entry
+    pub fn push(&mut self, text: String) {
+        self.entries.push_front(text);
+        self.entries.truncate(KILL_RING_CAPACITY);
+        self.yank_index = 0;
+    }
+
+    /// Add text killed after the newest entry's text to the end of it, as
+    /// when killing several lines in a row
+    pub fn append(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(newest) => newest.push_str(text),
+            None => self.entries.push_front(text.to_string()),
+        }
+        self.yank_index = 0;
+    }
+
+    /// Add text killed before the newest entry's text to the start of it, as
+    /// when killing several words backwards
+    pub fn prepend(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(newest) => newest.insert_str(0, text),
+            None => self.entries.push_front(text.to_string()),
+        }
+        self.yank_index = 0;
+    }
+
+    pub fn newest(&self) -> Option<&str> {
+        self.entries.front().map(String::as_str)
+    }
+
+    /// The newest entry, to paste
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.newest()
+    }
+
+    /// The entry before the one last yanked, to replace it with
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.entries.len();
+        self.entries.get(self.yank_index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yank_pop_cycles_through_kills() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.yank_pop(), None);
+
+        ring.push("one".to_string());
+        ring.push("two".to_string());
+        ring.push("three".to_string());
+        assert_eq!(ring.yank(), Some("three"));
+        assert_eq!(ring.yank_pop(), Some("two"));
+        assert_eq!(ring.yank_pop(), Some("one"));
+        assert_eq!(ring.yank_pop(), Some("three"));
+
+        // A new kill starts over from the newest entry
+        ring.yank_pop();
+        ring.push("four".to_string());
+        assert_eq!(ring.yank_pop(), Some("three"));
+        assert_eq!(ring.yank(), Some("four"));
+    }
+
+    #[test]
+    fn test_kills_in_a_row_join_one_entry() {
+        let mut ring = KillRing::new();
+        ring.append("first line\n");
+        ring.append("second line\n");
+        ring.prepend("before ");
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.newest(), Some("before first line\nsecond line\n"));
+    }
+
+    #[test]
+    fn test_oldest_kills_are_dropped() {
+        let mut ring = KillRing::new();
+        for kill in 0..KILL_RING_CAPACITY + 5 {
+            ring.push(kill.to_string());
+        }
+        assert_eq!(ring.len(), KILL_RING_CAPACITY);
+        assert_eq!(ring.newest(), Some((KILL_RING_CAPACITY + 4).to_string().as_str()));
+    }
+}
@@ -0,0 +1,502 @@
+//! Markdown list and blockquote prefixes of source lines
+//!
+//! `TextDocument` uses these to continue a list or quote on Enter, to indent
+//! and outdent list items, to keep ordered lists numbered and to tick tasks.
+//! Everything here works on single lines of source text without their line
+//! ending. Prefixes are ASCII, so their byte lengths are also their lengths
+//! in chars.
+
+use std::fmt;
+
+/// Columns a tab advances to, for comparing indentation
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkerKind {
+    /// `-`, `*` or `+`
+    Bullet(char),
+    /// `1.` or `1)`
+    Ordered { number: usize, delimiter: char },
+}
+
+/// A list item marker with the whitespace and task box after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListMarker {
+    pub kind: MarkerKind,
+    /// Whitespace between the marker and the item text
+    pub spacing: String,
+    /// Task box with its trailing space, e.g. `[x] `
+    pub task: Option<String>,
+}
+
+impl ListMarker {
+    pub fn is_ordered(&self) -> bool {
+        matches!(self.kind, MarkerKind::Ordered { .. })
+    }
+
+    /// Whether the task box is ticked, `None` if this isn't a task item
+    pub fn task_checked(&self) -> Option<bool> {
+        self.task.as_ref().map(|task| task.as_bytes()[1] != b' ')
+    }
+
+    /// Width of the marker and its spacing, which is how far nested content
+    /// has to be indented
+    pub fn width(&self) -> usize {
+        self.marker_text().len() + self.spacing.len()
+    }
+
+    fn marker_text(&self) -> String {
+        match self.kind {
+            MarkerKind::Bullet(bullet) => bullet.to_string(),
+            MarkerKind::Ordered { number, delimiter } => format!("{}{}", number, delimiter),
+        }
+    }
+}
+
+/// The container markup at the start of a line: blockquote markers,
+/// indentation and a list marker, each of which may be missing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinePrefix {
+    /// Blockquote markers, e.g. `> > `
+    pub quote: String,
+    /// Whitespace after the quote markers
+    pub indent: String,
+    pub marker: Option<ListMarker>,
+}
+
+impl LinePrefix {
+    pub fn parse(line: &str) -> Self {
+        let mut rest = line;
+        let mut quote_length = 0;
+        loop {
+            let spaces = rest.len() - rest.trim_start_matches(' ').len();
+            if spaces > 3 || !rest[spaces..].starts_with('>') {
+                break;
+            }
+            let mut length = spaces + 1;
+            if rest[length..].starts_with(' ') {
+                length += 1;
+            }
+            quote_length += length;
+            rest = &rest[length..];
+        }
+        let quote = line[..quote_length].to_string();
+
+        let indent_length = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let indent = rest[..indent_length].to_string();
+        rest = &rest[indent_length..];
+
+        let marker = if is_thematic_break(rest) { None } else { parse_marker(rest) };
+        Self { quote, indent, marker }
+    }
+
+    /// Length of the whole prefix
+    pub fn len(&self) -> usize {
+        self.to_string().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quote.is_empty() && self.indent.is_empty() && self.marker.is_none()
+    }
+
+    pub fn quote_depth(&self) -> usize {
+        self.quote.matches('>').count()
+    }
+
+    pub fn indent_width(&self) -> usize {
+        indent_width(&self.indent)
+    }
+
+    /// The prefix for the line after this one: the same quote, and the next
+    /// item of the same list with an empty task box if this is a task
+    pub fn next_item(&self) -> Self {
+        let marker = self.marker.as_ref().map(|marker| ListMarker {
+            kind: match marker.kind {
+                MarkerKind::Ordered { number, delimiter } => MarkerKind::Ordered { number: number + 1, delimiter },
+                ref bullet => bullet.clone(),
+            },
+            spacing: marker.spacing.clone(),
+            task: marker.task.as_ref().map(|_| "[ ] ".to_string()),
+        });
+        Self { marker, ..self.clone() }
+    }
+
+    /// The prefix with its innermost container removed: the list marker
+    /// and indentation if there is one, otherwise the last quote level
+    pub fn outer(&self) -> Self {
+        if self.marker.is_some() {
+            return Self { quote: self.quote.clone(), ..Self::default() };
+        }
+        let quote = match self.quote.rfind('>') {
+            Some(last) => self.quote[..last].to_string(),
+            None => String::new(),
+        };
+        Self { quote, ..Self::default() }
+    }
+}
+
+impl fmt::Display for LinePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.quote, self.indent)?;
+        if let Some(marker) = &self.marker {
+            write!(f, "{}{}{}", marker.marker_text(), marker.spacing, marker.task.as_deref().unwrap_or(""))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_marker(text: &str) -> Option<ListMarker> {
+    let digits = text.len() - text.trim_start_matches(|ch: char| ch.is_ascii_digit()).len();
+    let (kind, marker_length) = match text.chars().next()? {
+        bullet @ ('-' | '*' | '+') => (MarkerKind::Bullet(bullet), 1),
+        _ if (1..=9).contains(&digits) => {
+            let delimiter = text[digits..].chars().next().filter(|&ch| ch == '.' || ch == ')')?;
+            (MarkerKind::Ordered { number: text[..digits].parse().ok()?, delimiter }, digits + 1)
+        }
+        _ => return None,
+    };
+
+    let rest = &text[marker_length..];
+    let spacing_length = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    if spacing_length == 0 {
+        return None;
+    }
+    let rest = &rest[spacing_length..];
+
+    let task = ["[ ]", "[x]", "[X]"]
+        .iter()
+        .find(|task| rest.starts_with(**task) && matches!(rest.as_bytes().get(3), None | Some(b' ')))
+        .map(|task| if rest.len() > 3 { format!("{} ", task) } else { task.to_string() });
+    let spacing = text[marker_length..marker_length + spacing_length].to_string();
+    Some(ListMarker { kind, spacing, task })
+}
+
+/// `---`, `* * *` and the like, which look like list markers but aren't
+fn is_thematic_break(text: &str) -> bool {
+    let text = text.trim_end();
+    let Some(first) = text.chars().next().filter(|ch| matches!(ch, '-' | '*' | '_')) else {
+        return false;
+    };
+    text.chars().all(|ch| ch == first || ch == ' ' || ch == '\t') && text.matches(first).count() >= 3
+}
+
+pub fn indent_width(indent: &str) -> usize {
+    indent.chars().fold(0, |width, ch| match ch {
+        '\t' => width + TAB_WIDTH - width % TAB_WIDTH,
+        _ => width + 1,
+    })
+}
+
+/// A line with nothing in it but blockquote markers
+pub fn is_blank(line: &str) -> bool {
+    let prefix = LinePrefix::parse(line);
+    prefix.marker.is_none() && line[prefix.len()..].trim().is_empty()
+}
+
+/// `line` with the indentation after its quote markers replaced by `width` spaces
+pub fn with_indent(line: &str, width: usize) -> String {
+    let prefix = LinePrefix::parse(line);
+    let text_start = prefix.quote.len() + prefix.indent.len();
+    format!("{}{}{}", prefix.quote, " ".repeat(width), &line[text_start..])
+}
+
+/// End (exclusive) of the list item on `lines[index]`: the lines after it
+/// that are indented under it, such as nested items and more of its text
+pub fn item_end(lines: &[String], index: usize) -> usize {
+    let prefix = LinePrefix::parse(&lines[index]);
+    let mut end = index + 1;
+    for (offset, line) in lines[index + 1..].iter().enumerate() {
+        if is_blank(line) {
+            continue;
+        }
+        let below = LinePrefix::parse(line);
+        if below.quote_depth() != prefix.quote_depth() || below.indent_width() <= prefix.indent_width() {
+            break;
+        }
+        end = index + offset + 2;
+    }
+    end
+}
+
+/// How many columns Tab (`indent`) or Shift+Tab moves the list item on
+/// `lines[index]`: under the item above it, or out to its parent's level
+pub fn shift_width(lines: &[String], index: usize, indent: bool) -> isize {
+    let prefix = LinePrefix::parse(&lines[index]);
+    let width = prefix.indent_width();
+    let Some(marker) = &prefix.marker else {
+        return if indent { TAB_WIDTH as isize } else { -(width.min(TAB_WIDTH) as isize) };
+    };
+
+    for line in lines[..index].iter().rev() {
+        let above = LinePrefix::parse(line);
+        if above.quote_depth() != prefix.quote_depth() {
+            break;
+        }
+        if is_blank(line) {
+            continue;
+        }
+        let above_width = above.indent_width();
+        match &above.marker {
+            // A sibling: indenting nests this item under it
+            Some(sibling) if above_width == width && indent => return sibling.width() as isize,
+            Some(_) if above_width >= width => continue,
+            // The parent: outdenting makes this item its sibling
+            Some(_) if above_width < width && !indent => return above_width as isize - width as isize,
+            Some(_) if above_width < width => break,
+            // Nested items and the text of the items above
+            _ if above_width >= width => continue,
+            _ => break,
+        }
+    }
+
+    if indent {
+        marker.width() as isize
+    } else {
+        -(width as isize)
+    }
+}
+
+/// The list item `lines[index]` is nested under, if it's in a list
+pub fn parent_item(lines: &[String], index: usize) -> Option<usize> {
+    let prefix = LinePrefix::parse(&lines[index]);
+    let width = prefix.indent_width();
+    for above in (0..index).rev() {
+        let above_prefix = LinePrefix::parse(&lines[above]);
+        if above_prefix.quote_depth() != prefix.quote_depth() {
+            break;
+        }
+        if above_prefix.marker.is_some() && above_prefix.indent_width() < width {
+            return (item_end(lines, above) > index).then_some(above);
+        }
+    }
+    None
+}
+
+/// Tick the task boxes on the `targets` lines, or clear them if they are all
+/// ticked already. Each parent task of a changed one is then ticked if all of
+/// its subtasks are done and cleared if not. Returns false if none of the
+/// lines is a task.
+pub fn toggle_tasks(lines: &mut [String], targets: &[usize]) -> bool {
+    let states: Vec<bool> = targets
+        .iter()
+        .filter_map(|&index| LinePrefix::parse(&lines[index]).marker?.task_checked())
+        .collect();
+    if states.is_empty() {
+        return false;
+    }
+    let checked = !states.iter().all(|&state| state);
+
+    for &index in targets {
+        set_task(&mut lines[index], checked);
+    }
+    for &index in targets {
+        let mut child = index;
+        while let Some(parent) = parent_item(lines, child) {
+            let done = subtasks_done(lines, parent);
+            if !set_task(&mut lines[parent], done) {
+                break;
+            }
+            child = parent;
+        }
+    }
+    true
+}
+
+/// Whether every task directly under the item on `lines[index]` is ticked
+fn subtasks_done(lines: &[String], index: usize) -> bool {
+    (index + 1..item_end(lines, index))
+        .filter(|&line| parent_item(lines, line) == Some(index))
+        .filter_map(|line| LinePrefix::parse(&lines[line]).marker?.task_checked())
+        .all(|checked| checked)
+}
+
+/// Tick or clear the task box on a line. Returns false if it isn't a task.
+fn set_task(line: &mut String, checked: bool) -> bool {
+    let mut prefix = LinePrefix::parse(line);
+    let length = prefix.len();
+    let Some(task) = prefix.marker.as_mut().and_then(|marker| marker.task.as_mut()) else {
+        return false;
+    };
+    if (task.as_bytes()[1] != b' ') != checked {
+        task.replace_range(1..2, if checked { "x" } else { " " });
+        *line = format!("{}{}", prefix, &line[length..]);
+    }
+    true
+}
+
+/// One open list while renumbering
+struct OpenList {
+    indent: usize,
+    /// `None` for bullet lists
+    delimiter: Option<char>,
+    /// Line indices of the items
+    items: Vec<usize>,
+    /// Index of the line before the first item, if there is one
+    previous_line: Option<usize>,
+    nested: bool,
+}
+
+/// Renumber the ordered lists in `lines` that contain one of the `touched`
+/// lines so their items count up one at a time. A nested list that starts
+/// at or right after a touched line (e.g. an item that was just indented)
+/// starts again from 1; other lists keep the number of their first item.
+pub fn renumber(lines: &mut [String], touched: &[usize]) {
+    let mut open: Vec<OpenList> = Vec::new();
+    let mut quote_depth = None;
+    let mut previous_blank = false;
+    let mut previous_line = None;
+
+    for index in 0..lines.len() {
+        let prefix = LinePrefix::parse(&lines[index]);
+        if quote_depth != Some(prefix.quote_depth()) {
+            close_lists(lines, &mut open, 0, index, touched);
+            quote_depth = Some(prefix.quote_depth());
+        }
+        if is_blank(&lines[index]) {
+            previous_blank = true;
+            continue;
+        }
+
+        let width = prefix.indent_width();
+        match &prefix.marker {
+            Some(marker) => {
+                let keep = open.iter().take_while(|list| list.indent <= width).count();
+                close_lists(lines, &mut open, keep, index, touched);
+                let delimiter = match marker.kind {
+                    MarkerKind::Ordered { delimiter, .. } => Some(delimiter),
+                    MarkerKind::Bullet(_) => None,
+                };
+                let same_list = open.last().is_some_and(|list| list.indent == width && list.delimiter == delimiter);
+                if !same_list {
+                    if open.last().is_some_and(|list| list.indent == width) {
+                        let keep = open.len() - 1;
+                        close_lists(lines, &mut open, keep, index, touched);
+                    }
+                    open.push(OpenList {
+                        indent: width,
+                        delimiter,
+                        items: Vec::new(),
+                        previous_line,
+                        nested: !open.is_empty(),
+                    });
+                }
+                if let Some(list) = open.last_mut() {
+                    list.items.push(index);
+                }
+            }
+            // Text after a blank line ends the lists it isn't indented under
+            None if previous_blank => {
+                let keep = open.iter().take_while(|list| list.indent < width).count();
+                close_lists(lines, &mut open, keep, index, touched);
+            }
+            None => {}
+        }
+        previous_blank = false;
+        previous_line = Some(index);
+    }
+    close_lists(lines, &mut open, 0, lines.len(), touched);
+}
+
+/// Close the lists after the first `keep`, renumbering the touched ones
+fn close_lists(lines: &mut [String], open: &mut Vec<OpenList>, keep: usize, end: usize, touched: &[usize]) {
+    while open.len() > keep {
+        let Some(list) = open.pop() else { break };
+        let (Some(delimiter), Some(&first)) = (list.delimiter, list.items.first()) else {
+            continue;
+        };
+        if !touched.iter().any(|&line| first <= line && line < end) {
+            continue;
+        }
+
+        let restarted = touched.contains(&first) || list.previous_line.is_some_and(|line| touched.contains(&line));
+        let start = match LinePrefix::parse(&lines[first]).marker.map(|marker| marker.kind) {
+            _ if list.nested && restarted => 1,
+            Some(MarkerKind::Ordered { number, .. }) => number,
+            _ => 1,
+        };
+        for (offset, &index) in list.items.iter().enumerate() {
+            let mut prefix = LinePrefix::parse(&lines[index]);
+            let length = prefix.len();
+            if let Some(marker) = &mut prefix.marker {
+                marker.kind = MarkerKind::Ordered { number: start + offset, delimiter };
+            }
+            lines[index] = format!("{}{}", prefix, &lines[index][length..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_line_prefix() {
+        let prefix = LinePrefix::parse("> > 12) [x] done");
+        assert_eq!(prefix.quote, "> > ");
+        assert_eq!(prefix.quote_depth(), 2);
+        let marker = prefix.marker.as_ref().unwrap();
+        assert_eq!(marker.kind, MarkerKind::Ordered { number: 12, delimiter: ')' });
+        assert_eq!(marker.task_checked(), Some(true));
+        assert_eq!(prefix.len(), "> > 12) [x] ".len());
+        assert_eq!(prefix.next_item().to_string(), "> > 13) [ ] ");
+
+        let nested = LinePrefix::parse("\t  - item");
+        assert_eq!(nested.indent_width(), 6);
+        assert_eq!(nested.marker.unwrap().width(), 2);
+
+        assert!(LinePrefix::parse("plain text").is_empty());
+        assert_eq!(LinePrefix::parse("-not a list").marker, None);
+        assert_eq!(LinePrefix::parse("- - -").marker, None);
+        assert_eq!(LinePrefix::parse("1234567890. too long").marker, None);
+        assert_eq!(LinePrefix::parse("> quoted").outer().to_string(), "");
+        assert_eq!(LinePrefix::parse("> - ").outer().to_string(), "> ");
+    }
+
+    #[test]
+    fn test_shift_width() {
+        let list = lines("1. first\n2. second\n   - nested\n   - more\n3. third");
+        assert_eq!(shift_width(&list, 1, true), 3);
+        assert_eq!(shift_width(&list, 3, true), 2);
+        assert_eq!(shift_width(&list, 3, false), -3);
+        assert_eq!(shift_width(&list, 4, true), 3);
+        assert_eq!(shift_width(&list, 0, false), 0);
+        assert_eq!(item_end(&list, 1), 4);
+        assert_eq!(item_end(&list, 2), 3);
+    }
+
+    #[test]
+    fn test_toggle_tasks() {
+        let mut list = lines("- [ ] parent\n  - [x] done\n  - [ ] todo\n    more text\n- [ ] other");
+        assert_eq!(parent_item(&list, 3), Some(2));
+        assert_eq!(parent_item(&list, 4), None);
+
+        // Finishing the last subtask finishes the parent, and undoing it reopens it
+        assert!(toggle_tasks(&mut list, &[2]));
+        assert_eq!(list, lines("- [x] parent\n  - [x] done\n  - [x] todo\n    more text\n- [ ] other"));
+        assert!(toggle_tasks(&mut list, &[1]));
+        assert_eq!(list, lines("- [ ] parent\n  - [ ] done\n  - [x] todo\n    more text\n- [ ] other"));
+
+        // A mixed selection is ticked, then cleared
+        assert!(toggle_tasks(&mut list, &[1, 2, 4]));
+        assert_eq!(list, lines("- [x] parent\n  - [x] done\n  - [x] todo\n    more text\n- [x] other"));
+        assert!(toggle_tasks(&mut list, &[4]));
+        assert_eq!(list[4], "- [ ] other");
+        assert!(!toggle_tasks(&mut lines("- plain\ntext"), &[0, 1]));
+    }
+
+    #[test]
+    fn test_renumber_touched_lists() {
+        let mut list = lines("1. a\n1. b\n   5. x\n   6. y\n7. c\n\n8. loose\n\nText\n1. other\n1. list");
+        renumber(&mut list, &[1]);
+        assert_eq!(list, lines("1. a\n2. b\n   5. x\n   6. y\n3. c\n\n4. loose\n\nText\n1. other\n1. list"));
+
+        // An item indented under its sibling starts a new list at 1
+        let mut list = lines("1. a\n   2. b\n3. c");
+        renumber(&mut list, &[1]);
+        assert_eq!(list, lines("1. a\n   1. b\n2. c"));
+    }
+}
@@ -5,16 +5,25 @@ pub mod cursor;
 pub mod cursor_movement;
 #[cfg(test)]
 pub mod cursor_wrapping_tests;
+pub mod diff;
+pub mod formatting;
+pub mod kill_ring;
+pub mod lists;
 pub mod point;
 pub mod scroll_state;
+pub mod search;
 pub mod selection;
+pub mod tables;
 pub mod text_document;
 pub mod test_undo_integration;
+pub mod test_undo_selections;
+pub mod undo_tree;
 pub mod viewport;
 
 pub use coordinate_mapping::{CoordinateConversion, RopeCoordinateMapper, ScreenPosition};
 pub use cursor_movement::CursorMovementService;
 pub use point::Point;
 pub use scroll_state::ScrollState;
+pub use search::{IncrementalSearch, SearchMatch, SearchOptions, SearchQuery};
 pub use text_document::TextDocument;
 pub use viewport::ViewportManager;
\ No newline at end of file
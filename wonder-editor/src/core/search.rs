@@ -0,0 +1,299 @@
+//! Find and replace over the document rope
+//!
+//! A `SearchQuery` compiles a pattern (plain text or regex) with case and
+//! whole-word options and finds matches in a `Rope`. Matches are reported in
+//! char positions, the same units as the cursor and selection.
+
+use crate::input::SearchDirection;
+use regex::{Regex, RegexBuilder};
+use ropey::Rope;
+use std::ops::Range;
+
+/// Options controlling how a search pattern is matched
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Treat the pattern as a regular expression instead of plain text
+    pub regex: bool,
+}
+
+/// A single match in char positions
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SearchMatch {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// A compiled search pattern
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pattern: String,
+    options: SearchOptions,
+    regex: Regex,
+}
+
+impl SearchQuery {
+    pub fn new(pattern: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        let mut source = if options.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        if options.whole_word {
+            source = format!(r"\b(?:{})\b", source);
+        }
+
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(!options.case_sensitive)
+            .multi_line(true)
+            .build()?;
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            options,
+            regex,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// All non-empty matches in document order
+    pub fn find_all(&self, rope: &Rope) -> Vec<SearchMatch> {
+        let text = rope.to_string();
+        self.regex
+            .find_iter(&text)
+            .filter(|m| !m.is_empty())
+            .map(|m| SearchMatch {
+                start: rope.byte_to_char(m.start()),
+                end: rope.byte_to_char(m.end()),
+            })
+            .collect()
+    }
+
+    /// First match starting at or after `from`, wrapping around to the start of the document
+    pub fn find_next(&self, rope: &Rope, from: usize) -> Option<SearchMatch> {
+        let matches = self.find_all(rope);
+        matches
+            .iter()
+            .find(|m| m.start >= from)
+            .or_else(|| matches.first())
+            .cloned()
+    }
+
+    /// Last match ending at or before `from`, wrapping around to the end of the document
+    pub fn find_previous(&self, rope: &Rope, from: usize) -> Option<SearchMatch> {
+        let matches = self.find_all(rope);
+        matches
+            .iter()
+            .rev()
+            .find(|m| m.end <= from)
+            .or_else(|| matches.last())
+            .cloned()
+    }
+
+    /// Text that replaces `found`. In regex mode `$1`, `${name}` and `$$` in
+    /// `replacement` expand to the match's capture groups; otherwise it is used as is.
+    pub fn replacement_for(&self, rope: &Rope, found: &SearchMatch, replacement: &str) -> String {
+        if !self.options.regex {
+            return replacement.to_string();
+        }
+
+        let text = rope.to_string();
+        self.expand(&text, rope.char_to_byte(found.start), replacement)
+            .unwrap_or_else(|| replacement.to_string())
+    }
+
+    /// Every match paired with its replacement text, in document order
+    pub fn replacements(&self, rope: &Rope, replacement: &str) -> Vec<(SearchMatch, String)> {
+        let text = rope.to_string();
+        self.regex
+            .captures_iter(&text)
+            .filter_map(|caps| {
+                let whole = caps.get(0)?;
+                if whole.is_empty() {
+                    return None;
+                }
+                let replaced = if self.options.regex {
+                    let mut expanded = String::new();
+                    caps.expand(replacement, &mut expanded);
+                    expanded
+                } else {
+                    replacement.to_string()
+                };
+                let found = SearchMatch {
+                    start: rope.byte_to_char(whole.start()),
+                    end: rope.byte_to_char(whole.end()),
+                };
+                Some((found, replaced))
+            })
+            .collect()
+    }
+
+    fn expand(&self, text: &str, byte_start: usize, replacement: &str) -> Option<String> {
+        let caps = self.regex.captures_at(text, byte_start)?;
+        if caps.get(0)?.start() != byte_start {
+            return None;
+        }
+        let mut expanded = String::new();
+        caps.expand(replacement, &mut expanded);
+        Some(expanded)
+    }
+}
+
+/// A search typed a character at a time, Emacs style, with each character
+/// moving to the first match from where the search started
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalSearch {
+    pub text: String,
+    pub direction: SearchDirection,
+    /// Where the cursor was when the search started
+    pub origin: usize,
+    /// Whether the text has no matches
+    pub failing: bool,
+}
+
+impl IncrementalSearch {
+    pub fn new(direction: SearchDirection, origin: usize) -> Self {
+        Self { text: String::new(), direction, origin, failing: false }
+    }
+
+    /// Ignores case unless the text has upper case letters
+    pub fn options(&self) -> SearchOptions {
+        SearchOptions {
+            case_sensitive: self.text.chars().any(char::is_uppercase),
+            ..Default::default()
+        }
+    }
+
+    /// What the search looks like in the status bar, e.g. `I-search backward: foo`
+    pub fn prompt(&self) -> String {
+        format!(
+            "{}I-search{}: {}",
+            if self.failing { "Failing " } else { "" },
+            if self.direction == SearchDirection::Backward { " backward" } else { "" },
+            self.text
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pattern: &str, options: SearchOptions) -> SearchQuery {
+        SearchQuery::new(pattern, options).unwrap()
+    }
+
+    #[test]
+    fn test_plain_text_search_is_case_insensitive_by_default() {
+        let rope = Rope::from_str("Cat cat CAT");
+        let matches = query("cat", SearchOptions::default()).find_all(&rope);
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[1], SearchMatch { start: 4, end: 7 });
+    }
+
+    #[test]
+    fn test_case_sensitive_search() {
+        let rope = Rope::from_str("Cat cat CAT");
+        let options = SearchOptions { case_sensitive: true, ..Default::default() };
+        let matches = query("cat", options).find_all(&rope);
+        assert_eq!(matches, vec![SearchMatch { start: 4, end: 7 }]);
+    }
+
+    #[test]
+    fn test_whole_word_search() {
+        let rope = Rope::from_str("cat concat cats cat");
+        let options = SearchOptions { whole_word: true, ..Default::default() };
+        let matches = query("cat", options).find_all(&rope);
+        assert_eq!(matches, vec![SearchMatch { start: 0, end: 3 }, SearchMatch { start: 16, end: 19 }]);
+    }
+
+    #[test]
+    fn test_plain_text_escapes_regex_characters() {
+        let rope = Rope::from_str("a.b axb");
+        let matches = query("a.b", SearchOptions::default()).find_all(&rope);
+        assert_eq!(matches, vec![SearchMatch { start: 0, end: 3 }]);
+    }
+
+    #[test]
+    fn test_regex_search_uses_char_positions() {
+        // Multi-byte characters before the match must not shift positions
+        let rope = Rope::from_str("héllo wörld 42");
+        let options = SearchOptions { regex: true, ..Default::default() };
+        let matches = query(r"\d+", options).find_all(&rope);
+        assert_eq!(matches, vec![SearchMatch { start: 12, end: 14 }]);
+    }
+
+    #[test]
+    fn test_regex_anchors_match_per_line() {
+        let rope = Rope::from_str("# One\ntext\n# Two");
+        let options = SearchOptions { regex: true, ..Default::default() };
+        let matches = query(r"^# \w+$", options).find_all(&rope);
+        assert_eq!(matches.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_regex_is_an_error() {
+        let options = SearchOptions { regex: true, ..Default::default() };
+        assert!(SearchQuery::new("(unclosed", options).is_err());
+    }
+
+    #[test]
+    fn test_find_next_and_previous_wrap_around() {
+        let rope = Rope::from_str("one two one two");
+        let q = query("two", SearchOptions::default());
+
+        assert_eq!(q.find_next(&rope, 0), Some(SearchMatch { start: 4, end: 7 }));
+        assert_eq!(q.find_next(&rope, 5), Some(SearchMatch { start: 12, end: 15 }));
+        assert_eq!(q.find_next(&rope, 13), Some(SearchMatch { start: 4, end: 7 }));
+
+        assert_eq!(q.find_previous(&rope, 15), Some(SearchMatch { start: 12, end: 15 }));
+        assert_eq!(q.find_previous(&rope, 12), Some(SearchMatch { start: 4, end: 7 }));
+        assert_eq!(q.find_previous(&rope, 3), Some(SearchMatch { start: 12, end: 15 }));
+    }
+
+    #[test]
+    fn test_no_matches() {
+        let rope = Rope::from_str("nothing here");
+        let q = query("missing", SearchOptions::default());
+        assert!(q.find_all(&rope).is_empty());
+        assert_eq!(q.find_next(&rope, 0), None);
+        assert_eq!(q.find_previous(&rope, 0), None);
+    }
+
+    #[test]
+    fn test_capture_group_replacement() {
+        let rope = Rope::from_str("[a](x.md) and [b](y.md)");
+        let options = SearchOptions { regex: true, ..Default::default() };
+        let q = query(r"\[(?P<text>\w)\]\((\w)\.md\)", options);
+
+        let replacements = q.replacements(&rope, "${text}->$2");
+        assert_eq!(replacements.len(), 2);
+        assert_eq!(replacements[0].1, "a->x");
+        assert_eq!(replacements[1].1, "b->y");
+
+        let second = replacements[1].0.clone();
+        assert_eq!(q.replacement_for(&rope, &second, "$2"), "y");
+    }
+
+    #[test]
+    fn test_plain_text_replacement_is_literal() {
+        let rope = Rope::from_str("cost");
+        let q = query("cost", SearchOptions::default());
+        let found = q.find_next(&rope, 0).unwrap();
+        assert_eq!(q.replacement_for(&rope, &found, "$1"), "$1");
+    }
+}
@@ -1,19 +1,37 @@
+use super::commands::EditRange;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Selection {
     anchor: Option<usize>,
+    /// Set by `set_mark`: cursor movement extends the selection instead of
+    /// clearing it, until the selection is cleared or started again
+    mark: bool,
 }
 
 impl Selection {
     pub fn new() -> Self {
-        Self { anchor: None }
+        Self { anchor: None, mark: false }
     }
 
     pub fn start(&mut self, position: usize) {
         self.anchor = Some(position);
+        self.mark = false;
+    }
+
+    /// Start a selection at `position` that cursor movement extends, like
+    /// the Emacs mark
+    pub fn set_mark(&mut self, position: usize) {
+        self.anchor = Some(position);
+        self.mark = true;
     }
 
     pub fn clear(&mut self) {
         self.anchor = None;
+        self.mark = false;
+    }
+
+    pub fn is_mark_active(&self) -> bool {
+        self.mark
     }
 
     pub fn is_active(&self) -> bool {
@@ -51,6 +69,54 @@ impl Default for Selection {
     }
 }
 
+/// One cursor of a multi-cursor selection set: a cursor position plus its
+/// optional selection anchor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionRegion {
+    pub anchor: Option<usize>,
+    pub head: usize,
+}
+
+impl SelectionRegion {
+    pub fn cursor(position: usize) -> Self {
+        Self { anchor: None, head: position }
+    }
+
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor: Some(anchor), head }
+    }
+
+    /// The selected range, or an empty range at the cursor
+    pub fn range(&self) -> (usize, usize) {
+        match self.anchor {
+            Some(anchor) if anchor <= self.head => (anchor, self.head),
+            Some(anchor) => (self.head, anchor),
+            None => (self.head, self.head),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let (start, end) = self.range();
+        start == end
+    }
+
+    /// Move this region to follow an edit made elsewhere in the text
+    pub fn map_through(&self, edit: &EditRange) -> Self {
+        Self {
+            anchor: self.anchor.map(|anchor| edit.map_position(anchor)),
+            head: edit.map_position(self.head),
+        }
+    }
+}
+
+/// Every cursor and selection at one moment, kept with an undo step so undo
+/// and redo can put them back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectionSnapshot {
+    pub primary: SelectionRegion,
+    pub secondary: Vec<SelectionRegion>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,4 +168,13 @@ mod tests {
         assert_eq!(selection.length(1), 2);
         assert_eq!(selection.length(3), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_selection_region_range() {
+        assert_eq!(SelectionRegion::cursor(4).range(), (4, 4));
+        assert!(SelectionRegion::cursor(4).is_empty());
+        assert_eq!(SelectionRegion::new(2, 6).range(), (2, 6));
+        assert_eq!(SelectionRegion::new(6, 2).range(), (2, 6));
+        assert!(!SelectionRegion::new(6, 2).is_empty());
+    }
+}
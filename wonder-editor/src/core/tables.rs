@@ -0,0 +1,308 @@
+//! GFM tables in source text
+//!
+//! A `Table` is read from its source lines (header, delimiter row, body
+//! rows), edited as a grid of cell texts and written back with the pipes
+//! lined up. Cell positions are char offsets into a single line, while
+//! columns are padded by display width, so wide characters such as CJK stay
+//! lined up. Only unescaped pipes separate cells, as in GFM.
+
+use std::ops::Range;
+
+use pulldown_cmark::Alignment;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub alignments: Vec<Alignment>,
+    /// Cell texts by row, header first. Every row has a cell per column.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Read a table from its lines, starting with the header. Returns `None`
+    /// if the second line isn't a delimiter row for the header.
+    pub fn parse(lines: &[String]) -> Option<Self> {
+        let header = cells(lines.first()?);
+        let alignments = cells(lines.get(1)?)
+            .iter()
+            .map(|cell| delimiter_alignment(cell))
+            .collect::<Option<Vec<_>>>()?;
+        if alignments.len() != header.len() {
+            return None;
+        }
+
+        let mut table = Self {
+            alignments,
+            rows: std::iter::once(header).chain(lines[2..].iter().map(|line| cells(line))).collect(),
+        };
+        // Extra cells in a body row get a column of their own rather than being dropped
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        table.alignments.resize(columns, Alignment::None);
+        for row in &mut table.rows {
+            row.resize(columns, String::new());
+        }
+        Some(table)
+    }
+
+    pub fn columns(&self) -> usize {
+        self.alignments.len()
+    }
+
+    /// The table's source lines with every column padded to the same width
+    pub fn render(&self) -> Vec<String> {
+        render_table(&self.rows, &self.alignments)
+    }
+
+    /// Add an empty body row at `index` (1 is the first row after the header)
+    pub fn insert_row(&mut self, index: usize) {
+        self.rows.insert(index.clamp(1, self.rows.len()), vec![String::new(); self.columns()]);
+    }
+
+    /// Delete a body row. The header can't be deleted.
+    pub fn delete_row(&mut self, index: usize) -> bool {
+        if index == 0 || index >= self.rows.len() {
+            return false;
+        }
+        self.rows.remove(index);
+        true
+    }
+
+    pub fn insert_column(&mut self, index: usize) {
+        let index = index.min(self.columns());
+        self.alignments.insert(index, Alignment::None);
+        for row in &mut self.rows {
+            row.insert(index, String::new());
+        }
+    }
+
+    /// Delete a column, unless it's the only one
+    pub fn delete_column(&mut self, index: usize) -> bool {
+        if self.columns() <= 1 || index >= self.columns() {
+            return false;
+        }
+        self.alignments.remove(index);
+        for row in &mut self.rows {
+            row.remove(index);
+        }
+        true
+    }
+
+    /// Swap a column with the one to its right
+    pub fn swap_columns(&mut self, index: usize) -> bool {
+        if index + 1 >= self.columns() {
+            return false;
+        }
+        self.alignments.swap(index, index + 1);
+        for row in &mut self.rows {
+            row.swap(index, index + 1);
+        }
+        true
+    }
+}
+
+/// Rows of cell texts as table lines with lined-up pipes, the delimiter row
+/// after the header. Columns are at least three characters wide.
+pub fn render_table(rows: &[Vec<String>], alignments: &[Alignment]) -> Vec<String> {
+    let widths: Vec<usize> = (0..alignments.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].width())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let mut lines: Vec<String> = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .zip(alignments)
+            .map(|((cell, &width), alignment)| pad(cell, width, *alignment))
+            .collect();
+        lines.push(line(cells));
+        if index == 0 {
+            let delimiters = widths
+                .iter()
+                .zip(alignments)
+                .map(|(&width, alignment)| match alignment {
+                    Alignment::None => "-".repeat(width),
+                    Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+                    Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+                    Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+                })
+                .collect();
+            lines.push(line(delimiters));
+        }
+    }
+    lines
+}
+
+/// `cell` with spaces added to fill `width` columns on screen, on the side
+/// its alignment calls for
+fn pad(cell: &str, width: usize, alignment: Alignment) -> String {
+    let padding = width.saturating_sub(cell.width());
+    let (left, right) = match alignment {
+        Alignment::Right => (padding, 0),
+        Alignment::Center => (padding / 2, padding - padding / 2),
+        _ => (0, padding),
+    };
+    format!("{}{}{}", " ".repeat(left), cell, " ".repeat(right))
+}
+
+/// Whether `line` could be a table row: it has a pipe and other text
+pub fn is_row(line: &str) -> bool {
+    line.contains('|') && !line.trim().is_empty()
+}
+
+pub fn is_delimiter_row(line: &str) -> bool {
+    line.contains('|') && cells(line).iter().all(|cell| delimiter_alignment(cell).is_some())
+}
+
+fn delimiter_alignment(cell: &str) -> Option<Alignment> {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    if dashes.is_empty() || !dashes.chars().all(|ch| ch == '-') {
+        return None;
+    }
+    Some(match (cell.starts_with(':'), cell.len() > 1 && cell.ends_with(':')) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::None,
+    })
+}
+
+/// The texts of the cells in a row, trimmed
+pub fn cells(line: &str) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    cell_ranges(line).into_iter().map(|range| chars[range].iter().collect()).collect()
+}
+
+/// Char ranges of each cell's text in a row, without the spaces around it.
+/// An empty cell gets an empty range one space after its pipe.
+pub fn cell_ranges(line: &str) -> Vec<Range<usize>> {
+    let chars: Vec<char> = line.chars().collect();
+    segments(&chars)
+        .into_iter()
+        .map(|segment| {
+            let mut start = segment.start;
+            let mut end = segment.end;
+            while start < end && chars[start].is_whitespace() {
+                start += 1;
+            }
+            while end > start && chars[end - 1].is_whitespace() {
+                end -= 1;
+            }
+            if start == end {
+                start = (segment.start + 1).min(segment.end);
+                end = start;
+            }
+            start..end
+        })
+        .collect()
+}
+
+/// The cell a char offset in a row is in
+pub fn cell_at(line: &str, offset: usize) -> usize {
+    let chars: Vec<char> = line.chars().collect();
+    segments(&chars)
+        .iter()
+        .rposition(|segment| segment.start <= offset)
+        .unwrap_or(0)
+}
+
+/// Char ranges between the pipes of a row. A leading or trailing pipe
+/// doesn't start or end a cell of its own.
+fn segments(chars: &[char]) -> Vec<Range<usize>> {
+    let pipes: Vec<usize> = (0..chars.len())
+        .filter(|&index| chars[index] == '|' && (index == 0 || chars[index - 1] != '\\'))
+        .collect();
+    let first_text = chars.iter().position(|ch| !ch.is_whitespace());
+    let last_text = chars.iter().rposition(|ch| !ch.is_whitespace());
+    let leading = first_text.is_some_and(|first| pipes.first() == Some(&first));
+    let trailing = last_text.is_some_and(|last| pipes.last() == Some(&last));
+
+    let mut starts = Vec::new();
+    let mut ends = Vec::new();
+    if !leading {
+        starts.push(0);
+    }
+    for &pipe in &pipes {
+        if starts.len() > ends.len() {
+            ends.push(pipe);
+        }
+        starts.push(pipe + 1);
+    }
+    if trailing {
+        starts.pop();
+    } else {
+        ends.push(chars.len());
+    }
+    starts.into_iter().zip(ends).map(|(start, end)| start..end).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_and_render() {
+        let table = Table::parse(&lines("Name | Qty\n:-- | --:\napple | 3\n`a\\|b` | 12 | extra")).unwrap();
+        assert_eq!(table.alignments, vec![Alignment::Left, Alignment::Right, Alignment::None]);
+        assert_eq!(table.rows[2], vec!["`a\\|b`", "12", "extra"]);
+        assert_eq!(
+            table.render(),
+            lines(
+                "| Name   | Qty |       |\n\
+                 | :----- | --: | ----- |\n\
+                 | apple  |   3 |       |\n\
+                 | `a\\|b` |  12 | extra |"
+            )
+        );
+        assert_eq!(Table::parse(&lines("a | b\n--- | nope")), None);
+        assert_eq!(Table::parse(&lines("a | b\n---")), None);
+    }
+
+    #[test]
+    fn test_cell_positions() {
+        let row = "| one |   | three";
+        assert_eq!(cell_ranges(row), vec![2..5, 8..8, 12..17]);
+        assert_eq!(cell_at(row, 0), 0);
+        assert_eq!(cell_at(row, 4), 0);
+        assert_eq!(cell_at(row, 7), 1);
+        assert_eq!(cell_at(row, 17), 2);
+        assert_eq!(cells("a|b"), vec!["a", "b"]);
+        assert!(is_delimiter_row("|:-:|---|"));
+        assert!(!is_delimiter_row("| a | b |"));
+    }
+
+    #[test]
+    fn test_row_and_column_edits() {
+        let mut table = Table::parse(&lines("| a | b |\n|---|:-:|\n| 1 | 2 |")).unwrap();
+        table.insert_column(1);
+        table.swap_columns(1);
+        assert_eq!(table.rows[1], vec!["1", "2", ""]);
+        assert_eq!(table.alignments[1], Alignment::Center);
+        assert!(table.delete_column(2));
+        table.insert_row(1);
+        assert_eq!(table.rows.len(), 3);
+        assert!(!table.delete_row(0));
+        assert!(table.delete_row(1));
+        assert_eq!(table.render(), lines("| a   |  b  |\n| --- | :-: |\n| 1   |  2  |"));
+    }
+
+    #[test]
+    fn test_wide_characters_stay_lined_up() {
+        let table = Table::parse(&lines("| 名前 | b |\n|---|--:|\n| x | 🍎 |")).unwrap();
+        let rendered = table.render();
+        assert_eq!(rendered, lines("| 名前 |   b |\n| ---- | --: |\n| x    |  🍎 |"));
+        let widths: Vec<usize> = rendered.iter().map(|line| line.width()).collect();
+        assert!(widths.iter().all(|&width| width == widths[0]));
+    }
+}
@@ -3,6 +3,7 @@
 #[cfg(test)]
 mod tests {
     use crate::core::TextDocument;
+    use crate::input::{ActionHandler, EditorAction, FormatType};
 
     #[test]
     fn test_typing_creates_undoable_commands() {
@@ -74,4 +75,91 @@ mod tests {
         assert!(!doc.can_redo());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_typing_undoes_word_by_word() {
+        let mut doc = TextDocument::new();
+        for ch in "hello world".chars() {
+            doc.insert_char(ch);
+        }
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "hello");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "");
+    }
+
+    #[test]
+    fn test_deleting_after_typing_is_a_new_step() {
+        let mut doc = TextDocument::new();
+        for ch in "abc".chars() {
+            doc.insert_char(ch);
+        }
+        doc.backspace();
+        doc.backspace();
+        assert_eq!(doc.content(), "a");
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "abc");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "");
+    }
+
+    #[test]
+    fn test_typing_over_selection_is_one_step() {
+        let mut doc = TextDocument::with_content("hello world".to_string());
+        doc.set_cursor_position(6);
+        doc.start_selection();
+        doc.set_cursor_position(11);
+        for ch in "there".chars() {
+            doc.handle_action(EditorAction::InsertChar(ch));
+        }
+        assert_eq!(doc.content(), "hello there");
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "hello world");
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_formatting_is_its_own_step() {
+        let mut doc = TextDocument::new();
+        for ch in "word".chars() {
+            doc.handle_action(EditorAction::InsertChar(ch));
+        }
+        doc.handle_action(EditorAction::ToggleFormat(FormatType::Italic));
+        doc.handle_action(EditorAction::InsertChar('s'));
+        assert_eq!(doc.content(), "word*s*");
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "word**");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "word");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "");
+    }
+
+    #[test]
+    fn test_typing_after_undo_keeps_the_undone_text() {
+        let mut doc = TextDocument::new();
+        doc.handle_action(EditorAction::InsertText("first".to_string()));
+        assert!(doc.perform_undo());
+        doc.handle_action(EditorAction::InsertText("second".to_string()));
+        assert!(!doc.can_redo());
+
+        // Older states go back in time across branches
+        doc.handle_action(EditorAction::UndoOlder);
+        assert_eq!(doc.content(), "first");
+        assert_eq!(doc.cursor_position(), 5);
+        assert_eq!(doc.undo_branches().len(), 2);
+        doc.handle_action(EditorAction::UndoOlder);
+        assert_eq!(doc.content(), "");
+        doc.handle_action(EditorAction::UndoNewer);
+        doc.handle_action(EditorAction::UndoNewer);
+        assert_eq!(doc.content(), "second");
+
+        let first = doc.undo_branches()[0].tip();
+        assert!(doc.go_to_undo_node(first));
+        assert_eq!(doc.content(), "first");
+        assert!(!doc.go_to_undo_node(99));
+    }
+}
@@ -0,0 +1,128 @@
+//! Test that undo and redo put back the cursors and selections of each step
+
+#[cfg(test)]
+mod tests {
+    use crate::core::selection::SelectionRegion;
+    use crate::core::TextDocument;
+    use crate::input::{ActionHandler, EditorAction, FormatType, Movement};
+
+    fn select(doc: &mut TextDocument, start: usize, end: usize) {
+        doc.clear_selection();
+        doc.set_cursor_position(start);
+        doc.start_selection();
+        doc.set_cursor_position(end);
+    }
+
+    #[test]
+    fn test_typing_undoes_to_cursor_before_it() {
+        let mut doc = TextDocument::with_content("Hello world".to_string());
+        doc.set_cursor_position(5);
+        doc.handle_action(EditorAction::InsertText(", big".to_string()));
+        assert_eq!(doc.content(), "Hello, big world");
+
+        // Move away before undoing
+        doc.handle_action(EditorAction::MoveCursor(Movement::DocumentEnd));
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "Hello world");
+        assert_eq!(doc.cursor_position(), 5);
+
+        doc.handle_action(EditorAction::MoveCursor(Movement::DocumentStart));
+        assert!(doc.perform_redo());
+        assert_eq!(doc.content(), "Hello, big world");
+        assert_eq!(doc.cursor_position(), 10);
+    }
+
+    #[test]
+    fn test_wrap_with_bold_undoes_to_original_selection() {
+        let mut doc = TextDocument::with_content("hello world".to_string());
+        select(&mut doc, 6, 11);
+        doc.handle_action(EditorAction::ToggleFormat(FormatType::Bold));
+        assert_eq!(doc.content(), "hello **world**");
+        assert_eq!(doc.selection_range(), Some((8, 13)));
+
+        doc.handle_action(EditorAction::MoveCursor(Movement::DocumentStart));
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "hello world");
+        assert_eq!(doc.selection_range(), Some((6, 11)));
+        assert_eq!(doc.cursor_position(), 11);
+
+        doc.handle_action(EditorAction::ClearSelection);
+        assert!(doc.perform_redo());
+        assert_eq!(doc.content(), "hello **world**");
+        assert_eq!(doc.selection_range(), Some((8, 13)));
+    }
+
+    #[test]
+    fn test_deleted_selection_comes_back_selected() {
+        let mut doc = TextDocument::with_content("one two three".to_string());
+        // Selected backwards, so the cursor is at the start
+        select(&mut doc, 7, 4);
+        doc.handle_action(EditorAction::Backspace);
+        assert_eq!(doc.content(), "one  three");
+        assert!(!doc.has_selection());
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "one two three");
+        assert_eq!(doc.selection_range(), Some((4, 7)));
+        assert_eq!(doc.cursor_position(), 4);
+
+        assert!(doc.perform_redo());
+        assert!(!doc.has_selection());
+        assert_eq!(doc.cursor_position(), 4);
+    }
+
+    #[test]
+    fn test_multi_cursor_edit_restores_every_cursor() {
+        let mut doc = TextDocument::with_content("a\nb\nc".to_string());
+        doc.set_cursor_position(0);
+        doc.add_cursor_below();
+        doc.add_cursor_below();
+        let cursors = doc.selection_regions();
+
+        doc.handle_action(EditorAction::InsertText("- ".to_string()));
+        assert_eq!(doc.content(), "- a\n- b\n- c");
+        let typed_cursors = doc.selection_regions();
+
+        doc.handle_action(EditorAction::ClearSelection);
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "a\nb\nc");
+        assert_eq!(doc.selection_regions(), cursors);
+
+        doc.handle_action(EditorAction::ClearSelection);
+        assert!(doc.perform_redo());
+        assert_eq!(doc.selection_regions(), typed_cursors);
+    }
+
+    #[test]
+    fn test_undo_group_restores_selection_from_its_start() {
+        let mut doc = TextDocument::with_content("change this word".to_string());
+        select(&mut doc, 7, 11);
+        doc.begin_undo_group("Change word");
+        doc.handle_action(EditorAction::Backspace);
+        doc.handle_action(EditorAction::InsertText("that".to_string()));
+        doc.handle_action(EditorAction::MoveCursor(Movement::LineEnd));
+        doc.end_undo_group();
+        assert_eq!(doc.content(), "change that word");
+
+        doc.set_cursor_position(0);
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "change this word");
+        assert_eq!(doc.selection_range(), Some((7, 11)));
+
+        // Redo goes to where the group ended
+        assert!(doc.perform_redo());
+        assert_eq!(doc.selection_regions(), vec![SelectionRegion::cursor(16)]);
+    }
+
+    #[test]
+    fn test_direct_edits_undo_to_cursor_before_them() {
+        let mut doc = TextDocument::with_content("abc".to_string());
+        doc.set_cursor_position(1);
+        doc.insert_char('x');
+        doc.set_cursor_position(4);
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "abc");
+        assert_eq!(doc.cursor_position(), 1);
+    }
+}
@@ -1,18 +1,60 @@
 use super::{
-    command_history::CommandHistory,
-    commands::{DeleteCommand, InsertCommand, UndoableCommand},
+    command_history::{CommandHistory, CommandTransaction, HistoryRecord},
+    commands::{CommandRecord, DeleteCommand, EditRange, InsertCommand, ReplaceCommand, UndoableCommand},
     cursor::Cursor,
-    selection::Selection,
+    diff,
+    formatting,
+    kill_ring::KillRing,
+    lists::{self, LinePrefix},
+    search::{IncrementalSearch, SearchMatch, SearchOptions, SearchQuery},
+    selection::{Selection, SelectionRegion, SelectionSnapshot},
+    tables::{self, Table},
+    undo_tree::{UndoBranch, UndoMove},
 };
+use crate::format::format_markdown;
+use crate::markdown_ast::{MarkdownAst, NodeId, NodeKind};
+use crate::markdown_parser::MarkdownParser;
+use pulldown_cmark::Alignment;
 use ropey::Rope;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct TextDocument {
     content: Rope,
     cursor: Cursor,
     selection: Selection,
-    clipboard: Option<String>,
+    /// Extra cursors and selections beyond the primary `cursor`/`selection`
+    secondary_selections: Vec<SelectionRegion>,
+    /// Commands collected while an edit is applied to every selection, or
+    /// while an undo group is open
+    pending_commands: Option<Vec<Box<dyn UndoableCommand>>>,
+    /// The undo step collecting edits while an undo group is open
+    undo_group: Option<CommandTransaction>,
+    /// Cursors and selections from before the edit being applied, for the
+    /// undo step it goes into
+    edit_selections: Option<SelectionSnapshot>,
+    /// Whether the action being applied has added a command to the history,
+    /// so the rest of its commands join the same undo step
+    edit_step_started: bool,
+    /// Cut, copied and killed text, newest first
+    kill_ring: KillRing,
+    /// Where the last kill left the cursor, and the version after it, so a
+    /// kill straight after it joins the same kill ring entry
+    last_kill: Option<(usize, u64)>,
+    /// The text the last paste or yank-pop inserted, and the version after
+    /// it, so a yank-pop straight after it can replace it
+    last_yank: Option<(Range<usize>, u64)>,
     command_history: CommandHistory,
+    /// Every edit applied since the last `take_recorded_edits`, when recording
+    /// is on (used by the crash recovery journal)
+    recorded_edits: Option<Vec<CommandRecord>>,
+    /// Active find/replace query, if any
+    search: Option<SearchQuery>,
+    /// Matches of `search` and the version they were found at, so they're
+    /// only looked for again after an edit
+    search_matches: Option<(u64, Vec<SearchMatch>)>,
+    /// The search being typed, during an incremental search
+    incremental_search: Option<IncrementalSearch>,
     /// Version number incremented on any text modification
     /// Used for invalidating visual line caches
     version: u64,
@@ -24,8 +66,19 @@ impl TextDocument {
             content: Rope::new(),
             cursor: Cursor::new(),
             selection: Selection::new(),
-            clipboard: None,
+            secondary_selections: Vec::new(),
+            pending_commands: None,
+            undo_group: None,
+            edit_selections: None,
+            edit_step_started: false,
+            kill_ring: KillRing::new(),
+            last_kill: None,
+            last_yank: None,
             command_history: CommandHistory::new(),
+            recorded_edits: None,
+            search: None,
+            search_matches: None,
+            incremental_search: None,
             version: 0,
         }
     }
@@ -37,12 +90,134 @@ impl TextDocument {
             content: Rope::from_str(&content),
             cursor,
             selection: Selection::new(),
-            clipboard: None,
+            secondary_selections: Vec::new(),
+            pending_commands: None,
+            undo_group: None,
+            edit_selections: None,
+            edit_step_started: false,
+            kill_ring: KillRing::new(),
+            last_kill: None,
+            last_yank: None,
             command_history: CommandHistory::new(),
+            recorded_edits: None,
+            search: None,
+            search_matches: None,
+            incremental_search: None,
             version: 1, // Start at 1 since we have content
         }
     }
 
+    /// Replace the whole document, e.g. when switching to another file.
+    /// Undo history, selections and search are reset and the cursor moves to
+    /// the start. The version keeps counting up so render caches are invalidated.
+    pub fn load_content(&mut self, content: String) {
+        self.content = Rope::from_str(&content);
+        self.cursor.set_position(0);
+        self.selection.clear();
+        self.secondary_selections.clear();
+        self.set_search_query(None);
+        self.incremental_search = None;
+        self.command_history.clear();
+        if let Some(recorded) = &mut self.recorded_edits {
+            recorded.clear();
+        }
+        self.increment_version();
+    }
+
+    /// Bring in a version of the content changed outside the editor, e.g. by
+    /// a script or `git pull`, as one undoable edit. Cursors and selections
+    /// follow their text through a line diff, so they only move if the lines
+    /// they're on changed.
+    pub fn apply_external_change(&mut self, new_content: &str) {
+        self.replace_content("Reload from disk", new_content, |old, new| diff::PositionMap::new(old, new));
+    }
+
+    /// Rewrite the document as canonical Markdown (see `format::format_markdown`)
+    /// in one undo step, keeping cursors and selections on the same text.
+    /// Returns false if it was already formatted.
+    pub fn format_document(&mut self) -> bool {
+        let formatted = format_markdown(&self.content());
+        self.replace_content("Format document", &formatted, |old, new| diff::PositionMap::in_rewrite(old, new))
+    }
+
+    /// Replace the whole content as a single `ReplaceCommand`, moving every
+    /// selection through the map `positions` builds. Returns false if nothing
+    /// changed.
+    fn replace_content(
+        &mut self,
+        description: &str,
+        new_content: &str,
+        positions: for<'a> fn(&'a str, &'a str) -> diff::PositionMap<'a>,
+    ) -> bool {
+        let old_content = self.content();
+        if old_content == new_content {
+            return false;
+        }
+
+        let positions = positions(&old_content, new_content);
+        let map = |position: usize| positions.map(position);
+        let map_region = |region: SelectionRegion| SelectionRegion {
+            anchor: region.anchor.map(map),
+            head: map(region.head),
+        };
+        let primary = map_region(self.primary_region());
+        let secondary: Vec<SelectionRegion> = self.secondary_selections.iter().copied().map(map_region).collect();
+
+        let command = replace_changed_span(0, &old_content, new_content);
+        self.with_selections_recorded(|document| {
+            document.apply_transaction(description, vec![command]);
+            document.set_primary_region(primary);
+            document.secondary_selections = secondary;
+            document.merge_selections();
+        });
+        true
+    }
+
+    /// Start or stop recording every applied edit as a `CommandRecord`
+    pub fn set_edit_recording(&mut self, enabled: bool) {
+        self.recorded_edits = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Edits applied since the last call, in order. Undo and redo show up as
+    /// the edits they perform, so replaying the list onto the old content
+    /// always reproduces the current content.
+    pub fn take_recorded_edits(&mut self) -> Vec<CommandRecord> {
+        self.recorded_edits.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Apply recorded edits as one undo step, stopping at the first one that
+    /// doesn't fit the current content. Returns how many were applied.
+    pub fn replay_edits(&mut self, records: &[CommandRecord]) -> usize {
+        let mut transaction = CommandTransaction::new("Replay edits".to_string());
+        transaction.set_selections_before(self.edit_selections_before());
+        let mut applied = 0;
+        for record in records {
+            if !record.applies_to(&self.content) {
+                break;
+            }
+            let command = record.clone().into_command();
+            self.content = command.execute(&self.content);
+            self.record_edit(command.as_ref());
+            transaction.add_command(command);
+            applied += 1;
+        }
+
+        if applied > 0 {
+            self.command_history.record_transaction(transaction);
+            self.cursor.set_position(self.cursor.position().min(self.content.len_chars()));
+            self.selection.clear();
+            self.clamp_secondary_selections();
+            self.increment_version();
+        }
+        applied
+    }
+
+    fn record_edit(&mut self, command: &dyn UndoableCommand) {
+        if let Some(recorded) = &mut self.recorded_edits {
+            recorded.push(command.to_record());
+        }
+    }
+
     // Content access
     pub fn content(&self) -> String {
         self.content.to_string()
@@ -133,109 +308,191 @@ impl TextDocument {
 
     // Formatting operations
     pub fn toggle_bold(&mut self) {
-        if self.has_selection() {
-            if self.selection_has_formatting("**", "**") {
-                self.remove_formatting_from_selection("**", "**");
-            } else {
-                self.wrap_selection_with("**", "**");
-            }
-        } else {
-            self.insert_text("****");
-            // Move cursor between the markers
-            self.move_cursor_left();
-            self.move_cursor_left();
-        }
+        self.toggle_format(FormatType::Bold);
     }
 
     pub fn toggle_italic(&mut self) {
-        if self.has_selection() {
-            if self.selection_has_formatting("*", "*") {
-                self.remove_formatting_from_selection("*", "*");
-            } else {
-                self.wrap_selection_with("*", "*");
-            }
-        } else {
-            self.insert_text("**");
-            // Move cursor between the markers
-            self.move_cursor_left();
-        }
+        self.toggle_format(FormatType::Italic);
     }
 
-    fn wrap_selection_with(&mut self, start_marker: &str, end_marker: &str) {
-        if let Some((start, end)) = self.selection_range() {
-            let selected_content = self.safe_slice(start, end);
-            let wrapped_content = format!("{}{}{}", start_marker, selected_content, end_marker);
-
-            // Replace selected text with wrapped content
-            let char_start = self.byte_to_char_position(start);
-            let _char_end = self.byte_to_char_position(end);
-
-            self.content.remove(start..end);
-            self.content.insert(start, &wrapped_content);
+    /// Add or remove `format` at every selection. Inline formats wrap the
+    /// selection in markers, or remove the markers around it if it's already
+    /// formatted; line formats apply to every selected line.
+    pub fn toggle_format(&mut self, format: FormatType) {
+        // Formatting is an undo step of its own, apart from any typing around it
+        self.command_history.finish_current_transaction();
+        self.toggle_format_at_selections(format);
+        self.command_history.finish_current_transaction();
+    }
 
-            // Update cursor position to end of wrapped content
-            let new_position = char_start + wrapped_content.chars().count();
-            self.cursor.set_position(new_position);
+    fn toggle_format_at_selections(&mut self, format: FormatType) {
+        if self.for_each_selection(|document| document.toggle_format_at_selections(format.clone())).is_some() {
+            return;
+        }
 
-            // Clear selection
-            self.clear_selection();
+        match format {
+            FormatType::Bold => self.toggle_inline_format("**"),
+            FormatType::Italic => self.toggle_inline_format("*"),
+            FormatType::Code => self.toggle_inline_format("`"),
+            FormatType::Strikethrough => self.toggle_inline_format("~~"),
+            FormatType::Highlight => self.toggle_inline_format("=="),
+            FormatType::Heading(level) => {
+                let level = usize::from(level.clamp(1, 6));
+                self.toggle_line_format("Toggle heading", |lines| formatting::toggle_heading(lines, level));
+            }
+            FormatType::BulletList => self.toggle_line_format("Toggle bullet list", formatting::toggle_bullet_list),
+            FormatType::TaskList => self.toggle_line_format("Toggle task list", formatting::toggle_task_list),
+            FormatType::BlockQuote => self.toggle_line_format("Toggle quote", formatting::toggle_blockquote),
         }
     }
 
-    fn byte_to_char_position(&self, byte_position: usize) -> usize {
-        byte_position.min(self.content.len_chars())
+    fn toggle_inline_format(&mut self, marker: &str) {
+        let region = self.primary_region();
+        let (start, end) = region.range();
+        let first = self.content.char_to_line(start);
+        let last = self.content.char_to_line(end);
+        let offset = self.content.line_to_char(first);
+        let text = (first..=last).map(|line| self.line_text(line)).collect::<Vec<_>>().join("\n");
+
+        if let Some(pair) = formatting::enclosing_pair(&text, marker, start - offset, end - offset) {
+            let open = offset + pair.open.start..offset + pair.open.end;
+            let close = offset + pair.close.start..offset + pair.close.end;
+            let formatted = self.safe_slice(open.start, close.end);
+            let inner = self.safe_slice(open.end, close.start);
+            let command = Box::new(ReplaceCommand::new(open.start, close.end, formatted, inner));
+            self.execute_command_in_transaction(command);
+
+            // Positions inside a marker move to where it was
+            let map = |position: usize| {
+                if position <= open.start {
+                    position
+                } else if position <= open.end {
+                    open.start
+                } else if position <= close.start {
+                    position - open.len()
+                } else if position <= close.end {
+                    close.start - open.len()
+                } else {
+                    position - open.len() - close.len()
+                }
+            };
+            self.set_primary_region(SelectionRegion {
+                anchor: region.anchor.map(map),
+                head: map(region.head),
+            });
+        } else if start < end {
+            let selected = self.safe_slice(start, end);
+            let wrapped = format!("{}{}{}", marker, selected, marker);
+            let command = Box::new(ReplaceCommand::new(start, end, selected, wrapped));
+            self.execute_command_in_transaction(command);
+
+            // Keep the same text selected, now inside the markers
+            let length = marker.chars().count();
+            self.set_primary_region(SelectionRegion {
+                anchor: region.anchor.map(|anchor| anchor + length),
+                head: region.head + length,
+            });
+        } else {
+            self.insert_text(&marker.repeat(2));
+            // Move cursor between the markers
+            self.cursor.set_position(self.cursor.position() - marker.chars().count());
+        }
     }
 
-    fn selection_has_formatting(&self, start_marker: &str, end_marker: &str) -> bool {
-        if let Some((start, end)) = self.selection_range() {
-            let start_marker_len = start_marker.len();
-            let end_marker_len = end_marker.len();
-
-            // Check if there's enough content before and after selection for markers
-            if start < start_marker_len || end + end_marker_len > self.content.len_chars() {
-                return false;
-            }
+    /// Rewrite the selected lines with `toggle` as one undo step
+    fn toggle_line_format(&mut self, description: &str, toggle: impl FnOnce(&mut [String])) {
+        let (first, last) = self.selected_lines();
+        let old_lines: Vec<String> = (first..=last).map(|line| self.line_text(line)).collect();
+        let mut lines = old_lines.clone();
+        toggle(&mut lines);
+        self.replace_lines_keeping_selection(description, first..last + 1, &old_lines, &lines);
+    }
 
-            let before_selection = self.safe_slice(start.saturating_sub(start_marker_len), start);
-            let after_selection = self.safe_slice(end, end + end_marker_len);
+    /// Turn the selection into a link, `[text](|)`, with the cursor where the
+    /// URL goes. A selected URL becomes the destination instead: `[|](url)`.
+    pub fn insert_link(&mut self) {
+        if self.for_each_selection(Self::insert_link).is_some() {
+            return;
+        }
 
-            before_selection == start_marker && after_selection == end_marker
+        let (start, end) = self.primary_region().range();
+        let selected = self.safe_slice(start, end);
+        let is_url = ["http://", "https://", "mailto:"].iter().any(|scheme| selected.starts_with(scheme))
+            && !selected.contains(char::is_whitespace);
+        let (link, cursor) = if is_url {
+            (format!("[]({})", selected), start + 1)
         } else {
-            false
-        }
+            (format!("[{}]()", selected), start + selected.chars().count() + 3)
+        };
+        let command = Box::new(ReplaceCommand::new(start, end, selected, link));
+        self.execute_command_in_transaction(command);
+        self.selection.clear();
+        self.cursor.set_position(cursor);
     }
 
-    fn remove_formatting_from_selection(&mut self, start_marker: &str, end_marker: &str) {
-        if let Some((start, end)) = self.selection_range() {
-            let start_marker_len = start_marker.len();
-            let end_marker_len = end_marker.len();
-
-            // Remove end marker first (so positions don't change)
-            self.content.remove(end..end + end_marker_len);
-
-            // Remove start marker
-            self.content.remove(start - start_marker_len..start);
+    /// Tick or clear the task on the cursor's line, or every task in the
+    /// selection, at each cursor. Returns false if there's no task there.
+    pub fn toggle_tasks(&mut self) -> bool {
+        if let Some(toggled) = self.for_each_selection(Self::toggle_tasks) {
+            return toggled.contains(&true);
+        }
+        let (first, last) = self.selected_lines();
+        self.toggle_tasks_on_lines(first, last)
+    }
 
-            // Update cursor position
-            let char_start = self.byte_to_char_position(start - start_marker_len);
-            let selected_text_len =
-                self.byte_to_char_position(end) - self.byte_to_char_position(start);
-            let new_position = char_start + selected_text_len;
-            self.cursor.set_position(new_position);
+    /// Tick or clear the task whose `[ ]` box is at `position`, as a click on
+    /// the box does, leaving the cursor and selection where they are. Returns
+    /// false if there's no task box at `position`.
+    pub fn toggle_task_at(&mut self, position: usize) -> bool {
+        let position = position.min(self.content.len_chars());
+        let line = self.content.char_to_line(position);
+        let column = position - self.content.line_to_char(line);
+        let prefix = LinePrefix::parse(&self.line_text(line));
+        let Some(task) = prefix.marker.as_ref().and_then(|marker| marker.task.as_ref()) else {
+            return false;
+        };
+        let box_start = prefix.len() - task.len();
+        if !(box_start..=box_start + 3).contains(&column) {
+            return false;
+        }
+        self.toggle_tasks_on_lines(line, line)
+    }
 
-            // Clear selection
-            self.clear_selection();
+    fn toggle_tasks_on_lines(&mut self, first: usize, last: usize) -> bool {
+        // Parent tasks above the lines may change too
+        let area = self.list_region(first, last);
+        let old_lines: Vec<String> = area.clone().map(|line| self.line_text(line)).collect();
+        let mut lines = old_lines.clone();
+        // Task boxes in code are just text
+        let ast = self.parse_ast();
+        let targets: Vec<usize> = (first..=last)
+            .filter(|&line| !self.line_in_code_block(&ast, line))
+            .map(|line| line - area.start)
+            .collect();
+        if !lists::toggle_tasks(&mut lines, &targets) {
+            return false;
         }
+        self.replace_lines_keeping_selection("Toggle task", area, &old_lines, &lines);
+        true
     }
 
     // Text modification
     pub fn insert_char(&mut self, ch: char) {
+        if self.for_each_selection(|document| document.insert_char(ch)).is_some() {
+            return;
+        }
+        if ch == '\n' && !self.has_selection() && self.continue_list() {
+            return;
+        }
+
         // Handle selection deletion first with command
         if self.has_selection() {
             if let Some((start, end)) = self.selection_range() {
                 let deleted_text = self.safe_slice(start, end);
                 let delete_command = Box::new(DeleteCommand::new(start, end, deleted_text));
                 self.execute_command_in_transaction(delete_command);
+                self.cursor.set_position(start);
+                self.selection.clear();
             }
         }
 
@@ -251,12 +508,18 @@ impl TextDocument {
     }
 
     pub fn insert_text(&mut self, text: &str) {
+        if self.for_each_selection(|document| document.insert_text(text)).is_some() {
+            return;
+        }
+
         // Handle selection deletion first with command
         if self.has_selection() {
             if let Some((start, end)) = self.selection_range() {
                 let deleted_text = self.safe_slice(start, end);
                 let delete_command = Box::new(DeleteCommand::new(start, end, deleted_text));
                 self.execute_command_in_transaction(delete_command);
+                self.cursor.set_position(start);
+                self.selection.clear();
             }
         }
 
@@ -271,11 +534,17 @@ impl TextDocument {
     }
 
     pub fn delete_char(&mut self) -> bool {
+        if let Some(results) = self.for_each_selection(Self::delete_char) {
+            return results.contains(&true);
+        }
+
         if self.has_selection() {
             if let Some((start, end)) = self.selection_range() {
                 let deleted_text = self.safe_slice(start, end);
                 let delete_command = Box::new(DeleteCommand::new(start, end, deleted_text));
                 self.execute_command_in_transaction(delete_command);
+                self.cursor.set_position(start);
+                self.selection.clear();
                 return true;
             }
         }
@@ -293,11 +562,16 @@ impl TextDocument {
     }
 
     pub fn backspace(&mut self) -> bool {
+        if let Some(results) = self.for_each_selection(Self::backspace) {
+            return results.contains(&true);
+        }
+
         if self.has_selection() {
             if let Some((start, end)) = self.selection_range() {
                 let deleted_text = self.safe_slice(start, end);
                 let delete_command = Box::new(DeleteCommand::new(start, end, deleted_text));
                 self.execute_command_in_transaction(delete_command);
+                self.cursor.set_position(start);
                 self.clear_selection();
                 return true;
             }
@@ -319,8 +593,16 @@ impl TextDocument {
     }
 
     pub fn delete_selection(&mut self) -> bool {
+        if let Some(results) = self.for_each_selection(Self::delete_selection) {
+            return results.contains(&true);
+        }
+
         if let Some((start, end)) = self.selection_range() {
-            self.content.remove(start..end);
+            if start < end {
+                let deleted_text = self.safe_slice(start, end);
+                let delete_command = Box::new(DeleteCommand::new(start, end, deleted_text));
+                self.execute_command_in_transaction(delete_command);
+            }
             self.cursor.set_position(start);
             self.selection.clear();
             true
@@ -796,6 +1078,43 @@ impl TextDocument {
     }
 }
 
+/// `text` with the first letter of each word upper case and the rest lower case
+fn capitalize_words(text: &str) -> String {
+    let mut in_word = false;
+    let mut capitalized = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if in_word {
+            capitalized.extend(ch.to_lowercase());
+        } else {
+            capitalized.extend(ch.to_uppercase());
+        }
+        in_word = ch.is_alphanumeric();
+    }
+    capitalized
+}
+
+/// A `ReplaceCommand` turning `old_text`, found at `offset`, into `new_text`
+/// that only replaces the span between their unchanged start and end
+fn replace_changed_span(offset: usize, old_text: &str, new_text: &str) -> Box<dyn UndoableCommand> {
+    let prefix = old_text
+        .chars()
+        .zip(new_text.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_length = old_text.chars().count();
+    let new_length = new_text.chars().count();
+    let suffix = old_text
+        .chars()
+        .rev()
+        .zip(new_text.chars().rev())
+        .take(old_length.min(new_length) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed: String = old_text.chars().skip(prefix).take(old_length - suffix - prefix).collect();
+    let inserted: String = new_text.chars().skip(prefix).take(new_length - suffix - prefix).collect();
+    Box::new(ReplaceCommand::new(offset + prefix, offset + old_length - suffix, removed, inserted))
+}
+
 impl Default for TextDocument {
     fn default() -> Self {
         Self::new()
@@ -803,10 +1122,30 @@ impl Default for TextDocument {
 }
 
 // Implement ActionHandler for TextDocument
-use crate::input::{ActionHandler, EditorAction, FormatType, Movement};
+use crate::input::context::KeyContext;
+use crate::input::macros::MacroTarget;
+use crate::input::{
+    ActionHandler, CaseChange, ColumnAlignment, EditorAction, FormatType, Movement, SearchDirection, TableEdit,
+};
 
 impl ActionHandler for TextDocument {
     fn handle_action(&mut self, action: EditorAction) -> bool {
+        match action {
+            EditorAction::Undo => self.perform_undo(),
+            EditorAction::Redo => self.perform_redo(),
+            EditorAction::UndoOlder => self.go_to_older_state(),
+            EditorAction::UndoNewer => self.go_to_newer_state(),
+            action => self.with_selections_recorded(|document| document.apply_action(action)),
+        }
+    }
+
+    fn key_context(&self) -> KeyContext {
+        TextDocument::key_context(self)
+    }
+}
+
+impl TextDocument {
+    fn apply_action(&mut self, action: EditorAction) -> bool {
         match action {
             EditorAction::InsertChar(ch) => {
                 self.insert_char(ch);
@@ -819,7 +1158,11 @@ impl ActionHandler for TextDocument {
             EditorAction::Backspace => self.backspace(),
             EditorAction::Delete => self.delete_char(),
             EditorAction::MoveCursor(movement) => {
-                self.handle_cursor_movement(movement);
+                if self.is_mark_active() {
+                    self.handle_selection_extension(movement);
+                } else {
+                    self.handle_cursor_movement(movement);
+                }
                 true
             }
             EditorAction::ExtendSelection(movement) => {
@@ -827,27 +1170,26 @@ impl ActionHandler for TextDocument {
                 true
             }
             EditorAction::SelectAll => {
+                self.clear_secondary_selections();
                 self.select_all();
                 true
             }
             EditorAction::ClearSelection => {
+                self.clear_secondary_selections();
                 self.clear_selection();
                 true
             }
-            EditorAction::ToggleFormat(format_type) => match format_type {
-                FormatType::Bold => {
-                    self.toggle_bold();
-                    true
-                }
-                FormatType::Italic => {
-                    self.toggle_italic();
-                    true
-                }
-                FormatType::Code => {
-                    unimplemented!("Code formatting not yet implemented")
-                }
-            },
+            EditorAction::ToggleFormat(format_type) => {
+                self.toggle_format(format_type);
+                true
+            }
+            EditorAction::ToggleTask => self.toggle_tasks(),
+            EditorAction::InsertLink => {
+                self.insert_link();
+                true
+            }
             EditorAction::MoveToPosition(position) => {
+                self.clear_secondary_selections();
                 self.set_cursor_position(position);
                 true
             }
@@ -871,7 +1213,7 @@ impl ActionHandler for TextDocument {
                 true
             }
             EditorAction::Paste => {
-                // Paste with no external text (will use internal clipboard)
+                // Paste with no external text (will use the kill ring)
                 self.paste(None);
                 true
             }
@@ -898,7 +1240,51 @@ impl ActionHandler for TextDocument {
             }
             EditorAction::Undo => self.perform_undo(),
             EditorAction::Redo => self.perform_redo(),
+            EditorAction::UndoOlder => self.go_to_older_state(),
+            EditorAction::UndoNewer => self.go_to_newer_state(),
+            EditorAction::FindNext => self.find_next(),
+            EditorAction::FindPrevious => self.find_previous(),
+            EditorAction::CloseFind => {
+                self.clear_search();
+                true
+            }
+            EditorAction::ReplaceCurrent(replacement) => self.replace_current(&replacement),
+            EditorAction::ReplaceAll(replacement) => self.replace_all(&replacement) > 0,
+            EditorAction::AddCursorAbove => self.add_cursor_above(),
+            EditorAction::AddCursorBelow => self.add_cursor_below(),
+            EditorAction::AddNextOccurrence => self.add_next_occurrence(),
+            EditorAction::SplitSelectionIntoLines => self.split_selection_into_lines(),
+            EditorAction::Indent => self.indent_lines(),
+            EditorAction::Outdent => self.outdent_lines(),
+            EditorAction::EditTable(edit) => self.edit_table(edit),
+            EditorAction::Kill(movement) => self.kill(movement),
+            EditorAction::YankPop => self.yank_pop(),
+            EditorAction::SetMark => {
+                self.set_mark();
+                true
+            }
+            EditorAction::TransposeChars => self.transpose_chars(),
+            EditorAction::TransposeWords => self.transpose_words(),
+            EditorAction::TransposeLines => self.transpose_lines(),
+            EditorAction::ChangeCase(case) => self.change_case(case),
+            EditorAction::IncrementalSearch(direction) => self.incremental_search(direction),
             
+            // File operations need dialogs - handled by editor
+            EditorAction::NewFile |
+            EditorAction::OpenFile |
+            EditorAction::Save |
+            EditorAction::SaveAs => false,
+
+            // Vim mode, the command palette, the find prompt and macros belong to the editor
+            EditorAction::OpenFind |
+            EditorAction::ToggleVimMode |
+            EditorAction::ToggleCommandPalette |
+            EditorAction::ToggleMacroRecording |
+            EditorAction::PlayMacro(_) |
+            EditorAction::RepeatMacro |
+            EditorAction::PlayNamedMacro(_) |
+            EditorAction::SaveMacro => false,
+
             // ENG-191: Scroll actions are not text document operations - handled by editor
             EditorAction::ScrollUp |
             EditorAction::ScrollDown |
@@ -910,9 +1296,27 @@ impl ActionHandler for TextDocument {
     }
 }
 
+impl MacroTarget for TextDocument {
+    fn begin_macro_run(&mut self) {
+        self.begin_undo_group("Macro");
+    }
+
+    fn end_macro_run(&mut self) {
+        self.end_undo_group();
+    }
+
+    fn chars_after_cursor(&self) -> usize {
+        self.content.len_chars() - self.cursor_position()
+    }
+}
+
 impl TextDocument {
     /// Handle cursor movement actions
     fn handle_cursor_movement(&mut self, movement: Movement) {
+        if self.for_each_selection(|document| document.handle_cursor_movement(movement.clone())).is_some() {
+            return;
+        }
+
         // Clear selection before any cursor movement (without Shift key)
         self.selection.clear();
 
@@ -934,6 +1338,10 @@ impl TextDocument {
 
     /// Handle selection extension actions
     fn handle_selection_extension(&mut self, movement: Movement) {
+        if self.for_each_selection(|document| document.handle_selection_extension(movement.clone())).is_some() {
+            return;
+        }
+
         match movement {
             Movement::Left => self.extend_selection_left(),
             Movement::Right => self.extend_selection_right(),
@@ -984,10 +1392,17 @@ impl TextDocument {
 
     // Clipboard operations - returns text to be copied to system clipboard
     pub fn copy(&mut self) -> Option<String> {
+        if self.has_multiple_cursors() {
+            // Copy the text of every selection, one per line
+            let text = self.joined_selection_text();
+            self.kill_ring.push(text.clone());
+            return Some(text);
+        }
+
         if self.has_selection() {
             // Copy selected text
             if let Some(text) = self.selected_text() {
-                self.clipboard = Some(text.clone());
+                self.kill_ring.push(text.clone());
                 Some(text)
             } else {
                 None
@@ -995,16 +1410,27 @@ impl TextDocument {
         } else {
             // Copy current line
             let line_text = self.get_current_line_with_newline();
-            self.clipboard = Some(line_text.clone());
+            self.kill_ring.push(line_text.clone());
             Some(line_text)
         }
     }
 
     pub fn cut(&mut self) -> Option<String> {
+        self.with_selections_recorded(Self::cut_selections)
+    }
+
+    fn cut_selections(&mut self) -> Option<String> {
+        if self.has_multiple_cursors() {
+            let text = self.joined_selection_text();
+            self.kill_ring.push(text.clone());
+            self.delete_selection();
+            return Some(text);
+        }
+
         if self.has_selection() {
             // Cut selected text
             if let Some(text) = self.selected_text() {
-                self.clipboard = Some(text.clone());
+                self.kill_ring.push(text.clone());
                 self.delete_selection();
                 Some(text)
             } else {
@@ -1013,31 +1439,221 @@ impl TextDocument {
         } else {
             // Cut current line
             let line_text = self.get_current_line_with_newline();
-            self.clipboard = Some(line_text.clone());
+            self.kill_ring.push(line_text.clone());
             self.delete_current_line();
             Some(line_text)
         }
     }
 
     pub fn get_clipboard_content(&self) -> Option<String> {
-        self.clipboard.clone()
+        self.kill_ring.newest().map(str::to_string)
     }
 
     pub fn copy_text_to_clipboard(&mut self, text: String) {
-        self.clipboard = Some(text);
+        self.kill_ring.push(text);
     }
 
     pub fn paste(&mut self, clipboard_text: Option<String>) {
-        // Try clipboard_text first (system clipboard), fallback to internal clipboard
-        let content = clipboard_text.or_else(|| self.clipboard.clone());
+        self.with_selections_recorded(|document| document.paste_at_selections(clipboard_text));
+    }
+
+    fn paste_at_selections(&mut self, clipboard_text: Option<String>) {
+        // Text copied in other apps (the system clipboard) joins the kill
+        // ring, so pasting always inserts its newest entry
+        if let Some(text) = clipboard_text {
+            if self.kill_ring.newest() != Some(text.as_str()) {
+                self.kill_ring.push(text);
+            }
+        }
+        let content = self.kill_ring.yank().map(str::to_string);
 
         if let Some(text) = content {
+            if self.for_each_selection(|document| document.insert_text(&text)).is_some() {
+                return;
+            }
             if self.has_selection() {
                 // Replace selection with pasted content
                 self.delete_selection();
             }
+            let start = self.cursor.position();
             self.insert_text(&text);
+            self.last_yank = Some((start..self.cursor.position(), self.version));
+        }
+    }
+
+    // Emacs-style editing
+
+    /// Start a selection at the cursor that cursor movement extends, until
+    /// it is cleared (Emacs C-Space)
+    pub fn set_mark(&mut self) {
+        self.clear_secondary_selections();
+        self.selection.set_mark(self.cursor.position());
+    }
+
+    pub fn is_mark_active(&self) -> bool {
+        self.selection.is_mark_active()
+    }
+
+    /// Cut from the cursor to where `movement` would take it into the kill
+    /// ring, at the primary cursor. `LineEnd` at the end of a line kills the
+    /// line break. A kill straight after another one adds to its kill ring
+    /// entry, so several lines killed in a row paste back together.
+    pub fn kill(&mut self, movement: Movement) -> bool {
+        self.clear_secondary_selections();
+        let origin = self.cursor.position();
+        self.handle_cursor_movement(movement.clone());
+        let mut target = self.cursor.position();
+        self.cursor.set_position(origin);
+        if movement == Movement::LineEnd && target == origin && origin < self.content.len_chars() {
+            target += 1;
+        }
+        if target == origin {
+            return false;
+        }
+
+        let (start, end) = (origin.min(target), origin.max(target));
+        let text = self.safe_slice(start, end);
+        match self.last_kill {
+            Some(last) if last == (origin, self.version) && target < origin => self.kill_ring.prepend(&text),
+            Some(last) if last == (origin, self.version) => self.kill_ring.append(&text),
+            _ => self.kill_ring.push(text.clone()),
+        }
+        self.apply_edit("Kill", vec![Box::new(DeleteCommand::new(start, end, text))]);
+        self.cursor.set_position(start);
+        self.last_kill = Some((start, self.version));
+        true
+    }
+
+    /// Right after a paste or yank-pop, replace the text it inserted with the
+    /// kill ring entry before it (Emacs M-y)
+    pub fn yank_pop(&mut self) -> bool {
+        let Some((range, version)) = self.last_yank.take() else {
+            return false;
+        };
+        if version != self.version || self.cursor.position() != range.end || self.has_multiple_cursors() {
+            return false;
+        }
+        let Some(text) = self.kill_ring.yank_pop().map(str::to_string) else {
+            return false;
+        };
+
+        let old_text = self.safe_slice(range.start, range.end);
+        self.selection.clear();
+        self.apply_edit("Yank", vec![replace_changed_span(range.start, &old_text, &text)]);
+        let end = range.start + text.chars().count();
+        self.cursor.set_position(end);
+        self.last_yank = Some((range.start..end, self.version));
+        true
+    }
+
+    /// Swap the characters either side of the cursor and move past them, or
+    /// the two before the cursor at the end of a line (Emacs C-t)
+    pub fn transpose_chars(&mut self) -> bool {
+        if let Some(results) = self.for_each_selection(Self::transpose_chars) {
+            return results.contains(&true);
+        }
+
+        let position = self.cursor.position();
+        let at_line_end = matches!(self.content.get_char(position), None | Some('\n'));
+        let (start, new_position) = if at_line_end {
+            (position.checked_sub(2), position)
+        } else {
+            (position.checked_sub(1), position + 1)
+        };
+        let Some(start) = start else {
+            return false;
+        };
+
+        let old_text = self.safe_slice(start, start + 2);
+        let new_text: String = old_text.chars().rev().collect();
+        self.selection.clear();
+        self.apply_edit("Transpose characters", vec![replace_changed_span(start, &old_text, &new_text)]);
+        self.cursor.set_position(new_position);
+        true
+    }
+
+    /// Swap the word the cursor is in or after with the next one, and move
+    /// past them both (Emacs M-t)
+    pub fn transpose_words(&mut self) -> bool {
+        if let Some(results) = self.for_each_selection(Self::transpose_words) {
+            return results.contains(&true);
+        }
+
+        let is_word_char = |ch: char| ch.is_alphanumeric();
+        let first_start = self.find_word_start(self.cursor.position());
+        if !self.content.get_char(first_start).is_some_and(is_word_char) {
+            return false;
+        }
+        let first_end = self.find_word_end(first_start);
+        let second_end = self.find_word_end(first_end);
+        let second_start = self.find_word_start(second_end);
+        if second_start < first_end {
+            return false;
+        }
+
+        let old_text = self.safe_slice(first_start, second_end);
+        let new_text = format!(
+            "{}{}{}",
+            self.safe_slice(second_start, second_end),
+            self.safe_slice(first_end, second_start),
+            self.safe_slice(first_start, first_end)
+        );
+        self.selection.clear();
+        self.apply_edit("Transpose words", vec![replace_changed_span(first_start, &old_text, &new_text)]);
+        self.cursor.set_position(second_end);
+        true
+    }
+
+    /// Swap the cursor's line with the one above, and move to the start of
+    /// the line after them (Emacs C-x C-t)
+    pub fn transpose_lines(&mut self) -> bool {
+        if let Some(results) = self.for_each_selection(Self::transpose_lines) {
+            return results.contains(&true);
+        }
+
+        let line = self.content.char_to_line(self.cursor.position());
+        if line == 0 {
+            return false;
+        }
+        let lines = [self.line_text(line), self.line_text(line - 1)];
+        self.selection.clear();
+        self.replace_lines("Transpose lines", line - 1..line + 1, &lines);
+        let next_line = (line + 1).min(self.content.len_lines() - 1);
+        let position = if next_line > line {
+            self.content.line_to_char(next_line)
+        } else {
+            self.content.len_chars()
+        };
+        self.cursor.set_position(position);
+        true
+    }
+
+    /// Change the case of the selection, or from the cursor to the end of
+    /// the word, and move past it (Emacs M-u, M-l and M-c)
+    pub fn change_case(&mut self, case: CaseChange) -> bool {
+        if let Some(results) = self.for_each_selection(|document| document.change_case(case)) {
+            return results.contains(&true);
+        }
+
+        let (start, end) = self
+            .selection_range()
+            .unwrap_or_else(|| (self.cursor.position(), self.find_word_end(self.cursor.position())));
+        if start == end {
+            return false;
+        }
+
+        let old_text = self.safe_slice(start, end);
+        let new_text = match case {
+            CaseChange::Upper => old_text.to_uppercase(),
+            CaseChange::Lower => old_text.to_lowercase(),
+            CaseChange::Capitalize => capitalize_words(&old_text),
+        };
+        self.selection.clear();
+        if new_text != old_text {
+            self.apply_edit("Change case", vec![replace_changed_span(start, &old_text, &new_text)]);
         }
+        self.cursor.set_position(start + new_text.chars().count());
+        true
     }
 
     fn get_current_line_with_newline(&self) -> String {
@@ -1063,6 +1679,10 @@ impl TextDocument {
     }
 
     fn delete_current_line(&mut self) {
+        if self.for_each_selection(Self::delete_current_line).is_some() {
+            return;
+        }
+
         let cursor_pos = self.cursor_position();
         let content_chars: Vec<char> = self.content.to_string().chars().collect();
 
@@ -1082,12 +1702,11 @@ impl TextDocument {
         }
 
         // Remove the line
-        let new_content: String = content_chars[..line_start]
-            .iter()
-            .chain(content_chars[line_end..].iter())
-            .collect();
-
-        self.content = Rope::from_str(&new_content);
+        if line_start < line_end {
+            let deleted_text: String = content_chars[line_start..line_end].iter().collect();
+            let delete_command = Box::new(DeleteCommand::new(line_start, line_end, deleted_text));
+            self.execute_command_in_transaction(delete_command);
+        }
         // Set cursor to start of next line (or end if this was last line)
         let new_cursor_pos = if line_start < self.content.len_chars() {
             line_start
@@ -1113,6 +1732,10 @@ impl TextDocument {
 
     /// Delete the previous word from cursor position
     pub fn delete_previous_word(&mut self) {
+        if self.for_each_selection(Self::delete_previous_word).is_some() {
+            return;
+        }
+
         let cursor_pos = self.cursor_position();
         let content_chars: Vec<char> = self.content.to_string().chars().collect();
 
@@ -1124,17 +1747,18 @@ impl TextDocument {
         let word_start = self.find_word_boundary_backward(cursor_pos);
 
         // Delete from word start to cursor
-        let new_content: String = content_chars[..word_start]
-            .iter()
-            .chain(content_chars[cursor_pos..].iter())
-            .collect();
-
-        self.content = Rope::from_str(&new_content);
+        let deleted_text: String = content_chars[word_start..cursor_pos].iter().collect();
+        let delete_command = Box::new(DeleteCommand::new(word_start, cursor_pos, deleted_text));
+        self.execute_command_in_transaction(delete_command);
         self.set_cursor_position(word_start);
     }
 
     /// Delete the next word from cursor position
     pub fn delete_next_word(&mut self) {
+        if self.for_each_selection(Self::delete_next_word).is_some() {
+            return;
+        }
+
         let cursor_pos = self.cursor_position();
         let content_chars: Vec<char> = self.content.to_string().chars().collect();
 
@@ -1146,17 +1770,18 @@ impl TextDocument {
         let word_end = self.find_word_boundary_forward(cursor_pos);
 
         // Delete from cursor to word end
-        let new_content: String = content_chars[..cursor_pos]
-            .iter()
-            .chain(content_chars[word_end..].iter())
-            .collect();
-
-        self.content = Rope::from_str(&new_content);
+        let deleted_text: String = content_chars[cursor_pos..word_end].iter().collect();
+        let delete_command = Box::new(DeleteCommand::new(cursor_pos, word_end, deleted_text));
+        self.execute_command_in_transaction(delete_command);
         // Cursor position stays the same
     }
 
     /// Delete from cursor to line start
     pub fn delete_to_line_start(&mut self) {
+        if self.for_each_selection(Self::delete_to_line_start).is_some() {
+            return;
+        }
+
         let cursor_pos = self.cursor_position();
         let content_chars: Vec<char> = self.content.to_string().chars().collect();
 
@@ -1167,17 +1792,20 @@ impl TextDocument {
         }
 
         // Delete from line start to cursor
-        let new_content: String = content_chars[..line_start]
-            .iter()
-            .chain(content_chars[cursor_pos..].iter())
-            .collect();
-
-        self.content = Rope::from_str(&new_content);
+        if line_start < cursor_pos {
+            let deleted_text: String = content_chars[line_start..cursor_pos].iter().collect();
+            let delete_command = Box::new(DeleteCommand::new(line_start, cursor_pos, deleted_text));
+            self.execute_command_in_transaction(delete_command);
+        }
         self.set_cursor_position(line_start);
     }
 
     /// Delete from cursor to line end
     pub fn delete_to_line_end(&mut self) {
+        if self.for_each_selection(Self::delete_to_line_end).is_some() {
+            return;
+        }
+
         let cursor_pos = self.cursor_position();
         let content_chars: Vec<char> = self.content.to_string().chars().collect();
 
@@ -1188,12 +1816,11 @@ impl TextDocument {
         }
 
         // Delete from cursor to line end
-        let new_content: String = content_chars[..cursor_pos]
-            .iter()
-            .chain(content_chars[line_end..].iter())
-            .collect();
-
-        self.content = Rope::from_str(&new_content);
+        if cursor_pos < line_end {
+            let deleted_text: String = content_chars[cursor_pos..line_end].iter().collect();
+            let delete_command = Box::new(DeleteCommand::new(cursor_pos, line_end, deleted_text));
+            self.execute_command_in_transaction(delete_command);
+        }
         // Cursor position stays the same
     }
 
@@ -1279,75 +1906,1160 @@ impl TextDocument {
         pos
     }
 
-    /// Perform undo operation
-    pub fn perform_undo(&mut self) -> bool {
-        if let Some(new_content) = self.command_history.undo(&self.content) {
-            self.content = new_content;
+    // Multiple cursors
+    /// Every cursor and selection, the primary one included, in document order
+    pub fn selection_regions(&self) -> Vec<SelectionRegion> {
+        let mut regions = self.secondary_selections.clone();
+        regions.push(self.primary_region());
+        regions.sort_by_key(|region| region.range());
+        regions
+    }
 
-            // Ensure cursor is within bounds after content change
-            let current_position = self.cursor.position();
-            let max_position = self.content.len_chars();
-            if current_position > max_position {
-                self.cursor.set_position(max_position);
-            }
+    /// Cursors and selections beyond the primary one
+    pub fn secondary_selections(&self) -> &[SelectionRegion] {
+        &self.secondary_selections
+    }
 
-            // Clear selection if it extends beyond the new content
-            if let Some((start, end)) = self.selection.range(current_position) {
-                if start > max_position || end > max_position {
-                    self.selection.clear();
-                }
-            }
+    pub fn has_multiple_cursors(&self) -> bool {
+        !self.secondary_selections.is_empty()
+    }
 
-            // Increment version to invalidate visual line caches
-            self.increment_version();
+    /// Drop every cursor except the primary one
+    pub fn clear_secondary_selections(&mut self) {
+        self.secondary_selections.clear();
+    }
 
-            true
+    /// Add a cursor at `position`, keeping the existing ones
+    pub fn add_cursor_at(&mut self, position: usize) {
+        let position = position.min(self.content.len_chars());
+        self.secondary_selections.push(SelectionRegion::cursor(position));
+        self.merge_selections();
+    }
+
+    /// Add a cursor on the line above the topmost cursor, in the same column
+    pub fn add_cursor_above(&mut self) -> bool {
+        let topmost = self.selection_regions().iter().map(|region| region.head).min().unwrap_or(0);
+        let line = self.content.char_to_line(topmost);
+        if line == 0 {
+            return false;
+        }
+
+        let column = topmost - self.content.line_to_char(line);
+        let position = self.get_position_from_line_and_column(line - 1, column);
+        self.add_cursor_at(position);
+        true
+    }
+
+    /// Add a cursor on the line below the bottommost cursor, in the same column
+    pub fn add_cursor_below(&mut self) -> bool {
+        let bottommost = self.selection_regions().iter().map(|region| region.head).max().unwrap_or(0);
+        let line = self.content.char_to_line(bottommost);
+        if line + 1 >= self.content.len_lines() {
+            return false;
+        }
+
+        let column = bottommost - self.content.line_to_char(line);
+        let position = self.get_position_from_line_and_column(line + 1, column);
+        self.add_cursor_at(position);
+        true
+    }
+
+    /// Select the word at the cursor, or if something is already selected, add a
+    /// selection on its next occurrence and make that the primary selection
+    pub fn add_next_occurrence(&mut self) -> bool {
+        let (start, end) = self.primary_region().range();
+        if start == end {
+            let (word_start, word_end) = self.word_range_at(start);
+            if word_start == word_end {
+                return false;
+            }
+            self.set_primary_region(SelectionRegion::new(word_start, word_end));
+            self.merge_selections();
+            return true;
+        }
+
+        let options = SearchOptions {
+            case_sensitive: true,
+            ..SearchOptions::default()
+        };
+        let query = match SearchQuery::new(&self.safe_slice(start, end), options) {
+            Ok(query) => query,
+            Err(_) => return false,
+        };
+
+        // Look after the primary selection first, then wrap around
+        let taken: Vec<(usize, usize)> = self.selection_regions().iter().map(|region| region.range()).collect();
+        let matches = query.find_all(&self.content);
+        let next = matches
+            .iter()
+            .filter(|found| found.start >= end)
+            .chain(matches.iter().filter(|found| found.start < end))
+            .find(|found| {
+                !taken
+                    .iter()
+                    .any(|&(taken_start, taken_end)| found.start < taken_end && taken_start < found.end)
+            });
+
+        match next {
+            Some(found) => {
+                self.secondary_selections.push(self.primary_region());
+                self.set_primary_region(SelectionRegion::new(found.start, found.end));
+                self.merge_selections();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace every selection that spans several lines with one selection per line
+    pub fn split_selection_into_lines(&mut self) -> bool {
+        let primary = self.primary_region();
+        let mut new_primary = primary;
+        let mut regions = Vec::new();
+        let mut split = false;
+
+        for region in self.selection_regions() {
+            let (start, end) = region.range();
+            let first_line = self.content.char_to_line(start);
+            let last_line = self.content.char_to_line(end);
+            if first_line == last_line {
+                regions.push(region);
+                continue;
+            }
+
+            split = true;
+            for line in first_line..=last_line {
+                let line_start = self.content.line_to_char(line).max(start);
+                if line > first_line && line_start == end {
+                    // The selection stops at the very start of this line
+                    break;
+                }
+                let line_end = self.get_position_from_line_and_column(line, usize::MAX).min(end);
+                regions.push(if line_start == line_end {
+                    SelectionRegion::cursor(line_end)
+                } else {
+                    SelectionRegion::new(line_start, line_end)
+                });
+            }
+            if region == primary {
+                new_primary = regions[regions.len() - 1];
+            }
+        }
+
+        if split {
+            let primary_index = regions.iter().position(|region| *region == new_primary).unwrap_or(0);
+            regions.remove(primary_index);
+            self.secondary_selections = regions;
+            self.set_primary_region(new_primary);
+            self.merge_selections();
+        }
+        split
+    }
+
+    fn primary_region(&self) -> SelectionRegion {
+        SelectionRegion {
+            anchor: self.selection.anchor(),
+            head: self.cursor.position(),
+        }
+    }
+
+    fn set_primary_region(&mut self, region: SelectionRegion) {
+        self.cursor.set_position(region.head);
+        match region.anchor {
+            Some(anchor) => self.selection.start(anchor),
+            None => self.selection.clear(),
+        }
+    }
+
+    /// Sort the selections and merge any that overlap or share a cursor position
+    fn merge_selections(&mut self) {
+        if self.secondary_selections.is_empty() {
+            return;
+        }
+
+        let primary = self.primary_region();
+        let mut merged: Vec<(SelectionRegion, bool)> = Vec::new();
+        for region in self.selection_regions() {
+            let is_primary = region == primary;
+            if let Some((last, last_is_primary)) = merged.last_mut() {
+                let (last_start, last_end) = last.range();
+                let (start, end) = region.range();
+                let touches_cursor = start == last_end && (region.is_empty() || last.is_empty());
+                if start < last_end || start == last_start || touches_cursor {
+                    let merged_end = last_end.max(end);
+                    if merged_end > last_end {
+                        *last = SelectionRegion::new(last_start, merged_end);
+                    }
+                    *last_is_primary |= is_primary;
+                    continue;
+                }
+            }
+            merged.push((region, is_primary));
+        }
+
+        let primary_index = merged.iter().position(|(_, is_primary)| *is_primary).unwrap_or(0);
+        let (primary, _) = merged.remove(primary_index);
+        self.secondary_selections = merged.into_iter().map(|(region, _)| region).collect();
+        self.set_primary_region(primary);
+    }
+
+    /// Keep the secondary selections inside the document after its length changes
+    fn clamp_secondary_selections(&mut self) {
+        let max_position = self.content.len_chars();
+        for region in &mut self.secondary_selections {
+            region.head = region.head.min(max_position);
+            region.anchor = region.anchor.map(|anchor| anchor.min(max_position));
+        }
+        self.merge_selections();
+    }
+
+    /// Run `edit` once at every cursor when there are several, recording all of
+    /// the resulting commands as one undo step. Returns `None` when there is a
+    /// single cursor, or from inside another multi-cursor edit, so the caller
+    /// carries on editing at the primary cursor.
+    fn for_each_selection<R>(&mut self, mut edit: impl FnMut(&mut Self) -> R) -> Option<Vec<R>> {
+        if self.secondary_selections.is_empty() || self.pending_commands.is_some() {
+            return None;
+        }
+
+        let selections_before = self.edit_selections_before();
+        let primary = self.primary_region();
+        let mut regions = self.selection_regions();
+        let primary_index = regions.iter().position(|region| *region == primary).unwrap_or(0);
+        self.secondary_selections.clear();
+        self.pending_commands = Some(Vec::new());
+
+        // Edit from the last selection back so earlier ones are rarely moved
+        let mut results = Vec::with_capacity(regions.len());
+        for index in (0..regions.len()).rev() {
+            self.set_primary_region(regions[index]);
+            let applied = self.pending_commands.as_ref().map_or(0, Vec::len);
+            results.push(edit(self));
+            regions[index] = self.primary_region();
+
+            let edits: Vec<EditRange> = self
+                .pending_commands
+                .as_ref()
+                .map(|pending| pending[applied..].iter().map(|command| command.edit_range()).collect())
+                .unwrap_or_default();
+            for (other_index, region) in regions.iter_mut().enumerate() {
+                if other_index != index {
+                    *region = edits.iter().fold(*region, |region, edit_range| region.map_through(edit_range));
+                }
+            }
+        }
+        results.reverse();
+
+        let commands = self.pending_commands.take().unwrap_or_default();
+        if !commands.is_empty() {
+            if self.edit_step_started {
+                self.command_history.add_commands_to_current(commands);
+            } else {
+                self.command_history.add_commands(commands);
+            }
+            self.edit_step_started = self.edit_selections.is_some();
+            self.command_history.set_selections_before(selections_before);
+        }
+
+        let primary = regions.remove(primary_index);
+        self.secondary_selections = regions;
+        self.set_primary_region(primary);
+        self.merge_selections();
+        Some(results)
+    }
+
+    /// Range of the word touching `position`, empty if there is none
+    fn word_range_at(&self, position: usize) -> (usize, usize) {
+        let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+        let mut start = position;
+        while start > 0 && is_word_char(self.content.char(start - 1)) {
+            start -= 1;
+        }
+        let mut end = position;
+        while end < self.content.len_chars() && is_word_char(self.content.char(end)) {
+            end += 1;
+        }
+        (start, end)
+    }
+
+    /// Text of every non-empty selection in document order, one per line
+    fn joined_selection_text(&self) -> String {
+        self.selection_regions()
+            .iter()
+            .filter(|region| !region.is_empty())
+            .map(|region| {
+                let (start, end) = region.range();
+                self.safe_slice(start, end)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Lists and blockquotes
+
+    /// Indent the list items on the selected lines one level, nesting them
+    /// under the item above, as one undo step. Outside a list this types a
+    /// tab, or indents the selected lines by four spaces.
+    pub fn indent_lines(&mut self) -> bool {
+        self.shift_lines(true)
+    }
+
+    /// Move the list items on the selected lines out to their parent's level
+    pub fn outdent_lines(&mut self) -> bool {
+        self.shift_lines(false)
+    }
+
+    fn shift_lines(&mut self, indent: bool) -> bool {
+        if let Some(results) = self.for_each_selection(|document| document.shift_lines(indent)) {
+            return results.contains(&true);
+        }
+
+        let region = self.primary_region();
+        let (first, last) = self.selected_lines();
+        let area = self.list_region(first, last);
+        let old_lines: Vec<String> = area.clone().map(|line| self.line_text(line)).collect();
+        // Items take the lines nested under them along
+        let selected_end = (first - area.start..=last - area.start)
+            .filter(|&index| LinePrefix::parse(&old_lines[index]).marker.is_some())
+            .map(|index| lists::item_end(&old_lines, index))
+            .fold(last - area.start + 1, usize::max);
+        let selected = first - area.start..selected_end;
+
+        let first_item = selected.clone().find(|&index| LinePrefix::parse(&old_lines[index]).marker.is_some());
+        if first_item.is_none() && indent && region.is_empty() {
+            self.insert_char('\t');
+            return true;
+        }
+        let shift = lists::shift_width(&old_lines, first_item.unwrap_or(first - area.start), indent);
+        if shift == 0 {
+            return false;
+        }
+
+        let mut lines = old_lines.clone();
+        let mut moved = Vec::new();
+        for index in selected {
+            if lists::is_blank(&lines[index]) {
+                continue;
+            }
+            let width = LinePrefix::parse(&lines[index]).indent_width();
+            lines[index] = lists::with_indent(&lines[index], width.saturating_add_signed(shift));
+            moved.push(index);
+        }
+        lists::renumber(&mut lines, &moved);
+
+        self.replace_lines_keeping_selection(if indent { "Indent" } else { "Outdent" }, area, &old_lines, &lines);
+        true
+    }
+
+    /// First and last line of the selection, leaving out the line a selection
+    /// ends at the very start of
+    fn selected_lines(&self) -> (usize, usize) {
+        let (start, end) = self.primary_region().range();
+        let first = self.content.char_to_line(start);
+        let last = self.content.char_to_line(end);
+        if last > first && end == self.content.line_to_char(last) {
+            (first, last - 1)
+        } else {
+            (first, last)
+        }
+    }
+
+    /// Enter after a list marker or blockquote prefix: start the next item
+    /// with the same prefix, renumbering the ordered items after it, or end
+    /// the list if this item is empty. Returns false when the cursor isn't
+    /// after such a prefix, for a plain newline.
+    fn continue_list(&mut self) -> bool {
+        let (line, column) = self.get_cursor_line_and_column();
+        let text = self.line_text(line);
+        let prefix = LinePrefix::parse(&text);
+        if (prefix.quote.is_empty() && prefix.marker.is_none()) || column < prefix.len() {
+            return false;
+        }
+        // Lines in code only look like list items
+        if self.in_code_block(&self.parse_ast(), self.cursor.position()) {
+            return false;
+        }
+
+        let area = self.list_region(line, line);
+        let mut lines: Vec<String> = area.clone().map(|line| self.line_text(line)).collect();
+        let index = line - area.start;
+
+        if text[prefix.len()..].trim().is_empty() {
+            // An empty nested item moves out a level, anything else leaves
+            // its innermost list or quote
+            if prefix.marker.is_some() && !prefix.indent.is_empty() {
+                return self.outdent_lines();
+            }
+            lines[index] = prefix.outer().to_string();
+            let end = lines[index].len();
+            self.replace_lines("End list", area, &lines);
+            self.cursor.set_position(self.content.line_to_char(line) + end);
+            return true;
+        }
+
+        let split = text.char_indices().nth(column).map_or(text.len(), |(offset, _)| offset);
+        let rest = text[split..].trim_start_matches([' ', '\t']);
+        lines[index] = text[..split].to_string();
+        lines.insert(index + 1, format!("{}{}", prefix.next_item(), rest));
+        lists::renumber(&mut lines, &[index + 1]);
+
+        let new_prefix = LinePrefix::parse(&lines[index + 1]).len();
+        self.replace_lines("Continue list", area, &lines);
+        self.cursor.set_position(self.content.line_to_char(line + 1) + new_prefix);
+        true
+    }
+
+    /// Text of a line without its line ending
+    fn line_text(&self, line: usize) -> String {
+        let mut text = self.content.line(line).to_string();
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        text
+    }
+
+    /// The lines around `first..=last` that a list on them could span: up to
+    /// a change in quote depth or two blank lines in a row
+    fn list_region(&self, first: usize, last: usize) -> Range<usize> {
+        let line_count = self.content.len_lines();
+        let depth = LinePrefix::parse(&self.line_text(first)).quote_depth();
+        let belongs = |line: usize| LinePrefix::parse(&self.line_text(line)).quote_depth() == depth;
+        let is_text = |line: usize| belongs(line) && !lists::is_blank(&self.line_text(line));
+        // A blank line only belongs if there is more text past it
+        let joins = |line: usize, beyond: Option<usize>| {
+            is_text(line) || (belongs(line) && beyond.is_some_and(|beyond| beyond < line_count && is_text(beyond)))
+        };
+
+        let mut start = first;
+        while start > 0 && joins(start - 1, (start - 1).checked_sub(1)) {
+            start -= 1;
+        }
+        let mut end = last + 1;
+        while end < line_count && joins(end, Some(end + 1)) {
+            end += 1;
+        }
+        start..end
+    }
+
+    /// `replace_lines` for edits to the starts of lines, such as indentation
+    /// or markers, keeping the cursor and selection on the same text
+    fn replace_lines_keeping_selection(&mut self, description: &str, area: Range<usize>, old_lines: &[String], lines: &[String]) {
+        let place = |position: usize| {
+            let line = self.content.char_to_line(position);
+            let column = position - self.content.line_to_char(line);
+            if !area.contains(&line) {
+                return (line, column);
+            }
+            let (old_line, new_line) = (&old_lines[line - area.start], &lines[line - area.start]);
+            let unchanged = old_line.chars().zip(new_line.chars()).take_while(|(a, b)| a == b).count();
+            if column <= unchanged {
+                return (line, column);
+            }
+            let change = new_line.chars().count() as isize - old_line.chars().count() as isize;
+            (line, column.saturating_add_signed(change).max(unchanged))
+        };
+        let region = self.primary_region();
+        let (anchor, head) = (region.anchor.map(place), place(region.head));
+
+        self.replace_lines(description, area, lines);
+        let position = |(line, column): (usize, usize)| self.content.line_to_char(line) + column;
+        self.set_primary_region(SelectionRegion {
+            anchor: anchor.map(position),
+            head: position(head),
+        });
+    }
+
+    /// Replace the text of the lines in `range` with `lines` as one undo step
+    fn replace_lines(&mut self, description: &str, range: Range<usize>, lines: &[String]) {
+        let start = self.content.line_to_char(range.start);
+        let end = if range.end < self.content.len_lines() {
+            self.content.line_to_char(range.end) - 1
+        } else {
+            self.content.len_chars()
+        };
+        let old_text = self.safe_slice(start, end);
+        let new_text = lines.join("\n");
+        if old_text != new_text {
+            self.apply_edit(description, vec![replace_changed_span(start, &old_text, &new_text)]);
+        }
+    }
+
+    // Tables
+
+    /// Tab in a table: line the table up and move to the end of the next
+    /// cell, adding a row after the last one. Returns false outside a table.
+    pub fn next_table_cell(&mut self) -> bool {
+        self.edit_table_at_cursor("Next cell", |table, row, column, _| {
+            if column + 1 < table.columns() {
+                Some((row, column + 1, None))
+            } else {
+                if row + 1 == table.rows.len() {
+                    table.insert_row(row + 1);
+                }
+                Some((row + 1, 0, None))
+            }
+        })
+    }
+
+    /// Shift+Tab in a table: line the table up and move to the previous cell
+    pub fn previous_table_cell(&mut self) -> bool {
+        self.edit_table_at_cursor("Previous cell", |table, row, column, _| match (row, column) {
+            (0, 0) => Some((0, 0, None)),
+            (row, 0) => Some((row - 1, table.columns() - 1, None)),
+            (row, column) => Some((row, column - 1, None)),
+        })
+    }
+
+    /// Apply `edit` to the table around the cursor. The cursor moves into a
+    /// new row or column, follows a moved column and otherwise stays in its
+    /// cell. Returns false if the cursor isn't in a table or the edit isn't
+    /// possible there.
+    pub fn edit_table(&mut self, edit: TableEdit) -> bool {
+        match edit {
+            TableEdit::NextCell => return self.next_table_cell(),
+            TableEdit::PreviousCell => return self.previous_table_cell(),
+            _ => {}
+        }
+        self.edit_table_at_cursor("Edit table", |table, row, column, offset| {
+            let offset = Some(offset);
+            match edit {
+                TableEdit::NextCell | TableEdit::PreviousCell => None,
+                TableEdit::InsertRowAbove => {
+                    // A row above the header goes below it instead
+                    table.insert_row(row.max(1));
+                    Some((row.max(1), column, None))
+                }
+                TableEdit::InsertRowBelow => {
+                    table.insert_row(row + 1);
+                    Some((row + 1, column, None))
+                }
+                TableEdit::DeleteRow => table
+                    .delete_row(row)
+                    .then(|| (row.min(table.rows.len() - 1), column, Some(0))),
+                TableEdit::InsertColumnLeft => {
+                    table.insert_column(column);
+                    Some((row, column, None))
+                }
+                TableEdit::InsertColumnRight => {
+                    table.insert_column(column + 1);
+                    Some((row, column + 1, None))
+                }
+                TableEdit::DeleteColumn => table
+                    .delete_column(column)
+                    .then(|| (row, column.min(table.columns() - 1), Some(0))),
+                TableEdit::MoveColumnLeft => {
+                    (column > 0 && table.swap_columns(column - 1)).then(|| (row, column - 1, offset))
+                }
+                TableEdit::MoveColumnRight => table.swap_columns(column).then(|| (row, column + 1, offset)),
+                TableEdit::AlignColumn(alignment) => {
+                    table.alignments[column] = match alignment {
+                        ColumnAlignment::None => Alignment::None,
+                        ColumnAlignment::Left => Alignment::Left,
+                        ColumnAlignment::Center => Alignment::Center,
+                        ColumnAlignment::Right => Alignment::Right,
+                    };
+                    Some((row, column, offset))
+                }
+            }
+        })
+    }
+
+    /// Run `edit` on the table around the cursor with the cursor's row (0 is
+    /// the header), column and offset into the cell's text. `edit` returns
+    /// the cell to put the cursor in, at an offset or at the end of its text.
+    /// The table is rewritten with its pipes lined up as one undo step.
+    fn edit_table_at_cursor(
+        &mut self,
+        description: &str,
+        edit: impl FnOnce(&mut Table, usize, usize, usize) -> Option<(usize, usize, Option<usize>)>,
+    ) -> bool {
+        if self.has_multiple_cursors() {
+            return false;
+        }
+        let (line, column) = self.get_cursor_line_and_column();
+        let Some((area, mut table)) = self.table_at(line) else {
+            return false;
+        };
+
+        let text = self.line_text(line);
+        let cell = tables::cell_at(&text, column).min(table.columns() - 1);
+        let offset = tables::cell_ranges(&text)
+            .get(cell)
+            .map_or(0, |range| column.saturating_sub(range.start).min(range.len()));
+        // The delimiter row counts as part of the header
+        let row = (line - area.start).saturating_sub(1);
+        let Some((row, cell, offset)) = edit(&mut table, row, cell, offset) else {
+            return false;
+        };
+
+        let lines = table.render();
+        self.replace_lines(description, area.clone(), &lines);
+        let line_index = if row == 0 { 0 } else { row + 1 };
+        let range = tables::cell_ranges(&lines[line_index])[cell].clone();
+        let position = self.content.line_to_char(area.start + line_index)
+            + range.start
+            + offset.map_or(range.len(), |offset| offset.min(range.len()));
+        self.selection.clear();
+        self.cursor.set_position(position);
+        true
+    }
+
+    /// The lines of the table `line` is in, from its header to its last row
+    fn table_at(&self, line: usize) -> Option<(Range<usize>, Table)> {
+        if !tables::is_row(&self.line_text(line)) || self.line_in_code_block(&self.parse_ast(), line) {
+            return None;
+        }
+        let mut start = line;
+        while start > 0 && tables::is_row(&self.line_text(start - 1)) {
+            start -= 1;
+        }
+        let mut end = line + 1;
+        while end < self.content.len_lines() && tables::is_row(&self.line_text(end)) {
+            end += 1;
+        }
+
+        // The header is the line before the first delimiter row
+        let header = (start + 1..end).find(|&line| tables::is_delimiter_row(&self.line_text(line)))? - 1;
+        if line < header {
+            return None;
+        }
+        let lines: Vec<String> = (header..end).map(|line| self.line_text(line)).collect();
+        Table::parse(&lines).map(|table| (header..end, table))
+    }
+
+    /// Where the cursor is in the document's syntax tree and what's active,
+    /// for key bindings that depend on it
+    pub fn key_context(&self) -> KeyContext {
+        let ast = self.parse_ast();
+        let nodes = self.nodes_at(&ast, self.cursor.position());
+        let inside = |matches: fn(&NodeKind) -> bool| nodes.iter().any(|&node| matches(&ast.node(node).kind));
+
+        KeyContext {
+            in_table: inside(|kind| matches!(kind, NodeKind::Table(_))),
+            in_list: inside(|kind| matches!(kind, NodeKind::Item)),
+            in_code_block: inside(|kind| matches!(kind, NodeKind::CodeBlock(_))),
+            has_selection: self.has_selection(),
+            find_open: self.search.is_some(),
+        }
+    }
+
+    fn parse_ast(&self) -> MarkdownAst {
+        MarkdownParser::new().parse_ast(&self.content.to_string())
+    }
+
+    /// The nodes of `ast` that `position` is in. A position at the end of a
+    /// line is still in the block on that line.
+    fn nodes_at(&self, ast: &MarkdownAst, position: usize) -> Vec<NodeId> {
+        let mut nodes = ast.path_at(self.content.char_to_byte(position));
+        if position > 0 && matches!(self.content.get_char(position), None | Some('\n')) {
+            nodes.extend(ast.path_at(self.content.char_to_byte(position - 1)));
+        }
+        nodes
+    }
+
+    /// Whether `position` is in a fenced or indented code block, where
+    /// markdown syntax is just text
+    fn in_code_block(&self, ast: &MarkdownAst, position: usize) -> bool {
+        self.nodes_at(ast, position)
+            .iter()
+            .any(|&node| matches!(ast.node(node).kind, NodeKind::CodeBlock(_)))
+    }
+
+    /// Whether the text on `line` is in a code block
+    fn line_in_code_block(&self, ast: &MarkdownAst, line: usize) -> bool {
+        let indent = self.line_text(line).chars().take_while(|ch| ch.is_whitespace()).count();
+        self.in_code_block(ast, self.content.line_to_char(line) + indent)
+    }
+
+    // Find and replace
+    /// Start searching for `pattern`, returning the number of matches
+    pub fn set_search(&mut self, pattern: &str, options: SearchOptions) -> Result<usize, regex::Error> {
+        let query = SearchQuery::new(pattern, options)?;
+        self.set_search_query(Some(query));
+        Ok(self.search_matches().len())
+    }
+
+    pub fn clear_search(&mut self) {
+        self.set_search_query(None);
+    }
+
+    fn set_search_query(&mut self, query: Option<SearchQuery>) {
+        self.search = query;
+        self.search_matches = None;
+    }
+
+    pub fn search_query(&self) -> Option<&SearchQuery> {
+        self.search.as_ref()
+    }
+
+    /// All matches of the active search, for highlighting. They're found
+    /// again only when the document has changed since the last call.
+    pub fn search_matches(&mut self) -> &[SearchMatch] {
+        let Some(query) = &self.search else {
+            return &[];
+        };
+        let version = self.version;
+        if !matches!(&self.search_matches, Some((found_at, _)) if *found_at == version) {
+            self.search_matches = Some((version, query.find_all(&self.content)));
+        }
+        self.search_matches.as_ref().map(|(_, matches)| matches.as_slice()).unwrap_or_default()
+    }
+
+    /// The match that is currently selected, if the selection covers exactly one match
+    pub fn current_search_match(&mut self) -> Option<SearchMatch> {
+        let (start, end) = self.selection_range()?;
+        self.search_matches()
+            .iter()
+            .find(|m| m.start == start && m.end == end)
+            .cloned()
+    }
+
+    /// Select the next match after the cursor, wrapping around at the end of the document
+    pub fn find_next(&mut self) -> bool {
+        let from = self.cursor.position();
+        let found = self.search.as_ref().and_then(|query| query.find_next(&self.content, from));
+        self.select_search_match(found)
+    }
+
+    /// Select the previous match before the cursor or selection, wrapping around at the start
+    pub fn find_previous(&mut self) -> bool {
+        let from = self.selection_range()
+            .map(|(start, _)| start)
+            .unwrap_or_else(|| self.cursor.position());
+        let found = self.search.as_ref().and_then(|query| query.find_previous(&self.content, from));
+        self.select_search_match(found)
+    }
+
+    fn select_search_match(&mut self, found: Option<SearchMatch>) -> bool {
+        match found {
+            Some(found) => {
+                self.secondary_selections.clear();
+                self.selection.start(found.start);
+                self.cursor.set_position(found.end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the selected match and select the next one. If no match is
+    /// selected, only the next match gets selected and nothing is replaced.
+    pub fn replace_current(&mut self, replacement: &str) -> bool {
+        let (Some(query), Some(current)) = (self.search.clone(), self.current_search_match()) else {
+            self.find_next();
+            return false;
+        };
+
+        let old_text = self.safe_slice(current.start, current.end);
+        let new_text = query.replacement_for(&self.content, &current, replacement);
+        let new_end = current.start + new_text.chars().count();
+        let command: Box<dyn UndoableCommand> = Box::new(ReplaceCommand::new(current.start, current.end, old_text, new_text));
+        self.apply_transaction("Replace", vec![command]);
+
+        self.selection.clear();
+        self.cursor.set_position(new_end);
+        self.find_next();
+        true
+    }
+
+    /// Replace every match as a single undoable transaction, returning the number of replacements
+    pub fn replace_all(&mut self, replacement: &str) -> usize {
+        let Some(query) = self.search.as_ref() else {
+            return 0;
+        };
+
+        // Apply back to front so earlier match positions stay valid
+        let commands: Vec<Box<dyn UndoableCommand>> = query
+            .replacements(&self.content, replacement)
+            .into_iter()
+            .rev()
+            .map(|(found, new_text)| {
+                let old_text = self.safe_slice(found.start, found.end);
+                Box::new(ReplaceCommand::new(found.start, found.end, old_text, new_text)) as Box<dyn UndoableCommand>
+            })
+            .collect();
+
+        let replaced = commands.len();
+        if replaced > 0 {
+            self.apply_transaction("Replace all", commands);
+            self.selection.clear();
+            let max_position = self.content.len_chars();
+            self.cursor.set_position(self.cursor.position().min(max_position));
+        }
+        replaced
+    }
+
+    // Incremental search
+
+    /// Start a search typed a character at a time from the cursor or, while
+    /// one is being typed, go on to its next match in `direction` (Emacs C-s
+    /// and C-r)
+    pub fn incremental_search(&mut self, direction: SearchDirection) -> bool {
+        match &mut self.incremental_search {
+            Some(search) => {
+                search.direction = direction;
+                self.show_incremental_match(true)
+            }
+            None => {
+                self.incremental_search = Some(IncrementalSearch::new(direction, self.cursor.position()));
+                self.clear_secondary_selections();
+                self.selection.clear();
+                true
+            }
+        }
+    }
+
+    pub fn incremental_search_state(&self) -> Option<&IncrementalSearch> {
+        self.incremental_search.as_ref()
+    }
+
+    /// Add typed text to the incremental search and select its first match
+    pub fn extend_incremental_search(&mut self, text: &str) -> bool {
+        let Some(search) = &mut self.incremental_search else {
+            return false;
+        };
+        search.text.push_str(text);
+        self.show_incremental_match(false)
+    }
+
+    /// Take the last character back out of the incremental search
+    pub fn backspace_incremental_search(&mut self) -> bool {
+        let Some(search) = &mut self.incremental_search else {
+            return false;
+        };
+        if search.text.pop().is_none() {
+            return false;
+        }
+        // The shorter text can match before the current match, so look again
+        // from where the search started
+        let origin = search.origin;
+        self.selection.clear();
+        self.cursor.set_position(origin);
+        self.show_incremental_match(false);
+        true
+    }
+
+    /// End the incremental search, leaving the cursor at its match
+    pub fn finish_incremental_search(&mut self) {
+        if self.incremental_search.take().is_some() {
+            self.set_search_query(None);
+            self.selection.clear();
+        }
+    }
+
+    /// End the incremental search and go back to where it started
+    pub fn cancel_incremental_search(&mut self) {
+        if let Some(search) = self.incremental_search.take() {
+            self.set_search_query(None);
+            self.selection.clear();
+            self.cursor.set_position(search.origin);
+        }
+    }
+
+    /// Select the incremental search's match nearest the selected match, or
+    /// where the search started if none is: at or after it going forward, at
+    /// or before it going back, wrapping around the ends of the document.
+    /// `skip_current` moves on past the selected match.
+    fn show_incremental_match(&mut self, skip_current: bool) -> bool {
+        let selected_start = self.selection_range().map(|(start, _)| start);
+        let Some(search) = &mut self.incremental_search else {
+            return false;
+        };
+        if search.text.is_empty() {
+            search.failing = false;
+            let origin = search.origin;
+            self.set_search_query(None);
+            self.selection.clear();
+            self.cursor.set_position(origin);
+            return false;
+        }
+        let Ok(query) = SearchQuery::new(&search.text, search.options()) else {
+            return false;
+        };
+
+        let from = selected_start.unwrap_or(search.origin);
+        let matches = query.find_all(&self.content);
+        let found = match search.direction {
+            SearchDirection::Forward => matches
+                .iter()
+                .find(|m| m.start > from || (m.start == from && !skip_current))
+                .or(matches.first()),
+            SearchDirection::Backward => matches
+                .iter()
+                .rev()
+                .find(|m| m.start < from || (m.start == from && !skip_current))
+                .or(matches.last()),
+        }
+        .cloned();
+        search.failing = found.is_none();
+        let direction = search.direction;
+        self.set_search_query(Some(query));
+        self.search_matches = Some((self.version, matches));
+
+        // The cursor goes to the end of the match the search moves towards
+        let Some(found) = found else {
+            return false;
+        };
+        let (anchor, head) = match direction {
+            SearchDirection::Forward => (found.start, found.end),
+            SearchDirection::Backward => (found.end, found.start),
+        };
+        self.selection.start(anchor);
+        self.cursor.set_position(head);
+        true
+    }
+
+    /// Execute commands in order and record them as one undo step
+    fn apply_transaction(&mut self, description: &str, commands: Vec<Box<dyn UndoableCommand>>) {
+        let mut transaction = CommandTransaction::new(description.to_string());
+        transaction.set_selections_before(self.edit_selections_before());
+        for command in commands {
+            self.content = command.execute(&self.content);
+            self.record_edit(command.as_ref());
+            transaction.add_command(command);
+        }
+        self.command_history.record_transaction(transaction);
+        self.clamp_secondary_selections();
+        self.increment_version();
+    }
+
+    /// Apply `commands` as their own undo step, or as part of the edit being
+    /// applied to every selection
+    fn apply_edit(&mut self, description: &str, commands: Vec<Box<dyn UndoableCommand>>) {
+        if self.pending_commands.is_some() {
+            for command in commands {
+                self.execute_command_in_transaction(command);
+            }
+        } else {
+            self.apply_transaction(description, commands);
+        }
+    }
+
+    /// Collect every edit until `end_undo_group` into one undo step, such as
+    /// a vim change and the text typed after it. While the group is open,
+    /// edits only apply at the primary selection.
+    pub fn begin_undo_group(&mut self, description: &str) {
+        if self.pending_commands.is_some() {
+            return;
+        }
+        self.command_history.finish_current_transaction();
+        self.pending_commands = Some(Vec::new());
+        let mut transaction = CommandTransaction::new(description.to_string());
+        transaction.set_selections_before(self.edit_selections_before());
+        self.undo_group = Some(transaction);
+    }
+
+    /// Record the edits made since `begin_undo_group` as one undo step
+    pub fn end_undo_group(&mut self) {
+        let Some(mut transaction) = self.undo_group.take() else {
+            return;
+        };
+        for command in self.pending_commands.take().unwrap_or_default() {
+            transaction.add_command(command);
+        }
+        transaction.set_selections_after(self.selection_snapshot());
+        self.command_history.record_transaction(transaction);
+    }
+
+    /// Every cursor and selection as they are now
+    fn selection_snapshot(&self) -> SelectionSnapshot {
+        SelectionSnapshot {
+            primary: self.primary_region(),
+            secondary: self.secondary_selections.clone(),
+        }
+    }
+
+    /// The cursors and selections to undo an edit back to: the ones from
+    /// before the action making it, or else the current ones
+    fn edit_selections_before(&self) -> SelectionSnapshot {
+        self.edit_selections.clone().unwrap_or_else(|| self.selection_snapshot())
+    }
+
+    /// Run `edit`, keeping the cursors and selections from before and after
+    /// it with the undo step its edits go into
+    fn with_selections_recorded<R>(&mut self, edit: impl FnOnce(&mut Self) -> R) -> R {
+        if self.edit_selections.is_some() {
+            return edit(self);
+        }
+        let version = self.version;
+        self.edit_selections = Some(self.selection_snapshot());
+        let result = edit(self);
+        self.edit_selections = None;
+        self.edit_step_started = false;
+
+        // An open undo group keeps its selections when it ends
+        if self.version != version && self.undo_group.is_none() {
+            self.command_history.set_selections_after(self.selection_snapshot());
+        }
+        result
+    }
+
+    /// Put back cursors and selections kept with an undo step, or keep the
+    /// current ones inside the document if none were kept
+    fn restore_selections(&mut self, selections: Option<SelectionSnapshot>) {
+        let max_position = self.content.len_chars();
+        match selections {
+            Some(selections) => {
+                let clamp = |region: SelectionRegion| SelectionRegion {
+                    anchor: region.anchor.map(|anchor| anchor.min(max_position)),
+                    head: region.head.min(max_position),
+                };
+                self.set_primary_region(clamp(selections.primary));
+                self.secondary_selections = selections.secondary;
+            }
+            None => {
+                // Ensure cursor is within bounds after content change
+                let current_position = self.cursor.position();
+                if current_position > max_position {
+                    self.cursor.set_position(max_position);
+                }
+
+                // Clear selection if it extends beyond the new content
+                if let Some((start, end)) = self.selection.range(current_position) {
+                    if start > max_position || end > max_position {
+                        self.selection.clear();
+                    }
+                }
+            }
+        }
+        self.clamp_secondary_selections();
+    }
+
+    /// Perform undo operation
+    pub fn perform_undo(&mut self) -> bool {
+        self.end_undo_group();
+        if let Some(new_content) = self.command_history.undo(&self.content) {
+            self.content = new_content;
+            if let (Some(recorded), Some(transaction)) =
+                (&mut self.recorded_edits, self.command_history.last_undone())
+            {
+                recorded.extend(transaction.commands().iter().rev().map(|command| command.to_record().inverse()));
+            }
+
+            let selections = self.command_history.last_undone().and_then(|transaction| transaction.selections_before().cloned());
+            self.restore_selections(selections);
+
+            // Increment version to invalidate visual line caches
+            self.increment_version();
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Perform redo operation
+    pub fn perform_redo(&mut self) -> bool {
+        self.end_undo_group();
+        self.command_history.finish_current_transaction();
+        match self.command_history.redo_node() {
+            Some(child) => self.redo_branch(child),
+            None => false,
+        }
+    }
+
+    /// Redo the step to `child`, one of the undo tree nodes after the
+    /// current one
+    fn redo_branch(&mut self, child: usize) -> bool {
+        if let Some(new_content) = self.command_history.redo_branch(child, &self.content) {
+            self.content = new_content;
+            if let (Some(recorded), Some(transaction)) =
+                (&mut self.recorded_edits, self.command_history.last_done())
+            {
+                recorded.extend(transaction.commands().iter().map(|command| command.to_record()));
+            }
+
+            let selections = self.command_history.last_done().and_then(|transaction| transaction.selections_after().cloned());
+            self.restore_selections(selections);
+
+            // Increment version to invalidate visual line caches
+            self.increment_version();
+
+            true
         } else {
             false
         }
     }
 
-    /// Perform redo operation
-    pub fn perform_redo(&mut self) -> bool {
-        if let Some(new_content) = self.command_history.redo(&self.content) {
-            self.content = new_content;
+    /// Undo and redo to the state of undo tree node `id`, on any branch.
+    /// Returns false if there's no such node.
+    pub fn go_to_undo_node(&mut self, id: usize) -> bool {
+        self.end_undo_group();
+        self.command_history.finish_current_transaction();
+        let Some(moves) = self.command_history.path_to(id) else {
+            return false;
+        };
+        for step in moves {
+            let moved = match step {
+                UndoMove::Undo => self.perform_undo(),
+                UndoMove::Redo(child) => self.redo_branch(child),
+            };
+            if !moved {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Go to the state made before the current one, whichever branch it's
+    /// on (vim's `g-`)
+    pub fn go_to_older_state(&mut self) -> bool {
+        self.end_undo_group();
+        self.command_history.finish_current_transaction();
+        self.command_history.older_node().is_some_and(|id| self.go_to_undo_node(id))
+    }
 
-            // Ensure cursor is within bounds after content change
-            let current_position = self.cursor.position();
-            let max_position = self.content.len_chars();
-            if current_position > max_position {
-                self.cursor.set_position(max_position);
-            }
+    /// Go to the state made after the current one, whichever branch it's
+    /// on (vim's `g+`)
+    pub fn go_to_newer_state(&mut self) -> bool {
+        self.end_undo_group();
+        self.command_history.finish_current_transaction();
+        self.command_history.newer_node().is_some_and(|id| self.go_to_undo_node(id))
+    }
 
-            // Clear selection if it extends beyond the new content
-            if let Some((start, end)) = self.selection.range(current_position) {
-                if start > max_position || end > max_position {
-                    self.selection.clear();
-                }
-            }
+    /// The undo tree node the document is at
+    pub fn current_undo_node(&self) -> usize {
+        self.command_history.current_node()
+    }
 
-            // Increment version to invalidate visual line caches
-            self.increment_version();
+    /// Every branch of the undo tree, including ones left by undoing and
+    /// then editing
+    pub fn undo_branches(&self) -> Vec<UndoBranch> {
+        self.command_history.branches()
+    }
 
-            true
-        } else {
-            false
-        }
+    /// The undo history as plain data, for saving alongside the file. Edits
+    /// still being grouped are finished first, and the oldest steps are left
+    /// out until it fits in about `max_bytes`.
+    pub fn undo_history(&mut self, max_bytes: usize) -> HistoryRecord {
+        self.end_undo_group();
+        self.command_history.finish_current_transaction();
+        self.command_history.to_record(max_bytes)
+    }
+
+    /// Bring back an undo history saved when the document had its current
+    /// content. Returns false, keeping the history as it is, if the saved
+    /// steps don't fit the content.
+    pub fn restore_undo_history(&mut self, record: HistoryRecord) -> bool {
+        self.command_history.restore(record, &self.content)
     }
 
     /// Execute a command and add it to the history
     pub fn execute_command(&mut self, command: Box<dyn UndoableCommand>) -> bool {
         // Execute the command
         let new_content = command.execute(&self.content);
+        self.record_edit(command.as_ref());
 
         // For direct execute_command calls, ensure each is individually undoable
         // by finishing current transaction and starting a new one
         self.command_history.finish_current_transaction();
         self.command_history.add_command(command);
+        self.command_history.set_selections_before(self.edit_selections_before());
         self.command_history.finish_current_transaction();
 
         // Apply the new content
         self.content = new_content;
+        self.increment_version();
         true
     }
 
@@ -1355,9 +3067,23 @@ impl TextDocument {
     fn execute_command_in_transaction(&mut self, command: Box<dyn UndoableCommand>) -> bool {
         // Execute the command
         let new_content = command.execute(&self.content);
-
-        // Add to current transaction (will be grouped with other operations)
-        self.command_history.add_command(command);
+        self.record_edit(command.as_ref());
+
+        // Add to the undo step the grouping policy picks, or the one this
+        // action already started, or hold it back while an edit is applied
+        // to every selection
+        match &mut self.pending_commands {
+            Some(pending) => pending.push(command),
+            None => {
+                if self.edit_step_started {
+                    self.command_history.add_command_to_current(command);
+                } else {
+                    self.command_history.add_command(command);
+                }
+                self.edit_step_started = self.edit_selections.is_some();
+                self.command_history.set_selections_before(self.edit_selections_before());
+            }
+        }
 
         // Apply the new content
         self.content = new_content;
@@ -1383,6 +3109,7 @@ impl TextDocument {
 mod tests {
     use super::*;
     use crate::input::actions::EditorAction;
+    use crate::input::InputRouter;
 
     #[test]
     fn test_text_document_creation() {
@@ -2051,4 +3778,842 @@ mod tests {
         let affected = doc.get_affected_lines_for_replacement(10, 30, "Single replacement");
         assert!(affected.len() >= 2); // Should affect multiple lines
     }
+
+    #[test]
+    fn test_find_next_selects_matches_in_order() {
+        let mut doc = TextDocument::with_content("foo bar foo baz foo".to_string());
+        doc.set_cursor_position(0);
+        assert_eq!(doc.set_search("foo", SearchOptions::default()).unwrap(), 3);
+
+        assert!(doc.find_next());
+        assert_eq!(doc.selection_range(), Some((0, 3)));
+        assert!(doc.find_next());
+        assert_eq!(doc.selection_range(), Some((8, 11)));
+        assert!(doc.find_next());
+        assert_eq!(doc.selection_range(), Some((16, 19)));
+
+        // Wraps around to the first match
+        assert!(doc.find_next());
+        assert_eq!(doc.selection_range(), Some((0, 3)));
+        assert_eq!(doc.current_search_match(), Some(SearchMatch { start: 0, end: 3 }));
+
+        // And backwards past the start
+        assert!(doc.find_previous());
+        assert_eq!(doc.selection_range(), Some((16, 19)));
+        assert!(doc.find_previous());
+        assert_eq!(doc.selection_range(), Some((8, 11)));
+    }
+
+    #[test]
+    fn test_find_without_search_does_nothing() {
+        let mut doc = TextDocument::with_content("foo".to_string());
+        assert!(!doc.find_next());
+        assert!(!doc.handle_action(EditorAction::FindPrevious));
+        assert!(doc.search_matches().is_empty());
+        assert_eq!(doc.replace_all("bar"), 0);
+    }
+
+    #[test]
+    fn test_replace_current_replaces_selected_match_and_moves_on() {
+        let mut doc = TextDocument::with_content("cat and cat".to_string());
+        doc.set_cursor_position(0);
+        doc.set_search("cat", SearchOptions::default()).unwrap();
+
+        // Nothing selected yet: only selects the first match
+        assert!(!doc.replace_current("dog"));
+        assert_eq!(doc.selection_range(), Some((0, 3)));
+
+        assert!(doc.replace_current("dog"));
+        assert_eq!(doc.content(), "dog and cat");
+        assert_eq!(doc.selection_range(), Some((8, 11)));
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "cat and cat");
+    }
+
+    #[test]
+    fn test_replace_all_is_a_single_undo_step() {
+        let mut doc = TextDocument::with_content("a1 b22 c333".to_string());
+        doc.insert_text("!");
+        let options = SearchOptions { regex: true, ..Default::default() };
+        doc.set_search(r"([a-z])(\d+)", options).unwrap();
+
+        let version = doc.version();
+        assert_eq!(doc.replace_all("$2$1"), 3);
+        assert_eq!(doc.content(), "1a 22b 333c!");
+        assert!(doc.version() > version);
+        assert!(doc.search_matches().is_empty());
+
+        // One undo restores every replacement but keeps the earlier edit
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "a1 b22 c333!");
+        assert!(doc.perform_redo());
+        assert_eq!(doc.content(), "1a 22b 333c!");
+    }
+
+    #[test]
+    fn test_replace_all_with_multibyte_text() {
+        let mut doc = TextDocument::with_content("héllo wörld, héllo".to_string());
+        doc.set_search("héllo", SearchOptions { whole_word: true, ..Default::default() }).unwrap();
+
+        assert!(doc.handle_action(EditorAction::ReplaceAll("hi".to_string())));
+        assert_eq!(doc.content(), "hi wörld, hi");
+        assert!(doc.cursor_position() <= doc.len());
+    }
+
+    #[test]
+    fn test_invalid_search_pattern_keeps_previous_search() {
+        let mut doc = TextDocument::with_content("foo (".to_string());
+        doc.set_search("foo", SearchOptions::default()).unwrap();
+
+        let options = SearchOptions { regex: true, ..Default::default() };
+        assert!(doc.set_search("(", options).is_err());
+        assert_eq!(doc.search_query().map(|q| q.pattern()), Some("foo"));
+    }
+
+    #[test]
+    fn test_search_matches_follow_edits() {
+        let mut doc = TextDocument::with_content("foo bar".to_string());
+        doc.set_search("foo", SearchOptions::default()).unwrap();
+        assert_eq!(doc.search_matches(), [SearchMatch { start: 0, end: 3 }]);
+
+        doc.set_cursor_position(7);
+        doc.insert_text(" foo");
+        assert_eq!(doc.search_matches().len(), 2);
+        assert!(doc.perform_undo());
+        assert_eq!(doc.search_matches().len(), 1);
+
+        doc.clear_search();
+        assert!(doc.search_matches().is_empty());
+    }
+
+    #[test]
+    fn test_add_cursor_below_edits_every_line() {
+        let mut doc = TextDocument::with_content("- a\n- bb\n- c".to_string());
+        doc.set_cursor_position(2);
+
+        assert!(doc.add_cursor_below());
+        assert!(doc.add_cursor_below());
+        assert!(!doc.add_cursor_below());
+        let heads: Vec<usize> = doc.selection_regions().iter().map(|region| region.head).collect();
+        assert_eq!(heads, vec![2, 6, 11]);
+
+        doc.insert_text("[ ] ");
+        assert_eq!(doc.content(), "- [ ] a\n- [ ] bb\n- [ ] c");
+        let heads: Vec<usize> = doc.selection_regions().iter().map(|region| region.head).collect();
+        assert_eq!(heads, vec![6, 14, 23]);
+
+        // All three insertions undo together
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "- a\n- bb\n- c");
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_add_cursor_above_clamps_column() {
+        let mut doc = TextDocument::with_content("ab\nlonger line".to_string());
+        doc.set_cursor_position(10);
+
+        assert!(doc.add_cursor_above());
+        assert_eq!(doc.secondary_selections(), &[SelectionRegion::cursor(2)]);
+        assert!(!doc.add_cursor_above());
+    }
+
+    #[test]
+    fn test_deletions_apply_at_every_cursor() {
+        let mut doc = TextDocument::with_content("one two\nthree four".to_string());
+        doc.set_cursor_position(7);
+        doc.add_cursor_at(18);
+
+        assert!(doc.backspace());
+        assert_eq!(doc.content(), "one tw\nthree fou");
+
+        doc.delete_previous_word();
+        assert_eq!(doc.content(), "one \nthree ");
+
+        // Both cursors' edits undo together
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "one two\nthree four");
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_add_next_occurrence() {
+        let mut doc = TextDocument::with_content("foo bar foo baz foo".to_string());
+        doc.set_cursor_position(1);
+
+        // The first call selects the word under the cursor
+        assert!(doc.add_next_occurrence());
+        assert_eq!(doc.selection_range(), Some((0, 3)));
+        assert!(doc.add_next_occurrence());
+        assert!(doc.add_next_occurrence());
+        assert_eq!(doc.selection_range(), Some((16, 19)));
+        assert!(!doc.add_next_occurrence());
+        assert_eq!(doc.selection_regions().len(), 3);
+
+        doc.insert_text("qux");
+        assert_eq!(doc.content(), "qux bar qux baz qux");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "foo bar foo baz foo");
+    }
+
+    #[test]
+    fn test_split_selection_into_lines_and_toggle_bold() {
+        let mut doc = TextDocument::with_content("one\ntwo\nthree".to_string());
+        doc.select_all();
+
+        assert!(doc.split_selection_into_lines());
+        let ranges: Vec<(usize, usize)> = doc.selection_regions().iter().map(|region| region.range()).collect();
+        assert_eq!(ranges, vec![(0, 3), (4, 7), (8, 13)]);
+
+        doc.toggle_bold();
+        assert_eq!(doc.content(), "**one**\n**two**\n**three**");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn test_movement_applies_to_every_cursor_and_merges() {
+        let mut doc = TextDocument::with_content("abc\ndef".to_string());
+        doc.set_cursor_position(1);
+        doc.add_cursor_at(5);
+
+        doc.handle_action(EditorAction::MoveCursor(Movement::Right));
+        let heads: Vec<usize> = doc.selection_regions().iter().map(|region| region.head).collect();
+        assert_eq!(heads, vec![2, 6]);
+
+        // Cursors that land on the same spot become one
+        doc.handle_action(EditorAction::MoveCursor(Movement::DocumentStart));
+        assert!(!doc.has_multiple_cursors());
+        assert_eq!(doc.cursor_position(), 0);
+
+        doc.add_cursor_at(4);
+        assert!(doc.handle_action(EditorAction::ClearSelection));
+        assert!(!doc.has_multiple_cursors());
+    }
+
+    #[test]
+    fn test_copy_and_cut_with_multiple_selections() {
+        let mut doc = TextDocument::with_content("foo = 1\nfoo = 2".to_string());
+        doc.set_cursor_position(0);
+        doc.add_next_occurrence();
+        doc.add_next_occurrence();
+
+        assert_eq!(doc.copy(), Some("foo\nfoo".to_string()));
+        assert_eq!(doc.cut(), Some("foo\nfoo".to_string()));
+        assert_eq!(doc.content(), " = 1\n = 2");
+    }
+
+    #[test]
+    fn test_word_deletion_is_undoable() {
+        let mut doc = TextDocument::with_content("hello world".to_string());
+        doc.delete_previous_word();
+        assert_eq!(doc.content(), "hello ");
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "hello world");
+    }
+
+    #[test]
+    fn test_load_content_resets_history() {
+        let mut doc = TextDocument::with_content("old".to_string());
+        doc.insert_text(" text");
+        doc.add_cursor_at(0);
+        let version = doc.version();
+
+        doc.load_content("new file".to_string());
+        assert_eq!(doc.content(), "new file");
+        assert_eq!(doc.cursor_position(), 0);
+        assert!(!doc.has_multiple_cursors());
+        assert!(!doc.can_undo());
+        assert!(doc.version() > version);
+    }
+
+    #[test]
+    fn test_apply_external_change_keeps_cursor_on_its_text() {
+        let mut doc = TextDocument::with_content("# Notes\n\n- milk\n- eggs\n".to_string());
+        let eggs = doc.content().find("eggs").unwrap();
+        doc.set_cursor_position(eggs + 2);
+        let version = doc.version();
+
+        doc.apply_external_change("# Groceries\n\n- bread\n- milk\n- eggs\n");
+        assert_eq!(doc.content(), "# Groceries\n\n- bread\n- milk\n- eggs\n");
+        let eggs = doc.content().find("eggs").unwrap();
+        assert_eq!(doc.cursor_position(), eggs + 2);
+        assert!(doc.version() > version);
+
+        // The reload is a single undo step
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "# Notes\n\n- milk\n- eggs\n");
+    }
+
+    #[test]
+    fn test_apply_external_change_maps_selections() {
+        let mut doc = TextDocument::with_content("one\ntwo\nthree".to_string());
+        doc.set_cursor_position(4);
+        doc.start_selection();
+        doc.set_cursor_position(7);
+        doc.add_cursor_at(10);
+
+        doc.apply_external_change("zero\none\ntwo\nthree");
+        assert_eq!(doc.selected_text(), Some("two".to_string()));
+        assert_eq!(doc.secondary_selections()[0].head, 15);
+    }
+
+    #[test]
+    fn test_format_document_is_one_undo_step() {
+        let original = "Shopping\n========\n\n* __milk__\n* eggs\n";
+        let mut doc = TextDocument::with_content(original.to_string());
+        let eggs = doc.content().find("ggs").unwrap();
+        doc.set_cursor_position(eggs);
+        doc.add_cursor_at(original.find("ilk").unwrap());
+
+        assert!(doc.format_document());
+        assert_eq!(doc.content(), "# Shopping\n\n- **milk**\n- eggs\n");
+        // Cursors stay inside the same words
+        let content = doc.content();
+        assert_eq!(&content[doc.cursor_position()..], "ggs\n");
+        assert_eq!(&content[doc.secondary_selections()[0].head..][..3], "ilk");
+        assert!(!doc.format_document());
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), original);
+    }
+
+    /// Put the cursor at `|` in `marked` and press Enter
+    fn press_enter(marked: &str) -> TextDocument {
+        let mut doc = TextDocument::with_content(marked.replace('|', ""));
+        doc.set_cursor_position(marked[..marked.find('|').unwrap()].chars().count());
+        doc.insert_char('\n');
+        doc
+    }
+
+    fn with_cursor(doc: &TextDocument) -> String {
+        let mut content: Vec<char> = doc.content().chars().collect();
+        content.insert(doc.cursor_position(), '|');
+        content.into_iter().collect()
+    }
+
+    #[test]
+    fn test_enter_continues_lists_and_quotes() {
+        assert_eq!(with_cursor(&press_enter("- one|\n- two")), "- one\n- |\n- two");
+        assert_eq!(with_cursor(&press_enter("* spl|it")), "* spl\n* |it");
+        assert_eq!(with_cursor(&press_enter("- [x] done|")), "- [x] done\n- [ ] |");
+        assert_eq!(with_cursor(&press_enter("> > quoted|")), "> > quoted\n> > |");
+        assert_eq!(with_cursor(&press_enter("> 1) in a quote|")), "> 1) in a quote\n> 2) |");
+        // Following items are renumbered, nested ones are left alone
+        assert_eq!(
+            with_cursor(&press_enter("1. one|\n2. two\n   1. nested\n3. three")),
+            "1. one\n2. |\n3. two\n   1. nested\n4. three"
+        );
+        assert_eq!(
+            with_cursor(&press_enter("8. eight\n9. nine|\n10. ten")),
+            "8. eight\n9. nine\n10. |\n11. ten"
+        );
+        // Before the marker, or outside a list, Enter is a plain newline
+        assert_eq!(with_cursor(&press_enter("|- item")), "\n|- item");
+        assert_eq!(with_cursor(&press_enter("  indented|")), "  indented\n|");
+    }
+
+    #[test]
+    fn test_enter_in_code_block_is_a_plain_newline() {
+        assert_eq!(with_cursor(&press_enter("```\n- item|\n```")), "```\n- item\n|\n```");
+        assert_eq!(with_cursor(&press_enter("```yaml\n> quoted|\n```\n")), "```yaml\n> quoted\n|\n```\n");
+        assert_eq!(with_cursor(&press_enter("    - code|")), "    - code\n|");
+        // A list right after the block still continues
+        assert_eq!(with_cursor(&press_enter("```\ncode\n```\n- item|")), "```\ncode\n```\n- item\n- |");
+    }
+
+    #[test]
+    fn test_enter_on_empty_item_ends_list() {
+        assert_eq!(with_cursor(&press_enter("- one\n- |")), "- one\n|");
+        assert_eq!(with_cursor(&press_enter("- one\n- [ ] |")), "- one\n|");
+        assert_eq!(with_cursor(&press_enter("> - one\n> - |")), "> - one\n> |");
+        assert_eq!(with_cursor(&press_enter("> > text\n> > |")), "> > text\n> |");
+        // An empty nested item moves out a level first
+        assert_eq!(with_cursor(&press_enter("1. one\n   - |")), "1. one\n- |");
+    }
+
+    #[test]
+    fn test_list_edits_are_single_undo_steps() {
+        let original = "1. one\n2. two\n3. three";
+        let mut doc = TextDocument::with_content(original.to_string());
+        doc.set_cursor_position(6);
+        doc.insert_char('\n');
+        assert_eq!(doc.content(), "1. one\n2. \n3. two\n4. three");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), original);
+
+        doc.set_cursor_position(10);
+        assert!(doc.handle_action(EditorAction::Indent));
+        assert_eq!(doc.content(), "1. one\n   1. two\n2. three");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), original);
+    }
+
+    #[test]
+    fn test_undo_group_is_one_step() {
+        let mut doc = TextDocument::with_content("one two".to_string());
+        doc.insert_text(" three");
+        doc.begin_undo_group("Change word");
+        doc.set_cursor_position(4);
+        doc.start_selection();
+        doc.set_cursor_position(7);
+        doc.delete_selection();
+        for ch in "2\n- item".chars() {
+            doc.insert_char(ch);
+        }
+        doc.end_undo_group();
+        assert_eq!(doc.content(), "one 2\n- item three");
+
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "one two three");
+        assert!(doc.perform_redo());
+        assert_eq!(doc.content(), "one 2\n- item three");
+    }
+
+    #[test]
+    fn test_tab_indents_and_outdents_list_items() {
+        let mut doc = TextDocument::with_content("- one\n- two\n  more text\n- three".to_string());
+        doc.set_cursor_position(9);
+        assert!(doc.indent_lines());
+        assert_eq!(doc.content(), "- one\n  - two\n    more text\n- three");
+        assert_eq!(&doc.content()[doc.cursor_position()..][..2], "wo");
+        assert!(doc.outdent_lines());
+        assert_eq!(doc.content(), "- one\n- two\n  more text\n- three");
+        assert!(!doc.outdent_lines());
+
+        // Every item in the selection moves, and the selection moves with them
+        let mut doc = TextDocument::with_content("1. a\n2. b\n3. c\n4. d".to_string());
+        doc.set_cursor_position(5);
+        doc.start_selection();
+        doc.set_cursor_position(14);
+        assert!(doc.indent_lines());
+        assert_eq!(doc.content(), "1. a\n   1. b\n   2. c\n2. d");
+        assert_eq!(doc.selected_text().unwrap(), "   1. b\n   2. c");
+
+        // Outside a list Tab still types a tab
+        let mut doc = TextDocument::with_content("text".to_string());
+        assert!(doc.indent_lines());
+        assert_eq!(doc.content(), "text\t");
+    }
+
+    fn with_selection(doc: &TextDocument) -> String {
+        let (start, end) = doc.primary_region().range();
+        let mut content: Vec<char> = doc.content().chars().collect();
+        content.insert(end, ']');
+        content.insert(start, '[');
+        content.into_iter().collect()
+    }
+
+    #[test]
+    fn test_toggle_inline_formats() {
+        let mut doc = TextDocument::with_content("some code here".to_string());
+        doc.set_cursor_position(5);
+        doc.start_selection();
+        doc.set_cursor_position(9);
+        doc.handle_action(EditorAction::ToggleFormat(FormatType::Code));
+        assert_eq!(with_selection(&doc), "some `[code]` here");
+        doc.handle_action(EditorAction::ToggleFormat(FormatType::Code));
+        assert_eq!(with_selection(&doc), "some [code] here");
+
+        // The cursor or a selection inside the markers is enough to remove them
+        let mut doc = TextDocument::with_content("a ~~struck~~ and ==marked== word".to_string());
+        doc.set_cursor_position(6);
+        doc.toggle_format(FormatType::Strikethrough);
+        assert_eq!(doc.content(), "a struck and ==marked== word");
+        assert_eq!(doc.cursor_position(), 4);
+        doc.set_cursor_position(17);
+        doc.start_selection();
+        doc.set_cursor_position(19);
+        doc.toggle_format(FormatType::Highlight);
+        assert_eq!(with_selection(&doc), "a struck and ma[rk]ed word");
+
+        // Bold and italic are told apart
+        let mut doc = TextDocument::with_content("**bold** and *it*".to_string());
+        doc.set_cursor_position(4);
+        doc.toggle_italic();
+        assert_eq!(doc.content(), "**bo**ld** and *it*");
+        assert!(doc.perform_undo());
+        doc.set_cursor_position(4);
+        doc.toggle_bold();
+        assert_eq!(doc.content(), "bold and *it*");
+    }
+
+    #[test]
+    fn test_toggle_line_formats() {
+        let mut doc = TextDocument::with_content("# Title\nfirst\nsecond".to_string());
+        doc.set_cursor_position(4);
+        doc.toggle_format(FormatType::Heading(3));
+        assert_eq!(doc.content(), "### Title\nfirst\nsecond");
+        assert_eq!(&doc.content()[doc.cursor_position()..][..3], "tle");
+
+        doc.set_cursor_position(10);
+        doc.start_selection();
+        doc.set_cursor_position(doc.len());
+        doc.toggle_format(FormatType::BulletList);
+        assert_eq!(doc.content(), "### Title\n- first\n- second");
+        assert_eq!(doc.selected_text().unwrap(), "- first\n- second");
+        doc.toggle_format(FormatType::TaskList);
+        assert_eq!(doc.content(), "### Title\n- [ ] first\n- [ ] second");
+        doc.toggle_format(FormatType::BlockQuote);
+        assert_eq!(doc.content(), "### Title\n> - [ ] first\n> - [ ] second");
+
+        // Each toggle is its own undo step
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "### Title\n- [ ] first\n- [ ] second");
+    }
+
+    #[test]
+    fn test_insert_link() {
+        let mut doc = TextDocument::with_content("see docs".to_string());
+        doc.set_cursor_position(4);
+        doc.start_selection();
+        doc.set_cursor_position(8);
+        doc.handle_action(EditorAction::InsertLink);
+        assert_eq!(with_cursor(&doc), "see [docs](|)");
+
+        let mut doc = TextDocument::with_content("https://example.com".to_string());
+        doc.select_all();
+        doc.insert_link();
+        assert_eq!(with_cursor(&doc), "[|](https://example.com)");
+    }
+
+    #[test]
+    fn test_toggle_tasks() {
+        let original = "- [ ] parent\n  - [x] one\n  - [ ] two\n- [ ] other";
+        let mut doc = TextDocument::with_content(original.to_string());
+        doc.set_cursor_position(original.find("two").unwrap());
+        assert!(doc.handle_action(EditorAction::ToggleTask));
+        assert_eq!(with_cursor(&doc), "- [x] parent\n  - [x] one\n  - [x] |two\n- [ ] other");
+
+        // The whole toggle, parent included, is one undo step
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), original);
+
+        // Every task in the selection is ticked, then cleared
+        doc.set_cursor_position(0);
+        doc.start_selection();
+        doc.set_cursor_position(doc.len());
+        assert!(doc.toggle_tasks());
+        assert_eq!(doc.content(), "- [x] parent\n  - [x] one\n  - [x] two\n- [x] other");
+        assert!(doc.toggle_tasks());
+        assert_eq!(doc.content(), "- [ ] parent\n  - [ ] one\n  - [ ] two\n- [ ] other");
+        assert_eq!(doc.selected_text().unwrap(), doc.content());
+
+        let mut doc = TextDocument::with_content("- plain item".to_string());
+        assert!(!doc.toggle_tasks());
+
+        // Tasks in code blocks are left as they are, by key or by click
+        let original = "- [ ] real
+
+```
+- [ ] x
+```
+
+    - [ ] indented";
+        let mut doc = TextDocument::with_content(original.to_string());
+        doc.set_cursor_position(original.find("x\n").unwrap());
+        assert!(!doc.toggle_tasks());
+        assert!(!doc.toggle_task_at(original.find("[ ] x").unwrap()));
+        assert!(!doc.toggle_task_at(original.find("[ ] indented").unwrap()));
+        doc.select_all();
+        assert!(doc.toggle_tasks());
+        assert_eq!(doc.content(), "- [x] real\n\n```\n- [ ] x\n```\n\n    - [ ] indented");
+    }
+
+    #[test]
+    fn test_toggle_task_at_position_keeps_cursor() {
+        let mut doc = TextDocument::with_content("- [ ] first\n- [ ] second".to_string());
+        doc.set_cursor_position(9);
+        assert!(doc.toggle_task_at(15));
+        assert_eq!(with_cursor(&doc), "- [ ] fir|st\n- [x] second");
+        assert!(!doc.toggle_task_at(20));
+        assert!(doc.toggle_task_at(2));
+        assert_eq!(with_cursor(&doc), "- [x] fir|st\n- [x] second");
+    }
+
+    #[test]
+    fn test_tab_moves_between_table_cells() {
+        // Tab and Shift+Tab go through the default keymap, which picks the
+        // table bindings from the cursor's context
+        let mut router = InputRouter::new();
+        let mut press = |doc: &mut TextDocument, key: &str| router.handle_key_binding(key.parse().unwrap(), doc);
+
+        let mut doc = TextDocument::with_content("Name|Qty\n-|-:\napple|3\n\nafter".to_string());
+        doc.set_cursor_position(2);
+        assert!(press(&mut doc, "tab"));
+        assert_eq!(with_cursor(&doc), "| Name  | Qty| |\n| ----- | --: |\n| apple |   3 |\n\nafter");
+        assert!(press(&mut doc, "tab"));
+        assert!(press(&mut doc, "tab"));
+        assert_eq!(with_cursor(&doc), "| Name  | Qty |\n| ----- | --: |\n| apple |   3| |\n\nafter");
+
+        // Tab in the last cell adds a row
+        assert!(press(&mut doc, "tab"));
+        assert_eq!(
+            with_cursor(&doc),
+            "| Name  | Qty |\n| ----- | --: |\n| apple |   3 |\n| |      |     |\n\nafter"
+        );
+        assert!(press(&mut doc, "shift-tab"));
+        assert!(press(&mut doc, "shift-tab"));
+        assert_eq!(
+            with_cursor(&doc),
+            "| Name  | Qty |\n| ----- | --: |\n| apple| |   3 |\n|       |     |\n\nafter"
+        );
+
+        // Lining the table up and adding the row are undo steps, moving isn't
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "| Name  | Qty |\n| ----- | --: |\n| apple |   3 |\n\nafter");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "Name|Qty\n-|-:\napple|3\n\nafter");
+
+        // Outside the table Tab still indents
+        doc.set_cursor_position(doc.len());
+        assert!(press(&mut doc, "tab"));
+        assert!(doc.content().ends_with("after\t"));
+    }
+
+    #[test]
+    fn test_tab_in_code_block_table_types_a_tab() {
+        let mut router = InputRouter::new();
+        let original = "```\n| a | b |\n|---|---|\n| 1| 2 |\n```";
+        let mut doc = TextDocument::with_content(original.to_string());
+        doc.set_cursor_position(original.find("1|").unwrap() + 1);
+        assert!(router.handle_key_binding("tab".parse().unwrap(), &mut doc));
+        assert_eq!(with_cursor(&doc), "```\n| a | b |\n|---|---|\n| 1\t|| 2 |\n```");
+        assert!(!doc.edit_table(TableEdit::InsertRowBelow));
+        assert!(!doc.next_table_cell());
+    }
+
+    #[test]
+    fn test_key_context_at_cursor() {
+        let content = "- item\n\n| a | b |\n|---|---|\n\n```\ncode\n```\n\ntext";
+        let mut doc = TextDocument::with_content(content.to_string());
+        let context_at = |doc: &mut TextDocument, position: usize| {
+            doc.set_cursor_position(position);
+            doc.key_context()
+        };
+
+        assert!(context_at(&mut doc, 3).in_list);
+        // The end of a line is still inside its block
+        assert!(context_at(&mut doc, 6).in_list);
+        assert!(context_at(&mut doc, 11).in_table);
+        assert!(context_at(&mut doc, content.find("code").unwrap()).in_code_block);
+        assert_eq!(context_at(&mut doc, content.len()), KeyContext::default());
+
+        doc.select_all();
+        doc.set_search("text", SearchOptions::default()).unwrap();
+        let context = doc.key_context();
+        assert!(context.has_selection && context.find_open);
+        assert!(doc.handle_action(EditorAction::CloseFind));
+        assert!(!doc.key_context().find_open);
+    }
+
+    #[test]
+    fn test_table_row_and_column_edits() {
+        let original = "| a | b |\n|---|---|\n| 1 | 2 |";
+        let mut doc = TextDocument::with_content(original.to_string());
+        doc.set_cursor_position(original.len() - 3);
+        assert!(doc.edit_table(TableEdit::InsertRowBelow));
+        assert_eq!(with_cursor(&doc), "| a   | b   |\n| --- | --- |\n| 1   | 2   |\n|     | |    |");
+        assert!(doc.edit_table(TableEdit::DeleteRow));
+        assert_eq!(with_cursor(&doc), "| a   | b   |\n| --- | --- |\n| 1   | |2   |");
+
+        assert!(doc.edit_table(TableEdit::InsertColumnLeft));
+        assert_eq!(with_cursor(&doc), "| a   |     | b   |\n| --- | --- | --- |\n| 1   | |    | 2   |");
+        assert!(doc.edit_table(TableEdit::DeleteColumn));
+        assert!(doc.edit_table(TableEdit::MoveColumnLeft));
+        assert_eq!(with_cursor(&doc), "| b   | a   |\n| --- | --- |\n| |2   | 1   |");
+        assert!(!doc.edit_table(TableEdit::MoveColumnLeft));
+        assert!(doc.edit_table(TableEdit::AlignColumn(ColumnAlignment::Center)));
+        assert_eq!(doc.content(), "|  b  | a   |\n| :-: | --- |\n|  2  | 1   |");
+
+        // The header row can't be deleted, and rows inserted above it go below it
+        doc.set_cursor_position(2);
+        assert!(!doc.edit_table(TableEdit::DeleteRow));
+        assert!(doc.edit_table(TableEdit::InsertRowAbove));
+        assert_eq!(
+            with_cursor(&doc),
+            "|  b  | a   |\n| :-: | --- |\n| |    |     |\n|  2  | 1   |"
+        );
+
+        // Every edit is one undo step
+        for _ in 0..6 {
+            assert!(doc.perform_undo());
+        }
+        assert_eq!(doc.content(), "| a   | b   |\n| --- | --- |\n| 1   | 2   |\n|     |     |");
+
+        // Outside a table there is nothing to edit
+        let mut doc = TextDocument::with_content("no table".to_string());
+        assert!(!doc.handle_action(EditorAction::EditTable(TableEdit::DeleteRow)));
+    }
+
+    #[test]
+    fn test_recorded_edits_replay_to_same_content() {
+        let original = "# Title\n\nSome text";
+        let mut doc = TextDocument::with_content(original.to_string());
+        doc.set_edit_recording(true);
+
+        doc.insert_text("!");
+        doc.set_cursor_position(2);
+        doc.delete_previous_word();
+        doc.select_all();
+        doc.toggle_bold();
+        doc.perform_undo();
+        doc.perform_redo();
+        doc.perform_undo();
+
+        let edits = doc.take_recorded_edits();
+        assert!(doc.take_recorded_edits().is_empty());
+
+        let mut replayed = TextDocument::with_content(original.to_string());
+        assert_eq!(replayed.replay_edits(&edits), edits.len());
+        assert_eq!(replayed.content(), doc.content());
+
+        // The replay is one undo step back to the original
+        assert!(replayed.perform_undo());
+        assert_eq!(replayed.content(), original);
+    }
+
+    #[test]
+    fn test_replay_stops_at_edits_that_do_not_fit() {
+        let mut doc = TextDocument::with_content("abc".to_string());
+        let records = vec![
+            CommandRecord::Insert { position: 3, text: "d".to_string() },
+            CommandRecord::Delete { start: 0, end: 1, deleted_text: "x".to_string() },
+            CommandRecord::Insert { position: 0, text: "never".to_string() },
+        ];
+
+        assert_eq!(doc.replay_edits(&records), 1);
+        assert_eq!(doc.content(), "abcd");
+    }
+
+    fn with_marked_cursor(marked: &str) -> TextDocument {
+        let mut doc = TextDocument::with_content(marked.replace('|', ""));
+        doc.set_cursor_position(marked[..marked.find('|').unwrap()].chars().count());
+        doc
+    }
+
+    #[test]
+    fn test_kill_ring_and_yank_pop() {
+        let mut doc = with_marked_cursor("|one\ntwo\nthree");
+        // Ctrl+K twice kills the line and then its line break, as one kill
+        doc.handle_action(EditorAction::Kill(Movement::LineEnd));
+        doc.handle_action(EditorAction::Kill(Movement::LineEnd));
+        assert_eq!(doc.get_clipboard_content(), Some("one\n".to_string()));
+        doc.handle_action(EditorAction::MoveCursor(Movement::DocumentEnd));
+        doc.handle_action(EditorAction::Kill(Movement::WordStart));
+        assert_eq!(with_cursor(&doc), "two\n|");
+
+        doc.handle_action(EditorAction::Paste);
+        assert_eq!(with_cursor(&doc), "two\nthree|");
+        assert!(doc.handle_action(EditorAction::YankPop));
+        assert_eq!(with_cursor(&doc), "two\none\n|");
+        assert!(doc.handle_action(EditorAction::YankPop));
+        assert_eq!(with_cursor(&doc), "two\nthree|");
+        // Yank-pop only follows a paste
+        doc.handle_action(EditorAction::MoveCursor(Movement::Left));
+        assert!(!doc.handle_action(EditorAction::YankPop));
+
+        // Backward kills in a row join at the front
+        let mut doc = with_marked_cursor("a b c|");
+        doc.handle_action(EditorAction::Kill(Movement::WordStart));
+        doc.handle_action(EditorAction::Kill(Movement::WordStart));
+        assert_eq!(with_cursor(&doc), "a |");
+        assert_eq!(doc.get_clipboard_content(), Some("b c".to_string()));
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "a b ");
+    }
+
+    #[test]
+    fn test_mark_makes_movement_select() {
+        let mut doc = with_marked_cursor("|hello world");
+        doc.handle_action(EditorAction::SetMark);
+        doc.handle_action(EditorAction::MoveCursor(Movement::WordEnd));
+        doc.handle_action(EditorAction::MoveCursor(Movement::Right));
+        assert_eq!(doc.selected_text(), Some("hello ".to_string()));
+
+        doc.handle_action(EditorAction::Cut);
+        assert_eq!(doc.content(), "world");
+        // Clearing the selection drops the mark
+        doc.handle_action(EditorAction::MoveCursor(Movement::Right));
+        assert!(!doc.has_selection());
+        assert!(!doc.is_mark_active());
+    }
+
+    #[test]
+    fn test_transpose() {
+        let transpose = |marked: &str, action: EditorAction| {
+            let mut doc = with_marked_cursor(marked);
+            doc.handle_action(action).then(|| with_cursor(&doc))
+        };
+        assert_eq!(transpose("ab|c", EditorAction::TransposeChars), Some("acb|".to_string()));
+        assert_eq!(transpose("ab|\nc", EditorAction::TransposeChars), Some("ba|\nc".to_string()));
+        assert_eq!(transpose("|abc", EditorAction::TransposeChars), None);
+        assert_eq!(
+            transpose("one tw|o, three", EditorAction::TransposeWords),
+            Some("one three, two|".to_string())
+        );
+        assert_eq!(transpose("one two|", EditorAction::TransposeWords), None);
+        assert_eq!(
+            transpose("one\ntw|o\nthree", EditorAction::TransposeLines),
+            Some("two\none\n|three".to_string())
+        );
+        assert_eq!(transpose("one\ntw|o", EditorAction::TransposeLines), Some("two\none|".to_string()));
+        assert_eq!(transpose("o|ne\ntwo", EditorAction::TransposeLines), None);
+    }
+
+    #[test]
+    fn test_change_case() {
+        let mut doc = with_marked_cursor("|hello wORLD again");
+        doc.handle_action(EditorAction::ChangeCase(CaseChange::Upper));
+        assert_eq!(with_cursor(&doc), "HELLO| wORLD again");
+        doc.handle_action(EditorAction::ChangeCase(CaseChange::Capitalize));
+        assert_eq!(with_cursor(&doc), "HELLO World| again");
+
+        doc.handle_action(EditorAction::SelectAll);
+        doc.handle_action(EditorAction::ChangeCase(CaseChange::Lower));
+        assert_eq!(with_cursor(&doc), "hello world again|");
+        assert!(doc.perform_undo());
+        assert_eq!(doc.content(), "HELLO World again");
+    }
+
+    #[test]
+    fn test_incremental_search() {
+        let mut doc = with_marked_cursor("|cat cart car");
+        doc.handle_action(EditorAction::IncrementalSearch(SearchDirection::Forward));
+        doc.extend_incremental_search("c");
+        assert_eq!(doc.selection_range(), Some((0, 1)));
+        // Each character moves on to the first match from where the search started
+        doc.extend_incremental_search("ar");
+        assert_eq!(doc.selection_range(), Some((4, 7)));
+        doc.handle_action(EditorAction::IncrementalSearch(SearchDirection::Forward));
+        assert_eq!(doc.selection_range(), Some((9, 12)));
+        doc.handle_action(EditorAction::IncrementalSearch(SearchDirection::Forward));
+        assert_eq!(doc.selection_range(), Some((4, 7)));
+
+        // A match that stops matching stays selected while the search fails
+        doc.extend_incremental_search("t");
+        assert!(!doc.extend_incremental_search("x"));
+        assert_eq!(doc.selection_range(), Some((4, 8)));
+        assert_eq!(doc.incremental_search_state().unwrap().prompt(), "Failing I-search: cartx");
+        doc.backspace_incremental_search();
+        assert!(!doc.incremental_search_state().unwrap().failing);
+        doc.finish_incremental_search();
+        assert_eq!(with_cursor(&doc), "cat cart| car");
+        assert!(doc.search_query().is_none());
+
+        // Going back leaves the cursor at the start of the match, and
+        // cancelling goes back to where the search started
+        doc.handle_action(EditorAction::IncrementalSearch(SearchDirection::Backward));
+        doc.extend_incremental_search("Ca");
+        assert!(doc.incremental_search_state().unwrap().failing);
+        doc.backspace_incremental_search();
+        doc.backspace_incremental_search();
+        doc.extend_incremental_search("ca");
+        assert_eq!(with_cursor(&doc), "cat |cart car");
+        doc.cancel_incremental_search();
+        assert_eq!(with_cursor(&doc), "cat cart| car");
+        assert!(!doc.has_selection());
+    }
 }
@@ -0,0 +1,426 @@
+//! Undo Tree
+//!
+//! Every undo step is a node whose parent is the state it was made in, so an
+//! edit made after undoing starts a new branch instead of throwing away the
+//! steps that were undone. Node ids count up as steps are made, which orders
+//! the states in time for moving to older and newer ones on any branch.
+
+use super::command_history::CommandTransaction;
+use std::collections::BTreeMap;
+
+/// The oldest state kept, before every step in the tree
+pub const ROOT_NODE: usize = 0;
+
+#[derive(Debug, Clone)]
+struct UndoNode {
+    /// The step from the parent's state to this one (empty at the root)
+    transaction: CommandTransaction,
+    parent: usize,
+    /// Children in the order they were made
+    children: Vec<usize>,
+    /// The child redo goes to: the one made or visited last
+    redo_child: Option<usize>,
+}
+
+impl UndoNode {
+    fn new(transaction: CommandTransaction, parent: usize) -> Self {
+        Self {
+            transaction,
+            parent,
+            children: Vec::new(),
+            redo_child: None,
+        }
+    }
+}
+
+/// One move to a neighbouring state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoMove {
+    /// Undo the current node's step, back to its parent
+    Undo,
+    /// Redo this child of the current node
+    Redo(usize),
+}
+
+/// The steps from the oldest state kept to one nothing was done after
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoBranch {
+    /// Node ids, oldest first
+    pub nodes: Vec<usize>,
+}
+
+impl UndoBranch {
+    /// The newest node on the branch
+    pub fn tip(&self) -> usize {
+        self.nodes.last().copied().unwrap_or(ROOT_NODE)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoTree {
+    nodes: BTreeMap<usize, UndoNode>,
+    /// The node whose state the document is in
+    current: usize,
+    next_id: usize,
+}
+
+impl UndoTree {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(ROOT_NODE, UndoNode::new(CommandTransaction::new(String::new()), ROOT_NODE));
+        Self {
+            nodes,
+            current: ROOT_NODE,
+            next_id: ROOT_NODE + 1,
+        }
+    }
+
+    /// The node whose state the document is in
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Number of steps in the tree, on every branch
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    /// The step leading to node `id`, or `None` for the root
+    pub fn transaction(&self, id: usize) -> Option<&CommandTransaction> {
+        self.nodes.get(&id).filter(|_| id != ROOT_NODE).map(|node| &node.transaction)
+    }
+
+    pub fn transaction_mut(&mut self, id: usize) -> Option<&mut CommandTransaction> {
+        self.nodes.get_mut(&id).filter(|_| id != ROOT_NODE).map(|node| &mut node.transaction)
+    }
+
+    /// Add a step made in the current state, as the current state's newest
+    /// child, and move to it
+    pub fn push(&mut self, transaction: CommandTransaction) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(id, UndoNode::new(transaction, self.current));
+        if let Some(parent) = self.nodes.get_mut(&self.current) {
+            parent.children.push(id);
+            parent.redo_child = Some(id);
+        }
+        self.current = id;
+        id
+    }
+
+    /// Move to the current node's parent, returning the step to undo
+    pub fn undo(&mut self) -> Option<&CommandTransaction> {
+        if self.current == ROOT_NODE {
+            return None;
+        }
+        let undone = self.current;
+        let parent = self.nodes[&undone].parent;
+        if let Some(node) = self.nodes.get_mut(&parent) {
+            node.redo_child = Some(undone);
+        }
+        self.current = parent;
+        self.transaction(undone)
+    }
+
+    /// The child of the current node redo goes to
+    pub fn redo_child(&self) -> Option<usize> {
+        self.nodes[&self.current].redo_child
+    }
+
+    /// Move to `child` of the current node, returning the step to redo
+    pub fn redo(&mut self, child: usize) -> Option<&CommandTransaction> {
+        let node = self.nodes.get_mut(&self.current)?;
+        if !node.children.contains(&child) {
+            return None;
+        }
+        node.redo_child = Some(child);
+        self.current = child;
+        self.transaction(child)
+    }
+
+    /// Node `id` and its ancestors up to the root, nearest first
+    fn ancestors(&self, id: usize) -> Vec<usize> {
+        let mut ancestors = vec![id];
+        let mut node = id;
+        while node != ROOT_NODE {
+            node = self.nodes[&node].parent;
+            ancestors.push(node);
+        }
+        ancestors
+    }
+
+    /// The moves from the current state to node `id`: undoing back to the
+    /// nearest state both are after, then redoing down to `id`
+    pub fn path_to(&self, id: usize) -> Option<Vec<UndoMove>> {
+        if !self.contains(id) {
+            return None;
+        }
+        let from = self.ancestors(self.current);
+        let to = self.ancestors(id);
+        let common = from.iter().position(|node| to.contains(node))?;
+        let common_in_to = to.iter().position(|node| *node == from[common])?;
+
+        let mut moves = vec![UndoMove::Undo; common];
+        moves.extend(to[..common_in_to].iter().rev().map(|&node| UndoMove::Redo(node)));
+        Some(moves)
+    }
+
+    /// The state made just before the current one, on any branch
+    pub fn older(&self) -> Option<usize> {
+        self.nodes.range(..self.current).next_back().map(|(&id, _)| id)
+    }
+
+    /// The state made just after the current one, on any branch
+    pub fn newer(&self) -> Option<usize> {
+        self.nodes.range(self.current + 1..).next().map(|(&id, _)| id)
+    }
+
+    /// Number of undo steps from the current state back to the root
+    pub fn undo_depth(&self) -> usize {
+        self.ancestors(self.current).len() - 1
+    }
+
+    /// Number of redo steps ahead of the current state
+    pub fn redo_depth(&self) -> usize {
+        let mut depth = 0;
+        let mut node = self.current;
+        while let Some(child) = self.nodes[&node].redo_child {
+            depth += 1;
+            node = child;
+        }
+        depth
+    }
+
+    /// Every branch, ordered by when its newest step was made
+    pub fn branches(&self) -> Vec<UndoBranch> {
+        self.nodes
+            .iter()
+            .filter(|(&id, node)| id != ROOT_NODE && node.children.is_empty())
+            .map(|(&tip, _)| {
+                let mut nodes = self.ancestors(tip);
+                nodes.pop();
+                nodes.reverse();
+                UndoBranch { nodes }
+            })
+            .collect()
+    }
+
+    /// Every step as (id, parent, step), oldest first
+    pub fn steps(&self) -> impl Iterator<Item = (usize, usize, &CommandTransaction)> {
+        self.nodes
+            .iter()
+            .filter(|(&id, _)| id != ROOT_NODE)
+            .map(|(&id, node)| (id, node.parent, &node.transaction))
+    }
+
+    /// Rebuild a tree at node `current` from steps listed the way `steps`
+    /// lists them. Returns `None` unless every step's parent comes before it.
+    pub fn from_steps(steps: Vec<(usize, usize, CommandTransaction)>, current: usize) -> Option<Self> {
+        let mut tree = Self::new();
+        for (id, parent, transaction) in steps {
+            if id <= parent || tree.contains(id) || !tree.contains(parent) {
+                return None;
+            }
+            tree.nodes.insert(id, UndoNode::new(transaction, parent));
+            if let Some(parent) = tree.nodes.get_mut(&parent) {
+                parent.children.push(id);
+                parent.redo_child = Some(id);
+            }
+            tree.next_id = id + 1;
+        }
+        if !tree.contains(current) {
+            return None;
+        }
+        tree.current = current;
+        Some(tree)
+    }
+
+    /// Estimated memory used by every step
+    pub fn estimated_memory_usage(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|(&id, _)| id != ROOT_NODE)
+            .map(|(_, node)| node.transaction.estimated_memory_usage())
+            .sum()
+    }
+
+    /// Drop the oldest branch's tip, of the branches the document isn't on,
+    /// or once there are none, the oldest step of all, whose state the root
+    /// then becomes. Returns false if the tree is empty.
+    pub fn prune_oldest(&mut self) -> bool {
+        let abandoned_tip = self
+            .nodes
+            .iter()
+            .find(|(&id, node)| id != ROOT_NODE && id != self.current && node.children.is_empty())
+            .map(|(&id, _)| id);
+        if let Some(id) = abandoned_tip {
+            self.remove_tip(id);
+            return true;
+        }
+
+        // Every step is on the current branch now, so the root has one child
+        match *self.nodes[&ROOT_NODE].children.as_slice() {
+            [id] => {
+                self.remove_base(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove a node with no children
+    fn remove_tip(&mut self, id: usize) {
+        let Some(node) = self.nodes.remove(&id) else {
+            return;
+        };
+        if let Some(parent) = self.nodes.get_mut(&node.parent) {
+            parent.children.retain(|child| *child != id);
+            if parent.redo_child == Some(id) {
+                parent.redo_child = parent.children.last().copied();
+            }
+        }
+    }
+
+    /// Remove the root's only child, making its state the root's
+    fn remove_base(&mut self, id: usize) {
+        let Some(node) = self.nodes.remove(&id) else {
+            return;
+        };
+        for child in &node.children {
+            if let Some(child) = self.nodes.get_mut(child) {
+                child.parent = ROOT_NODE;
+            }
+        }
+        if let Some(root) = self.nodes.get_mut(&ROOT_NODE) {
+            root.children = node.children;
+            root.redo_child = node.redo_child;
+        }
+        if self.current == id {
+            self.current = ROOT_NODE;
+        }
+    }
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(name: &str) -> CommandTransaction {
+        CommandTransaction::new(name.to_string())
+    }
+
+    fn description(tree: &UndoTree, id: usize) -> &str {
+        tree.transaction(id).map_or("root", |transaction| transaction.description())
+    }
+
+    /// a - b - c, then undo twice and make d after a
+    fn branched_tree() -> UndoTree {
+        let mut tree = UndoTree::new();
+        tree.push(step("a"));
+        tree.push(step("b"));
+        tree.push(step("c"));
+        tree.undo();
+        tree.undo();
+        tree.push(step("d"));
+        tree
+    }
+
+    #[test]
+    fn test_edits_after_undo_start_a_branch() {
+        let mut tree = branched_tree();
+        assert_eq!(tree.len(), 4);
+        assert_eq!(description(&tree, tree.current()), "d");
+        assert_eq!(tree.redo_child(), None);
+
+        let branches = tree.branches();
+        assert_eq!(branches, vec![UndoBranch { nodes: vec![1, 2, 3] }, UndoBranch { nodes: vec![1, 4] }]);
+        assert_eq!(branches[0].tip(), 3);
+
+        // Redo from a goes to the branch made or visited last
+        tree.undo();
+        assert_eq!(tree.redo_child(), Some(4));
+        assert_eq!(tree.redo(2).map(|step| step.description()), Some("b"));
+        tree.undo();
+        assert_eq!(tree.redo_child(), Some(2));
+        assert!(tree.redo(3).is_none());
+    }
+
+    #[test]
+    fn test_older_and_newer_go_in_time_order() {
+        let tree = branched_tree();
+        assert_eq!(tree.older(), Some(3));
+        assert_eq!(tree.newer(), None);
+
+        let mut tree = tree;
+        let mut visited = Vec::new();
+        while let Some(older) = tree.older() {
+            let moves = tree.path_to(older).unwrap();
+            for step in moves {
+                match step {
+                    UndoMove::Undo => tree.undo(),
+                    UndoMove::Redo(child) => tree.redo(child),
+                };
+            }
+            visited.push(description(&tree, tree.current()).to_string());
+        }
+        assert_eq!(visited, ["c", "b", "a", "root"]);
+    }
+
+    #[test]
+    fn test_path_between_branches() {
+        let tree = branched_tree();
+        assert_eq!(tree.path_to(3), Some(vec![UndoMove::Undo, UndoMove::Redo(2), UndoMove::Redo(3)]));
+        assert_eq!(tree.path_to(ROOT_NODE), Some(vec![UndoMove::Undo, UndoMove::Undo]));
+        assert_eq!(tree.path_to(4), Some(vec![]));
+        assert_eq!(tree.path_to(9), None);
+        assert_eq!(tree.undo_depth(), 2);
+    }
+
+    #[test]
+    fn test_rebuilds_from_steps() {
+        let tree = branched_tree();
+        let steps: Vec<_> = tree.steps().map(|(id, parent, step)| (id, parent, step.clone())).collect();
+        let rebuilt = UndoTree::from_steps(steps, 2).unwrap();
+        assert_eq!(rebuilt.branches(), tree.branches());
+        assert_eq!(description(&rebuilt, rebuilt.current()), "b");
+        assert_eq!(rebuilt.redo_child(), Some(3));
+
+        // A step can't come before its parent
+        assert!(UndoTree::from_steps(vec![(2, 1, step("b")), (1, ROOT_NODE, step("a"))], 1).is_none());
+        assert!(UndoTree::from_steps(vec![(1, ROOT_NODE, step("a"))], 5).is_none());
+    }
+
+    #[test]
+    fn test_prunes_oldest_branches_first() {
+        let mut tree = branched_tree();
+        // c is the oldest tip the document isn't at
+        assert!(tree.prune_oldest());
+        assert!(!tree.contains(3));
+        assert!(tree.prune_oldest());
+        assert!(!tree.contains(2));
+
+        // Then the oldest step, a, whose state becomes the root
+        assert!(tree.prune_oldest());
+        assert_eq!(tree.branches(), vec![UndoBranch { nodes: vec![4] }]);
+        assert_eq!(tree.undo_depth(), 1);
+
+        assert!(tree.prune_oldest());
+        assert_eq!(tree.current(), ROOT_NODE);
+        assert!(!tree.prune_oldest());
+        assert!(tree.is_empty());
+    }
+}
@@ -15,6 +15,12 @@ pub(super) struct EditorElement {
     pub(super) focus_handle: FocusHandle,
     pub(super) cursor_position: usize,
     pub(super) selection: Option<std::ops::Range<usize>>,
+    // Extra cursors and selections when editing with multiple cursors
+    pub(super) secondary_cursors: Vec<usize>,
+    pub(super) secondary_selections: Vec<std::ops::Range<usize>>,
+    // Find/replace matches to highlight, and the selected one among them
+    pub(super) search_matches: Vec<std::ops::Range<usize>>,
+    pub(super) current_search_match: Option<std::ops::Range<usize>>,
     pub(super) hybrid_renderer: HybridTextRenderer,
     // Manage all visual lines for the document
     pub(super) visual_line_manager: VisualLineManager,
@@ -286,10 +292,14 @@ impl Element for EditorElement {
         let text_origin = bounds.origin + gpui::point(padding, padding - scroll_offset_px);
         let mut actual_line_positions = Vec::new();
 
-        // Paint selection first (behind text)
+        // Paint search matches and selection first (behind text)
+        self.paint_search_matches(bounds, window);
         if let Some(ref selection_range) = self.selection {
             self.paint_selection(bounds, shaped_lines, selection_range.clone(), window);
         }
+        for selection_range in &self.secondary_selections {
+            self.paint_selection(bounds, shaped_lines, selection_range.clone(), window);
+        }
 
         let line_height = px(24.0);
         for (shaped_line_index, shaped_line) in shaped_lines.iter_mut().enumerate() {
@@ -323,6 +333,9 @@ impl Element for EditorElement {
         // Paint cursor if focused
         if self.focused {
             self.paint_cursor(bounds, window, cx);
+            for &position in &self.secondary_cursors {
+                self.paint_cursor_at(position, bounds, window);
+            }
         }
     }
 }
@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use gpui::{Context, PathPromptOptions, PromptLevel, Window};
+
+use super::MarkdownEditor;
+use crate::file::{FileError, UnsavedChanges};
+use crate::input::EditorAction;
+
+/// How often the open file is checked for changes made by other programs
+const EXTERNAL_CHANGE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long edits can wait before they're written to the recovery journal
+const JOURNAL_FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+// Dialog flows for New/Open/Save/Save As. The file logic itself lives in
+// `FileSession`; this only asks the user for paths and confirmations.
+impl MarkdownEditor {
+    /// Handle a file action from the keymap, returning false for other actions
+    pub(super) fn handle_file_action(
+        &mut self,
+        action: &EditorAction,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> bool {
+        match action {
+            EditorAction::NewFile => self.resolve_unsaved_changes_then(window, cx, |editor, window, cx| {
+                if let Err(error) = editor.new_file(UnsavedChanges::Discard) {
+                    editor.report_file_error(&error, window, cx);
+                }
+                cx.notify();
+            }),
+            EditorAction::OpenFile => self.resolve_unsaved_changes_then(window, cx, |editor, window, cx| {
+                editor.prompt_to_open(window, cx);
+            }),
+            EditorAction::Save => self.save_then(window, cx, |_, _, cx| cx.notify()),
+            EditorAction::SaveAs => self.save_as_then(window, cx, |_, _, cx| cx.notify()),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Run `then` once any unsaved changes have been saved or discarded.
+    /// Nothing happens if the user cancels.
+    fn resolve_unsaved_changes_then(
+        &mut self,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+        then: impl FnOnce(&mut Self, &mut Window, &mut Context<Self>) + 'static,
+    ) {
+        if !self.is_dirty() {
+            then(self, window, cx);
+            return;
+        }
+
+        let message = format!("Save changes to {}?", self.file_session.display_name());
+        let answer = window.prompt(
+            PromptLevel::Warning,
+            &message,
+            Some("Your changes will be lost if you don't save them."),
+            &["Save", "Don't Save", "Cancel"],
+            cx,
+        );
+        cx.spawn_in(window, async move |this, cx| {
+            let answer = answer.await;
+            this.update_in(cx, |editor, window, cx| match answer {
+                Ok(0) => editor.save_then(window, cx, then),
+                Ok(1) => then(editor, window, cx),
+                _ => {}
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn save_then(
+        &mut self,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+        then: impl FnOnce(&mut Self, &mut Window, &mut Context<Self>) + 'static,
+    ) {
+        match self.save() {
+            Ok(()) => then(self, window, cx),
+            Err(FileError::NoPath) => self.save_as_then(window, cx, then),
+            Err(error) => self.report_file_error(&error, window, cx),
+        }
+    }
+
+    fn save_as_then(
+        &mut self,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+        then: impl FnOnce(&mut Self, &mut Window, &mut Context<Self>) + 'static,
+    ) {
+        let new_path = cx.prompt_for_new_path(&self.dialog_directory());
+        cx.spawn_in(window, async move |this, cx| {
+            if let Ok(Ok(Some(path))) = new_path.await {
+                this.update_in(cx, |editor, window, cx| match editor.save_as(&path) {
+                    Ok(()) => then(editor, window, cx),
+                    Err(error) => editor.report_file_error(&error, window, cx),
+                })
+                .ok();
+            }
+        })
+        .detach();
+    }
+
+    fn prompt_to_open(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: false,
+        });
+        cx.spawn_in(window, async move |this, cx| {
+            if let Ok(Ok(Some(paths))) = paths.await {
+                if let Some(path) = paths.into_iter().next() {
+                    this.update_in(cx, |editor, window, cx| {
+                        // Unsaved changes were already dealt with before the dialog opened
+                        match editor.open_file(&path, UnsavedChanges::Discard) {
+                            Ok(()) => editor.offer_recovery(window, cx),
+                            Err(error) => editor.report_file_error(&error, window, cx),
+                        }
+                        cx.notify();
+                    })
+                    .ok();
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Ask whether to replay edits found in the opened file's swap file
+    pub fn offer_recovery(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if !self.has_pending_recovery() {
+            return;
+        }
+
+        let message = format!("Recover unsaved changes to {}?", self.file_session.display_name());
+        let answer = window.prompt(
+            PromptLevel::Warning,
+            &message,
+            Some("Wonder closed before these changes were saved."),
+            &["Recover", "Discard"],
+            cx,
+        );
+        cx.spawn_in(window, async move |this, cx| {
+            let answer = answer.await;
+            this.update_in(cx, |editor, _, cx| {
+                if let Ok(0) = answer {
+                    editor.recover_unsaved_edits();
+                } else {
+                    editor.discard_recovery();
+                }
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Keep checking the open file for changes made by scripts, `git pull`
+    /// and the like for as long as the window is open
+    pub fn watch_for_external_changes(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        cx.spawn_in(window, async move |this, cx| loop {
+            cx.background_executor().timer(EXTERNAL_CHANGE_POLL_INTERVAL).await;
+            let checked = this.update_in(cx, |editor, window, cx| editor.check_external_change(window, cx));
+            if checked.is_err() {
+                break;
+            }
+        })
+        .detach();
+    }
+
+    /// Write new edits to the recovery journal every so often, for as long
+    /// as the window is open. Ticks without edits don't touch the disk.
+    pub fn flush_journal_periodically(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        cx.spawn_in(window, async move |this, cx| loop {
+            cx.background_executor().timer(JOURNAL_FLUSH_INTERVAL).await;
+            let flushed = this.update_in(cx, |editor, _, _| editor.flush_journal());
+            if flushed.is_err() {
+                break;
+            }
+        })
+        .detach();
+    }
+
+    /// Reload silently when there's nothing to lose, otherwise ask. Nothing
+    /// is checked while the user is still answering the last question.
+    fn check_external_change(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.merge_prompt_open {
+            return;
+        }
+        match self.file_session.check_external_change() {
+            Ok(Some(disk_content)) if self.is_dirty() => self.offer_merge(disk_content, window, cx),
+            Ok(Some(disk_content)) => {
+                self.file_session.reload(&mut self.document, disk_content);
+                cx.notify();
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!("Could not check {} for changes: {}", self.file_session.display_name(), error),
+        }
+    }
+
+    fn offer_merge(&mut self, disk_content: String, window: &mut Window, cx: &mut Context<Self>) {
+        let message = format!("{} changed on disk", self.file_session.display_name());
+        let answer = window.prompt(
+            PromptLevel::Warning,
+            &message,
+            Some("Merge your unsaved changes into the new version, keep only yours, or reload and lose them?"),
+            &["Merge", "Keep Mine", "Reload"],
+            cx,
+        );
+        self.merge_prompt_open = true;
+        cx.spawn_in(window, async move |this, cx| {
+            let answer = answer.await;
+            this.update_in(cx, |editor, window, cx| {
+                editor.merge_prompt_open = false;
+                let result = match answer {
+                    Ok(0) => editor
+                        .file_session
+                        .merge(&mut editor.document, disk_content)
+                        .map(|merged| {
+                            if !merged.is_clean() {
+                                let message = format!("{} conflicting changes are marked in the document.", merged.conflicts);
+                                let _ = window.prompt(PromptLevel::Info, "Merged with conflicts", Some(&message), &["OK"], cx);
+                            }
+                        }),
+                    Ok(2) => {
+                        editor.file_session.reload(&mut editor.document, disk_content);
+                        Ok(())
+                    }
+                    _ => editor.file_session.keep_mine(&mut editor.document, disk_content),
+                };
+                if let Err(error) = result {
+                    editor.report_file_error(&error, window, cx);
+                }
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Directory the file dialogs start in: next to the current file if there is one
+    fn dialog_directory(&self) -> PathBuf {
+        self.file_session
+            .path()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+    }
+
+    fn report_file_error(&mut self, error: &FileError, window: &mut Window, cx: &mut Context<Self>) {
+        eprintln!("File operation failed: {}", error);
+        let _ = window.prompt(PromptLevel::Critical, &error.to_string(), None, &["OK"], cx);
+    }
+}
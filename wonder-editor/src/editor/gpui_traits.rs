@@ -137,6 +137,24 @@ impl Render for MarkdownEditor {
         } else {
             None
         };
+        let secondary_cursors = self.document.secondary_selections()
+            .iter()
+            .map(|region| region.head)
+            .collect();
+        let secondary_selections = self.document.secondary_selections()
+            .iter()
+            .filter(|region| !region.is_empty())
+            .map(|region| {
+                let (start, end) = region.range();
+                start..end
+            })
+            .collect();
+        let search_matches = self.document.search_matches()
+            .iter()
+            .map(|search_match| search_match.range())
+            .collect();
+        let current_search_match = self.document.current_search_match()
+            .map(|search_match| search_match.range());
 
         // Sync our internal focused state with GPUI's focus system
         let is_gpui_focused = self.focus_handle.is_focused(window);
@@ -160,6 +178,7 @@ impl Render for MarkdownEditor {
             .on_key_down(cx.listener(Self::handle_key_down))
             .on_scroll_wheel(cx.listener(Self::handle_scroll_wheel))
             .size_full()
+            .relative()
             .flex()
             .flex_col()
             .child(
@@ -173,6 +192,10 @@ impl Render for MarkdownEditor {
                         focus_handle: self.focus_handle.clone(),
                         cursor_position,
                         selection,
+                        secondary_cursors,
+                        secondary_selections,
+                        search_matches,
+                        current_search_match,
                         hybrid_renderer: self.hybrid_renderer.clone(),
                         visual_line_manager: crate::rendering::VisualLineManager::new(),
                         scroll_offset: self.scroll_offset(),
@@ -181,5 +204,6 @@ impl Render for MarkdownEditor {
                     },
                 ),
             )
+            .children(self.render_command_palette())
     }
 }
@@ -1,4 +1,4 @@
-use gpui::{ClipboardItem, Context, KeyDownEvent, Window};
+use gpui::{ClipboardItem, Context, KeyDownEvent, PromptLevel, Window};
 
 use super::MarkdownEditor;
 use super::cursor_diagnostics::{
@@ -6,6 +6,10 @@ use super::cursor_diagnostics::{
     set_diagnostics_enabled, diagnostics_enabled, generate_diagnostic_report
 };
 use crate::core::{CoordinateConversion, RopeCoordinateMapper};
+use crate::input::keymap::KeyLookup;
+use crate::input::EditorAction;
+use crate::input::router::KeyResolution;
+use crate::input::ActionHandler;
 use ropey::Rope;
 
 impl MarkdownEditor {
@@ -13,7 +17,7 @@ impl MarkdownEditor {
     pub(super) fn handle_key_down(
         &mut self,
         event: &KeyDownEvent,
-        _window: &mut Window,
+        window: &mut Window,
         cx: &mut Context<Self>,
     ) {
         // Special handling for diagnostics toggle (Ctrl+Shift+D)
@@ -51,40 +55,126 @@ impl MarkdownEditor {
             }
         }
         
-        // Special handling for clipboard operations that need GPUI context
-        let is_cmd_or_ctrl = event.keystroke.modifiers.platform || event.keystroke.modifiers.control;
-        
-        if is_cmd_or_ctrl {
-            match event.keystroke.key.as_str() {
-                "c" => {
-                    // Copy to system clipboard
-                    if let Some(text) = self.document.copy() {
-                        cx.write_to_clipboard(ClipboardItem::new_string(text));
-                    }
-                    cx.notify();
-                    return;
+        let chord_key = crate::input::keymap::KeyBinding {
+            key: event.keystroke.key.clone(),
+            modifiers: crate::input::keymap::Modifiers::from_gpui(&event.keystroke.modifiers),
+        };
+
+        // The open command palette takes every key
+        if self.command_palette.is_some() {
+            self.handle_palette_key(event, &chord_key, window, cx);
+            cx.stop_propagation();
+            cx.notify();
+            return;
+        }
+
+        // In vim mode, keys are commands in normal and visual mode and text in
+        // insert mode. Keys vim doesn't use, such as shortcuts, go on to the
+        // keymap, and so does everything once a chord has started.
+        if let (Some(vim), None) = (&mut self.vim, self.input_router.pending_keys()) {
+            let modifiers = &event.keystroke.modifiers;
+            // Typed characters such as `$` come as themselves, without Shift
+            let vim_key = match event.keystroke.key_char.as_deref() {
+                Some(text)
+                    if !modifiers.control
+                        && !modifiers.platform
+                        && text.chars().count() == 1
+                        && !text.chars().any(char::is_control) =>
+                {
+                    crate::input::keymap::KeyBinding::new(text)
                 }
-                "x" => {
-                    // Cut to system clipboard
-                    if let Some(text) = self.document.cut() {
-                        cx.write_to_clipboard(ClipboardItem::new_string(text));
-                    }
-                    cx.notify();
-                    return;
+                _ => chord_key.clone(),
+            };
+            if vim.handle_key(&vim_key, &mut self.document) {
+                self.ensure_cursor_visible();
+                cx.stop_propagation();
+                cx.notify();
+                return;
+            }
+        }
+
+        // Bindings such as Tab in a table depend on where the cursor is
+        let key_context = self.input_router.key_context_for(&chord_key, &self.document);
+
+        // While a search is typed, typed characters go into it and Backspace
+        // takes them back out. Its own key goes to the next match, Enter or
+        // Escape ends it at the match and Ctrl+G (clear selection) goes back
+        // to where it started. Any other key ends it and then does its job.
+        if self.document.incremental_search_state().is_some() && self.input_router.pending_keys().is_none() {
+            let bound_action = match self.input_router.keymap().lookup_in(std::slice::from_ref(&chord_key), &key_context) {
+                KeyLookup::Action(action) => Some(action.clone()),
+                _ => None,
+            };
+            let modifiers = &event.keystroke.modifiers;
+            let typed_text = event.keystroke.key_char.clone()
+                .filter(|text| !modifiers.control && !modifiers.platform && !text.chars().any(char::is_control));
+            let handled = match (bound_action, typed_text) {
+                (Some(EditorAction::IncrementalSearch(direction)), _) => {
+                    self.document.incremental_search(direction);
+                    true
+                }
+                (Some(EditorAction::ClearSelection), _) => {
+                    self.document.cancel_incremental_search();
+                    true
                 }
-                "v" => {
-                    // Paste from system clipboard
-                    let clipboard_text = cx.read_from_clipboard().and_then(|item| {
-                        item.text()
-                    });
-                    self.document.paste(clipboard_text);
-                    cx.notify();
-                    return;
+                _ if event.keystroke.key == "backspace" => {
+                    self.document.backspace_incremental_search();
+                    true
                 }
-                _ => {}
+                _ if event.keystroke.key == "enter" || event.keystroke.key == "escape" => {
+                    self.document.finish_incremental_search();
+                    true
+                }
+                (_, Some(text)) => {
+                    self.document.extend_incremental_search(&text);
+                    true
+                }
+                _ => {
+                    self.document.finish_incremental_search();
+                    false
+                }
+            };
+            if handled {
+                self.ensure_cursor_visible();
+                cx.stop_propagation();
+                cx.notify();
+                return;
             }
         }
-        
+
+        // A key that starts or continues a chord such as Ctrl+K Ctrl+C only
+        // adds to the pending keys, and the finished chord runs its action.
+        // Nothing else sees these keys, so they aren't typed either.
+        if self.input_router.pending_keys().is_some()
+            || self.input_router.keymap().is_prefix_in(std::slice::from_ref(&chord_key), &key_context)
+        {
+            match self.input_router.resolve_key(chord_key, &key_context) {
+                KeyResolution::Action(action) => {
+                    if !self.run_editor_action(&action, window, cx) {
+                        self.handle_action(action);
+                    }
+                }
+                KeyResolution::Pending => self.drop_chord_after_timeout(window, cx),
+                KeyResolution::Cancelled | KeyResolution::Unbound => {}
+            }
+            cx.stop_propagation();
+            cx.notify();
+            return;
+        }
+
+        // Clipboard operations use the system clipboard, file operations open
+        // dialogs and vim mode, the command palette and macros belong to the
+        // editor
+        if let KeyLookup::Action(action) =
+            self.input_router.keymap().lookup_in(std::slice::from_ref(&chord_key), &key_context)
+        {
+            let action = action.clone();
+            if self.run_editor_action(&action, window, cx) {
+                self.input_router.record_action(&action);
+                return;
+            }
+        }
+
         // Capture state before handling
         let old_position = self.document.cursor_position();
         let old_selection = self.document.selection_range();
@@ -101,16 +191,24 @@ impl MarkdownEditor {
             modifiers: crate::input::keymap::Modifiers::from_gpui(&event.keystroke.modifiers),
         };
         
-        if let Some(action) = self.input_router.keymap().get(&key_binding) {
-            match action {
+        // With several cursors, movement goes through the document so every cursor moves
+        let visual_movement_action = match self.input_router.keymap().lookup_in(std::slice::from_ref(&key_binding), &key_context) {
+            KeyLookup::Action(action) if !self.document.has_multiple_cursors() => Some(action.clone()),
+            _ => None,
+        };
+        if let Some(action) = visual_movement_action {
+            match &action {
                 crate::input::actions::EditorAction::MoveCursor(movement) => {
+                    // With the mark set, movement extends the selection from it
+                    let extending = self.document.is_mark_active();
                     if self.cursor_movement.move_cursor(
                         movement.clone(),
                         &mut self.document,
                         self.hybrid_renderer.line_wrapper(),
                         &self.visual_line_manager,
-                        false, // not extending selection
+                        extending,
                     ) {
+                        self.input_router.record_action(&action);
                         cx.notify();
                         
                         // Log the movement
@@ -151,6 +249,7 @@ impl MarkdownEditor {
                         &self.visual_line_manager,
                         true, // extending selection
                     ) {
+                        self.input_router.record_action(&action);
                         cx.notify();
                         
                         // Log the movement
@@ -196,8 +295,9 @@ impl MarkdownEditor {
             self.ensure_cursor_visible();
         }
         
-        // Special handling for Enter key (newline)
-        if event.keystroke.key == "enter" {
+        // Special handling for Enter key (newline), unless it was bound
+        // with modifiers such as Cmd+Enter
+        if event.keystroke.key == "enter" && !handled {
             self.input_router.handle_char_input('\n', &mut self.document);
             cx.notify();
             // Ensure cursor remains visible after Enter
@@ -230,29 +330,6 @@ impl MarkdownEditor {
             return;
         }
         
-        // Special handling for Tab key
-        if event.keystroke.key == "tab" {
-            self.input_router.handle_char_input('\t', &mut self.document);
-            cx.notify();
-            
-            // Log the operation
-            let new_position = self.document.cursor_position();
-            let new_content = self.document.content();
-            let new_rope = Rope::from_str(&new_content);
-            let new_mapper = RopeCoordinateMapper::new(new_rope);
-            let _new_point = new_mapper.offset_to_point(new_position);
-            
-            log_keyboard_input(
-                "tab",
-                &format!("{:?}", event.keystroke.modifiers),
-                old_position,
-                new_position,
-                self.document.has_selection()
-            );
-            
-            return;
-        }
-        
         if handled {
             // Log the operation
             let new_position = self.document.cursor_position();
@@ -297,4 +374,84 @@ impl MarkdownEditor {
             cx.notify();
         }
     }
-}
\ No newline at end of file
+
+    /// Drop the pending chord, and the keys shown for it, once it has waited
+    /// the chord timeout for its next key. Each key of the chord restarts the
+    /// wait: replacing the timer cancels the one before.
+    fn drop_chord_after_timeout(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let timeout = self.input_router.chord_timeout();
+        self.chord_timer = Some(cx.spawn_in(window, async move |this, cx| {
+            cx.background_executor().timer(timeout).await;
+            this.update_in(cx, |editor, _, cx| {
+                editor.input_router.cancel_pending_keys();
+                cx.notify();
+            })
+            .ok();
+        }));
+    }
+
+    /// Run an action that needs more than the document: the clipboard ones,
+    /// which use the system clipboard, file ones, which can open dialogs, and
+    /// the editor's own. Returns false for other actions.
+    pub(super) fn run_editor_action(
+        &mut self,
+        action: &EditorAction,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> bool {
+        match action {
+            EditorAction::Copy => {
+                // Copy to system clipboard
+                if let Some(text) = self.document.copy() {
+                    cx.write_to_clipboard(ClipboardItem::new_string(text));
+                }
+            }
+            EditorAction::Cut => {
+                // Cut to system clipboard
+                if let Some(text) = self.document.cut() {
+                    cx.write_to_clipboard(ClipboardItem::new_string(text));
+                }
+                self.ensure_cursor_visible();
+            }
+            EditorAction::Kill(_) => {
+                // The kill ring entry, which may have grown, goes to the system clipboard
+                if self.handle_action(action.clone()) {
+                    if let Some(text) = self.document.get_clipboard_content() {
+                        cx.write_to_clipboard(ClipboardItem::new_string(text));
+                    }
+                }
+            }
+            EditorAction::Paste => {
+                // Paste from system clipboard
+                let clipboard_text = cx.read_from_clipboard().and_then(|item| {
+                    item.text()
+                });
+                self.document.paste(clipboard_text);
+                self.ensure_cursor_visible();
+            }
+            EditorAction::ToggleMacroRecording => {
+                if !self.toggle_macro_recording() {
+                    let _ = window.prompt(
+                        PromptLevel::Info,
+                        "Macros can't be recorded in vim mode",
+                        Some("Turn vim mode off to record a macro."),
+                        &["OK"],
+                        cx,
+                    );
+                }
+            }
+            EditorAction::ToggleVimMode
+            | EditorAction::ToggleCommandPalette
+            | EditorAction::OpenFind
+            | EditorAction::PlayMacro(_)
+            | EditorAction::RepeatMacro
+            | EditorAction::PlayNamedMacro(_)
+            | EditorAction::SaveMacro => {
+                self.handle_action(action.clone());
+            }
+            _ => return self.handle_file_action(action, window, cx),
+        }
+        cx.notify();
+        true
+    }
+}
@@ -1,5 +1,6 @@
 pub(self) mod cursor_diagnostics;
 pub(self) mod element;
+mod files;
 #[cfg(test)]
 mod element_wrapping_tests;
 mod gpui_traits;
@@ -7,21 +8,38 @@ mod gpui_traits;
 mod mouse_wrapping_tests;
 mod keyboard;
 mod mouse;
+mod palette;
 mod rendering;
 #[cfg(test)]
 mod scroll_integration_tests;
 
 use crate::core::{TextDocument, CursorMovementService, ViewportManager};
+use crate::file::{FileError, FileSession, UnsavedChanges};
 #[cfg(test)]
 mod tests;
 use crate::hybrid_renderer::HybridTextRenderer;
-use crate::input::InputRouter;
+use crate::input::config::{load_user_keymap, load_user_macros};
+use crate::input::keymap::format_key_sequence;
+use crate::input::vim::Vim;
+use crate::input::macros::MacroTarget;
+use crate::input::{CommandPalette, CommandRegistry, InputRouter, MacroRepeat, PalettePrompt};
 use crate::rendering::VisualLineManager;
-use gpui::{Bounds, Context, FocusHandle, Pixels};
+use gpui::{Bounds, Context, FocusHandle, Pixels, Task};
 
 pub struct MarkdownEditor {
     document: TextDocument,
+    // File backing the document and the version last saved
+    file_session: FileSession,
+    // Whether the changed-on-disk prompt is showing, so polling doesn't stack another
+    merge_prompt_open: bool,
     input_router: InputRouter,
+    // Drops an unfinished chord once it has waited too long for its next key
+    chord_timer: Option<Task<()>>,
+    // Vim-style modal editing, when it's on
+    vim: Option<Vim>,
+    // Named commands, and the palette for finding them when it's open
+    command_registry: CommandRegistry,
+    command_palette: Option<CommandPalette>,
     hybrid_renderer: HybridTextRenderer,
     focused: bool,
     focus_handle: FocusHandle,
@@ -43,14 +61,34 @@ pub struct MarkdownEditor {
     viewport_manager: ViewportManager,
 }
 
+/// The router with the user's keymap and saved macros, and the commands with
+/// one for each saved macro
+fn load_user_input() -> (InputRouter, CommandRegistry) {
+    let mut input_router = InputRouter::with_keymap(load_user_keymap());
+    let mut command_registry = CommandRegistry::default();
+    for (name, saved) in load_user_macros() {
+        command_registry.register_macro(&name);
+        input_router.macros_mut().set_named(&name, saved);
+    }
+    (input_router, command_registry)
+}
+
 impl MarkdownEditor {
     pub fn new(cx: &mut Context<Self>) -> Self {
         let focus_handle = cx.focus_handle();
-        let input_router = InputRouter::new();
+        let (input_router, command_registry) = load_user_input();
+        let document = TextDocument::new();
+        let file_session = FileSession::new(&document);
         
         Self {
-            document: TextDocument::new(),
+            document,
+            file_session,
+            merge_prompt_open: false,
             input_router,
+            chord_timer: None,
+            vim: None,
+            command_registry,
+            command_palette: None,
             hybrid_renderer: HybridTextRenderer::with_line_wrapping(gpui::px(600.0)),
             focused: true, // Start focused
             focus_handle,
@@ -70,11 +108,19 @@ impl MarkdownEditor {
 
     pub fn new_with_content(content: String, cx: &mut Context<Self>) -> Self {
         let focus_handle = cx.focus_handle();
-        let input_router = InputRouter::new();
+        let (input_router, command_registry) = load_user_input();
+        let document = TextDocument::with_content(content);
+        let file_session = FileSession::new(&document);
         
         Self {
-            document: TextDocument::with_content(content),
+            document,
+            file_session,
+            merge_prompt_open: false,
             input_router,
+            chord_timer: None,
+            vim: None,
+            command_registry,
+            command_palette: None,
             hybrid_renderer: HybridTextRenderer::with_line_wrapping(gpui::px(600.0)),
             focused: true, // Start focused
             focus_handle,
@@ -174,6 +220,59 @@ impl MarkdownEditor {
         &mut self.document
     }
     
+    // File operations
+    pub fn file_session(&self) -> &FileSession {
+        &self.file_session
+    }
+
+    /// Whether the document has edits that haven't been saved
+    pub fn is_dirty(&self) -> bool {
+        self.file_session.is_dirty(&self.document)
+    }
+
+    /// Start a new untitled document
+    pub fn new_file(&mut self, unsaved: UnsavedChanges) -> Result<(), FileError> {
+        self.file_session.new_document(&mut self.document, unsaved)?;
+        self.scroll_to_top();
+        Ok(())
+    }
+
+    /// Open a `.md`, `.markdown` or `.txt` file, replacing the current document
+    pub fn open_file(&mut self, path: &std::path::Path, unsaved: UnsavedChanges) -> Result<(), FileError> {
+        self.file_session.open(&mut self.document, path, unsaved)?;
+        self.scroll_to_top();
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<(), FileError> {
+        self.file_session.save(&mut self.document)
+    }
+
+    pub fn save_as(&mut self, path: &std::path::Path) -> Result<(), FileError> {
+        self.file_session.save_as(&mut self.document, path)
+    }
+
+    /// Whether the opened file has edits left over from a crash
+    pub fn has_pending_recovery(&self) -> bool {
+        self.file_session.pending_recovery().is_some()
+    }
+
+    /// Replay edits left over from a crash, returning how many applied
+    pub fn recover_unsaved_edits(&mut self) -> usize {
+        self.file_session.recover(&mut self.document)
+    }
+
+    pub fn discard_recovery(&mut self) {
+        self.file_session.dismiss_recovery();
+    }
+
+    /// Write edits made since the last flush to the recovery journal
+    pub fn flush_journal(&mut self) {
+        if let Err(error) = self.file_session.flush_journal(&mut self.document) {
+            eprintln!("Could not write recovery journal: {}", error);
+        }
+    }
+
     // Viewport management methods
     pub fn viewport_manager(&self) -> &ViewportManager {
         &self.viewport_manager
@@ -224,6 +323,102 @@ impl MarkdownEditor {
         self.viewport_manager.scroll_to_bottom();
     }
     
+    /// The keys of an unfinished chord or vim command, e.g. `ctrl-k` or
+    /// `d2`, for the UI to show
+    pub fn pending_keys_text(&self) -> Option<String> {
+        self.input_router
+            .pending_keys()
+            .map(format_key_sequence)
+            .or_else(|| self.vim.as_ref().and_then(Vim::pending_keys))
+    }
+
+    /// Switch vim-style modal editing on or off. It starts in normal mode.
+    pub fn toggle_vim_mode(&mut self) {
+        self.vim = match self.vim.take() {
+            Some(_) => {
+                // Close the undo step of a change still being typed
+                self.document.end_undo_group();
+                None
+            }
+            None => {
+                // Vim commands can't be recorded, so a recording ends here
+                self.input_router.macros_mut().stop_recording();
+                Some(Vim::new())
+            }
+        };
+        self.document.clear_selection();
+    }
+
+    /// The current vim mode, e.g. `INSERT`, while vim mode is on
+    pub fn vim_mode_label(&self) -> Option<&'static str> {
+        self.vim.as_ref().map(|vim| vim.mode().label())
+    }
+
+    /// Open the command palette with nothing typed, or close it
+    pub fn toggle_command_palette(&mut self) {
+        self.command_palette = match self.command_palette {
+            Some(_) => None,
+            None => Some(CommandPalette::new()),
+        };
+    }
+
+    pub fn is_command_palette_open(&self) -> bool {
+        self.command_palette.is_some()
+    }
+
+    /// Start recording a macro, or stop and keep it as the last macro.
+    /// Returns false, without recording, in vim mode: vim commands edit the
+    /// document directly rather than running actions a macro could replay.
+    pub fn toggle_macro_recording(&mut self) -> bool {
+        let macros = self.input_router.macros_mut();
+        if macros.is_recording() {
+            macros.stop_recording();
+        } else if self.vim.is_some() {
+            return false;
+        } else {
+            macros.start_recording();
+        }
+        true
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        self.input_router.macros().is_recording()
+    }
+
+    /// Play the last macro, returning how many times it ran. While a macro
+    /// is recorded this stops recording instead, as in Emacs.
+    pub fn play_macro(&mut self, repeat: MacroRepeat) -> usize {
+        let macros = self.input_router.macros_mut();
+        if macros.is_recording() {
+            macros.stop_recording();
+            return 0;
+        }
+        let Some(last) = macros.last_macro().cloned() else {
+            return 0;
+        };
+        last.play(self, repeat)
+    }
+
+    /// Play the macro saved under `name` once
+    pub fn play_named_macro(&mut self, name: &str) -> usize {
+        match self.input_router.macros().named(name).cloned() {
+            Some(saved) => saved.play(self, MacroRepeat::Times(1)),
+            None => 0,
+        }
+    }
+
+    /// Ask in the palette about the last macro, if there is one
+    fn prompt_for_last_macro(&mut self, prompt: PalettePrompt) {
+        if self.input_router.macros().last_macro().is_some() {
+            self.command_palette = Some(CommandPalette::prompting(prompt));
+        }
+    }
+
+    /// The search being typed, e.g. `I-search: foo`, during an incremental search
+    pub fn incremental_search_prompt(&self) -> Option<String> {
+        self.document.incremental_search_state().map(|search| search.prompt())
+    }
+
     /// Ensure cursor is visible by scrolling if necessary
     pub fn ensure_cursor_visible(&mut self) {
         // Calculate which line the cursor is on
@@ -251,6 +446,10 @@ impl MarkdownEditor {
 // ActionHandler implementation for MarkdownEditor
 // This ensures auto-scroll happens after all cursor movements and text modifications
 impl crate::input::actions::ActionHandler for MarkdownEditor {
+    fn key_context(&self) -> crate::input::context::KeyContext {
+        self.document.key_context()
+    }
+
     fn handle_action(&mut self, action: crate::input::actions::EditorAction) -> bool {
         // ENG-191: Handle scroll actions directly (not text document operations)
         match &action {
@@ -278,6 +477,40 @@ impl crate::input::actions::ActionHandler for MarkdownEditor {
                 self.scroll_to_bottom(); // Scroll to document end
                 return true;
             }
+            crate::input::actions::EditorAction::ToggleVimMode => {
+                self.toggle_vim_mode();
+                return true;
+            }
+            crate::input::actions::EditorAction::ToggleCommandPalette => {
+                self.toggle_command_palette();
+                return true;
+            }
+            crate::input::actions::EditorAction::OpenFind => {
+                self.command_palette = Some(CommandPalette::prompting(PalettePrompt::Find));
+                return true;
+            }
+            crate::input::actions::EditorAction::ToggleMacroRecording => {
+                self.toggle_macro_recording();
+                return true;
+            }
+            crate::input::actions::EditorAction::PlayMacro(repeat) => {
+                self.play_macro(*repeat);
+                self.ensure_cursor_visible();
+                return true;
+            }
+            crate::input::actions::EditorAction::PlayNamedMacro(name) => {
+                self.play_named_macro(name);
+                self.ensure_cursor_visible();
+                return true;
+            }
+            crate::input::actions::EditorAction::RepeatMacro => {
+                self.prompt_for_last_macro(PalettePrompt::MacroRepeatCount);
+                return true;
+            }
+            crate::input::actions::EditorAction::SaveMacro => {
+                self.prompt_for_last_macro(PalettePrompt::MacroName);
+                return true;
+            }
             _ => {
                 // For other actions, delegate to document first
             }
@@ -297,7 +530,24 @@ impl crate::input::actions::ActionHandler for MarkdownEditor {
                 crate::input::actions::EditorAction::Backspace |
                 crate::input::actions::EditorAction::Delete |
                 crate::input::actions::EditorAction::SelectAll |
-                crate::input::actions::EditorAction::ClearSelection => {
+                crate::input::actions::EditorAction::ClearSelection |
+                crate::input::actions::EditorAction::FindNext |
+                crate::input::actions::EditorAction::FindPrevious |
+                crate::input::actions::EditorAction::ReplaceCurrent(_) |
+                crate::input::actions::EditorAction::ReplaceAll(_) |
+                crate::input::actions::EditorAction::AddCursorAbove |
+                crate::input::actions::EditorAction::AddCursorBelow |
+                crate::input::actions::EditorAction::AddNextOccurrence |
+                crate::input::actions::EditorAction::Indent |
+                crate::input::actions::EditorAction::Outdent |
+                crate::input::actions::EditorAction::EditTable(_) |
+                crate::input::actions::EditorAction::Kill(_) |
+                crate::input::actions::EditorAction::YankPop |
+                crate::input::actions::EditorAction::TransposeChars |
+                crate::input::actions::EditorAction::TransposeWords |
+                crate::input::actions::EditorAction::TransposeLines |
+                crate::input::actions::EditorAction::ChangeCase(_) |
+                crate::input::actions::EditorAction::IncrementalSearch(_) => {
                     // Ensure cursor visibility after actions that may change cursor position
                     self.ensure_cursor_visible();
                 }
@@ -311,4 +561,18 @@ impl crate::input::actions::ActionHandler for MarkdownEditor {
     }
 }
 
+// Macros play through the editor, so scrolling and the other actions it
+// handles itself work in them too
+impl MacroTarget for MarkdownEditor {
+    fn begin_macro_run(&mut self) {
+        self.document.begin_macro_run();
+    }
 
+    fn end_macro_run(&mut self) {
+        self.document.end_macro_run();
+    }
+
+    fn chars_after_cursor(&self) -> usize {
+        self.document.chars_after_cursor()
+    }
+}
@@ -31,6 +31,19 @@ impl MarkdownEditor {
         window.focus(&self.focus_handle);
         self.focused = true;
 
+        // A click on the box of a task that isn't being edited ticks it
+        // without moving the cursor
+        if !event.modifiers.shift {
+            let position = self.convert_point_to_character_index(event.position, window);
+            if self.handle_task_click_at_position(position) {
+                cx.notify();
+                return;
+            }
+        }
+
+        // A click always collapses multiple cursors back to one
+        self.document.clear_secondary_selections();
+
         // ENG-137/138: Use CursorMovementService for screen-to-text conversion when possible
         let character_position = if self.element_bounds.is_some() {
             // Use the new unified screen coordinate system
@@ -1142,6 +1155,20 @@ impl MarkdownEditor {
         true // Successfully handled
     }
     
+    /// Tick or clear the task whose box is at `position` when its line is
+    /// shown as preview, i.e. no cursor or selection is on it. Returns false
+    /// for an ordinary click.
+    pub fn handle_task_click_at_position(&mut self, position: usize) -> bool {
+        let content = self.document.content();
+        let line_of = |offset: usize| content.chars().take(offset).filter(|&ch| ch == '\n').count();
+        let line = line_of(position.min(content.chars().count()));
+        let editing = self.document.selection_regions().iter().any(|region| {
+            let (start, end) = region.range();
+            (line_of(start)..=line_of(end)).contains(&line)
+        });
+        !editing && self.document.toggle_task_at(position)
+    }
+
     /// Handle click using screen coordinates - preferred for mouse input
     pub fn handle_click_at_screen_position(&mut self, screen_point: Point<Pixels>, window: &mut Window) -> bool {
         if let Some(element_bounds) = self.element_bounds {
@@ -0,0 +1,165 @@
+use gpui::{div, prelude::*, px, rgb, Context, Div, KeyDownEvent, PromptLevel, Window};
+
+use super::MarkdownEditor;
+use crate::core::SearchOptions;
+use crate::input::config::save_user_macro;
+use crate::input::keymap::{format_key_sequence, KeyBinding, KeyLookup};
+use crate::input::{ActionHandler, EditorAction, MacroRepeat, PalettePrompt};
+
+// The command palette: type to find a command by name, Up/Down to pick one,
+// Enter to run it and Escape to close without running anything. The
+// registry ranks the commands, this only routes keys and draws the overlay.
+// The palette also asks for the text to find, a macro's name or how many
+// times to play it, and Enter answers with what's typed.
+impl MarkdownEditor {
+    pub(super) fn handle_palette_key(
+        &mut self,
+        event: &KeyDownEvent,
+        key_binding: &KeyBinding,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(palette) = &mut self.command_palette else {
+            return;
+        };
+        // The palette's own shortcut closes it again
+        let bound_action = self.input_router.keymap().lookup(std::slice::from_ref(key_binding));
+        if bound_action == KeyLookup::Action(&EditorAction::ToggleCommandPalette) {
+            self.command_palette = None;
+            return;
+        }
+
+        let modifiers = &event.keystroke.modifiers;
+        match event.keystroke.key.as_str() {
+            "escape" => self.command_palette = None,
+            "enter" => self.run_selected_command(window, cx),
+            "up" => palette.select_previous(&self.command_registry),
+            "down" => palette.select_next(&self.command_registry),
+            "backspace" => palette.backspace(),
+            _ => {
+                let typed_text = event.keystroke.key_char.as_deref()
+                    .filter(|text| !modifiers.control && !modifiers.platform && !text.chars().any(char::is_control));
+                if let Some(text) = typed_text {
+                    palette.push_str(text);
+                }
+            }
+        }
+    }
+
+    /// Close the palette and run the selected command, if any, or answer
+    /// what it asked
+    fn run_selected_command(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(palette) = self.command_palette.take() else {
+            return;
+        };
+        let answer = palette.query().trim();
+        match palette.prompt() {
+            Some(PalettePrompt::Find) if !answer.is_empty() => {
+                if self.document.set_search(answer, SearchOptions::default()).is_ok() {
+                    self.document.find_next();
+                }
+            }
+            Some(PalettePrompt::MacroName) if !answer.is_empty() => self.save_last_macro(answer, window, cx),
+            Some(PalettePrompt::MacroRepeatCount) => {
+                if let Ok(count) = answer.parse() {
+                    self.play_macro(MacroRepeat::Times(count));
+                }
+            }
+            Some(_) => {}
+            None => {
+                let Some(command) = palette.selected_command(&self.command_registry) else {
+                    return;
+                };
+                let (name, action) = (command.name.clone(), command.action.clone());
+                self.command_registry.record_use(&name);
+                self.input_router.record_action(&action);
+                if !self.run_editor_action(&action, window, cx) {
+                    self.handle_action(action);
+                }
+            }
+        }
+        self.ensure_cursor_visible();
+    }
+
+    /// Save the last macro in the user's config and add a command for it
+    fn save_last_macro(&mut self, name: &str, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(last) = self.input_router.macros().last_macro().cloned() else {
+            return;
+        };
+        if let Err(error) = save_user_macro(name, &last) {
+            let message = format!("Could not save macro {}: {}", name, error);
+            eprintln!("{}", message);
+            let _ = window.prompt(PromptLevel::Critical, &message, None, &["OK"], cx);
+            return;
+        }
+        self.input_router.macros_mut().set_named(name, last);
+        self.command_registry.register_macro(name);
+    }
+
+    /// The palette overlay, while it's open: the query and the best matching
+    /// commands with their category and shortcut
+    pub(super) fn render_command_palette(&self) -> Option<Div> {
+        let palette = self.command_palette.as_ref()?;
+        let keymap = self.input_router.keymap();
+        let rows = palette
+            .results(&self.command_registry)
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| {
+                let shortcut = keymap
+                    .keys_for(&result.command.action)
+                    .map(|keys| format_key_sequence(&keys))
+                    .unwrap_or_default();
+                let row = div()
+                    .flex()
+                    .items_center()
+                    .gap_2()
+                    .px_3()
+                    .py_1()
+                    .rounded_md()
+                    .child(div().text_color(rgb(0xcdd6f4)).child(result.command.name.clone()))
+                    .child(div().text_xs().text_color(rgb(0x7f849c)).child(result.command.category.to_string()))
+                    .child(div().ml_auto().text_xs().text_color(rgb(0xa6adc8)).child(shortcut));
+                if index == palette.selected_index() {
+                    row.bg(rgb(0x45475a))
+                } else {
+                    row
+                }
+            });
+
+        let query = match (palette.prompt(), palette.query()) {
+            (Some(prompt), query) => div()
+                .flex()
+                .gap_2()
+                .child(div().text_color(rgb(0xa6adc8)).child(prompt.label()))
+                .child(div().text_color(rgb(0xcdd6f4)).child(query.to_string())),
+            (None, "") => div().text_color(rgb(0x7f849c)).child("Type a command…"),
+            (None, query) => div().text_color(rgb(0xcdd6f4)).child(query.to_string()),
+        };
+        Some(
+            div()
+                .absolute()
+                .top(px(48.0))
+                .left_0()
+                .right_0()
+                .flex()
+                .justify_center()
+                .child(
+                    div()
+                        .w(px(520.0))
+                        .flex()
+                        .flex_col()
+                        .gap_1()
+                        .p_2()
+                        .bg(rgb(0x313244))
+                        .border_1()
+                        .border_color(rgb(0x45475a))
+                        .rounded_lg()
+                        .shadow_lg()
+                        .text_sm()
+                        .child(div().px_3().py_2().border_b_1().border_color(rgb(0x45475a)).child(query))
+                        .children(rows),
+                ),
+        )
+    }
+}
@@ -12,14 +12,48 @@ impl EditorElement {
         selection_range: std::ops::Range<usize>,
         window: &mut Window,
     ) {
-        let padding = px(16.0);
-        let line_height = px(24.0);
         let selection_color = gpui::Rgba {
             r: 0.337,
             g: 0.502,
             b: 0.761,
             a: 0.3, // Semi-transparent blue
         };
+        self.paint_range_highlight(bounds, selection_range, selection_color, window);
+    }
+
+    /// Paint find/replace matches behind the text, the selected one stronger
+    pub(super) fn paint_search_matches(&self, bounds: Bounds<Pixels>, window: &mut Window) {
+        let match_color = gpui::Rgba {
+            r: 0.976,
+            g: 0.886,
+            b: 0.686,
+            a: 0.35, // Semi-transparent yellow
+        };
+        let current_match_color = gpui::Rgba {
+            r: 0.980,
+            g: 0.702,
+            b: 0.529,
+            a: 0.6, // Orange
+        };
+        for search_match in &self.search_matches {
+            let color = if self.current_search_match.as_ref() == Some(search_match) {
+                current_match_color
+            } else {
+                match_color
+            };
+            self.paint_range_highlight(bounds, search_match.clone(), color, window);
+        }
+    }
+
+    fn paint_range_highlight(
+        &self,
+        bounds: Bounds<Pixels>,
+        selection_range: std::ops::Range<usize>,
+        selection_color: gpui::Rgba,
+        window: &mut Window,
+    ) {
+        let padding = px(16.0);
+        let line_height = px(24.0);
 
         let content = &self.content;
         let mut char_offset = 0;
@@ -193,6 +227,10 @@ impl EditorElement {
     pub(super) fn paint_cursor(&self, bounds: Bounds<Pixels>, window: &mut Window, cx: &mut App) {
         // Get the cursor position from the editor (original content position)
         let original_cursor_position = self.editor.read(cx).cursor_position();
+        self.paint_cursor_at(original_cursor_position, bounds, window);
+    }
+
+    pub(super) fn paint_cursor_at(&self, original_cursor_position: usize, bounds: Bounds<Pixels>, window: &mut Window) {
         let content = &self.content;
         
         eprintln!("DEBUG RENDER: Painting cursor at original position: {}", original_cursor_position);
@@ -0,0 +1,425 @@
+//! Standalone HTML export
+//!
+//! `HtmlExporter` walks the `MarkdownAst` from `MarkdownParser::parse_ast` and
+//! writes a complete HTML page with the stylesheet embedded, so the file can
+//! be sent to anyone with a browser. GFM tables, task lists and footnotes are
+//! supported, along with the editor's own highlight, tag, subscript and
+//! superscript syntax. Colors come from a `StyleContext`.
+
+use gpui::Hsla;
+use pulldown_cmark::Alignment;
+
+use crate::markdown_ast::{MarkdownAst, NodeId, NodeKind};
+use crate::markdown_parser::MarkdownParser;
+use crate::rendering::StyleContext;
+
+/// Export `markdown` as a standalone page using the default theme
+pub fn export_html(markdown: &str) -> String {
+    HtmlExporter::new(StyleContext::default()).export(markdown)
+}
+
+#[derive(Clone)]
+pub struct HtmlExporter {
+    parser: MarkdownParser,
+    style: StyleContext,
+    title: Option<String>,
+}
+
+impl HtmlExporter {
+    pub fn new(style: StyleContext) -> Self {
+        Self {
+            parser: MarkdownParser::new(),
+            style,
+            title: None,
+        }
+    }
+
+    /// Page title; defaults to the text of the first heading
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// A complete HTML page with the stylesheet in its `<head>`
+    pub fn export(&self, markdown: &str) -> String {
+        let ast = self.parser.parse_ast(markdown);
+        let title = self
+            .title
+            .clone()
+            .or_else(|| first_heading(&ast))
+            .unwrap_or_else(|| "Document".to_string());
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\" />\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n\
+             <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape_html(&title),
+            self.stylesheet(),
+            HtmlWriter::new(&ast).write()
+        )
+    }
+
+    /// Just the HTML for the document's content, without page or stylesheet
+    pub fn export_body(&self, markdown: &str) -> String {
+        HtmlWriter::new(&self.parser.parse_ast(markdown)).write()
+    }
+
+    fn stylesheet(&self) -> String {
+        let text = css_color(self.style.text_color);
+        let code = css_color(self.style.code_color);
+        let border = css_color(self.style.border_color);
+        let background = css_color(self.style.background_color);
+        format!(
+            "body {{ background: {background}; color: {text}; font-family: system-ui, -apple-system, sans-serif; \
+             line-height: 1.6; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; }}\n\
+             a {{ color: {code}; }}\n\
+             code, pre {{ font-family: ui-monospace, Menlo, Consolas, monospace; color: {code}; }}\n\
+             pre {{ border: 1px solid {border}; border-radius: 4px; padding: 0.75rem 1rem; overflow-x: auto; }}\n\
+             blockquote {{ border-left: 3px solid {border}; margin-left: 0; padding-left: 1rem; }}\n\
+             table {{ border-collapse: collapse; }}\n\
+             th, td {{ border: 1px solid {border}; padding: 0.25rem 0.75rem; }}\n\
+             hr {{ border: none; border-top: 1px solid {border}; }}\n\
+             mark {{ background: {code}; color: {background}; padding: 0 0.2em; }}\n\
+             .tag {{ color: {code}; }}\n\
+             .task-list-item {{ list-style: none; }}\n\
+             .footnotes {{ font-size: 0.9em; }}\n"
+        )
+    }
+}
+
+/// CSS for a theme color. StyleContext hues are in degrees.
+fn css_color(color: Hsla) -> String {
+    format!(
+        "hsla({}, {}%, {}%, {})",
+        round(color.h),
+        round(color.s * 100.0),
+        round(color.l * 100.0),
+        round(color.a)
+    )
+}
+
+/// Round to two decimals so the output is stable and readable
+fn round(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+fn first_heading(ast: &MarkdownAst) -> Option<String> {
+    ast.descendants(ast.root())
+        .find(|&id| matches!(ast.node(id).kind, NodeKind::Heading(_)))
+        .map(|id| ast.text(id))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Writes the HTML for one document. Footnote definitions are collected
+/// and written at the end, numbered in the order they're first referenced.
+struct HtmlWriter<'a> {
+    ast: &'a MarkdownAst,
+    html: String,
+    /// Footnote labels in the order they're first referenced
+    footnotes: Vec<String>,
+}
+
+impl<'a> HtmlWriter<'a> {
+    fn new(ast: &'a MarkdownAst) -> Self {
+        let mut footnotes: Vec<String> = Vec::new();
+        for node in ast.nodes() {
+            if let NodeKind::FootnoteReference(label) = &node.kind {
+                if !footnotes.contains(label) {
+                    footnotes.push(label.clone());
+                }
+            }
+        }
+        Self {
+            ast,
+            html: String::new(),
+            footnotes,
+        }
+    }
+
+    fn write(mut self) -> String {
+        self.children(self.ast.root());
+        self.write_footnotes();
+        self.html
+    }
+
+    fn children(&mut self, id: NodeId) {
+        for &child in self.ast.children(id) {
+            self.node(child);
+        }
+    }
+
+    fn wrap(&mut self, open: &str, id: NodeId, close: &str) {
+        self.html.push_str(open);
+        self.children(id);
+        self.html.push_str(close);
+    }
+
+    fn node(&mut self, id: NodeId) {
+        let ast = self.ast;
+        match &ast.node(id).kind {
+            NodeKind::Document => self.children(id),
+
+            NodeKind::Paragraph => self.wrap("<p>", id, "</p>\n"),
+            NodeKind::Heading(level) => {
+                let level = (*level).clamp(1, 6);
+                self.wrap(&format!("<h{}>", level), id, &format!("</h{}>\n", level));
+            }
+            NodeKind::BlockQuote => self.wrap("<blockquote>\n", id, "</blockquote>\n"),
+            NodeKind::CodeBlock(language) => {
+                match language {
+                    Some(language) => self
+                        .html
+                        .push_str(&format!("<pre><code class=\"language-{}\">", escape_html(language))),
+                    None => self.html.push_str("<pre><code>"),
+                }
+                self.html.push_str(&escape_html(&ast.text(id)));
+                self.html.push_str("</code></pre>\n");
+            }
+            NodeKind::HtmlBlock => self.children(id),
+            NodeKind::List(Some(1)) => self.wrap("<ol>\n", id, "</ol>\n"),
+            NodeKind::List(Some(start)) => self.wrap(&format!("<ol start=\"{}\">\n", start), id, "</ol>\n"),
+            NodeKind::List(None) => self.wrap("<ul>\n", id, "</ul>\n"),
+            NodeKind::Item => {
+                let is_task = ast
+                    .descendants(id)
+                    .take_while(|&descendant| !matches!(ast.node(descendant).kind, NodeKind::List(_)))
+                    .any(|descendant| matches!(ast.node(descendant).kind, NodeKind::TaskListMarker(_)));
+                let open = if is_task { "<li class=\"task-list-item\">" } else { "<li>" };
+                self.wrap(open, id, "</li>\n");
+            }
+            // Written after the content, see `write_footnotes`
+            NodeKind::FootnoteDefinition(_) => {}
+            NodeKind::Table(_) => {
+                self.html.push_str("<table>\n");
+                let (head, rows) = match ast.children(id).split_first() {
+                    Some((head, rows)) => (Some(*head), rows),
+                    None => (None, &[][..]),
+                };
+                if let Some(head) = head {
+                    self.wrap("<thead>\n<tr>\n", head, "</tr>\n</thead>\n");
+                }
+                if !rows.is_empty() {
+                    self.html.push_str("<tbody>\n");
+                    for &row in rows {
+                        self.node(row);
+                    }
+                    self.html.push_str("</tbody>\n");
+                }
+                self.html.push_str("</table>\n");
+            }
+            NodeKind::TableHead => self.children(id),
+            NodeKind::TableRow => self.wrap("<tr>\n", id, "</tr>\n"),
+            NodeKind::TableCell => self.table_cell(id),
+            NodeKind::MetadataBlock => {}
+            NodeKind::Rule => self.html.push_str("<hr />\n"),
+
+            NodeKind::Emphasis => self.wrap("<em>", id, "</em>"),
+            NodeKind::Strong => self.wrap("<strong>", id, "</strong>"),
+            NodeKind::Strikethrough => self.wrap("<del>", id, "</del>"),
+            NodeKind::Link { url, title } => {
+                let open = format!("<a href=\"{}\"{}>", escape_html(url), title_attribute(title));
+                self.wrap(&open, id, "</a>");
+            }
+            NodeKind::Image { url, title } => {
+                self.html.push_str(&format!(
+                    "<img src=\"{}\" alt=\"{}\"{} />",
+                    escape_html(url),
+                    escape_html(&ast.text(id)),
+                    title_attribute(title)
+                ));
+            }
+            NodeKind::Subscript => self.script(id, "sub"),
+            NodeKind::Superscript => self.script(id, "sup"),
+
+            NodeKind::Text(text) => self.text(id, text),
+            NodeKind::Code(code) => self.html.push_str(&format!("<code>{}</code>", escape_html(code))),
+            NodeKind::Html(html) => self.html.push_str(html),
+            NodeKind::FootnoteReference(label) => {
+                let number = self.footnote_number(label);
+                self.html.push_str(&format!(
+                    "<sup class=\"footnote-ref\"><a href=\"#fn-{label}\" id=\"fnref-{label}\">{number}</a></sup>",
+                    label = escape_html(label),
+                    number = number
+                ));
+            }
+            NodeKind::TaskListMarker(checked) => {
+                let checked = if *checked { " checked=\"\"" } else { "" };
+                self.html.push_str(&format!("<input type=\"checkbox\" disabled=\"\"{} /> ", checked));
+            }
+            NodeKind::SoftBreak => self.html.push('\n'),
+            NodeKind::HardBreak => self.html.push_str("<br />\n"),
+
+            NodeKind::Highlight(content) => self.html.push_str(&format!("<mark>{}</mark>", escape_html(content))),
+            NodeKind::Tag(content) => {
+                self.html.push_str(&format!("<span class=\"tag\">#{}</span>", escape_html(content)))
+            }
+            NodeKind::Emoji(content) => self.html.push_str(&escape_html(content)),
+        }
+    }
+
+    /// Text with any highlight, tag and emoji spans inside it
+    fn text(&mut self, id: NodeId, text: &str) {
+        let text_start = self.ast.node(id).range.start;
+        let mut last_end = 0;
+        for &span in self.ast.children(id) {
+            let span_range = self.ast.node(span).range.clone();
+            self.html.push_str(&escape_html(&text[last_end..span_range.start - text_start]));
+            self.node(span);
+            last_end = span_range.end - text_start;
+        }
+        self.html.push_str(&escape_html(&text[last_end..]));
+    }
+
+    /// Sub/superscript wrapper: its children include the source's own tags,
+    /// which are replaced so the output is always balanced
+    fn script(&mut self, id: NodeId, tag: &str) {
+        let open = format!("<{}>", tag);
+        let close = format!("</{}>", tag);
+        self.html.push_str(&open);
+        for &child in self.ast.children(id) {
+            match &self.ast.node(child).kind {
+                NodeKind::Html(html) if *html == open || *html == close => {}
+                NodeKind::Html(html) => self.html.push_str(html.trim_start_matches(&open).trim_end_matches(&close)),
+                _ => self.node(child),
+            }
+        }
+        self.html.push_str(&close);
+    }
+
+    fn table_cell(&mut self, id: NodeId) {
+        let ast = self.ast;
+        let row = ast.parent(id).unwrap_or(id);
+        let in_head = matches!(ast.node(row).kind, NodeKind::TableHead);
+        let column = ast.children(row).iter().position(|&cell| cell == id).unwrap_or(0);
+        let alignment = ast
+            .ancestors(id)
+            .find_map(|ancestor| match &ast.node(ancestor).kind {
+                NodeKind::Table(alignments) => alignments.get(column).copied(),
+                _ => None,
+            })
+            .unwrap_or(Alignment::None);
+
+        let tag = if in_head { "th" } else { "td" };
+        let style = match alignment {
+            Alignment::None => "",
+            Alignment::Left => " style=\"text-align: left\"",
+            Alignment::Center => " style=\"text-align: center\"",
+            Alignment::Right => " style=\"text-align: right\"",
+        };
+        self.wrap(&format!("<{}{}>", tag, style), id, &format!("</{}>\n", tag));
+    }
+
+    fn footnote_number(&mut self, label: &str) -> usize {
+        match self.footnotes.iter().position(|known| known == label) {
+            Some(index) => index + 1,
+            None => {
+                self.footnotes.push(label.to_string());
+                self.footnotes.len()
+            }
+        }
+    }
+
+    fn write_footnotes(&mut self) {
+        let ast = self.ast;
+        let mut definitions: Vec<(usize, NodeId, String)> = ast
+            .descendants(ast.root())
+            .filter_map(|id| match &ast.node(id).kind {
+                NodeKind::FootnoteDefinition(label) => Some((id, label.clone())),
+                _ => None,
+            })
+            .map(|(id, label)| (self.footnote_number(&label), id, label))
+            .collect();
+        if definitions.is_empty() {
+            return;
+        }
+        definitions.sort_by_key(|(number, _, _)| *number);
+
+        self.html.push_str("<section class=\"footnotes\">\n<hr />\n<ol>\n");
+        for (_, id, label) in definitions {
+            let label = escape_html(&label);
+            self.html.push_str(&format!("<li id=\"fn-{}\">\n", label));
+            self.children(id);
+            self.html.push_str(&format!("<a href=\"#fnref-{}\" class=\"footnote-backref\">↩</a>\n</li>\n", label));
+        }
+        self.html.push_str("</ol>\n</section>\n");
+    }
+}
+
+fn title_attribute(title: &str) -> String {
+    if title.is_empty() {
+        String::new()
+    } else {
+        format!(" title=\"{}\"", escape_html(title))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// Compare an export against `golden/<name>.html`. Run with
+    /// `UPDATE_GOLDEN=1` to rewrite the expected files after a deliberate change.
+    fn assert_golden(name: &str) {
+        let dir: PathBuf = Path::new(file!()).with_file_name("golden");
+        let markdown = fs::read_to_string(dir.join(format!("{}.md", name))).unwrap();
+        let exported = HtmlExporter::new(StyleContext::default()).export(&markdown);
+
+        let expected_path = dir.join(format!("{}.html", name));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&expected_path, &exported).unwrap();
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap();
+        assert_eq!(exported, expected, "export of {}.md differs from {}.html", name, name);
+    }
+
+    #[test]
+    fn test_golden_basics() {
+        assert_golden("basics");
+    }
+
+    #[test]
+    fn test_golden_gfm() {
+        assert_golden("gfm");
+    }
+
+    #[test]
+    fn test_golden_custom_syntax() {
+        assert_golden("custom_syntax");
+    }
+
+    #[test]
+    fn test_text_is_escaped() {
+        let exporter = HtmlExporter::new(StyleContext::default());
+        assert_eq!(
+            exporter.export_body("Use `a < b` & \"quotes\""),
+            "<p>Use <code>a &lt; b</code> &amp; “quotes”</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_title_and_theme_colors() {
+        let style = StyleContext::default().with_text_color(Hsla { h: 200.0, s: 0.5, l: 0.25, a: 1.0 });
+        let page = HtmlExporter::new(style.clone()).export("# Weekly notes\n\nBody");
+        assert!(page.contains("<title>Weekly notes</title>"));
+        assert!(page.contains("color: hsla(200, 50%, 25%, 1);"));
+
+        let page = HtmlExporter::new(style).with_title("Shared <draft>").export("Body");
+        assert!(page.contains("<title>Shared &lt;draft&gt;</title>"));
+    }
+}
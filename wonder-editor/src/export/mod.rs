@@ -0,0 +1,7 @@
+// Export module - Turning documents into formats for people without the editor
+
+pub mod html;
+pub mod text;
+
+pub use html::{export_html, HtmlExporter};
+pub use text::export_text;
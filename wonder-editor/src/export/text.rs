@@ -0,0 +1,239 @@
+//! Plain text export
+//!
+//! Strips the Markdown syntax but keeps the shape of the document: blocks are
+//! separated by blank lines, lists keep their bullets and numbers, quotes are
+//! prefixed with `> `, code blocks are indented and tables are padded into
+//! columns. Links keep their URL in parentheses and footnotes are listed at
+//! the end, so nothing the reader needs is lost.
+
+use crate::markdown_ast::{MarkdownAst, NodeId, NodeKind};
+use crate::markdown_parser::MarkdownParser;
+
+/// Export `markdown` as plain text, ending in a single newline
+pub fn export_text(markdown: &str) -> String {
+    let ast = MarkdownParser::new().parse_ast(markdown);
+    let mut writer = TextWriter { ast: &ast, footnotes: Vec::new() };
+    let mut blocks = writer.blocks(ast.root());
+
+    let mut definitions: Vec<(usize, String)> = ast
+        .descendants(ast.root())
+        .filter_map(|id| match &ast.node(id).kind {
+            NodeKind::FootnoteDefinition(label) => {
+                Some((writer.footnote_number(label), writer.blocks(id).join("\n\n")))
+            }
+            _ => None,
+        })
+        .collect();
+    definitions.sort();
+    if !definitions.is_empty() {
+        let lines: Vec<String> = definitions
+            .into_iter()
+            .map(|(number, text)| format!("[{}] {}", number, text))
+            .collect();
+        blocks.push(format!("----\n{}", lines.join("\n")));
+    }
+
+    let mut text = blocks.join("\n\n");
+    text.push('\n');
+    text
+}
+
+struct TextWriter<'a> {
+    ast: &'a MarkdownAst,
+    /// Footnote labels in the order they're first referenced
+    footnotes: Vec<String>,
+}
+
+impl<'a> TextWriter<'a> {
+    /// The text of each block inside `id`
+    fn blocks(&mut self, id: NodeId) -> Vec<String> {
+        let ast = self.ast;
+        let mut blocks = Vec::new();
+        for &child in ast.children(id) {
+            if let Some(block) = self.block(child) {
+                blocks.push(block);
+            }
+        }
+        blocks
+    }
+
+    fn block(&mut self, id: NodeId) -> Option<String> {
+        let ast = self.ast;
+        let text = match &ast.node(id).kind {
+            NodeKind::Paragraph | NodeKind::Heading(_) | NodeKind::TableCell => self.inline(id),
+            NodeKind::BlockQuote => prefix_lines(&self.blocks(id).join("\n\n"), "> ", "> "),
+            NodeKind::CodeBlock(_) => {
+                let code = ast.text(id);
+                prefix_lines(code.trim_end_matches('\n'), "    ", "    ")
+            }
+            NodeKind::List(start) => {
+                let mut items = Vec::new();
+                for (index, &item) in ast.children(id).iter().enumerate() {
+                    let marker = match start {
+                        Some(start) => format!("{}. ", *start as usize + index),
+                        None => "- ".to_string(),
+                    };
+                    let indent = " ".repeat(marker.chars().count());
+                    let content = self.item(item);
+                    items.push(prefix_lines(&content, &marker, &indent));
+                }
+                items.join("\n")
+            }
+            NodeKind::Table(_) => self.table(id),
+            NodeKind::Rule => "----".to_string(),
+            // Inline content directly inside an item of a tight list
+            kind if kind.is_inline() => self.inline_node(id),
+            _ => return None,
+        };
+        Some(text)
+    }
+
+    /// A list item: its inline text and nested blocks on following lines
+    fn item(&mut self, id: NodeId) -> String {
+        let ast = self.ast;
+        let mut parts: Vec<String> = Vec::new();
+        let mut inline = String::new();
+        for &child in ast.children(id) {
+            if ast.node(child).kind.is_inline() {
+                inline.push_str(&self.inline_node(child));
+            } else if let Some(block) = self.block(child) {
+                if !inline.is_empty() {
+                    parts.push(std::mem::take(&mut inline));
+                }
+                parts.push(block);
+            }
+        }
+        if !inline.is_empty() {
+            parts.push(inline);
+        }
+        parts.join("\n")
+    }
+
+    fn table(&mut self, id: NodeId) -> String {
+        let ast = self.ast;
+        let rows: Vec<Vec<String>> = ast
+            .children(id)
+            .iter()
+            .map(|&row| ast.children(row).iter().map(|&cell| self.inline(cell)).collect())
+            .collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                rows.iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        rows.iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect();
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn inline(&mut self, id: NodeId) -> String {
+        let ast = self.ast;
+        ast.children(id).iter().map(|&child| self.inline_node(child)).collect()
+    }
+
+    fn inline_node(&mut self, id: NodeId) -> String {
+        let ast = self.ast;
+        match &ast.node(id).kind {
+            NodeKind::Text(text) => {
+                // Highlight, tag and emoji spans only mark up part of the text
+                let text_start = ast.node(id).range.start;
+                let mut plain = String::new();
+                let mut last_end = 0;
+                for &span in ast.children(id) {
+                    let span_node = ast.node(span);
+                    plain.push_str(&text[last_end..span_node.range.start - text_start]);
+                    match &span_node.kind {
+                        NodeKind::Tag(content) => plain.push_str(&format!("#{}", content)),
+                        NodeKind::Highlight(content) | NodeKind::Emoji(content) => plain.push_str(content),
+                        _ => {}
+                    }
+                    last_end = span_node.range.end - text_start;
+                }
+                plain.push_str(&text[last_end..]);
+                plain
+            }
+            NodeKind::Code(code) => code.clone(),
+            NodeKind::SoftBreak | NodeKind::HardBreak => "\n".to_string(),
+            NodeKind::TaskListMarker(true) => "[x] ".to_string(),
+            NodeKind::TaskListMarker(false) => "[ ] ".to_string(),
+            NodeKind::Html(_) => String::new(),
+            NodeKind::Image { .. } => ast.text(id),
+            NodeKind::Link { url, .. } => {
+                let text = self.inline(id);
+                if text == *url || url.is_empty() {
+                    text
+                } else {
+                    format!("{} ({})", text, url)
+                }
+            }
+            NodeKind::FootnoteReference(label) => format!("[{}]", self.footnote_number(label)),
+            _ => self.inline(id),
+        }
+    }
+
+    fn footnote_number(&mut self, label: &str) -> usize {
+        match self.footnotes.iter().position(|known| known == label) {
+            Some(index) => index + 1,
+            None => {
+                self.footnotes.push(label.to_string());
+                self.footnotes.len()
+            }
+        }
+    }
+}
+
+/// Prefix the first line with `first` and the rest with `rest`
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let prefix = if index == 0 { first } else { rest };
+            if line.is_empty() {
+                prefix.trim_end().to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_text_keeps_structure() {
+        let markdown = "# Plan\n\nShip **soon**, see [notes](https://example.com).\n\n\
+                        - [x] Write\n- [ ] Review\n  1. Ana\n  2. Ben\n\n> Quoted\n> text\n\n\
+                        ```\nlet x = 1;\n```\n";
+        assert_eq!(
+            export_text(markdown),
+            "Plan\n\nShip soon, see notes (https://example.com).\n\n\
+             - [x] Write\n- [ ] Review\n  1. Ana\n  2. Ben\n\n> Quoted\n> text\n\n    let x = 1;\n"
+        );
+    }
+
+    #[test]
+    fn test_export_text_tables_and_footnotes() {
+        let markdown = "| Item | Owner |\n|---|---|\n| Docs | Ana |\n\nLate[^1] ==again==, a == b.\n\n[^1]: Holidays.\n";
+        assert_eq!(
+            export_text(markdown),
+            "Item  Owner\nDocs  Ana\n\nLate[1] again, a == b.\n\n----\n[1] Holidays.\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Write `contents` to `path` without ever leaving a truncated file behind.
+/// The data goes to a temporary file in the same directory, which is synced
+/// and then renamed over the destination, and the directory is synced so
+/// the rename survives a crash too. A symlink is written through to the file
+/// it points at, and an existing file keeps its permissions.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let permissions = fs::metadata(&path).ok().map(|metadata| metadata.permissions());
+    let temp_path = temp_path_for(&path);
+    let result = write_and_sync(&temp_path, contents, permissions)
+        .and_then(|_| fs::rename(&temp_path, &path))
+        .and_then(|_| sync_directory(&path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_and_sync(path: &Path, contents: &str, permissions: Option<fs::Permissions>) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// Sync the directory holding `path`, which makes a rename into it durable
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty());
+    fs::File::open(directory.unwrap_or(Path::new(".")))?.sync_all()
+}
+
+/// Other platforms can't open a directory to sync it
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Hidden sibling of `path`, so the rename never crosses file systems
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::scratch_dir;
+
+    #[test]
+    fn test_write_atomically_replaces_contents() {
+        let dir = scratch_dir("atomic-write");
+        let path = dir.join("note.md");
+
+        write_atomically(&path, "# First").unwrap();
+        write_atomically(&path, "# Second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Second");
+
+        // No temporary files are left next to the note
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_failed_write_leaves_original_untouched() {
+        let dir = scratch_dir("atomic-write-failure");
+        let path = dir.join("note.md");
+        write_atomically(&path, "keep me").unwrap();
+
+        // Renaming onto a directory fails after the temp file was written
+        let blocked = dir.join("blocked.md");
+        fs::create_dir(&blocked).unwrap();
+        fs::write(blocked.join("child"), "x").unwrap();
+        assert!(write_atomically(&blocked, "lost").is_err());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+        assert!(!temp_path_for(&blocked).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("atomic-write-permissions");
+        let path = dir.join("private.md");
+        write_atomically(&path, "secret").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomically(&path, "still secret").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_through_symlink() {
+        let dir = scratch_dir("atomic-write-symlink");
+        let target = dir.join("target.md");
+        let link = dir.join("link.md");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomically(&link, "new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors from opening or saving documents
+#[derive(Debug)]
+pub enum FileError {
+    /// The path doesn't have a supported extension
+    UnsupportedExtension(PathBuf),
+    /// The document has never been saved, so Save needs a path (Save As)
+    NoPath,
+    /// Switching files would lose edits that haven't been saved
+    UnsavedChanges,
+    Io(io::Error),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileError::UnsupportedExtension(path) => write!(
+                f,
+                "Unsupported file type: {} (expected .md, .markdown or .txt)",
+                path.display()
+            ),
+            FileError::NoPath => write!(f, "The document has not been saved yet"),
+            FileError::UnsavedChanges => write!(f, "The document has unsaved changes"),
+            FileError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(error: io::Error) -> Self {
+        FileError::Io(error)
+    }
+}
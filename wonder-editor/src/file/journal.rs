@@ -0,0 +1,157 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::content_hash;
+use crate::core::commands::CommandRecord;
+
+/// First line of a journal: which saved content the edits apply to
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalHeader {
+    base_hash: u64,
+}
+
+/// Append-only log of the edits made since a document was last saved.
+/// The first line identifies the saved content; every following line is one
+/// `CommandRecord` as JSON, so a crash can at worst cut off the last edit.
+#[derive(Debug)]
+pub struct RecoveryJournal {
+    path: PathBuf,
+    file: fs::File,
+}
+
+impl RecoveryJournal {
+    /// Swap file for a document: a hidden sibling such as `.note.md.swp`
+    pub fn swap_path_for(document_path: &Path) -> PathBuf {
+        let file_name = document_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        document_path.with_file_name(format!(".{}.swp", file_name))
+    }
+
+    /// Start a new journal for edits on top of content with `base_hash`,
+    /// replacing any journal already at `path`
+    pub fn create(path: &Path, base_hash: u64) -> io::Result<Self> {
+        let mut file = fs::File::create(path)?;
+        let header = serde_json::to_string(&JournalHeader { base_hash })?;
+        writeln!(file, "{}", header)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append edits; each write goes straight to the OS, so they survive the
+    /// editor crashing
+    pub fn append(&mut self, edits: &[CommandRecord]) -> io::Result<()> {
+        let mut lines = String::new();
+        for edit in edits {
+            lines.push_str(&serde_json::to_string(edit)?);
+            lines.push('\n');
+        }
+        self.file.write_all(lines.as_bytes())
+    }
+
+    /// Delete the swap file, e.g. once the document has been saved
+    pub fn remove(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+
+    /// Read the edits journaled on top of `base`. Returns `None` if there is no
+    /// journal, it was written for different content, or it holds no edits.
+    pub fn load(path: &Path, base: &str) -> Option<Vec<CommandRecord>> {
+        let contents = fs::read_to_string(path).ok()?;
+        let mut lines = contents.lines();
+        let header: JournalHeader = serde_json::from_str(lines.next()?).ok()?;
+        if header.base_hash != content_hash(base) {
+            return None;
+        }
+
+        // Stop at the first unreadable line: it was cut off mid-write
+        let edits: Vec<CommandRecord> = lines
+            .map_while(|line| serde_json::from_str(line).ok())
+            .collect();
+        if edits.is_empty() {
+            None
+        } else {
+            Some(edits)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::TextDocument;
+    use crate::file::scratch_dir;
+
+    #[test]
+    fn test_swap_path_is_hidden_sibling() {
+        assert_eq!(
+            RecoveryJournal::swap_path_for(Path::new("/notes/todo.md")),
+            PathBuf::from("/notes/.todo.md.swp")
+        );
+    }
+
+    #[test]
+    fn test_journal_replays_into_fresh_document() {
+        let dir = scratch_dir("journal-replay");
+        let saved = "# Groceries\n\n- milk\n";
+        let path = dir.join(".groceries.md.swp");
+
+        let mut document = TextDocument::with_content(saved.to_string());
+        document.set_edit_recording(true);
+        let mut journal = RecoveryJournal::create(&path, content_hash(saved)).unwrap();
+
+        document.insert_text("- eggs\n");
+        journal.append(&document.take_recorded_edits()).unwrap();
+        document.set_cursor_position(2);
+        document.delete_to_line_end();
+        document.insert_text("Shopping");
+        journal.append(&document.take_recorded_edits()).unwrap();
+        drop(journal);
+
+        // After a "crash", replay the journal onto the saved file
+        let edits = RecoveryJournal::load(&path, saved).unwrap();
+        let mut recovered = TextDocument::with_content(saved.to_string());
+        assert_eq!(recovered.replay_edits(&edits), edits.len());
+        assert_eq!(recovered.content(), document.content());
+    }
+
+    #[test]
+    fn test_journal_for_other_content_is_ignored() {
+        let dir = scratch_dir("journal-mismatch");
+        let path = dir.join(".note.md.swp");
+
+        let mut journal = RecoveryJournal::create(&path, content_hash("old")).unwrap();
+        journal
+            .append(&[CommandRecord::Insert { position: 0, text: "x".to_string() }])
+            .unwrap();
+
+        assert!(RecoveryJournal::load(&path, "changed on disk").is_none());
+        assert!(RecoveryJournal::load(&dir.join("missing.swp"), "old").is_none());
+        assert_eq!(RecoveryJournal::load(&path, "old").map(|edits| edits.len()), Some(1));
+    }
+
+    #[test]
+    fn test_truncated_last_line_is_skipped() {
+        let dir = scratch_dir("journal-truncated");
+        let path = dir.join(".note.md.swp");
+
+        let mut journal = RecoveryJournal::create(&path, content_hash("")).unwrap();
+        journal
+            .append(&[CommandRecord::Insert { position: 0, text: "kept".to_string() }])
+            .unwrap();
+        journal.file.write_all(b"{\"kind\":\"insert\",\"posi").unwrap();
+
+        let edits = RecoveryJournal::load(&path, "").unwrap();
+        assert_eq!(edits, vec![CommandRecord::Insert { position: 0, text: "kept".to_string() }]);
+    }
+}
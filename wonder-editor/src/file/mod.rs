@@ -0,0 +1,70 @@
+// File module - Loading and saving the documents behind the editor
+
+pub mod atomic_write;
+pub mod error;
+pub mod journal;
+pub mod session;
+pub mod undo_history;
+
+pub use atomic_write::write_atomically;
+pub use error::FileError;
+pub use journal::RecoveryJournal;
+pub use session::{FileSession, UnsavedChanges};
+pub use undo_history::{load_undo_history, save_undo_history, undo_path_for};
+
+use std::path::Path;
+
+/// File extensions the editor can open and save
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+
+/// Whether `path` has one of the supported extensions (case-insensitive)
+pub fn is_supported_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| {
+            SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|supported| supported.eq_ignore_ascii_case(extension))
+        })
+        .unwrap_or(false)
+}
+
+/// Stable 64-bit FNV-1a hash of `text`, used to tell whether saved content
+/// is still what a journal or sidecar file was written against
+pub fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A fresh, empty directory for tests that touch the file system
+#[cfg(test)]
+pub(crate) fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir()
+        .join("wonder-editor-tests")
+        .join(format!("{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_extensions() {
+        assert!(is_supported_path(Path::new("notes.md")));
+        assert!(is_supported_path(Path::new("notes.Markdown")));
+        assert!(is_supported_path(Path::new("/tmp/todo.TXT")));
+        assert!(!is_supported_path(Path::new("image.png")));
+        assert!(!is_supported_path(Path::new("README")));
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(""), 0xcbf29ce484222325);
+        assert_eq!(content_hash("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(content_hash("note"), content_hash("notes"));
+    }
+}
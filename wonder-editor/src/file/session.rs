@@ -0,0 +1,597 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::undo_history::{load_undo_history, save_undo_history, undo_path_for, MAX_UNDO_HISTORY_BYTES};
+use super::{content_hash, is_supported_path, write_atomically, FileError, RecoveryJournal};
+use crate::core::commands::CommandRecord;
+use crate::core::diff::{self, MergeResult};
+use crate::core::TextDocument;
+
+/// What to do with unsaved edits when switching to another document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsavedChanges {
+    /// Refuse to switch and report `FileError::UnsavedChanges`
+    Keep,
+    /// Throw the edits away
+    Discard,
+}
+
+/// Tracks the file behind a `TextDocument` and whether it has unsaved changes.
+/// The document is dirty whenever its version differs from the version that
+/// was last loaded or saved.
+///
+/// Edits to a file-backed document are also journaled to a swap file next to
+/// it (see `RecoveryJournal`) until the next save, so they can be recovered
+/// if the editor dies. Untitled documents aren't journaled.
+///
+/// Changes made to the file by other programs are picked up by polling
+/// `check_external_change`, then either reloaded or merged with the edits.
+#[derive(Debug)]
+pub struct FileSession {
+    path: Option<PathBuf>,
+    saved_version: u64,
+    /// The file's content as last loaded or saved: the base for merges
+    saved_content: String,
+    /// Modification time of the file when we last looked at it
+    disk_modified: Option<SystemTime>,
+    journal: Option<RecoveryJournal>,
+    /// Edits found in a swap file when the document was opened
+    recovery: Option<Vec<CommandRecord>>,
+}
+
+impl FileSession {
+    /// An untitled session that treats the document's current state as saved
+    pub fn new(document: &TextDocument) -> Self {
+        Self {
+            path: None,
+            saved_version: document.version(),
+            saved_content: document.content(),
+            disk_modified: None,
+            journal: None,
+            recovery: None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// File name for the title bar, "Untitled" until the first save
+    pub fn display_name(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string())
+    }
+
+    pub fn is_dirty(&self, document: &TextDocument) -> bool {
+        document.version() != self.saved_version
+    }
+
+    /// Guard to run before replacing the document with another one
+    pub fn check_unsaved(&self, document: &TextDocument, unsaved: UnsavedChanges) -> Result<(), FileError> {
+        if unsaved == UnsavedChanges::Keep && self.is_dirty(document) {
+            Err(FileError::UnsavedChanges)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replace the document with an empty, untitled one
+    pub fn new_document(&mut self, document: &mut TextDocument, unsaved: UnsavedChanges) -> Result<(), FileError> {
+        self.check_unsaved(document, unsaved)?;
+        self.close_journal();
+        document.load_content(String::new());
+        document.set_edit_recording(false);
+        self.path = None;
+        self.mark_saved(document);
+        Ok(())
+    }
+
+    /// Load `path` into the document. The undo history starts over, unless
+    /// one was saved next to the file when it last had this content.
+    pub fn open(&mut self, document: &mut TextDocument, path: &Path, unsaved: UnsavedChanges) -> Result<(), FileError> {
+        self.check_unsaved(document, unsaved)?;
+        if !is_supported_path(path) {
+            return Err(FileError::UnsupportedExtension(path.to_path_buf()));
+        }
+
+        let content = fs::read_to_string(path)?;
+        self.close_journal();
+        let recovery = RecoveryJournal::load(&RecoveryJournal::swap_path_for(path), &content);
+        let undo_history = load_undo_history(&undo_path_for(path), &content);
+        document.load_content(content);
+        if let Some(history) = undo_history {
+            document.restore_undo_history(history);
+        }
+        document.set_edit_recording(true);
+        self.path = Some(path.to_path_buf());
+        self.mark_saved(document);
+        self.recovery = recovery;
+        Ok(())
+    }
+
+    /// Save to the current path; untitled documents need `save_as`
+    pub fn save(&mut self, document: &mut TextDocument) -> Result<(), FileError> {
+        let path = self.path.clone().ok_or(FileError::NoPath)?;
+        write_atomically(&path, &document.content())?;
+        self.mark_saved(document);
+        self.store_undo_history(document);
+        Ok(())
+    }
+
+    /// Save to `path` and keep editing that file from now on
+    pub fn save_as(&mut self, document: &mut TextDocument, path: &Path) -> Result<(), FileError> {
+        if !is_supported_path(path) {
+            return Err(FileError::UnsupportedExtension(path.to_path_buf()));
+        }
+
+        write_atomically(path, &document.content())?;
+        self.path = Some(path.to_path_buf());
+        document.set_edit_recording(true);
+        self.mark_saved(document);
+        self.store_undo_history(document);
+        Ok(())
+    }
+
+    /// Edits found in the swap file when the document was opened, if any
+    pub fn pending_recovery(&self) -> Option<&[CommandRecord]> {
+        self.recovery.as_deref()
+    }
+
+    /// Replay the pending recovered edits onto the document, returning how
+    /// many applied. The document stays dirty until it is saved.
+    pub fn recover(&mut self, document: &mut TextDocument) -> usize {
+        match self.recovery.take() {
+            Some(edits) => document.replay_edits(&edits),
+            None => 0,
+        }
+    }
+
+    /// Throw the pending recovered edits away, along with their swap file
+    pub fn dismiss_recovery(&mut self) {
+        if self.recovery.take().is_some() {
+            if let Some(path) = &self.path {
+                let _ = fs::remove_file(RecoveryJournal::swap_path_for(path));
+            }
+        }
+    }
+
+    /// Append the document's latest edits to its swap file. While a recovery
+    /// is pending the edits are held back, so the old swap file isn't lost
+    /// before the user decides what to do with it.
+    pub fn flush_journal(&mut self, document: &mut TextDocument) -> Result<(), FileError> {
+        if self.recovery.is_some() {
+            return Ok(());
+        }
+        let edits = document.take_recorded_edits();
+        let path = match &self.path {
+            Some(path) if !edits.is_empty() => path,
+            _ => return Ok(()),
+        };
+
+        if self.journal.is_none() {
+            let swap_path = RecoveryJournal::swap_path_for(path);
+            self.journal = Some(RecoveryJournal::create(&swap_path, content_hash(&self.saved_content))?);
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.append(&edits)?;
+        }
+        Ok(())
+    }
+
+    /// Look for a change to the file made outside the editor since it was
+    /// last loaded, saved or checked. Returns the new content if it differs
+    /// from what was last saved. A missing file is left alone: the buffer is
+    /// the only copy and the user can save it again.
+    pub fn check_external_change(&mut self) -> Result<Option<String>, FileError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let modified = match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        if self.disk_modified == Some(modified) {
+            return Ok(None);
+        }
+
+        self.disk_modified = Some(modified);
+        let content = fs::read_to_string(path)?;
+        if content == self.saved_content {
+            Ok(None)
+        } else {
+            Ok(Some(content))
+        }
+    }
+
+    /// Take the content on disk, dropping any unsaved edits. The reload is
+    /// one undoable edit and the cursor stays on its text where possible.
+    pub fn reload(&mut self, document: &mut TextDocument, disk_content: String) {
+        document.apply_external_change(&disk_content);
+        self.mark_saved(document);
+    }
+
+    /// Three-way merge the unsaved edits with the content on disk, using the
+    /// last saved content as the base. Conflicting regions get conflict
+    /// markers. The document stays dirty: the merge hasn't been saved yet.
+    pub fn merge(&mut self, document: &mut TextDocument, disk_content: String) -> Result<MergeResult, FileError> {
+        let merged = diff::merge3(&self.saved_content, &document.content(), &disk_content);
+        document.apply_external_change(&merged.text);
+        self.rebase(document, disk_content)?;
+        Ok(merged)
+    }
+
+    /// Keep the document as it is, treating the content on disk as the new
+    /// saved version so the change isn't offered again
+    pub fn keep_mine(&mut self, document: &mut TextDocument, disk_content: String) -> Result<(), FileError> {
+        self.rebase(document, disk_content)
+    }
+
+    /// Switch the saved base to `disk_content` while keeping the document's
+    /// edits. The journal restarts from the new base with a single edit that
+    /// turns it into the current document.
+    fn rebase(&mut self, document: &mut TextDocument, disk_content: String) -> Result<(), FileError> {
+        self.close_journal();
+        document.take_recorded_edits();
+        let content = document.content();
+        if content == disk_content {
+            self.saved_version = document.version();
+        }
+        self.saved_content = disk_content;
+
+        let path = match &self.path {
+            Some(path) if content != self.saved_content => path,
+            _ => return Ok(()),
+        };
+        let swap_path = RecoveryJournal::swap_path_for(path);
+        let mut journal = RecoveryJournal::create(&swap_path, content_hash(&self.saved_content))?;
+        journal.append(&[CommandRecord::Replace {
+            start: 0,
+            end: self.saved_content.chars().count(),
+            old_text: self.saved_content.clone(),
+            new_text: content,
+        }])?;
+        self.journal = Some(journal);
+        Ok(())
+    }
+
+    /// The document now matches what's on disk: reset dirty tracking and the journal
+    fn mark_saved(&mut self, document: &mut TextDocument) {
+        self.saved_version = document.version();
+        self.saved_content = document.content();
+        self.disk_modified = self
+            .path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok());
+        document.take_recorded_edits();
+        self.close_journal();
+    }
+
+    /// Keep the undo history next to the saved file for the next time it's
+    /// opened. The file itself is already saved, so this can't fail the save.
+    fn store_undo_history(&self, document: &mut TextDocument) {
+        if let Some(path) = &self.path {
+            let history = document.undo_history(MAX_UNDO_HISTORY_BYTES);
+            let _ = save_undo_history(&undo_path_for(path), &document.content(), history);
+        }
+    }
+
+    /// Remove the current swap file; its edits are either saved or discarded
+    fn close_journal(&mut self) {
+        self.recovery = None;
+        if let Some(journal) = self.journal.take() {
+            let _ = journal.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::scratch_dir;
+
+    #[test]
+    fn test_open_edit_save_tracks_dirty_state() {
+        let dir = scratch_dir("session-save");
+        let path = dir.join("note.md");
+        fs::write(&path, "# Note").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        assert_eq!(document.content(), "# Note");
+        assert_eq!(session.display_name(), "note.md");
+        assert!(!session.is_dirty(&document));
+
+        document.set_cursor_position(document.len());
+        document.insert_text("\n\nBody");
+        assert!(session.is_dirty(&document));
+
+        session.save(&mut document).unwrap();
+        assert!(!session.is_dirty(&document));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Note\n\nBody");
+    }
+
+    #[test]
+    fn test_unsaved_changes_guard() {
+        let dir = scratch_dir("session-guard");
+        let first = dir.join("first.md");
+        let second = dir.join("second.markdown");
+        fs::write(&first, "first").unwrap();
+        fs::write(&second, "second").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &first, UnsavedChanges::Keep).unwrap();
+        document.insert_char('!');
+
+        let result = session.open(&mut document, &second, UnsavedChanges::Keep);
+        assert!(matches!(result, Err(FileError::UnsavedChanges)));
+        assert_eq!(document.content(), "!first");
+        assert_eq!(session.path(), Some(first.as_path()));
+
+        // Discarding switches files and drops the old undo history
+        session.open(&mut document, &second, UnsavedChanges::Discard).unwrap();
+        assert_eq!(document.content(), "second");
+        assert!(!document.can_undo());
+        assert!(!session.is_dirty(&document));
+    }
+
+    #[test]
+    fn test_new_document_and_save_as() {
+        let dir = scratch_dir("session-save-as");
+        let mut document = TextDocument::with_content("draft".to_string());
+        let mut session = FileSession::new(&document);
+
+        session.new_document(&mut document, UnsavedChanges::Keep).unwrap();
+        assert_eq!(document.content(), "");
+        assert_eq!(session.display_name(), "Untitled");
+
+        document.insert_text("todo");
+        assert!(matches!(session.save(&mut document), Err(FileError::NoPath)));
+
+        let path = dir.join("todo.txt");
+        session.save_as(&mut document, &path).unwrap();
+        assert_eq!(session.path(), Some(path.as_path()));
+        assert!(!session.is_dirty(&document));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "todo");
+    }
+
+    #[test]
+    fn test_rejects_unsupported_files() {
+        let dir = scratch_dir("session-unsupported");
+        let path = dir.join("picture.png");
+        fs::write(&path, "not markdown").unwrap();
+
+        let mut document = TextDocument::with_content("kept".to_string());
+        let mut session = FileSession::new(&document);
+        assert!(matches!(
+            session.open(&mut document, &path, UnsavedChanges::Keep),
+            Err(FileError::UnsupportedExtension(_))
+        ));
+        assert!(matches!(
+            session.save_as(&mut document, &path),
+            Err(FileError::UnsupportedExtension(_))
+        ));
+        assert_eq!(document.content(), "kept");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not markdown");
+    }
+
+    #[test]
+    fn test_journal_recovers_unsaved_edits_after_crash() {
+        let dir = scratch_dir("session-recover");
+        let path = dir.join("crash.md");
+        fs::write(&path, "# Title").unwrap();
+
+        // Edit and flush, then "crash" without saving
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.set_cursor_position(document.len());
+        document.insert_text(" and body");
+        document.backspace();
+        session.flush_journal(&mut document).unwrap();
+        let swap_path = RecoveryJournal::swap_path_for(&path);
+        assert!(swap_path.exists());
+        drop(session);
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        assert!(session.pending_recovery().is_some());
+
+        // Nothing is journaled while the offer is pending
+        session.flush_journal(&mut document).unwrap();
+        assert!(swap_path.exists());
+
+        assert_eq!(session.recover(&mut document), 2);
+        assert_eq!(document.content(), "# Title and bod");
+        assert!(session.is_dirty(&document));
+        assert!(session.pending_recovery().is_none());
+
+        // The recovered edits are journaled again until the next save
+        session.flush_journal(&mut document).unwrap();
+        assert!(swap_path.exists());
+        session.save(&mut document).unwrap();
+        assert!(!swap_path.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Title and bod");
+    }
+
+    #[test]
+    fn test_dismissed_or_stale_recovery_is_dropped() {
+        let dir = scratch_dir("session-dismiss");
+        let path = dir.join("stale.md");
+        fs::write(&path, "original").unwrap();
+        let swap_path = RecoveryJournal::swap_path_for(&path);
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.insert_char('!');
+        session.flush_journal(&mut document).unwrap();
+        drop(session);
+
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Discard).unwrap();
+        session.dismiss_recovery();
+        assert!(!swap_path.exists());
+        assert_eq!(document.content(), "original");
+
+        // A swap file from before the file changed on disk isn't offered
+        document.insert_char('!');
+        session.flush_journal(&mut document).unwrap();
+        fs::write(&path, "changed elsewhere").unwrap();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Discard).unwrap();
+        assert!(session.pending_recovery().is_none());
+    }
+
+    #[test]
+    fn test_untitled_documents_are_not_journaled() {
+        let dir = scratch_dir("session-untitled-journal");
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        document.insert_text("scratch");
+        session.flush_journal(&mut document).unwrap();
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_undo_history_survives_reopening() {
+        let dir = scratch_dir("session-undo-history");
+        let path = dir.join("diary.md");
+        fs::write(&path, "# Diary").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.set_cursor_position(document.len());
+        document.insert_text("\n\nMonday");
+        session.save(&mut document).unwrap();
+        assert!(undo_path_for(&path).exists());
+        drop(session);
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        assert!(document.can_undo());
+        assert!(document.perform_undo());
+        assert_eq!(document.content(), "# Diary");
+        assert!(session.is_dirty(&document));
+    }
+
+    #[test]
+    fn test_undo_history_is_dropped_when_file_changed_elsewhere() {
+        let dir = scratch_dir("session-undo-history-stale");
+        let path = dir.join("diary.md");
+        fs::write(&path, "# Diary").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.insert_text("Dear ");
+        session.save(&mut document).unwrap();
+        drop(session);
+
+        fs::write(&path, "Dear # Diary, edited elsewhere").unwrap();
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        assert_eq!(document.content(), "Dear # Diary, edited elsewhere");
+        assert!(!document.can_undo());
+    }
+
+    /// Write `content` the way another program would, with a modification
+    /// time that can't collide with the editor's own save
+    fn write_externally(path: &Path, content: &str, seconds_later: u64) {
+        fs::write(path, content).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(seconds_later)).unwrap();
+    }
+
+    #[test]
+    fn test_clean_document_reloads_external_change() {
+        let dir = scratch_dir("session-reload");
+        let path = dir.join("list.md");
+        fs::write(&path, "- milk\n- eggs\n").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.set_cursor_position(9);
+        assert_eq!(session.check_external_change().unwrap(), None);
+
+        write_externally(&path, "- bread\n- milk\n- eggs\n", 10);
+        let disk = session.check_external_change().unwrap().unwrap();
+        // Only reported once
+        assert_eq!(session.check_external_change().unwrap(), None);
+
+        session.reload(&mut document, disk);
+        assert_eq!(document.content(), "- bread\n- milk\n- eggs\n");
+        assert_eq!(document.cursor_position(), 17);
+        assert!(!session.is_dirty(&document));
+
+        // Touching the file without changing it isn't a change
+        write_externally(&path, "- bread\n- milk\n- eggs\n", 20);
+        assert_eq!(session.check_external_change().unwrap(), None);
+    }
+
+    #[test]
+    fn test_dirty_document_merges_external_change() {
+        let dir = scratch_dir("session-merge");
+        let path = dir.join("plan.md");
+        fs::write(&path, "# Plan\n\nmonday\n\nfriday\n").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.set_cursor_position(14);
+        document.insert_text(": write");
+        session.flush_journal(&mut document).unwrap();
+
+        write_externally(&path, "# Plan\n\nmonday\n\nfriday: review\n", 10);
+        let disk = session.check_external_change().unwrap().unwrap();
+        let merged = session.merge(&mut document, disk).unwrap();
+        assert!(merged.is_clean());
+        assert_eq!(document.content(), "# Plan\n\nmonday: write\n\nfriday: review\n");
+        assert!(session.is_dirty(&document));
+
+        // The journal now builds on the new content on disk
+        let swap_path = RecoveryJournal::swap_path_for(&path);
+        let disk = fs::read_to_string(&path).unwrap();
+        let mut recovered = TextDocument::with_content(disk.clone());
+        recovered.replay_edits(&RecoveryJournal::load(&swap_path, &disk).unwrap());
+        assert_eq!(recovered.content(), document.content());
+
+        session.save(&mut document).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Plan\n\nmonday: write\n\nfriday: review\n");
+    }
+
+    #[test]
+    fn test_keep_mine_ignores_external_change() {
+        let dir = scratch_dir("session-keep-mine");
+        let path = dir.join("draft.md");
+        fs::write(&path, "draft").unwrap();
+
+        let mut document = TextDocument::new();
+        let mut session = FileSession::new(&document);
+        session.open(&mut document, &path, UnsavedChanges::Keep).unwrap();
+        document.insert_text("my ");
+
+        write_externally(&path, "their draft", 10);
+        let disk = session.check_external_change().unwrap().unwrap();
+        let conflict = diff::merge3("draft", &document.content(), &disk);
+        assert_eq!(conflict.conflicts, 1);
+
+        session.keep_mine(&mut document, disk).unwrap();
+        assert_eq!(document.content(), "my draft");
+        assert!(session.is_dirty(&document));
+        assert_eq!(session.check_external_change().unwrap(), None);
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{content_hash, write_atomically};
+use crate::core::command_history::HistoryRecord;
+
+/// Largest undo file read
+pub const MAX_UNDO_FILE_BYTES: usize = 1024 * 1024;
+
+/// Largest history written, leaving room in the file for the content hash
+/// around it. Older steps are dropped to fit.
+pub const MAX_UNDO_HISTORY_BYTES: usize = MAX_UNDO_FILE_BYTES - 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UndoFile {
+    /// Hash of the content the history's current step leaves the file with
+    content_hash: u64,
+    history: HistoryRecord,
+}
+
+/// Undo file for a document: a hidden sibling such as `.note.md.undo`, so
+/// the history survives closing the editor
+pub fn undo_path_for(document_path: &Path) -> PathBuf {
+    let file_name = document_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    document_path.with_file_name(format!(".{}.undo", file_name))
+}
+
+/// Save `history`, made when the document's content was `content`. An empty
+/// history removes the undo file instead.
+pub fn save_undo_history(path: &Path, content: &str, history: HistoryRecord) -> io::Result<()> {
+    if history.steps.is_empty() {
+        return match fs::remove_file(path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        };
+    }
+    let file = UndoFile {
+        content_hash: content_hash(content),
+        history,
+    };
+    write_atomically(path, &serde_json::to_string(&file)?)
+}
+
+/// Read the history saved for `content`. Returns `None` if there is none, it
+/// was saved for different content, or the file is too big or unreadable.
+pub fn load_undo_history(path: &Path, content: &str) -> Option<HistoryRecord> {
+    let size = fs::metadata(path).ok()?.len();
+    if size > MAX_UNDO_FILE_BYTES as u64 {
+        return None;
+    }
+    let file: UndoFile = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    if file.content_hash != content_hash(content) {
+        return None;
+    }
+    Some(file.history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::TextDocument;
+    use crate::file::scratch_dir;
+
+    #[test]
+    fn test_undo_path_is_hidden_sibling() {
+        assert_eq!(undo_path_for(Path::new("/notes/todo.md")), PathBuf::from("/notes/.todo.md.undo"));
+    }
+
+    #[test]
+    fn test_history_round_trips() {
+        let dir = scratch_dir("undo-history-round-trip");
+        let path = dir.join(".note.md.undo");
+
+        let mut document = TextDocument::with_content("one".to_string());
+        document.set_cursor_position(3);
+        document.insert_text(" two");
+        document.insert_text(" three");
+        save_undo_history(&path, &document.content(), document.undo_history(MAX_UNDO_HISTORY_BYTES)).unwrap();
+
+        let mut reopened = TextDocument::with_content(document.content());
+        let history = load_undo_history(&path, &reopened.content()).unwrap();
+        assert!(reopened.restore_undo_history(history));
+        assert!(reopened.perform_undo());
+        assert_eq!(reopened.content(), "one two");
+        assert!(reopened.perform_undo());
+        assert_eq!(reopened.content(), "one");
+        assert!(reopened.perform_redo());
+        assert_eq!(reopened.content(), "one two");
+    }
+
+    #[test]
+    fn test_history_for_other_content_is_ignored() {
+        let dir = scratch_dir("undo-history-mismatch");
+        let path = dir.join(".note.md.undo");
+
+        let mut document = TextDocument::new();
+        document.insert_text("saved");
+        save_undo_history(&path, "saved", document.undo_history(MAX_UNDO_HISTORY_BYTES)).unwrap();
+
+        assert!(load_undo_history(&path, "changed on disk").is_none());
+        assert!(load_undo_history(&dir.join("missing.undo"), "saved").is_none());
+        assert!(load_undo_history(&path, "saved").is_some());
+
+        // Nothing to undo removes the file
+        save_undo_history(&path, "", TextDocument::new().undo_history(MAX_UNDO_HISTORY_BYTES)).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_oversized_history_is_trimmed_or_skipped() {
+        let dir = scratch_dir("undo-history-size");
+        let path = dir.join(".note.md.undo");
+
+        let mut document = TextDocument::new();
+        for _ in 0..4 {
+            document.insert_text(&"x".repeat(100));
+            document.insert_char(' ');
+        }
+        let all = document.undo_history(MAX_UNDO_HISTORY_BYTES).steps.len();
+        let trimmed = document.undo_history(400);
+        assert!(!trimmed.steps.is_empty() && trimmed.steps.len() < all);
+        assert_eq!(trimmed.steps.last().map(|step| step.id), Some(trimmed.current));
+
+        // The oldest steps are gone, but the rest still undo
+        let mut reopened = TextDocument::with_content(document.content());
+        assert!(reopened.restore_undo_history(trimmed));
+        while reopened.perform_undo() {}
+        assert!(reopened.content().starts_with("xxx"));
+
+        // Files over the cap, e.g. from a bigger limit, aren't read
+        fs::write(&path, " ".repeat(MAX_UNDO_FILE_BYTES + 1)).unwrap();
+        assert!(load_undo_history(&path, "").is_none());
+    }
+
+    #[test]
+    fn test_history_trimmed_to_the_cap_loads() {
+        let dir = scratch_dir("undo-history-cap");
+        let path = dir.join(".note.md.undo");
+        let document_with = |length: usize| {
+            let mut document = TextDocument::new();
+            document.insert_text("a");
+            document.insert_char(' ');
+            document.insert_text(&"x".repeat(length));
+            document
+        };
+
+        // Step lengths either side of where the whole history stops fitting
+        let overhead = serde_json::to_string(&document_with(0).undo_history(usize::MAX)).unwrap().len();
+        let fitting = MAX_UNDO_HISTORY_BYTES - overhead;
+        for length in fitting - 8..fitting + 8 {
+            let mut document = document_with(length);
+            save_undo_history(&path, &document.content(), document.undo_history(MAX_UNDO_HISTORY_BYTES)).unwrap();
+            assert!(fs::metadata(&path).unwrap().len() <= MAX_UNDO_FILE_BYTES as u64);
+            assert!(load_undo_history(&path, &document.content()).is_some());
+        }
+    }
+}
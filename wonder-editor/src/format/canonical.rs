@@ -0,0 +1,527 @@
+//! Canonical Markdown formatting
+//!
+//! `format_markdown` prints a document back out in one consistent style: ATX
+//! headings, `*`, `**` and `~~` for emphasis, strong and strikethrough, `-`
+//! bullets, `1.` numbering counted up from the list's start, fenced code
+//! blocks and tables with aligned pipes. Text is copied from the source rather
+//! than rebuilt from the parse, so escapes, entities and straight quotes come
+//! out exactly as they were written. Formatting already formatted Markdown
+//! changes nothing.
+
+use std::ops::Range;
+
+use pulldown_cmark::Alignment;
+
+use super::normalize_whitespace;
+use crate::core::tables::render_table;
+use crate::markdown_ast::{MarkdownAst, NodeId, NodeKind};
+use crate::markdown_parser::MarkdownParser;
+
+/// Rewrite `markdown` in the canonical style, ending in a single newline
+pub fn format_markdown(markdown: &str) -> String {
+    let ast = MarkdownParser::new().parse_ast(markdown);
+    let printer = Printer { ast: &ast, source: markdown };
+    normalize_whitespace(&printer.blocks(ast.root()).join("\n\n"))
+}
+
+struct Printer<'a> {
+    ast: &'a MarkdownAst,
+    source: &'a str,
+}
+
+impl<'a> Printer<'a> {
+    /// The formatted blocks inside a container. Inline children (tight list
+    /// items have no paragraphs) are grouped into one block, and link
+    /// reference definitions, which the parser doesn't report, are kept.
+    fn blocks(&self, id: NodeId) -> Vec<String> {
+        let ast = self.ast;
+        let node = ast.node(id);
+        let mut blocks = Vec::new();
+        let mut inline: Vec<NodeId> = Vec::new();
+        // The item marker and footnote label come before the first child
+        let mut gap_start = match node.kind {
+            NodeKind::Document | NodeKind::BlockQuote => Some(node.range.start),
+            _ => None,
+        };
+        // Consecutive lists of the same kind alternate markers so they don't merge
+        let mut list_run: Option<(bool, usize)> = None;
+
+        for &child in ast.children(id) {
+            let child_node = ast.node(child);
+            if child_node.kind.is_inline() {
+                inline.push(child);
+                gap_start = Some(self.node_end(child));
+                continue;
+            }
+            if !inline.is_empty() {
+                blocks.push(self.inline(&inline, false));
+                inline.clear();
+            }
+            if let Some(definitions) = gap_start.and_then(|start| self.definitions(start, child_node.range.start)) {
+                blocks.push(definitions);
+                list_run = None;
+            }
+
+            let block = match &child_node.kind {
+                NodeKind::List(start) => {
+                    let ordered = start.is_some();
+                    let run = match list_run {
+                        Some((previous, run)) if previous == ordered => run + 1,
+                        _ => 0,
+                    };
+                    list_run = Some((ordered, run));
+                    self.list(child, run % 2 == 1)
+                }
+                NodeKind::Rule if !blocks.is_empty() && ast.node(id).kind == NodeKind::Item => {
+                    // `---` right under an item's text would underline it as a heading
+                    list_run = None;
+                    "***".to_string()
+                }
+                _ => {
+                    list_run = None;
+                    self.block(child)
+                }
+            };
+            blocks.push(block);
+            gap_start = Some(self.node_end(child));
+        }
+
+        if !inline.is_empty() {
+            blocks.push(self.inline(&inline, false));
+        }
+        if let Some(definitions) = gap_start.and_then(|start| self.definitions(start, node.range.end)) {
+            blocks.push(definitions);
+        }
+        blocks
+    }
+
+    /// Link reference definitions in the source between two blocks
+    fn definitions(&self, start: usize, end: usize) -> Option<String> {
+        if start >= end {
+            return None;
+        }
+        let lines: Vec<&str> = self.source[start..end]
+            .lines()
+            .map(|line| line.trim_start_matches([' ', '\t', '>']).trim_end())
+            .filter(|line| !line.is_empty())
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    fn block(&self, id: NodeId) -> String {
+        let ast = self.ast;
+        let node = ast.node(id);
+        match &node.kind {
+            NodeKind::Paragraph => self.inline(ast.children(id), false),
+            NodeKind::Heading(level) => {
+                let text = self.inline(ast.children(id), true);
+                format!("{} {}", "#".repeat(*level as usize), text).trim_end().to_string()
+            }
+            NodeKind::BlockQuote => prefix_lines(&self.blocks(id).join("\n\n"), "> ", "> "),
+            NodeKind::CodeBlock(info) => self.code_block(id, info.is_some()),
+            NodeKind::HtmlBlock => {
+                // Indentation before the first line comes through as a text
+                // node and is dropped, so the block can't join a list above it
+                let html: String = ast
+                    .children(id)
+                    .iter()
+                    .filter_map(|&child| match &ast.node(child).kind {
+                        NodeKind::Html(html) => Some(html.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                html.trim_end_matches('\n').to_string()
+            }
+            NodeKind::List(_) => self.list(id, false),
+            NodeKind::FootnoteDefinition(label) => {
+                let content = self.blocks(id).join("\n\n");
+                prefix_lines(&content, &format!("[^{}]: ", label), "    ")
+            }
+            NodeKind::Table(alignments) => self.table(id, alignments),
+            NodeKind::Rule => "---".to_string(),
+            _ => self.source[node.range.clone()].trim_end().to_string(),
+        }
+    }
+
+    fn code_block(&self, id: NodeId, has_info: bool) -> String {
+        let mut code = self.ast.text(id);
+        if !code.is_empty() && !code.ends_with('\n') {
+            code.push('\n');
+        }
+        // The info string as written, since the parsed one has escapes resolved
+        let info = if has_info {
+            self.rest_of_line(self.ast.node(id).range.start)
+                .trim_start()
+                .trim_start_matches(['`', '~'])
+                .trim()
+        } else {
+            ""
+        };
+
+        // The fence has to be longer than any run of fence characters in the code
+        let fence_char = if info.contains('`') { '~' } else { '`' };
+        let longest_run = code
+            .split(|c| c != fence_char)
+            .map(|run| run.chars().count())
+            .max()
+            .unwrap_or(0);
+        let fence = fence_char.to_string().repeat((longest_run + 1).max(3));
+        format!("{}{}\n{}{}", fence, info, code, fence)
+    }
+
+    /// `alternate` switches to `*` bullets or `)` numbers, for a list that
+    /// directly follows another list of the same kind
+    fn list(&self, id: NodeId, alternate: bool) -> String {
+        let ast = self.ast;
+        let start = match ast.node(id).kind {
+            NodeKind::List(start) => start,
+            _ => None,
+        };
+        let loose = ast.children(id).iter().any(|&item| {
+            ast.children(item)
+                .iter()
+                .any(|&child| ast.node(child).kind == NodeKind::Paragraph)
+        });
+        let separator = if loose { "\n\n" } else { "\n" };
+
+        let items: Vec<String> = ast
+            .children(id)
+            .iter()
+            .enumerate()
+            .map(|(index, &item)| {
+                let marker = match (start, alternate) {
+                    (Some(start), false) => format!("{}. ", start + index as u64),
+                    (Some(start), true) => format!("{}) ", start + index as u64),
+                    (None, false) => "- ".to_string(),
+                    (None, true) => "* ".to_string(),
+                };
+                let indent = " ".repeat(marker.len());
+                let content = self.blocks(item).join(separator);
+                prefix_lines(&content, &marker, &indent)
+            })
+            .collect();
+        items.join(separator)
+    }
+
+    fn table(&self, id: NodeId, alignments: &[Alignment]) -> String {
+        let ast = self.ast;
+        let columns = alignments.len();
+        let rows: Vec<Vec<String>> = ast
+            .children(id)
+            .iter()
+            .map(|&row| {
+                let mut cells: Vec<String> = ast
+                    .children(row)
+                    .iter()
+                    .map(|&cell| self.inline(ast.children(cell), true))
+                    .collect();
+                cells.resize(columns, String::new());
+                cells
+            })
+            .collect();
+        render_table(&rows, alignments).join("\n")
+    }
+
+    /// Inline nodes as source text with canonical markers. With `single_line`
+    /// line breaks become spaces, as headings and table cells need.
+    fn inline(&self, nodes: &[NodeId], single_line: bool) -> String {
+        let ast = self.ast;
+        let mut text = String::new();
+        // `None` at the start of a line, where the source before the node is
+        // indentation and container prefixes
+        let mut last_end: Option<usize> = None;
+
+        for &id in nodes {
+            let node = ast.node(id);
+            match last_end {
+                Some(end) => text.push_str(&self.verbatim(end..node.range.start.max(end))),
+                None if self.is_escaped(id) => text.push('\\'),
+                None => {}
+            }
+
+            match &node.kind {
+                NodeKind::SoftBreak | NodeKind::HardBreak => {
+                    if single_line || node.kind == NodeKind::SoftBreak {
+                        text.truncate(text.trim_end_matches([' ', '\t']).len());
+                    }
+                    text.push_str(match (single_line, &node.kind) {
+                        (true, _) => " ",
+                        (false, NodeKind::HardBreak) => "\\\n",
+                        _ => "\n",
+                    });
+                    last_end = None;
+                    continue;
+                }
+                _ => text.push_str(&self.inline_node(id, single_line)),
+            }
+            last_end = Some(self.node_end(id).max(last_end.unwrap_or(0)));
+        }
+        if single_line {
+            return text;
+        }
+
+        // A line that would start a block stays part of the paragraph when
+        // indented four spaces
+        text.split('\n')
+            .enumerate()
+            .map(|(index, line)| {
+                if index > 0 && starts_block(line) {
+                    format!("    {}", line)
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn inline_node(&self, id: NodeId, single_line: bool) -> String {
+        let ast = self.ast;
+        let node = ast.node(id);
+        let children = ast.children(id);
+        let range = node.range.start..self.node_end(id);
+        let marker = match &node.kind {
+            NodeKind::Emphasis => Some(self.delimiter(id, "*", "_")),
+            NodeKind::Strong => Some(self.delimiter(id, "**", "__")),
+            NodeKind::Strikethrough => Some("~~"),
+            NodeKind::TaskListMarker(checked) => return if *checked { "[x]" } else { "[ ]" }.to_string(),
+            _ => None,
+        };
+        if let NodeKind::Code(code) = &node.kind {
+            if self.source[range.clone()].contains('\n') {
+                return code_span(code);
+            }
+        }
+        let (first, last) = match (children.first(), children.last()) {
+            (Some(&first), Some(&last)) if !matches!(node.kind, NodeKind::Text(_)) => (first, last),
+            _ => return self.verbatim(range),
+        };
+        // A backslash escaping the first child is written by `inline`
+        let escape = usize::from(self.is_escaped(first));
+        let prefix = range.start..ast.node(first).range.start - escape;
+        let suffix = ast.node(last).range.end..range.end;
+
+        if let Some(marker) = marker {
+            return format!("{}{}{}", marker, self.inline(children, single_line), marker);
+        }
+        // Shortcut and collapsed reference links are looked up by their text
+        let is_link = matches!(node.kind, NodeKind::Link { .. } | NodeKind::Image { .. });
+        if is_link && !self.source[suffix.clone()].starts_with("](") {
+            return self.verbatim(range);
+        }
+        // Links, images and sub/superscript keep their own syntax
+        format!("{}{}{}", self.verbatim(prefix), self.inline(children, single_line), self.verbatim(suffix))
+    }
+
+    /// End of a node in the source. The parser ends collapsed reference links
+    /// (`[text][]`) before their `[]`.
+    fn node_end(&self, id: NodeId) -> usize {
+        let range = &self.ast.node(id).range;
+        let is_link = matches!(self.ast.node(id).kind, NodeKind::Link { .. } | NodeKind::Image { .. });
+        if is_link && self.source[range.clone()].ends_with(']') && self.source[range.end..].starts_with("[]") {
+            range.end + 2
+        } else {
+            range.end
+        }
+    }
+
+    /// Source text with the indentation and quote markers of continuation
+    /// lines removed, so it can be prefixed again for its new container
+    fn verbatim(&self, range: Range<usize>) -> String {
+        self.source[range]
+            .split('\n')
+            .enumerate()
+            .map(|(index, line)| if index == 0 { line } else { line.trim_start_matches([' ', '\t', '>']) })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Text from `position` to the end of its source line
+    fn rest_of_line(&self, position: usize) -> &str {
+        let rest = &self.source[position..];
+        &rest[..rest.find('\n').unwrap_or(rest.len())]
+    }
+
+    /// Whether a text node starts with a character escaped by a backslash
+    /// that falls outside the node's range
+    fn is_escaped(&self, id: NodeId) -> bool {
+        let start = self.ast.node(id).range.start;
+        matches!(self.ast.node(id).kind, NodeKind::Text(_))
+            && self.source[..start].ends_with('\\')
+            && self.source[start..].starts_with(|c: char| c.is_ascii_punctuation())
+    }
+
+    /// The canonical `*` marker for an emphasis or strong span, or the `_`
+    /// form it was written with when a `*` right next to it would run into
+    /// the new marker
+    fn delimiter(&self, id: NodeId, star: &'static str, underscore: &'static str) -> &'static str {
+        let range = &self.ast.node(id).range;
+        let before = self.source[..range.start].chars().next_back();
+        let after = self.source[range.end..].chars().next();
+        let written_with_underscore = self.source[range.clone()].starts_with('_');
+        let touches_star = before == Some('*') || after == Some('*');
+        if written_with_underscore && touches_star {
+            underscore
+        } else {
+            star
+        }
+    }
+}
+
+/// A code span on one line. Used for spans that were wrapped over several
+/// lines, since the wrapped lines' indentation would end up in the code.
+fn code_span(code: &str) -> String {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run + 1);
+    let padded = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+    if padded {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+/// HTML tags that start an HTML block even in the middle of a paragraph
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "base", "basefont", "blockquote", "body", "caption", "center", "col",
+    "colgroup", "dd", "details", "dialog", "dir", "div", "dl", "dt", "fieldset", "figcaption", "figure",
+    "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hr",
+    "html", "iframe", "legend", "li", "link", "main", "menu", "menuitem", "nav", "noframes", "ol",
+    "optgroup", "option", "p", "param", "pre", "script", "search", "section", "style", "summary",
+    "table", "tbody", "td", "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul",
+];
+
+/// Whether a paragraph line would be read as the start of a block (a
+/// heading, quote, list item, rule, setext underline, fence, table, table
+/// delimiter row or HTML block)
+/// if it weren't indented
+fn starts_block(line: &str) -> bool {
+    let followed_by_space = |index: usize| line[index..].chars().next().is_none_or(char::is_whitespace);
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let tag: String = line
+        .trim_start_matches("</")
+        .trim_start_matches('<')
+        .chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .collect();
+    ((1..=6).contains(&hashes) && followed_by_space(hashes))
+        || line.starts_with(['>', '|'])
+        || line.starts_with("```")
+        || line.starts_with("~~~")
+        || (line.starts_with(['-', '+', '*']) && followed_by_space(1))
+        || (!line.trim().is_empty() && line.chars().all(|c| matches!(c, '-' | '*' | '_' | '=' | ' ' | '\t')))
+        || (line.contains('-') && line.chars().all(|c| matches!(c, '-' | ':' | '|' | ' ' | '\t')))
+        || ((1..=9).contains(&digits) && line[digits..].starts_with(['.', ')']) && followed_by_space(digits + 1))
+        || line.starts_with("<!")
+        || line.starts_with("<?")
+        || (line.starts_with('<') && BLOCK_TAGS.contains(&tag.to_ascii_lowercase().as_str()))
+}
+
+/// Prefix the first line with `first` and the rest with `rest`, leaving
+/// blank lines without trailing spaces
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    if text.is_empty() {
+        return first.trim_end().to_string();
+    }
+    text.split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            let prefix = if index == 0 { first } else { rest };
+            if line.is_empty() {
+                prefix.trim_end().to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Node kinds of the parse, with neighbouring text merged, so two
+    /// documents that mean the same thing compare equal
+    fn outline(markdown: &str) -> Vec<(usize, String)> {
+        let ast = MarkdownParser::new().parse_ast(markdown);
+        let mut outline: Vec<(usize, String)> = Vec::new();
+        let mut stack = vec![(ast.root(), 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let kind = &ast.node(id).kind;
+            match (kind, outline.last_mut()) {
+                (NodeKind::Text(text), Some((last_depth, last))) if *last_depth == depth && last.starts_with("Text:") => {
+                    last.push_str(text);
+                }
+                (NodeKind::Text(text), _) => outline.push((depth, format!("Text:{}", text))),
+                _ => outline.push((depth, format!("{:?}", kind))),
+            }
+            for &child in ast.children(id).iter().rev() {
+                stack.push((child, depth + 1));
+            }
+        }
+        outline
+    }
+
+    fn assert_round_trip(markdown: &str) {
+        let formatted = format_markdown(markdown);
+        assert_eq!(outline(&formatted), outline(markdown), "meaning changed:\n{}", formatted);
+        assert_eq!(format_markdown(&formatted), formatted, "not idempotent:\n{}", formatted);
+    }
+
+    #[test]
+    fn test_format_markdown_canonical_style() {
+        let markdown = "Title\n=====\n\nSome _emphasis_, __strong__ and ~struck~ text.\n\n\
+                        Subtitle\n--------\n\n\
+                        * one\n* two\n    + nested\n\n\
+                        3) three\n7) four\n\n\
+                        |Name|Qty|\n|:-|-:|\n|apple|10|\n|kiwi|2|\n\n\
+                        ~~~rust\nlet x = 1;\n~~~\n\n    indented code\n\n___\n";
+        let expected = "# Title\n\nSome *emphasis*, **strong** and ~~struck~~ text.\n\n\
+                        ## Subtitle\n\n\
+                        - one\n- two\n  - nested\n\n\
+                        3. three\n4. four\n\n\
+                        | Name  | Qty |\n| :---- | --: |\n| apple |  10 |\n| kiwi  |   2 |\n\n\
+                        ```rust\nlet x = 1;\n```\n\n```\nindented code\n```\n\n---\n";
+        assert_eq!(format_markdown(markdown), expected);
+        assert_round_trip(markdown);
+    }
+
+    #[test]
+    fn test_format_markdown_keeps_source_text() {
+        let markdown = "\"Quoted\" text with \\*escapes\\* and &amp; entities.  \nNext line\n\n\
+                        A [reference link][docs] and an ![image](cat.png \"Cat\").\n\n[docs]: https://example.com\n";
+        let formatted = format_markdown(markdown);
+        assert_eq!(
+            formatted,
+            "\"Quoted\" text with \\*escapes\\* and &amp; entities.\\\nNext line\n\n\
+             A [reference link][docs] and an ![image](cat.png \"Cat\").\n\n[docs]: https://example.com\n"
+        );
+        assert_round_trip(markdown);
+    }
+
+    #[test]
+    fn test_format_markdown_round_trips() {
+        let documents = [
+            "- [ ] todo\n- [X] done\n\n1. first\n\n   second paragraph\n2. next\n",
+            "> quote with *emphasis*\n> > nested\n>\n> ```\n> code\n> ```\n",
+            "- a\n- b\n\n* c\n* d\n\n+ e\n",
+            "Text[^note] here.\n\n[^note]: The note\n    continues here.\n",
+            "<div>\nhtml\n</div>\n\nx<sub>2</sub> and #tag ==highlight== :smile:\n",
+            "Line one\n    # not a heading\n    1. not a list\n",
+            "*a **b***\n\n_a_*b*\n\n```\n````\n```\n\n- item\n  ***\n",
+            "| a | `b\\|c` |\n|---|:---:|\n| 1 |\n",
+            "* [`a()`][]\n* [b][]\n\n[`a()`]: /a\n[b]: /b\n",
+            "\\* not a list, [\\#1](u) and `code\n  over lines`\n",
+            "Para\n     | a | b |\n     | --- | --- |\n",
+            "Text \\\nafter a backslash break\n",
+            "",
+        ];
+        for markdown in documents {
+            assert_round_trip(markdown);
+        }
+    }
+}
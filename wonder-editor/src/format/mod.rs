@@ -0,0 +1,112 @@
+//! Markdown source normalization
+//!
+//! `normalize_whitespace` tidies the whitespace of a document without
+//! changing what it renders to: trailing spaces go (except the two-space hard
+//! break), runs of blank lines collapse to one and the file ends in exactly
+//! one newline. Code blocks are left byte for byte as they are.
+//!
+//! `format_markdown` goes further and rewrites the markup itself in one
+//! canonical style.
+
+mod canonical;
+
+pub use canonical::format_markdown;
+
+use std::ops::Range;
+
+use crate::markdown_ast::NodeKind;
+use crate::markdown_parser::MarkdownParser;
+
+/// Normalize blank lines and trailing whitespace outside code blocks
+pub fn normalize_whitespace(markdown: &str) -> String {
+    let code_blocks = code_block_ranges(markdown);
+    let in_code = |line: Range<usize>| {
+        code_blocks.iter().any(|range| range.start < line.end && line.start < range.end)
+    };
+
+    let source_lines: Vec<&str> = markdown.split('\n').collect();
+    let mut lines: Vec<String> = Vec::new();
+    let mut offset = 0;
+    for (index, line) in source_lines.iter().enumerate() {
+        let line_range = offset..offset + line.len();
+        offset += line.len() + 1;
+        if in_code(line_range) {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let trimmed = trim_end_whitespace(line);
+        if trimmed.is_empty() {
+            if lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push(String::new());
+            }
+            continue;
+        }
+        // Trailing spaces (not tabs) before more text are a hard line break,
+        // kept as two spaces
+        let next_is_text = source_lines
+            .get(index + 1)
+            .is_some_and(|next| !trim_end_whitespace(next).trim_start_matches([' ', '\t']).is_empty());
+        let hard_break = next_is_text && line.ends_with("  ") && line.trim_end_matches(' ') == trimmed;
+        lines.push(if hard_break {
+            format!("{}  ", trimmed)
+        } else {
+            trimmed.to_string()
+        });
+    }
+
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut normalized = lines.join("\n");
+    normalized.push('\n');
+    normalized
+}
+
+/// Trim the whitespace Markdown treats as blank. Other Unicode spaces, like
+/// a non-breaking space, are text.
+fn trim_end_whitespace(line: &str) -> &str {
+    line.trim_end_matches([' ', '\t', '\r'])
+}
+
+/// Byte ranges of the lines inside code blocks, fences excluded
+pub(crate) fn code_block_ranges(markdown: &str) -> Vec<Range<usize>> {
+    let ast = MarkdownParser::new().parse_ast(markdown);
+    ast.descendants(ast.root())
+        .filter(|&id| matches!(ast.node(id).kind, NodeKind::CodeBlock(_)))
+        .filter_map(|id| {
+            let children = ast.children(id);
+            let start = ast.node(*children.first()?).range.start;
+            let end = ast.node(*children.last()?).range.end;
+            Some(start..end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_whitespace() {
+        let markdown = "\n# Title   \n\n\n\nLine with break  \nnext\t\n\n\n";
+        assert_eq!(normalize_whitespace(markdown), "# Title\n\nLine with break  \nnext\n");
+        assert_eq!(normalize_whitespace(""), "");
+        assert_eq!(normalize_whitespace("text"), "text\n");
+        assert_eq!(normalize_whitespace("- item\r\n\u{a0}\r\n"), "- item\n\u{a0}\n");
+    }
+
+    #[test]
+    fn test_normalize_whitespace_leaves_code_blocks_alone() {
+        let markdown = "Intro\n\n```\nfirst   \n\n\n\nlast\n```\n\n\n    indented  \n";
+        assert_eq!(
+            normalize_whitespace(markdown),
+            "Intro\n\n```\nfirst   \n\n\n\nlast\n```\n\n    indented  \n"
+        );
+        let normalized = normalize_whitespace(markdown);
+        assert_eq!(normalize_whitespace(&normalized), normalized);
+    }
+}
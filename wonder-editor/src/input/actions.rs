@@ -3,7 +3,10 @@
 //! This module defines all possible editor actions that can be triggered
 //! by keyboard shortcuts, menu items, or other user interactions.
 
-#[derive(Debug, Clone, PartialEq)]
+use super::context::KeyContext;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EditorAction {
     // Text insertion and deletion
     InsertChar(char),
@@ -28,6 +31,16 @@ pub enum EditorAction {
     
     // Text formatting
     ToggleFormat(FormatType),
+    InsertLink,
+    /// Tick or clear the task under the cursor or every task in the selection
+    ToggleTask,
+
+    // Tables
+    EditTable(TableEdit),
+
+    // List indentation (Tab / Shift+Tab)
+    Indent,
+    Outdent,
     
     // Navigation
     MoveToPosition(usize),
@@ -50,9 +63,70 @@ pub enum EditorAction {
     // Undo/Redo operations
     Undo,
     Redo,
+    /// Go to the state made before the current one, on any branch of the
+    /// undo tree
+    UndoOlder,
+    /// Go to the state made after the current one, on any branch of the
+    /// undo tree
+    UndoNewer,
+    
+    // Find and replace (uses the document's active search)
+    /// Ask for the text to find (handled by the editor)
+    OpenFind,
+    FindNext,
+    FindPrevious,
+    ReplaceCurrent(String),
+    ReplaceAll(String),
+    CloseFind,
+
+    // File operations (handled by the editor, which can show dialogs)
+    NewFile,
+    OpenFile,
+    Save,
+    SaveAs,
+
+    /// Switch vim-style modal editing on or off (handled by the editor)
+    ToggleVimMode,
+    /// Open or close the command palette (handled by the editor)
+    ToggleCommandPalette,
+
+    // Keyboard macros (handled by the editor)
+    /// Start recording the actions that follow, or stop and keep them as the
+    /// last macro
+    ToggleMacroRecording,
+    /// Play the last macro
+    PlayMacro(MacroRepeat),
+    /// Ask how many times to play the last macro, then play it
+    RepeatMacro,
+    /// Play a macro saved in the config under this name
+    PlayNamedMacro(String),
+    /// Ask for a name and save the last macro under it in the config
+    SaveMacro,
+
+    // Emacs-style editing
+    /// Cut from the cursor to where the movement would take it into the kill
+    /// ring. Kills in a row collect into one entry.
+    Kill(Movement),
+    /// Replace the text just pasted with the kill before it
+    YankPop,
+    /// Start a selection at the cursor that cursor movement extends
+    SetMark,
+    TransposeChars,
+    TransposeWords,
+    TransposeLines,
+    /// Change the case of the selection, or of the rest of the word at the cursor
+    ChangeCase(CaseChange),
+    /// Search as you type, or go to the next match while searching
+    IncrementalSearch(SearchDirection),
+
+    // Multiple cursors
+    AddCursorAbove,
+    AddCursorBelow,
+    AddNextOccurrence,
+    SplitSelectionIntoLines,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Movement {
     // Character-level movement
     Left,
@@ -77,11 +151,66 @@ pub enum Movement {
     PageDown,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FormatType {
+    // Inline
     Bold,
     Italic,
     Code,
+    Strikethrough,
+    Highlight,
+
+    // Whole lines
+    Heading(u8),
+    BulletList,
+    TaskList,
+    BlockQuote,
+}
+
+/// Edits to the table around the cursor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TableEdit {
+    NextCell,
+    PreviousCell,
+    InsertRowAbove,
+    InsertRowBelow,
+    DeleteRow,
+    InsertColumnLeft,
+    InsertColumnRight,
+    DeleteColumn,
+    MoveColumnLeft,
+    MoveColumnRight,
+    AlignColumn(ColumnAlignment),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ColumnAlignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaseChange {
+    Upper,
+    Lower,
+    /// Upper case first letter, lower case rest
+    Capitalize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+/// How many times a macro plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MacroRepeat {
+    Times(usize),
+    /// Until the end of the document, or until a run stops getting closer to it
+    ToEnd,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -94,6 +223,11 @@ pub enum DeleteDirection {
 pub trait ActionHandler {
     /// Execute an editor action and return whether it was handled successfully
     fn handle_action(&mut self, action: EditorAction) -> bool;
+
+    /// The context key bindings are resolved in
+    fn key_context(&self) -> KeyContext {
+        KeyContext::default()
+    }
 }
 
 #[cfg(test)]
@@ -161,4 +295,26 @@ mod tests {
         assert_ne!(scroll_up, scroll_page_up);
         assert_ne!(scroll_down, scroll_to_bottom);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_serialized_forms() {
+        // Saved macros and keymap configs depend on these staying the same
+        let forms = [
+            (EditorAction::Backspace, r#""Backspace""#),
+            (EditorAction::InsertChar('x'), r#"{"InsertChar":"x"}"#),
+            (EditorAction::InsertText("- ".to_string()), r#"{"InsertText":"- "}"#),
+            (EditorAction::MoveCursor(Movement::LineStart), r#"{"MoveCursor":"LineStart"}"#),
+            (EditorAction::ToggleFormat(FormatType::Heading(2)), r#"{"ToggleFormat":{"Heading":2}}"#),
+            (
+                EditorAction::EditTable(TableEdit::AlignColumn(ColumnAlignment::Center)),
+                r#"{"EditTable":{"AlignColumn":"Center"}}"#,
+            ),
+            (EditorAction::PlayMacro(MacroRepeat::Times(3)), r#"{"PlayMacro":{"Times":3}}"#),
+            (EditorAction::PlayMacro(MacroRepeat::ToEnd), r#"{"PlayMacro":"ToEnd"}"#),
+        ];
+        for (action, json) in forms {
+            assert_eq!(serde_json::to_string(&action).unwrap(), json);
+            assert_eq!(serde_json::from_str::<EditorAction>(json).unwrap(), action);
+        }
+    }
+}
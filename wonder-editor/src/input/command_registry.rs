@@ -0,0 +1,605 @@
+//! Named commands for the command palette
+//!
+//! The `CommandRegistry` gives editor actions a name, a description and a
+//! category, and finds them by fuzzy matching a query against their names.
+//! Results are ranked by how well they match, with recently run commands
+//! first among equals. `CommandPalette` is the state of the palette overlay:
+//! what's typed, which result is selected, and the question it asks instead
+//! when it prompts for something such as a macro's name.
+
+use super::actions::{
+    CaseChange, ColumnAlignment, EditorAction, FormatType, MacroRepeat, Movement, SearchDirection, TableEdit,
+};
+use std::fmt;
+
+/// Score bonus for the most recently run command, shrinking for older ones
+const RECENCY_BONUS: i32 = 10;
+/// Most a run of characters skipped between two matches costs, so matching
+/// a word start further on stays worth it
+const MAX_GAP_PENALTY: usize = 3;
+/// Results the palette shows at most
+pub const PALETTE_RESULT_LIMIT: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandCategory {
+    Edit,
+    Movement,
+    Selection,
+    Format,
+    Table,
+    Clipboard,
+    History,
+    Find,
+    File,
+    View,
+    Macro,
+}
+
+impl fmt::Display for CommandCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CommandCategory::Edit => "Edit",
+            CommandCategory::Movement => "Movement",
+            CommandCategory::Selection => "Selection",
+            CommandCategory::Format => "Format",
+            CommandCategory::Table => "Table",
+            CommandCategory::Clipboard => "Clipboard",
+            CommandCategory::History => "History",
+            CommandCategory::Find => "Find",
+            CommandCategory::File => "File",
+            CommandCategory::View => "View",
+            CommandCategory::Macro => "Macro",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An action under a name users can search for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub description: String,
+    pub category: CommandCategory,
+    pub action: EditorAction,
+}
+
+impl Command {
+    pub fn new(name: &str, description: &str, category: CommandCategory, action: EditorAction) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            category,
+            action,
+        }
+    }
+}
+
+/// How well a query matched a command name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i32,
+    /// Char indices of the name that the query's characters matched, for
+    /// highlighting
+    pub positions: Vec<usize>,
+}
+
+/// A search result
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandMatch<'a> {
+    pub command: &'a Command,
+    /// The fuzzy match score plus the bonus for having been run recently
+    pub score: i32,
+    pub positions: Vec<usize>,
+}
+
+/// Match the characters of `query`, in order but not necessarily next to each
+/// other, against `text`, ignoring case and the spaces in `query`. Returns
+/// the best scoring way to match, or `None` if they don't all appear in order.
+/// Matches at the start of `text` or of a word and runs of consecutive
+/// characters score higher, and skipping characters between matches lower.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query.chars().filter(|ch| !ch.is_whitespace()).flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+    if query.is_empty() {
+        return Some(FuzzyMatch { score: 0, positions: Vec::new() });
+    }
+
+    let at_word_start = |index: usize| {
+        index == 0 || !text[index - 1].is_alphanumeric() || (text[index - 1].is_lowercase() && text[index].is_uppercase())
+    };
+    let char_score = |index: usize| {
+        if index == 0 {
+            8
+        } else if at_word_start(index) {
+            6
+        } else {
+            1
+        }
+    };
+    let matches = |query_char: char, index: usize| text[index].to_lowercase().eq(std::iter::once(query_char));
+
+    // best[i][j]: the best score matching query[..=i] with query[i] at text[j],
+    // and the text index query[i - 1] matched to get it
+    let mut best: Vec<Vec<Option<(i32, usize)>>> = vec![vec![None; text.len()]; query.len()];
+    for (j, cell) in best[0].iter_mut().enumerate() {
+        if matches(query[0], j) {
+            *cell = Some((char_score(j), 0));
+        }
+    }
+    for i in 1..query.len() {
+        for j in i..text.len() {
+            if !matches(query[i], j) {
+                continue;
+            }
+            best[i][j] = (i - 1..j)
+                .filter_map(|previous| {
+                    let (score, _) = best[i - 1][previous]?;
+                    let step = if previous + 1 == j {
+                        char_score(j) + 4
+                    } else {
+                        char_score(j) - (j - previous - 1).min(MAX_GAP_PENALTY) as i32
+                    };
+                    Some((score + step, previous))
+                })
+                .max_by_key(|&(score, previous)| (score, std::cmp::Reverse(previous)));
+        }
+    }
+
+    let last = query.len() - 1;
+    let (end, (score, _)) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, cell)| cell.map(|cell| (j, cell)))
+        .max_by_key(|&(j, (score, _))| (score, std::cmp::Reverse(j)))?;
+    let mut positions = vec![end];
+    for i in (1..=last).rev() {
+        let (_, previous) = best[i][positions[0]]?;
+        positions.insert(0, previous);
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Every named command, and which were run most recently
+#[derive(Debug, Clone)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+    /// Names of the commands run, most recent first
+    recent: Vec<String>,
+}
+
+impl CommandRegistry {
+    /// A registry without any commands
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            recent: Vec::new(),
+        }
+    }
+
+    /// Add a command, replacing any with the same name
+    pub fn register(&mut self, command: Command) {
+        match self.commands.iter_mut().find(|existing| existing.name == command.name) {
+            Some(existing) => *existing = command,
+            None => self.commands.push(command),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|command| command.name == name)
+    }
+
+    /// Add a command that plays the macro saved under `name`
+    pub fn register_macro(&mut self, name: &str) {
+        self.register(Command::new(
+            &format!("Macro: {}", name),
+            &format!("Play the saved macro {}", name),
+            CommandCategory::Macro,
+            EditorAction::PlayNamedMacro(name.to_string()),
+        ));
+    }
+
+    /// The first command that runs `action`
+    pub fn command_for(&self, action: &EditorAction) -> Option<&Command> {
+        self.commands.iter().find(|command| command.action == *action)
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Note that a command was run, so it ranks higher in searches
+    pub fn record_use(&mut self, name: &str) {
+        self.recent.retain(|recent| recent != name);
+        self.recent.insert(0, name.to_string());
+    }
+
+    fn recency_bonus(&self, name: &str) -> i32 {
+        self.recent
+            .iter()
+            .position(|recent| recent == name)
+            .map_or(0, |index| (RECENCY_BONUS - 2 * index as i32).max(0))
+    }
+
+    /// Commands whose names match `query`, best first. Among equal scores
+    /// shorter names come first, then the order they were registered in. An
+    /// empty query lists every command, recently run ones first.
+    pub fn search(&self, query: &str) -> Vec<CommandMatch<'_>> {
+        let mut results: Vec<(usize, CommandMatch)> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(index, command)| {
+                let found = fuzzy_match(query, &command.name)?;
+                Some((
+                    index,
+                    CommandMatch {
+                        command,
+                        score: found.score + self.recency_bonus(&command.name),
+                        positions: found.positions,
+                    },
+                ))
+            })
+            .collect();
+        results.sort_by_key(|(index, result)| {
+            (std::cmp::Reverse(result.score), result.command.name.chars().count(), *index)
+        });
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+impl Default for CommandRegistry {
+    /// A registry with a command for every action that doesn't need an
+    /// argument typed in, such as text to insert or a replacement, except
+    /// for opening the palette itself
+    fn default() -> Self {
+        let mut registry = Self::new();
+        for command in editor_commands() {
+            registry.register(command);
+        }
+        registry
+    }
+}
+
+/// The built-in commands
+fn editor_commands() -> Vec<Command> {
+    use CommandCategory::{Clipboard, Edit, File, Find, Format, History, Macro, Selection, Table, View};
+
+    let movements = [
+        (Movement::Left, "Left", "one character left"),
+        (Movement::Right, "Right", "one character right"),
+        (Movement::Up, "Up", "one line up"),
+        (Movement::Down, "Down", "one line down"),
+        (Movement::WordStart, "to Word Start", "to the start of the word"),
+        (Movement::WordEnd, "to Word End", "to the end of the word"),
+        (Movement::LineStart, "to Line Start", "to the start of the line"),
+        (Movement::LineEnd, "to Line End", "to the end of the line"),
+        (Movement::DocumentStart, "to Document Start", "to the start of the document"),
+        (Movement::DocumentEnd, "to Document End", "to the end of the document"),
+        (Movement::PageUp, "Page Up", "one page up"),
+        (Movement::PageDown, "Page Down", "one page down"),
+    ];
+    let mut commands = Vec::new();
+    for (movement, name, description) in movements.iter().cloned() {
+        commands.push(Command::new(
+            &format!("Move {}", name),
+            &format!("Move the cursor {}", description),
+            CommandCategory::Movement,
+            EditorAction::MoveCursor(movement),
+        ));
+    }
+    for (movement, name, description) in movements {
+        commands.push(Command::new(
+            &format!("Select {}", name),
+            &format!("Extend the selection {}", description),
+            Selection,
+            EditorAction::ExtendSelection(movement),
+        ));
+    }
+
+    let formats = [
+        (FormatType::Bold, "Bold"),
+        (FormatType::Italic, "Italic"),
+        (FormatType::Code, "Code"),
+        (FormatType::Strikethrough, "Strikethrough"),
+        (FormatType::Highlight, "Highlight"),
+        (FormatType::BulletList, "Bullet List"),
+        (FormatType::TaskList, "Task List"),
+        (FormatType::BlockQuote, "Block Quote"),
+    ];
+    for (format, name) in formats {
+        commands.push(Command::new(
+            &format!("Toggle {}", name),
+            &format!("Turn {} on or off for the selection", name.to_lowercase()),
+            Format,
+            EditorAction::ToggleFormat(format),
+        ));
+    }
+    for level in 1..=6 {
+        commands.push(Command::new(
+            &format!("Toggle Heading {}", level),
+            &format!("Make the line a level {} heading, or a paragraph again", level),
+            Format,
+            EditorAction::ToggleFormat(FormatType::Heading(level)),
+        ));
+    }
+
+    let table_edits = [
+        (TableEdit::NextCell, "Next Cell", "Move to the next table cell"),
+        (TableEdit::PreviousCell, "Previous Cell", "Move to the previous table cell"),
+        (TableEdit::InsertRowAbove, "Insert Row Above", "Add a row above the cursor's row"),
+        (TableEdit::InsertRowBelow, "Insert Row Below", "Add a row below the cursor's row"),
+        (TableEdit::DeleteRow, "Delete Row", "Remove the cursor's row"),
+        (TableEdit::InsertColumnLeft, "Insert Column Left", "Add a column left of the cursor's column"),
+        (TableEdit::InsertColumnRight, "Insert Column Right", "Add a column right of the cursor's column"),
+        (TableEdit::DeleteColumn, "Delete Column", "Remove the cursor's column"),
+        (TableEdit::MoveColumnLeft, "Move Column Left", "Swap the cursor's column with the one on its left"),
+        (TableEdit::MoveColumnRight, "Move Column Right", "Swap the cursor's column with the one on its right"),
+        (TableEdit::AlignColumn(ColumnAlignment::None), "Unalign Column", "Remove the column's alignment"),
+        (TableEdit::AlignColumn(ColumnAlignment::Left), "Align Column Left", "Align the column's text left"),
+        (TableEdit::AlignColumn(ColumnAlignment::Center), "Align Column Center", "Center the column's text"),
+        (TableEdit::AlignColumn(ColumnAlignment::Right), "Align Column Right", "Align the column's text right"),
+    ];
+    for (edit, name, description) in table_edits {
+        commands.push(Command::new(&format!("Table: {}", name), description, Table, EditorAction::EditTable(edit)));
+    }
+
+    let others = [
+        ("Delete Backward", "Delete the character before the cursor", Edit, EditorAction::Backspace),
+        ("Delete Forward", "Delete the character after the cursor", Edit, EditorAction::Delete),
+        ("Delete Previous Word", "Delete back to the start of the word", Edit, EditorAction::DeletePreviousWord),
+        ("Delete Next Word", "Delete up to the end of the word", Edit, EditorAction::DeleteNextWord),
+        ("Delete to Line Start", "Delete back to the start of the line", Edit, EditorAction::DeleteToLineStart),
+        ("Delete to Line End", "Delete up to the end of the line", Edit, EditorAction::DeleteToLineEnd),
+        ("Delete Line", "Delete the cursor's line", Edit, EditorAction::DeleteCurrentLine),
+        ("Indent", "Indent the selected lines or list items", Edit, EditorAction::Indent),
+        ("Outdent", "Outdent the selected lines or list items", Edit, EditorAction::Outdent),
+        ("Transpose Characters", "Swap the characters around the cursor", Edit, EditorAction::TransposeChars),
+        ("Transpose Words", "Swap the word at the cursor with the next one", Edit, EditorAction::TransposeWords),
+        ("Transpose Lines", "Swap the cursor's line with the one above", Edit, EditorAction::TransposeLines),
+        ("Upper Case", "Make the selection or the rest of the word upper case", Edit, EditorAction::ChangeCase(CaseChange::Upper)),
+        ("Lower Case", "Make the selection or the rest of the word lower case", Edit, EditorAction::ChangeCase(CaseChange::Lower)),
+        ("Capitalize", "Capitalize the words in the selection or the rest of the word", Edit, EditorAction::ChangeCase(CaseChange::Capitalize)),
+        ("Toggle Task", "Tick or clear the tasks at the cursor or in the selection", Format, EditorAction::ToggleTask),
+        ("Insert Link", "Turn the selection into a link", Format, EditorAction::InsertLink),
+        ("Page Up", "Move the cursor up a page", CommandCategory::Movement, EditorAction::PageUp),
+        ("Page Down", "Move the cursor down a page", CommandCategory::Movement, EditorAction::PageDown),
+        ("Select All", "Select the whole document", Selection, EditorAction::SelectAll),
+        ("Clear Selection", "Deselect, leaving only the cursor", Selection, EditorAction::ClearSelection),
+        ("Set Mark", "Start a selection that cursor movement extends", Selection, EditorAction::SetMark),
+        ("Add Cursor Above", "Add a cursor on the line above", Selection, EditorAction::AddCursorAbove),
+        ("Add Cursor Below", "Add a cursor on the line below", Selection, EditorAction::AddCursorBelow),
+        ("Add Next Occurrence", "Also select the next occurrence of the selection", Selection, EditorAction::AddNextOccurrence),
+        ("Split Selection into Lines", "Put a cursor on every selected line", Selection, EditorAction::SplitSelectionIntoLines),
+        ("Copy", "Copy the selection or line", Clipboard, EditorAction::Copy),
+        ("Cut", "Cut the selection or line", Clipboard, EditorAction::Cut),
+        ("Paste", "Paste the clipboard", Clipboard, EditorAction::Paste),
+        ("Yank Pop", "Replace the text just pasted with the previous kill", Clipboard, EditorAction::YankPop),
+        ("Kill to Line End", "Cut to the end of the line into the kill ring", Clipboard, EditorAction::Kill(Movement::LineEnd)),
+        ("Kill Word", "Cut to the end of the word into the kill ring", Clipboard, EditorAction::Kill(Movement::WordEnd)),
+        ("Kill Word Backward", "Cut back to the start of the word into the kill ring", Clipboard, EditorAction::Kill(Movement::WordStart)),
+        ("Undo", "Undo the last edit", History, EditorAction::Undo),
+        ("Redo", "Redo the last undone edit", History, EditorAction::Redo),
+        ("Older Undo State", "Go to the edit made before this one, on any undo branch", History, EditorAction::UndoOlder),
+        ("Newer Undo State", "Go to the edit made after this one, on any undo branch", History, EditorAction::UndoNewer),
+        ("Find", "Search the document for text", Find, EditorAction::OpenFind),
+        ("Find Next", "Select the next match of the search", Find, EditorAction::FindNext),
+        ("Find Previous", "Select the previous match of the search", Find, EditorAction::FindPrevious),
+        ("Close Find", "Stop highlighting the search's matches", Find, EditorAction::CloseFind),
+        ("Incremental Search", "Search forward as you type", Find, EditorAction::IncrementalSearch(SearchDirection::Forward)),
+        ("Incremental Search Backward", "Search backward as you type", Find, EditorAction::IncrementalSearch(SearchDirection::Backward)),
+        ("New File", "Start a new, empty document", File, EditorAction::NewFile),
+        ("Open File", "Open a markdown file", File, EditorAction::OpenFile),
+        ("Save", "Save the document", File, EditorAction::Save),
+        ("Save As", "Save the document to a new file", File, EditorAction::SaveAs),
+        ("Scroll Up", "Scroll up a few lines", View, EditorAction::ScrollUp),
+        ("Scroll Down", "Scroll down a few lines", View, EditorAction::ScrollDown),
+        ("Scroll Page Up", "Scroll up a page", View, EditorAction::ScrollPageUp),
+        ("Scroll Page Down", "Scroll down a page", View, EditorAction::ScrollPageDown),
+        ("Scroll to Top", "Scroll to the start of the document", View, EditorAction::ScrollToTop),
+        ("Scroll to Bottom", "Scroll to the end of the document", View, EditorAction::ScrollToBottom),
+        ("Toggle Vim Mode", "Switch vim-style modal editing on or off", View, EditorAction::ToggleVimMode),
+        ("Toggle Macro Recording", "Start recording a macro, or stop", Macro, EditorAction::ToggleMacroRecording),
+        ("Play Macro", "Play the last recorded macro", Macro, EditorAction::PlayMacro(MacroRepeat::Times(1))),
+        ("Play Macro to End", "Play the last macro until the end of the document", Macro, EditorAction::PlayMacro(MacroRepeat::ToEnd)),
+        ("Play Macro Repeatedly", "Play the last macro a number of times", Macro, EditorAction::RepeatMacro),
+        ("Save Macro", "Save the last macro in the config under a name", Macro, EditorAction::SaveMacro),
+    ];
+    for (name, description, category, action) in others {
+        commands.push(Command::new(name, description, category, action));
+    }
+    commands
+}
+
+/// A question the palette asks in place of listing commands, answered with
+/// what's typed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PalettePrompt {
+    Find,
+    MacroName,
+    MacroRepeatCount,
+}
+
+impl PalettePrompt {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PalettePrompt::Find => "Find:",
+            PalettePrompt::MacroName => "Save macro as:",
+            PalettePrompt::MacroRepeatCount => "Play macro how many times:",
+        }
+    }
+}
+
+/// The command palette's query and selected result
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandPalette {
+    query: String,
+    /// Index into the results of the query
+    selected: usize,
+    prompt: Option<PalettePrompt>,
+}
+
+impl CommandPalette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A palette asking `prompt`, which lists no commands
+    pub fn prompting(prompt: PalettePrompt) -> Self {
+        Self { prompt: Some(prompt), ..Self::default() }
+    }
+
+    pub fn prompt(&self) -> Option<PalettePrompt> {
+        self.prompt
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Type into the query, which selects the best result again
+    pub fn push_str(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.selected = 0;
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    /// The results shown for the query
+    pub fn results<'a>(&self, registry: &'a CommandRegistry) -> Vec<CommandMatch<'a>> {
+        if self.prompt.is_some() {
+            return Vec::new();
+        }
+        let mut results = registry.search(&self.query);
+        results.truncate(PALETTE_RESULT_LIMIT);
+        results
+    }
+
+    /// Select the result after the selected one, wrapping around to the first
+    pub fn select_next(&mut self, registry: &CommandRegistry) {
+        let count = self.results(registry).len();
+        if count > 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+    }
+
+    /// Select the result before the selected one, wrapping around to the last
+    pub fn select_previous(&mut self, registry: &CommandRegistry) {
+        let count = self.results(registry).len();
+        if count > 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+    }
+
+    /// The selected result's command
+    pub fn selected_command<'a>(&self, registry: &'a CommandRegistry) -> Option<&'a Command> {
+        self.results(registry).get(self.selected).map(|result| result.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::keymap::{Keymap, KeymapPreset};
+
+    #[test]
+    fn test_fuzzy_match_prefers_word_starts_and_runs() {
+        assert_eq!(fuzzy_match("xyz", "Save"), None);
+        assert_eq!(fuzzy_match("", "Save").map(|found| found.score), Some(0));
+        assert_eq!(fuzzy_match("sv", "Save").unwrap().positions, vec![0, 2]);
+
+        // The N of Next, not the n of Find
+        let found = fuzzy_match("fn", "Find Next").unwrap();
+        assert_eq!(found.positions, vec![0, 5]);
+        assert_eq!(fuzzy_match("tb", "Toggle Bold").unwrap().positions, vec![0, 7]);
+        assert_eq!(fuzzy_match("Add Cursor", "Add Cursor Below").unwrap().positions.len(), 9);
+
+        let score = |query: &str, text: &str| fuzzy_match(query, text).unwrap().score;
+        assert!(score("save", "Save As") > score("save", "Table: Move Column Left Save"));
+        assert!(score("dl", "Delete Line") > score("dl", "Idle"));
+    }
+
+    #[test]
+    fn test_search_ranks_by_match_and_recency() {
+        let mut registry = CommandRegistry::default();
+        let names = |registry: &CommandRegistry, query: &str| -> Vec<String> {
+            registry.search(query).iter().take(3).map(|result| result.command.name.clone()).collect()
+        };
+
+        assert_eq!(names(&registry, "bold")[0], "Toggle Bold");
+        assert_eq!(names(&registry, "save")[..2], ["Save".to_string(), "Save As".to_string()]);
+        assert_eq!(names(&registry, "h3")[0], "Toggle Heading 3");
+
+        assert_eq!(names(&registry, "sa")[0], "Save");
+        registry.record_use("Select All");
+        assert_eq!(names(&registry, "sa")[0], "Select All");
+        // With nothing typed, recently run commands come first
+        registry.record_use("Undo");
+        assert_eq!(names(&registry, "")[..2], ["Undo".to_string(), "Select All".to_string()]);
+    }
+
+    #[test]
+    fn test_every_bound_action_has_a_command() {
+        let registry = CommandRegistry::default();
+        for preset in [KeymapPreset::MacOS, KeymapPreset::Linux, KeymapPreset::Emacs] {
+            let keymap = Keymap::for_preset(preset);
+            let actions = keymap
+                .all_bindings()
+                .values()
+                .chain(keymap.all_sequences().values())
+                .chain(keymap.context_bindings().iter().map(|binding| &binding.action));
+            for action in actions {
+                // Typing a tab in a code block, and the palette itself
+                let unlisted = matches!(action, EditorAction::InsertChar(_) | EditorAction::ToggleCommandPalette);
+                assert!(unlisted || registry.command_for(action).is_some(), "no command for {:?}", action);
+            }
+        }
+
+        let names: std::collections::HashSet<_> = registry.commands().iter().map(|command| &command.name).collect();
+        assert_eq!(names.len(), registry.commands().len());
+    }
+
+    #[test]
+    fn test_palette_selection() {
+        let registry = CommandRegistry::default();
+        let mut palette = CommandPalette::new();
+        assert_eq!(palette.results(&registry).len(), PALETTE_RESULT_LIMIT);
+
+        palette.push_str("togglehead");
+        assert_eq!(palette.results(&registry).len(), 6);
+        palette.select_previous(&registry);
+        assert_eq!(palette.selected_command(&registry).unwrap().name, "Toggle Heading 6");
+        palette.select_next(&registry);
+        palette.select_next(&registry);
+        assert_eq!(
+            palette.selected_command(&registry).unwrap().action,
+            EditorAction::ToggleFormat(FormatType::Heading(2))
+        );
+
+        palette.backspace();
+        assert_eq!(palette.query(), "togglehea");
+        assert_eq!(palette.selected_index(), 0);
+        palette.push_str("zzz");
+        assert_eq!(palette.selected_command(&registry), None);
+
+        let mut palette = CommandPalette::prompting(PalettePrompt::MacroName);
+        palette.push_str("save");
+        assert!(palette.results(&registry).is_empty());
+        assert_eq!(palette.selected_command(&registry), None);
+    }
+
+    #[test]
+    fn test_saved_macros_are_commands() {
+        let mut registry = CommandRegistry::default();
+        registry.register_macro("tidy notes");
+        let found = &registry.search("tidy")[0];
+        assert_eq!(found.command.name, "Macro: tidy notes");
+        assert_eq!(found.command.action, EditorAction::PlayNamedMacro("tidy notes".to_string()));
+    }
+}
@@ -0,0 +1,366 @@
+//! Keymap configuration files
+//!
+//! A keymap config is a JSON object that picks a preset and overrides or
+//! extends its bindings. Keys use the `KeyBinding` string syntax, with spaces
+//! between the keys of a chord. Actions are `EditorAction` variants, and
+//! `null` removes a binding. Bindings under `contexts` only apply where
+//! their `when` predicate holds, and `macros` holds saved keyboard macros as
+//! lists of actions:
+//!
+//! ```json
+//! {
+//!     "preset": "linux",
+//!     "bindings": {
+//!         "ctrl-shift-d": "DeleteCurrentLine",
+//!         "alt-1": { "ToggleFormat": { "Heading": 1 } },
+//!         "ctrl-k ctrl-c": { "ToggleFormat": "Code" },
+//!         "ctrl-k": null
+//!     },
+//!     "contexts": [
+//!         {
+//!             "when": "in_table && !has_selection",
+//!             "bindings": { "alt-enter": { "EditTable": "InsertRowBelow" } }
+//!         }
+//!     ],
+//!     "macros": {
+//!         "bullet": [{ "MoveCursor": "LineStart" }, { "InsertText": "- " }, { "MoveCursor": "Down" }]
+//!     }
+//! }
+//! ```
+
+use super::actions::EditorAction;
+use super::context::ContextPredicate;
+use super::keymap::{parse_key_sequence, KeyBinding, Keymap, KeymapPreset};
+use super::macros::Macro;
+use crate::file::write_atomically;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapConfig {
+    /// The bindings to start from, the current platform's if missing
+    preset: Option<KeymapPreset>,
+    #[serde(default)]
+    bindings: HashMap<KeySequence, Option<EditorAction>>,
+    #[serde(default)]
+    contexts: Vec<ContextBindings>,
+    #[serde(default)]
+    macros: BTreeMap<String, Macro>,
+}
+
+/// Bindings that only apply where `when` holds
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextBindings {
+    when: ContextPredicate,
+    bindings: HashMap<KeySequence, Option<EditorAction>>,
+}
+
+/// The keys of a binding: one shortcut, or a chord of several separated by
+/// spaces such as `ctrl-k ctrl-c`
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct KeySequence(Vec<KeyBinding>);
+
+impl<'de> Deserialize<'de> for KeySequence {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse_key_sequence(&text).map(KeySequence).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Errors from loading a keymap config
+#[derive(Debug)]
+pub enum KeymapConfigError {
+    Io(io::Error),
+    /// The config isn't valid. Lines and columns start at 1.
+    Invalid { line: usize, column: usize, message: String },
+}
+
+impl fmt::Display for KeymapConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeymapConfigError::Io(error) => write!(f, "{}", error),
+            KeymapConfigError::Invalid { line, column, message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeymapConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeymapConfigError::Io(error) => Some(error),
+            KeymapConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for KeymapConfigError {
+    fn from(error: io::Error) -> Self {
+        KeymapConfigError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for KeymapConfigError {
+    fn from(error: serde_json::Error) -> Self {
+        // serde_json puts the position at the end of its message
+        let message = error.to_string();
+        let position = format!(" at line {} column {}", error.line(), error.column());
+        KeymapConfigError::Invalid {
+            line: error.line(),
+            column: error.column(),
+            message: message.strip_suffix(&position).unwrap_or(&message).to_string(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Build a keymap from the text of a config
+    pub fn from_config(text: &str) -> Result<Self, KeymapConfigError> {
+        let config: KeymapConfig = serde_json::from_str(text)?;
+        let mut keymap = Keymap::for_preset(config.preset.unwrap_or_else(KeymapPreset::current));
+        for (KeySequence(keys), action) in config.bindings {
+            match action {
+                Some(action) => keymap.bind_sequence(keys, action),
+                None => {
+                    keymap.unbind_sequence(&keys);
+                }
+            }
+        }
+        for ContextBindings { when, bindings } in config.contexts {
+            for (KeySequence(keys), action) in bindings {
+                match action {
+                    Some(action) => keymap.bind_in(when.clone(), keys, action),
+                    None => {
+                        keymap.unbind_in(&when, &keys);
+                    }
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Build a keymap from a config file
+    pub fn load(path: &Path) -> Result<Self, KeymapConfigError> {
+        Self::from_config(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// The macros saved in the text of a config, by name
+pub fn macros_from_config(text: &str) -> Result<BTreeMap<String, Macro>, KeymapConfigError> {
+    let config: KeymapConfig = serde_json::from_str(text)?;
+    Ok(config.macros)
+}
+
+/// Save a macro under `name` in a config file, replacing any macro already
+/// there with that name. The rest of the config is kept in its order, though
+/// it's re-indented, and the file is created if it doesn't exist. The file is
+/// replaced atomically, so a crash can't leave the bindings half written.
+pub fn save_macro(path: &Path, name: &str, saved: &Macro) -> Result<(), KeymapConfigError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => "{}".to_string(),
+        Err(error) => return Err(error.into()),
+    };
+    let mut config: serde_json::Value = serde_json::from_str(&text)?;
+    let not_an_object = |what: &str| KeymapConfigError::Invalid {
+        line: 1,
+        column: 1,
+        message: format!("{} is not an object", what),
+    };
+    let macros = config
+        .as_object_mut()
+        .ok_or_else(|| not_an_object("the config"))?
+        .entry("macros")
+        .or_insert_with(|| serde_json::Value::Object(Default::default()))
+        .as_object_mut()
+        .ok_or_else(|| not_an_object("`macros`"))?;
+    macros.insert(name.to_string(), serde_json::to_value(saved)?);
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    write_atomically(path, &(serde_json::to_string_pretty(&config)? + "\n"))?;
+    Ok(())
+}
+
+/// Where the user's keymap config lives: `wonder/keymap.json` in
+/// `$XDG_CONFIG_HOME`, or in `~/.config` if that isn't set
+pub fn user_keymap_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("wonder").join("keymap.json"))
+}
+
+/// The macros saved in the user's config. A config that can't be loaded has
+/// none, and `load_user_keymap` reports why.
+pub fn load_user_macros() -> BTreeMap<String, Macro> {
+    user_keymap_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| macros_from_config(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Save a macro under `name` in the user's config
+pub fn save_user_macro(name: &str, saved: &Macro) -> Result<(), KeymapConfigError> {
+    let path = user_keymap_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    save_macro(&path, name, saved)
+}
+
+/// The user's keymap, or the current platform's preset if there is no
+/// config. A config that can't be loaded is reported and ignored.
+pub fn load_user_keymap() -> Keymap {
+    let default = || Keymap::for_preset(KeymapPreset::current());
+    let Some(path) = user_keymap_path().filter(|path| path.exists()) else {
+        return default();
+    };
+    match Keymap::load(&path) {
+        Ok(keymap) => {
+            for conflict in keymap.conflicts() {
+                eprintln!("Keymap {}: {}", path.display(), conflict);
+            }
+            keymap
+        }
+        Err(error) => {
+            eprintln!("Could not load keymap {}: {}", path.display(), error);
+            default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::actions::{FormatType, Movement, TableEdit};
+    use crate::input::context::KeyContext;
+    use crate::input::keymap::{KeyLookup, Modifiers};
+
+    #[test]
+    fn test_config_overrides_preset() {
+        let keymap = Keymap::from_config(
+            r#"{
+                "preset": "linux",
+                "bindings": {
+                    "ctrl-shift-d": "DeleteCurrentLine",
+                    "alt-1": { "ToggleFormat": { "Heading": 1 } },
+                    "alt-j": { "MoveCursor": "Down" },
+                    "ctrl-k ctrl-c": { "ToggleFormat": "Code" },
+                    "ctrl-b": null
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let get = |text: &str| keymap.get(&text.parse().unwrap()).cloned();
+        assert_eq!(get("ctrl-shift-d"), Some(EditorAction::DeleteCurrentLine));
+        assert_eq!(get("alt-1"), Some(EditorAction::ToggleFormat(FormatType::Heading(1))));
+        assert_eq!(get("alt-j"), Some(EditorAction::MoveCursor(Movement::Down)));
+        assert_eq!(get("ctrl-b"), None);
+        assert_eq!(
+            keymap.lookup(&parse_key_sequence("ctrl-k ctrl-c").unwrap()),
+            KeyLookup::Action(&EditorAction::ToggleFormat(FormatType::Code))
+        );
+        // Ctrl+K (insert link in the preset) now starts a chord
+        assert_eq!(keymap.conflicts().len(), 1);
+        // The rest of the preset is still there
+        assert_eq!(get("ctrl-i"), Some(EditorAction::ToggleFormat(FormatType::Italic)));
+        assert_eq!(keymap.get(&KeyBinding::with_modifiers("i", Modifiers::cmd())), None);
+    }
+
+    #[test]
+    fn test_config_context_bindings() {
+        let keymap = Keymap::from_config(
+            r#"{
+                "preset": "macos",
+                "contexts": [
+                    {
+                        "when": "in_table && !has_selection",
+                        "bindings": { "alt-enter": { "EditTable": "InsertRowBelow" } }
+                    },
+                    { "when": "in_code_block", "bindings": { "tab": null } }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let keys = |text: &str| parse_key_sequence(text).unwrap();
+        let table = KeyContext { in_table: true, ..Default::default() };
+        assert_eq!(
+            keymap.lookup_in(&keys("alt-enter"), &table),
+            KeyLookup::Action(&EditorAction::EditTable(TableEdit::InsertRowBelow))
+        );
+        let selecting = KeyContext { has_selection: true, ..table };
+        assert_eq!(keymap.lookup_in(&keys("alt-enter"), &selecting), KeyLookup::Unbound);
+        // Without its code block binding, Tab indents there like everywhere else
+        let code = KeyContext { in_code_block: true, ..Default::default() };
+        assert_eq!(keymap.lookup_in(&keys("tab"), &code), KeyLookup::Action(&EditorAction::Indent));
+    }
+
+    #[test]
+    fn test_config_errors_have_positions() {
+        let error = |text: &str| match Keymap::from_config(text) {
+            Err(KeymapConfigError::Invalid { line, column, message }) => (line, column, message),
+            other => panic!("expected an invalid config, got {:?}", other),
+        };
+
+        let (line, _, message) = error("{\n  \"bindings\": {\n    \"ctrl-x\": \"Explode\"\n  }\n}");
+        assert_eq!(line, 3);
+        assert!(message.contains("unknown variant `Explode`"), "{}", message);
+
+        let (line, column, message) = error("{\n  \"bindings\": { \"hyper-x\": \"Undo\" }\n}");
+        assert_eq!((line, column), (2, 25));
+        assert_eq!(message, "unknown modifier `hyper` in `hyper-x`");
+
+        let (line, _, _) = error("{\n  \"preset\": \"beos\"\n}");
+        assert_eq!(line, 2);
+
+        let (line, _, message) = error("{\n  \"contexts\": [\n    { \"when\": \"in_tabel\", \"bindings\": {} }\n  ]\n}");
+        assert_eq!(line, 3);
+        assert_eq!(message, "unknown context `in_tabel` in `in_tabel`");
+
+        assert!(Keymap::from_config("{ \"bindings\": {} ").is_err());
+    }
+
+    #[test]
+    fn test_saved_macros() {
+        let dir = crate::file::scratch_dir("config-macros");
+        let path = dir.join("wonder").join("keymap.json");
+        let bullet = Macro::new(vec![
+            EditorAction::MoveCursor(Movement::LineStart),
+            EditorAction::InsertText("- ".to_string()),
+        ]);
+
+        // The file and its directory are created
+        save_macro(&path, "bullet", &bullet).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(macros_from_config(&text).unwrap()["bullet"], bullet);
+
+        // Other settings and macros stay
+        std::fs::write(&path, r#"{ "preset": "emacs", "macros": { "old": ["Backspace"] } }"#).unwrap();
+        save_macro(&path, "bullet", &bullet).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let macros = macros_from_config(&text).unwrap();
+        assert_eq!(macros.keys().collect::<Vec<_>>(), ["bullet", "old"]);
+        assert_eq!(macros["old"].actions(), [EditorAction::Backspace]);
+        assert_eq!(Keymap::from_config(&text).unwrap().get(&"ctrl-f".parse().unwrap()), Some(&EditorAction::MoveCursor(Movement::Right)));
+
+        // Hand-written keys keep their order
+        std::fs::write(&path, r#"{ "preset": "emacs", "bindings": { "f2": "Save", "f1": "Undo" } }"#).unwrap();
+        save_macro(&path, "bullet", &bullet).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let order = ["\"preset\"", "\"bindings\"", "\"f2\"", "\"f1\"", "\"macros\""].map(|key| text.find(key).unwrap());
+        assert!(order.windows(2).all(|pair| pair[0] < pair[1]));
+
+        std::fs::write(&path, r#"{ "macros": [] }"#).unwrap();
+        assert!(matches!(save_macro(&path, "bullet", &bullet), Err(KeymapConfigError::Invalid { .. })));
+        assert!(macros_from_config(r#"{ "macros": { "bad": ["Explode"] } }"#).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Contexts for key bindings that only apply in some places
+//!
+//! A `KeyContext` describes where the cursor is (from the syntax tree) and
+//! what the editor is doing. A `ContextPredicate` such as
+//! `in_table && !has_selection` picks the contexts a binding applies in; the
+//! binding whose predicate has the most conditions wins.
+
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The state key bindings are resolved against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyContext {
+    pub in_table: bool,
+    pub in_list: bool,
+    pub in_code_block: bool,
+    pub has_selection: bool,
+    /// A search is active, so its matches are highlighted
+    pub find_open: bool,
+}
+
+impl KeyContext {
+    pub fn has(&self, flag: ContextFlag) -> bool {
+        match flag {
+            ContextFlag::InTable => self.in_table,
+            ContextFlag::InList => self.in_list,
+            ContextFlag::InCodeBlock => self.in_code_block,
+            ContextFlag::HasSelection => self.has_selection,
+            ContextFlag::FindOpen => self.find_open,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextFlag {
+    InTable,
+    InList,
+    InCodeBlock,
+    HasSelection,
+    FindOpen,
+}
+
+impl ContextFlag {
+    const ALL: [ContextFlag; 5] = [
+        ContextFlag::InTable,
+        ContextFlag::InList,
+        ContextFlag::InCodeBlock,
+        ContextFlag::HasSelection,
+        ContextFlag::FindOpen,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContextFlag::InTable => "in_table",
+            ContextFlag::InList => "in_list",
+            ContextFlag::InCodeBlock => "in_code_block",
+            ContextFlag::HasSelection => "has_selection",
+            ContextFlag::FindOpen => "find_open",
+        }
+    }
+}
+
+/// Conditions that must all hold for a binding to apply. Each one is a flag
+/// that has to be set, or clear if it is negated with `!`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ContextPredicate {
+    conditions: Vec<(ContextFlag, bool)>,
+}
+
+impl ContextPredicate {
+    /// A predicate that holds when `flag` is set
+    pub fn when(flag: ContextFlag) -> Self {
+        Self { conditions: vec![(flag, true)] }
+    }
+
+    /// This predicate with `flag` also required to be clear
+    pub fn and_not(mut self, flag: ContextFlag) -> Self {
+        self.conditions.push((flag, false));
+        self
+    }
+
+    pub fn matches(&self, context: &KeyContext) -> bool {
+        self.conditions.iter().all(|&(flag, set)| context.has(flag) == set)
+    }
+
+    /// How specific the predicate is: bindings with more conditions win
+    pub fn specificity(&self) -> usize {
+        self.conditions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+}
+
+/// Parses conditions joined by `&&`, e.g. `in_list && !has_selection`
+impl FromStr for ContextPredicate {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut conditions = Vec::new();
+        for condition in text.split("&&").map(str::trim) {
+            let (name, set) = match condition.strip_prefix('!') {
+                Some(name) => (name.trim(), false),
+                None => (condition, true),
+            };
+            let flag = ContextFlag::ALL
+                .into_iter()
+                .find(|flag| flag.name() == name)
+                .ok_or_else(|| format!("unknown context `{}` in `{}`", name, text))?;
+            conditions.push((flag, set));
+        }
+        Ok(Self { conditions })
+    }
+}
+
+impl fmt::Display for ContextPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let conditions: Vec<String> = self
+            .conditions
+            .iter()
+            .map(|&(flag, set)| format!("{}{}", if set { "" } else { "!" }, flag.name()))
+            .collect();
+        write!(f, "{}", conditions.join(" && "))
+    }
+}
+
+impl<'de> Deserialize<'de> for ContextPredicate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match_predicates() {
+        let predicate: ContextPredicate = "in_list && !has_selection".parse().unwrap();
+        assert_eq!(predicate, ContextPredicate::when(ContextFlag::InList).and_not(ContextFlag::HasSelection));
+        assert_eq!(predicate.to_string(), "in_list && !has_selection");
+        assert_eq!(predicate.specificity(), 2);
+
+        let mut context = KeyContext { in_list: true, ..Default::default() };
+        assert!(predicate.matches(&context));
+        context.has_selection = true;
+        assert!(!predicate.matches(&context));
+        assert!(ContextPredicate::default().matches(&context));
+
+        assert_eq!(
+            "in_tabel".parse::<ContextPredicate>(),
+            Err("unknown context `in_tabel` in `in_tabel`".to_string())
+        );
+        assert!("in_table &&".parse::<ContextPredicate>().is_err());
+    }
+}
@@ -3,8 +3,12 @@
 //! This module provides a configurable system for binding keyboard shortcuts
 //! to editor actions, allowing for customizable and extensible input handling.
 
-use super::actions::{EditorAction, Movement, FormatType};
+use super::actions::{CaseChange, EditorAction, MacroRepeat, Movement, FormatType, SearchDirection, TableEdit};
+use super::context::{ContextFlag, ContextPredicate, KeyContext};
+use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
 /// Represents a keyboard shortcut with key and modifiers
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -40,6 +44,70 @@ impl KeyBinding {
     }
 }
 
+/// Parses the config syntax for a shortcut: modifiers and a key joined by
+/// dashes, e.g. `cmd-shift-left` or `ctrl--` for Ctrl and the minus key
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key) = match text.strip_suffix("--") {
+            Some(rest) => (rest, "-"),
+            None if text == "-" => ("", "-"),
+            None => text.rsplit_once('-').unwrap_or(("", text)),
+        };
+        if key.is_empty() {
+            return Err(format!("missing key in `{}`", text));
+        }
+
+        let mut modifiers = Modifiers::none();
+        for name in modifier_names.split('-').filter(|name| !name.is_empty()) {
+            match name.to_lowercase().as_str() {
+                "cmd" | "super" | "win" | "platform" => modifiers.cmd = true,
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" | "option" | "opt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                _ => return Err(format!("unknown modifier `{}` in `{}`", name, text)),
+            }
+        }
+        Ok(Self::with_modifiers(&key.to_lowercase(), modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modifiers = [
+            (self.modifiers.ctrl, "ctrl-"),
+            (self.modifiers.alt, "alt-"),
+            (self.modifiers.shift, "shift-"),
+            (self.modifiers.cmd, "cmd-"),
+        ];
+        for (_, name) in modifiers.iter().filter(|(held, _)| *held) {
+            write!(f, "{}", name)?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+/// Parse a chord: shortcuts separated by spaces, e.g. `ctrl-k ctrl-c`
+pub fn parse_key_sequence(text: &str) -> Result<Vec<KeyBinding>, String> {
+    let keys = text.split_whitespace().map(str::parse).collect::<Result<Vec<KeyBinding>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key binding".to_string());
+    }
+    Ok(keys)
+}
+
+/// Shortcuts separated by spaces, as in `parse_key_sequence`
+pub fn format_key_sequence(keys: &[KeyBinding]) -> String {
+    keys.iter().map(KeyBinding::to_string).collect::<Vec<_>>().join(" ")
+}
+
+impl<'de> Deserialize<'de> for KeyBinding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl Modifiers {
     /// No modifiers
     pub fn none() -> Self {
@@ -87,10 +155,78 @@ impl Modifiers {
     }
 }
 
+/// Built-in sets of default bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeymapPreset {
+    /// Cmd shortcuts, with Ctrl versions of the common ones
+    MacOS,
+    /// Ctrl shortcuts in place of Cmd, since Cmd is the Super key here
+    Linux,
+    /// The same Ctrl shortcuts as Linux
+    Windows,
+    /// Emacs keys, with the macOS Cmd shortcuts still there alongside them
+    Emacs,
+}
+
+impl KeymapPreset {
+    /// The preset for the platform the editor was built for
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::MacOS
+        } else if cfg!(target_os = "windows") {
+            Self::Windows
+        } else {
+            Self::Linux
+        }
+    }
+}
+
+/// What a sequence of keys is bound to
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyLookup<'a> {
+    Action(&'a EditorAction),
+    /// The keys start a longer chord
+    Prefix,
+    Unbound,
+}
+
+/// A binding that can never run because its keys also start a longer chord,
+/// which takes precedence
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeymapConflict {
+    pub keys: Vec<KeyBinding>,
+    pub action: EditorAction,
+}
+
+impl fmt::Display for KeymapConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is bound to {:?} but also starts a longer chord, so it never runs",
+            format_key_sequence(&self.keys),
+            self.action
+        )
+    }
+}
+
+/// A binding that only applies where its predicate holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextBinding {
+    pub predicate: ContextPredicate,
+    pub keys: Vec<KeyBinding>,
+    pub action: EditorAction,
+}
+
 /// Maps keyboard shortcuts to editor actions
 #[derive(Debug)]
 pub struct Keymap {
     bindings: HashMap<KeyBinding, EditorAction>,
+    /// Chords of two or more keys, e.g. Ctrl+K Ctrl+C
+    sequences: HashMap<Vec<KeyBinding>, EditorAction>,
+    /// Bindings for some contexts only, which take precedence over the
+    /// others where they apply
+    contextual: Vec<ContextBinding>,
 }
 
 impl Keymap {
@@ -98,6 +234,8 @@ impl Keymap {
     pub fn new() -> Self {
         Self {
             bindings: HashMap::new(),
+            sequences: HashMap::new(),
+            contextual: Vec::new(),
         }
     }
 
@@ -108,6 +246,17 @@ impl Keymap {
         keymap
     }
 
+    /// Create a keymap with a preset's bindings
+    pub fn for_preset(preset: KeymapPreset) -> Self {
+        let mut keymap = Self::default();
+        match preset {
+            KeymapPreset::MacOS => {}
+            KeymapPreset::Linux | KeymapPreset::Windows => keymap.use_ctrl_for_cmd(),
+            KeymapPreset::Emacs => keymap.add_emacs_bindings(),
+        }
+        keymap
+    }
+
     /// Add a key binding
     pub fn bind(&mut self, key_binding: KeyBinding, action: EditorAction) {
         self.bindings.insert(key_binding, action);
@@ -123,6 +272,133 @@ impl Keymap {
         self.bindings.get(key_binding)
     }
 
+    /// Bind a chord of one or more keys
+    pub fn bind_sequence(&mut self, keys: Vec<KeyBinding>, action: EditorAction) {
+        match <[KeyBinding; 1]>::try_from(keys) {
+            Ok([key_binding]) => self.bind(key_binding, action),
+            Err(keys) => {
+                self.sequences.insert(keys, action);
+            }
+        }
+    }
+
+    /// Remove a chord of one or more keys
+    pub fn unbind_sequence(&mut self, keys: &[KeyBinding]) -> Option<EditorAction> {
+        match keys {
+            [key_binding] => self.unbind(key_binding),
+            _ => self.sequences.remove(keys),
+        }
+    }
+
+    /// Bind a chord of one or more keys for the contexts `predicate` holds in
+    pub fn bind_in(&mut self, predicate: ContextPredicate, keys: Vec<KeyBinding>, action: EditorAction) {
+        if predicate.is_empty() {
+            return self.bind_sequence(keys, action);
+        }
+        self.unbind_in(&predicate, &keys);
+        self.contextual.push(ContextBinding { predicate, keys, action });
+    }
+
+    /// Remove a binding made with `bind_in`
+    pub fn unbind_in(&mut self, predicate: &ContextPredicate, keys: &[KeyBinding]) -> Option<EditorAction> {
+        if predicate.is_empty() {
+            return self.unbind_sequence(keys);
+        }
+        let index = self
+            .contextual
+            .iter()
+            .position(|binding| binding.predicate == *predicate && binding.keys == keys)?;
+        Some(self.contextual.remove(index).action)
+    }
+
+    /// What `keys`, pressed one after another, are bound to outside any
+    /// particular context
+    pub fn lookup(&self, keys: &[KeyBinding]) -> KeyLookup<'_> {
+        self.lookup_in(keys, &KeyContext::default())
+    }
+
+    /// What `keys` are bound to in `context`. Keys that start a longer chord
+    /// are a prefix even if they are bound themselves. Otherwise the binding
+    /// with the most specific predicate that holds wins, the latest one if
+    /// there is a tie, and bindings without a predicate come last.
+    pub fn lookup_in(&self, keys: &[KeyBinding], context: &KeyContext) -> KeyLookup<'_> {
+        if self.is_prefix_in(keys, context) {
+            return KeyLookup::Prefix;
+        }
+        let contextual = self
+            .contextual
+            .iter()
+            .filter(|binding| binding.keys == keys && binding.predicate.matches(context))
+            .max_by_key(|binding| binding.predicate.specificity());
+        if let Some(binding) = contextual {
+            return KeyLookup::Action(&binding.action);
+        }
+        let action = match keys {
+            [key_binding] => self.bindings.get(key_binding),
+            _ => self.sequences.get(keys),
+        };
+        action.map_or(KeyLookup::Unbound, KeyLookup::Action)
+    }
+
+    /// Whether a binding for some contexts only is, or starts with, `keys`.
+    /// Other keys resolve the same in every context.
+    pub fn depends_on_context(&self, keys: &[KeyBinding]) -> bool {
+        self.contextual.iter().any(|binding| binding.keys.starts_with(keys))
+    }
+
+    /// Whether `keys` start a longer chord outside any particular context
+    pub fn is_prefix(&self, keys: &[KeyBinding]) -> bool {
+        self.sequences.keys().any(|sequence| sequence.len() > keys.len() && sequence.starts_with(keys))
+    }
+
+    /// Whether `keys` start a longer chord in `context`
+    pub fn is_prefix_in(&self, keys: &[KeyBinding], context: &KeyContext) -> bool {
+        self.is_prefix(keys)
+            || self.contextual.iter().any(|binding| {
+                binding.keys.len() > keys.len() && binding.keys.starts_with(keys) && binding.predicate.matches(context)
+            })
+    }
+
+    /// Bindings hidden by a longer chord that starts with the same keys
+    pub fn conflicts(&self) -> Vec<KeymapConflict> {
+        let singles = self.bindings.iter().map(|(key_binding, action)| (vec![key_binding.clone()], action));
+        let sequences = self.sequences.iter().map(|(keys, action)| (keys.clone(), action));
+        let mut conflicts: Vec<KeymapConflict> = singles
+            .chain(sequences)
+            .filter(|(keys, _)| self.is_prefix(keys))
+            .map(|(keys, action)| KeymapConflict { keys, action: action.clone() })
+            .collect();
+        conflicts.sort_by_key(|conflict| format_key_sequence(&conflict.keys));
+        conflicts
+    }
+
+    /// The keys to show for `action`, outside any particular context. Of
+    /// several bindings the shortest is picked, the one with the fewest
+    /// modifiers among those, and a Cmd shortcut over the same Ctrl one.
+    pub fn keys_for(&self, action: &EditorAction) -> Option<Vec<KeyBinding>> {
+        let singles = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key_binding, _)| vec![key_binding.clone()]);
+        let sequences = self
+            .sequences
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(keys, _)| keys.clone());
+        singles.chain(sequences).min_by_key(|keys| {
+            let modifier_count: usize = keys
+                .iter()
+                .map(|key_binding| {
+                    let modifiers = &key_binding.modifiers;
+                    [modifiers.ctrl, modifiers.alt, modifiers.shift, modifiers.cmd].iter().filter(|held| **held).count()
+                })
+                .sum();
+            let uses_ctrl = keys.iter().any(|key_binding| key_binding.modifiers.ctrl);
+            (keys.len(), modifier_count, uses_ctrl, format_key_sequence(keys))
+        })
+    }
+
     /// Add all default key bindings
     fn add_default_bindings(&mut self) {
         // Basic movement
@@ -286,6 +562,50 @@ impl Keymap {
             KeyBinding::with_modifiers("i", Modifiers::cmd()),
             EditorAction::ToggleFormat(FormatType::Italic)
         );
+        self.bind(
+            KeyBinding::with_modifiers("e", Modifiers::cmd()),
+            EditorAction::ToggleFormat(FormatType::Code)
+        );
+        self.bind(
+            KeyBinding::with_modifiers("x", Modifiers::cmd_shift()),
+            EditorAction::ToggleFormat(FormatType::Strikethrough)
+        );
+        self.bind(
+            KeyBinding::with_modifiers("h", Modifiers::cmd_shift()),
+            EditorAction::ToggleFormat(FormatType::Highlight)
+        );
+
+        // Headings (Cmd+1 to Cmd+6), lists and quotes
+        for level in 1..=6u8 {
+            self.bind(
+                KeyBinding::with_modifiers(&level.to_string(), Modifiers::cmd()),
+                EditorAction::ToggleFormat(FormatType::Heading(level))
+            );
+        }
+        self.bind(
+            KeyBinding::with_modifiers("8", Modifiers::cmd_shift()),
+            EditorAction::ToggleFormat(FormatType::BulletList)
+        );
+        self.bind(
+            KeyBinding::with_modifiers("9", Modifiers::cmd_shift()),
+            EditorAction::ToggleFormat(FormatType::TaskList)
+        );
+        self.bind(
+            KeyBinding::with_modifiers("b", Modifiers::cmd_shift()),
+            EditorAction::ToggleFormat(FormatType::BlockQuote)
+        );
+
+        // Tick or clear tasks (Cmd+Enter)
+        self.bind(
+            KeyBinding::with_modifiers("enter", Modifiers::cmd()),
+            EditorAction::ToggleTask
+        );
+
+        // Insert link (Cmd+K)
+        self.bind(
+            KeyBinding::with_modifiers("k", Modifiers::cmd()),
+            EditorAction::InsertLink
+        );
         
         // Clipboard operations (Cmd/Ctrl + C/X/V)
         self.bind(
@@ -345,6 +665,28 @@ impl Keymap {
             EditorAction::Redo
         );
         
+        // Find (Cmd+F), then next/previous match (Cmd/Ctrl + G, Shift+Cmd/Ctrl + G)
+        self.bind(
+            KeyBinding::with_modifiers("f", Modifiers::cmd()),
+            EditorAction::OpenFind
+        );
+        self.bind(
+            KeyBinding::with_modifiers("g", Modifiers::cmd()),
+            EditorAction::FindNext
+        );
+        self.bind(
+            KeyBinding::with_modifiers("g", Modifiers::cmd_shift()),
+            EditorAction::FindPrevious
+        );
+        self.bind(
+            KeyBinding::with_modifiers("g", Modifiers::ctrl()),
+            EditorAction::FindNext
+        );
+        self.bind(
+            KeyBinding::with_modifiers("g", Modifiers::ctrl_shift()),
+            EditorAction::FindPrevious
+        );
+        
         // Missing navigation shortcuts from ENG-133
         
         // Shift+Home/End - Extend selection to line boundaries
@@ -443,12 +785,223 @@ impl Keymap {
             KeyBinding::with_modifiers("k", Modifiers::ctrl_shift()),
             EditorAction::DeleteCurrentLine
         );
+
+        // File operations (Cmd/Ctrl + N/O/S, Shift+Cmd/Ctrl + S)
+        self.bind(
+            KeyBinding::with_modifiers("n", Modifiers::cmd()),
+            EditorAction::NewFile
+        );
+        self.bind(
+            KeyBinding::with_modifiers("o", Modifiers::cmd()),
+            EditorAction::OpenFile
+        );
+        self.bind(
+            KeyBinding::with_modifiers("s", Modifiers::cmd()),
+            EditorAction::Save
+        );
+        self.bind(
+            KeyBinding::with_modifiers("s", Modifiers::cmd_shift()),
+            EditorAction::SaveAs
+        );
+        self.bind(
+            KeyBinding::with_modifiers("n", Modifiers::ctrl()),
+            EditorAction::NewFile
+        );
+        self.bind(
+            KeyBinding::with_modifiers("o", Modifiers::ctrl()),
+            EditorAction::OpenFile
+        );
+        self.bind(
+            KeyBinding::with_modifiers("s", Modifiers::ctrl()),
+            EditorAction::Save
+        );
+        self.bind(
+            KeyBinding::with_modifiers("s", Modifiers::ctrl_shift()),
+            EditorAction::SaveAs
+        );
+
+        // Vim mode on and off: Cmd+Option+V
+        self.bind(
+            KeyBinding::with_modifiers("v", Modifiers { cmd: true, alt: true, ..Default::default() }),
+            EditorAction::ToggleVimMode
+        );
+
+        // Command palette: Cmd+Shift+P
+        self.bind(
+            KeyBinding::with_modifiers("p", Modifiers::cmd_shift()),
+            EditorAction::ToggleCommandPalette
+        );
+
+        // Macros: Cmd+Option+R starts and stops recording, Cmd+Option+E plays
+        self.bind(
+            KeyBinding::with_modifiers("r", Modifiers { cmd: true, alt: true, ..Default::default() }),
+            EditorAction::ToggleMacroRecording
+        );
+        self.bind(
+            KeyBinding::with_modifiers("e", Modifiers { cmd: true, alt: true, ..Default::default() }),
+            EditorAction::PlayMacro(MacroRepeat::Times(1))
+        );
+
+        // Multiple cursors: Option+Shift+Up/Down adds a cursor above/below,
+        // Cmd/Ctrl+D adds the next occurrence, Cmd/Ctrl+Shift+L splits into lines
+        self.bind(
+            KeyBinding::with_modifiers("up", Modifiers { alt: true, shift: true, ..Default::default() }),
+            EditorAction::AddCursorAbove
+        );
+        self.bind(
+            KeyBinding::with_modifiers("down", Modifiers { alt: true, shift: true, ..Default::default() }),
+            EditorAction::AddCursorBelow
+        );
+        self.bind(
+            KeyBinding::with_modifiers("d", Modifiers::cmd()),
+            EditorAction::AddNextOccurrence
+        );
+        self.bind(
+            KeyBinding::with_modifiers("d", Modifiers::ctrl()),
+            EditorAction::AddNextOccurrence
+        );
+        self.bind(
+            KeyBinding::with_modifiers("l", Modifiers::cmd_shift()),
+            EditorAction::SplitSelectionIntoLines
+        );
+        self.bind(
+            KeyBinding::with_modifiers("l", Modifiers::ctrl_shift()),
+            EditorAction::SplitSelectionIntoLines
+        );
+
+        // List indentation: Tab and Shift+Tab
+        self.bind(KeyBinding::new("tab"), EditorAction::Indent);
+        self.bind(
+            KeyBinding::with_modifiers("tab", Modifiers::shift()),
+            EditorAction::Outdent
+        );
+
+        // Tab moves between table cells and types a tab in code blocks
+        self.bind_in(
+            ContextPredicate::when(ContextFlag::InTable),
+            vec![KeyBinding::new("tab")],
+            EditorAction::EditTable(TableEdit::NextCell)
+        );
+        self.bind_in(
+            ContextPredicate::when(ContextFlag::InTable),
+            vec![KeyBinding::with_modifiers("tab", Modifiers::shift())],
+            EditorAction::EditTable(TableEdit::PreviousCell)
+        );
+        self.bind_in(
+            ContextPredicate::when(ContextFlag::InCodeBlock),
+            vec![KeyBinding::new("tab")],
+            EditorAction::InsertChar('\t')
+        );
+
+        // Escape closes find before it clears the selection
+        self.bind_in(
+            ContextPredicate::when(ContextFlag::FindOpen),
+            vec![KeyBinding::new("escape")],
+            EditorAction::CloseFind
+        );
+    }
+
+    /// Turn the macOS bindings into Linux/Windows ones: every Cmd shortcut
+    /// moves to Ctrl, replacing the Ctrl shortcut it clashes with, and the
+    /// arrow keys and Home/End move the way they do on those platforms.
+    fn use_ctrl_for_cmd(&mut self) {
+        let cmd_bindings: Vec<(KeyBinding, EditorAction)> = self
+            .bindings
+            .iter()
+            .filter(|(binding, _)| binding.modifiers.cmd)
+            .map(|(binding, action)| (binding.clone(), action.clone()))
+            .collect();
+        for (binding, action) in cmd_bindings {
+            self.bindings.remove(&binding);
+            let modifiers = Modifiers { cmd: false, ctrl: true, ..binding.modifiers };
+            self.bind(KeyBinding::with_modifiers(&binding.key, modifiers), action);
+        }
+
+        // Ctrl+Left/Right move by word, Ctrl+Home/End to the document ends
+        // and Ctrl+Up/Down scroll
+        let pc_bindings = [
+            ("left", Modifiers::ctrl(), EditorAction::MoveCursor(Movement::WordStart)),
+            ("right", Modifiers::ctrl(), EditorAction::MoveCursor(Movement::WordEnd)),
+            ("left", Modifiers::ctrl_shift(), EditorAction::ExtendSelection(Movement::WordStart)),
+            ("right", Modifiers::ctrl_shift(), EditorAction::ExtendSelection(Movement::WordEnd)),
+            ("home", Modifiers::ctrl(), EditorAction::MoveCursor(Movement::DocumentStart)),
+            ("end", Modifiers::ctrl(), EditorAction::MoveCursor(Movement::DocumentEnd)),
+            ("up", Modifiers::ctrl(), EditorAction::ScrollUp),
+            ("down", Modifiers::ctrl(), EditorAction::ScrollDown),
+        ];
+        for (key, modifiers, action) in pc_bindings {
+            self.bind(KeyBinding::with_modifiers(key, modifiers), action);
+        }
+    }
+
+    /// Emacs movement, kill ring, mark, transpose, case, search and macro
+    /// keys in place of the Ctrl shortcuts they clash with. Ctrl+X starts chords.
+    fn add_emacs_bindings(&mut self) {
+        self.unbind(&KeyBinding::with_modifiers("x", Modifiers::ctrl()));
+
+        let emacs_bindings = [
+            ("ctrl-f", EditorAction::MoveCursor(Movement::Right)),
+            ("ctrl-b", EditorAction::MoveCursor(Movement::Left)),
+            ("ctrl-n", EditorAction::MoveCursor(Movement::Down)),
+            ("ctrl-p", EditorAction::MoveCursor(Movement::Up)),
+            ("ctrl-a", EditorAction::MoveCursor(Movement::LineStart)),
+            ("ctrl-e", EditorAction::MoveCursor(Movement::LineEnd)),
+            ("alt-f", EditorAction::MoveCursor(Movement::WordEnd)),
+            ("alt-b", EditorAction::MoveCursor(Movement::WordStart)),
+            ("alt-<", EditorAction::MoveCursor(Movement::DocumentStart)),
+            ("alt->", EditorAction::MoveCursor(Movement::DocumentEnd)),
+            ("ctrl-v", EditorAction::MoveCursor(Movement::PageDown)),
+            ("alt-v", EditorAction::MoveCursor(Movement::PageUp)),
+            ("ctrl-d", EditorAction::Delete),
+            ("ctrl-k", EditorAction::Kill(Movement::LineEnd)),
+            ("alt-d", EditorAction::Kill(Movement::WordEnd)),
+            ("alt-backspace", EditorAction::Kill(Movement::WordStart)),
+            ("ctrl-w", EditorAction::Cut),
+            ("alt-w", EditorAction::Copy),
+            ("ctrl-y", EditorAction::Paste),
+            ("alt-y", EditorAction::YankPop),
+            ("ctrl-space", EditorAction::SetMark),
+            ("ctrl-g", EditorAction::ClearSelection),
+            ("ctrl-t", EditorAction::TransposeChars),
+            ("alt-t", EditorAction::TransposeWords),
+            ("ctrl-x ctrl-t", EditorAction::TransposeLines),
+            ("alt-u", EditorAction::ChangeCase(CaseChange::Upper)),
+            ("alt-l", EditorAction::ChangeCase(CaseChange::Lower)),
+            ("alt-c", EditorAction::ChangeCase(CaseChange::Capitalize)),
+            ("ctrl-s", EditorAction::IncrementalSearch(SearchDirection::Forward)),
+            ("ctrl-r", EditorAction::IncrementalSearch(SearchDirection::Backward)),
+            ("ctrl-/", EditorAction::Undo),
+            ("ctrl-_", EditorAction::Undo),
+            ("ctrl-x u", EditorAction::Undo),
+            ("ctrl-x h", EditorAction::SelectAll),
+            ("ctrl-x ctrl-f", EditorAction::OpenFile),
+            ("ctrl-x ctrl-s", EditorAction::Save),
+            ("ctrl-x ctrl-w", EditorAction::SaveAs),
+            ("alt-x", EditorAction::ToggleCommandPalette),
+            ("f3", EditorAction::ToggleMacroRecording),
+            ("f4", EditorAction::PlayMacro(MacroRepeat::Times(1))),
+            ("ctrl-x e", EditorAction::PlayMacro(MacroRepeat::Times(1))),
+        ];
+        for (keys, action) in emacs_bindings {
+            let keys = parse_key_sequence(keys).expect("built-in Emacs bindings parse");
+            self.bind_sequence(keys, action);
+        }
     }
 
     /// Get all key bindings (for debugging/inspection)
     pub fn all_bindings(&self) -> &HashMap<KeyBinding, EditorAction> {
         &self.bindings
     }
+
+    /// Get all chords of two or more keys
+    pub fn all_sequences(&self) -> &HashMap<Vec<KeyBinding>, EditorAction> {
+        &self.sequences
+    }
+
+    /// Get all bindings that only apply in some contexts
+    pub fn context_bindings(&self) -> &[ContextBinding] {
+        &self.contextual
+    }
 }
 
 impl Default for Keymap {
@@ -680,6 +1233,221 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_bindings() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("g", Modifiers::cmd())),
+            Some(&EditorAction::FindNext)
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("g", Modifiers::cmd_shift())),
+            Some(&EditorAction::FindPrevious)
+        );
+    }
+
+    #[test]
+    fn test_file_bindings() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("s", Modifiers::cmd())),
+            Some(&EditorAction::Save)
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("s", Modifiers::ctrl_shift())),
+            Some(&EditorAction::SaveAs)
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("o", Modifiers::cmd())),
+            Some(&EditorAction::OpenFile)
+        );
+    }
+
+    #[test]
+    fn test_multiple_cursor_bindings() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("down", Modifiers { alt: true, shift: true, ..Default::default() })),
+            Some(&EditorAction::AddCursorBelow)
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("d", Modifiers::cmd())),
+            Some(&EditorAction::AddNextOccurrence)
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("l", Modifiers::cmd_shift())),
+            Some(&EditorAction::SplitSelectionIntoLines)
+        );
+    }
+
+    #[test]
+    fn test_formatting_bindings() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("e", Modifiers::cmd())),
+            Some(&EditorAction::ToggleFormat(FormatType::Code))
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("3", Modifiers::cmd())),
+            Some(&EditorAction::ToggleFormat(FormatType::Heading(3)))
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("k", Modifiers::cmd())),
+            Some(&EditorAction::InsertLink)
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("enter", Modifiers::cmd())),
+            Some(&EditorAction::ToggleTask)
+        );
+    }
+
+    #[test]
+    fn test_parse_key_binding() {
+        assert_eq!(
+            "cmd-shift-left".parse::<KeyBinding>(),
+            Ok(KeyBinding::with_modifiers("left", Modifiers::cmd_shift()))
+        );
+        assert_eq!("Ctrl--".parse::<KeyBinding>(), Ok(KeyBinding::with_modifiers("-", Modifiers::ctrl())));
+        assert_eq!("f6".parse::<KeyBinding>(), Ok(KeyBinding::new("f6")));
+        assert!("hyper-a".parse::<KeyBinding>().is_err());
+        assert!("ctrl-".parse::<KeyBinding>().is_err());
+
+        let binding = KeyBinding::with_modifiers("z", Modifiers { ctrl: true, alt: true, shift: true, cmd: false });
+        assert_eq!(binding.to_string(), "ctrl-alt-shift-z");
+        assert_eq!(binding.to_string().parse::<KeyBinding>(), Ok(binding));
+    }
+
+    #[test]
+    fn test_chords_and_conflicts() {
+        let mut keymap = Keymap::new();
+        let keys = parse_key_sequence("ctrl-k  ctrl-c").unwrap();
+        assert_eq!(format_key_sequence(&keys), "ctrl-k ctrl-c");
+        keymap.bind_sequence(keys.clone(), EditorAction::ToggleFormat(FormatType::Code));
+        keymap.bind_sequence(vec![KeyBinding::new("x")], EditorAction::Delete);
+
+        assert_eq!(keymap.get(&KeyBinding::new("x")), Some(&EditorAction::Delete));
+        assert_eq!(keymap.lookup(&keys[..1]), KeyLookup::Prefix);
+        assert_eq!(keymap.lookup(&keys), KeyLookup::Action(&EditorAction::ToggleFormat(FormatType::Code)));
+        assert_eq!(keymap.lookup(&[keys[0].clone(), KeyBinding::new("x")]), KeyLookup::Unbound);
+        assert!(keymap.conflicts().is_empty());
+
+        // Ctrl+K on its own can't run once it starts a chord
+        keymap.bind(keys[0].clone(), EditorAction::InsertLink);
+        assert_eq!(keymap.lookup(&keys[..1]), KeyLookup::Prefix);
+        assert_eq!(
+            keymap.conflicts(),
+            vec![KeymapConflict { keys: keys[..1].to_vec(), action: EditorAction::InsertLink }]
+        );
+        assert_eq!(
+            keymap.conflicts()[0].to_string(),
+            "`ctrl-k` is bound to InsertLink but also starts a longer chord, so it never runs"
+        );
+
+        assert!(keymap.unbind_sequence(&keys).is_some());
+        assert!(keymap.conflicts().is_empty());
+        assert!(parse_key_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn test_context_bindings_resolve_by_specificity() {
+        let keymap = Keymap::default();
+        let tab = [KeyBinding::new("tab")];
+        let table = KeyContext { in_table: true, ..Default::default() };
+        let code = KeyContext { in_code_block: true, ..Default::default() };
+        assert_eq!(keymap.lookup(&tab), KeyLookup::Action(&EditorAction::Indent));
+        assert_eq!(keymap.lookup_in(&tab, &table), KeyLookup::Action(&EditorAction::EditTable(TableEdit::NextCell)));
+        assert_eq!(keymap.lookup_in(&tab, &code), KeyLookup::Action(&EditorAction::InsertChar('\t')));
+
+        let escape = [KeyBinding::new("escape")];
+        let find = KeyContext { find_open: true, has_selection: true, ..Default::default() };
+        assert_eq!(keymap.lookup_in(&escape, &find), KeyLookup::Action(&EditorAction::CloseFind));
+        assert_eq!(
+            keymap.lookup_in(&escape, &KeyContext { has_selection: true, ..Default::default() }),
+            KeyLookup::Action(&EditorAction::ClearSelection)
+        );
+
+        // The binding with more conditions wins
+        let mut keymap = Keymap::new();
+        let list = ContextPredicate::when(ContextFlag::InList);
+        keymap.bind_in(list.clone(), tab.to_vec(), EditorAction::Indent);
+        keymap.bind_in(list.clone().and_not(ContextFlag::HasSelection), tab.to_vec(), EditorAction::InsertChar('>'));
+        let in_list = KeyContext { in_list: true, ..Default::default() };
+        assert_eq!(keymap.lookup_in(&tab, &in_list), KeyLookup::Action(&EditorAction::InsertChar('>')));
+        let selecting = KeyContext { has_selection: true, ..in_list };
+        assert_eq!(keymap.lookup_in(&tab, &selecting), KeyLookup::Action(&EditorAction::Indent));
+        assert_eq!(keymap.lookup(&tab), KeyLookup::Unbound);
+        assert_eq!(keymap.unbind_in(&list, &tab), Some(EditorAction::Indent));
+        assert_eq!(keymap.lookup_in(&tab, &selecting), KeyLookup::Unbound);
+    }
+
+    #[test]
+    fn test_linux_preset_uses_ctrl() {
+        let keymap = Keymap::for_preset(KeymapPreset::Linux);
+        assert!(keymap.all_bindings().keys().all(|binding| !binding.modifiers.cmd));
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("b", Modifiers::ctrl())),
+            Some(&EditorAction::ToggleFormat(FormatType::Bold))
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("left", Modifiers::ctrl())),
+            Some(&EditorAction::MoveCursor(Movement::WordStart))
+        );
+        assert_eq!(
+            keymap.get(&KeyBinding::with_modifiers("home", Modifiers::ctrl_shift())),
+            Some(&EditorAction::ExtendSelection(Movement::DocumentStart))
+        );
+        assert_eq!(
+            Keymap::for_preset(KeymapPreset::MacOS).get(&KeyBinding::with_modifiers("left", Modifiers::cmd())),
+            Some(&EditorAction::MoveCursor(Movement::LineStart))
+        );
+    }
+
+    #[test]
+    fn test_emacs_preset() {
+        let keymap = Keymap::for_preset(KeymapPreset::Emacs);
+        let lookup = |keys: &str| keymap.lookup(&parse_key_sequence(keys).unwrap());
+
+        assert_eq!(lookup("ctrl-f"), KeyLookup::Action(&EditorAction::MoveCursor(Movement::Right)));
+        assert_eq!(lookup("ctrl-k"), KeyLookup::Action(&EditorAction::Kill(Movement::LineEnd)));
+        assert_eq!(lookup("alt-y"), KeyLookup::Action(&EditorAction::YankPop));
+        assert_eq!(lookup("ctrl-space"), KeyLookup::Action(&EditorAction::SetMark));
+        assert_eq!(
+            lookup("ctrl-s"),
+            KeyLookup::Action(&EditorAction::IncrementalSearch(SearchDirection::Forward))
+        );
+        assert_eq!(lookup("ctrl-x"), KeyLookup::Prefix);
+        assert_eq!(lookup("ctrl-x ctrl-s"), KeyLookup::Action(&EditorAction::Save));
+        assert_eq!(lookup("ctrl-x ctrl-t"), KeyLookup::Action(&EditorAction::TransposeLines));
+        assert_eq!(lookup("ctrl-x e"), KeyLookup::Action(&EditorAction::PlayMacro(MacroRepeat::Times(1))));
+        // Cmd shortcuts are still there
+        assert_eq!(lookup("cmd-b"), KeyLookup::Action(&EditorAction::ToggleFormat(FormatType::Bold)));
+        assert!(keymap.conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_keys_for_prefers_short_bindings() {
+        let keys_for = |keymap: &Keymap, action: EditorAction| keymap.keys_for(&action).map(|keys| format_key_sequence(&keys));
+
+        let macos = Keymap::for_preset(KeymapPreset::MacOS);
+        assert_eq!(keys_for(&macos, EditorAction::Save).as_deref(), Some("cmd-s"));
+        assert_eq!(keys_for(&macos, EditorAction::OpenFind).as_deref(), Some("cmd-f"));
+        assert_eq!(keys_for(&macos, EditorAction::ToggleCommandPalette).as_deref(), Some("shift-cmd-p"));
+        assert_eq!(keys_for(&macos, EditorAction::MoveCursor(Movement::LineStart)).as_deref(), Some("home"));
+        assert_eq!(keys_for(&macos, EditorAction::TransposeLines), None);
+
+        let linux = Keymap::for_preset(KeymapPreset::Linux);
+        assert_eq!(keys_for(&linux, EditorAction::ToggleCommandPalette).as_deref(), Some("ctrl-shift-p"));
+        assert_eq!(keys_for(&linux, EditorAction::OpenFind).as_deref(), Some("ctrl-f"));
+
+        let emacs = Keymap::for_preset(KeymapPreset::Emacs);
+        assert_eq!(keys_for(&emacs, EditorAction::ToggleCommandPalette).as_deref(), Some("alt-x"));
+        assert_eq!(keys_for(&emacs, EditorAction::TransposeLines).as_deref(), Some("ctrl-x ctrl-t"));
+    }
+
     #[test]
     fn test_gpui_modifiers_conversion() {
         let gpui_modifiers = gpui::Modifiers {
@@ -0,0 +1,270 @@
+//! Keyboard macros
+//!
+//! While recording, the `MacroRecorder` keeps the actions keys and the
+//! command palette run. Playing the macro runs them again, each run as one
+//! undo step. Macros can be saved in the config under a name and serialize
+//! as a list of actions, in the same form keymap configs use.
+
+use super::actions::{ActionHandler, EditorAction, MacroRepeat};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Most runs a macro plays at once, so playing it to the end of the
+/// document stops even if it never gets there, and a mistyped count
+/// doesn't hang the editor
+const MACRO_RUN_LIMIT: usize = 10_000;
+
+/// Actions recorded to run again
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Macro {
+    actions: Vec<EditorAction>,
+}
+
+/// What a macro plays on: undo steps around each run, and how far the end of
+/// the document is for playing up to it
+pub trait MacroTarget: ActionHandler {
+    fn begin_macro_run(&mut self);
+    fn end_macro_run(&mut self);
+    /// Characters between the cursor and the end of the document
+    fn chars_after_cursor(&self) -> usize;
+}
+
+impl Macro {
+    pub fn new(actions: Vec<EditorAction>) -> Self {
+        Self { actions }
+    }
+
+    pub fn actions(&self) -> &[EditorAction] {
+        &self.actions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Run the macro on `target`, returning how many times it ran. Played to
+    /// the end, it stops once the cursor is there or a run leaves it no
+    /// closer.
+    pub fn play<T: MacroTarget>(&self, target: &mut T, repeat: MacroRepeat) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let limit = match repeat {
+            MacroRepeat::Times(count) => count.min(MACRO_RUN_LIMIT),
+            MacroRepeat::ToEnd => MACRO_RUN_LIMIT,
+        };
+        let mut runs = 0;
+        while runs < limit {
+            let remaining = target.chars_after_cursor();
+            if repeat == MacroRepeat::ToEnd && remaining == 0 {
+                break;
+            }
+            target.begin_macro_run();
+            for action in &self.actions {
+                target.handle_action(action.clone());
+            }
+            target.end_macro_run();
+            runs += 1;
+            if repeat == MacroRepeat::ToEnd && target.chars_after_cursor() >= remaining {
+                break;
+            }
+        }
+        runs
+    }
+}
+
+/// Whether an action is kept in a macro. Ones that control macros, open
+/// dialogs or switch modes aren't.
+fn is_recordable(action: &EditorAction) -> bool {
+    !matches!(
+        action,
+        EditorAction::ToggleMacroRecording
+            | EditorAction::PlayMacro(_)
+            | EditorAction::RepeatMacro
+            | EditorAction::PlayNamedMacro(_)
+            | EditorAction::SaveMacro
+            | EditorAction::ToggleCommandPalette
+            | EditorAction::OpenFind
+            | EditorAction::ToggleVimMode
+            | EditorAction::NewFile
+            | EditorAction::OpenFile
+            | EditorAction::Save
+            | EditorAction::SaveAs
+    )
+}
+
+/// The macro being recorded, the last one recorded and the ones saved by name
+#[derive(Debug, Clone, Default)]
+pub struct MacroRecorder {
+    recording: Option<Vec<EditorAction>>,
+    last: Option<Macro>,
+    named: BTreeMap<String, Macro>,
+}
+
+impl MacroRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Start recording, dropping any recording not yet stopped
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// Stop recording and keep what was recorded as the last macro, unless
+    /// nothing was
+    pub fn stop_recording(&mut self) -> Option<&Macro> {
+        let actions = self.recording.take().filter(|actions| !actions.is_empty())?;
+        self.last = Some(Macro::new(actions));
+        self.last.as_ref()
+    }
+
+    /// Note an action that ran. Characters typed in a row are kept as one
+    /// `InsertText`, except newlines, which can continue lists.
+    pub fn record(&mut self, action: &EditorAction) {
+        let Some(actions) = &mut self.recording else {
+            return;
+        };
+        if !is_recordable(action) {
+            return;
+        }
+        if let (Some(last), EditorAction::InsertChar(ch)) = (actions.last_mut(), action) {
+            if *ch != '\n' {
+                match last {
+                    EditorAction::InsertText(text) => {
+                        text.push(*ch);
+                        return;
+                    }
+                    EditorAction::InsertChar(previous) if *previous != '\n' => {
+                        let text = [*previous, *ch].iter().collect();
+                        *last = EditorAction::InsertText(text);
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        actions.push(action.clone());
+    }
+
+    pub fn last_macro(&self) -> Option<&Macro> {
+        self.last.as_ref()
+    }
+
+    /// Keep a macro under a name, replacing any already there
+    pub fn set_named(&mut self, name: &str, saved: Macro) {
+        self.named.insert(name.to_string(), saved);
+    }
+
+    pub fn named(&self, name: &str) -> Option<&Macro> {
+        self.named.get(name)
+    }
+
+    /// The saved macros, by name
+    pub fn all_named(&self) -> &BTreeMap<String, Macro> {
+        &self.named
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::TextDocument;
+    use crate::input::actions::Movement;
+
+    fn bullet_macro() -> Macro {
+        Macro::new(vec![
+            EditorAction::MoveCursor(Movement::LineStart),
+            EditorAction::InsertText("- ".to_string()),
+            EditorAction::MoveCursor(Movement::Down),
+        ])
+    }
+
+    #[test]
+    fn test_recording_merges_typing() {
+        let mut recorder = MacroRecorder::new();
+        recorder.record(&EditorAction::Backspace);
+        assert!(!recorder.is_recording());
+
+        recorder.start_recording();
+        for action in [
+            EditorAction::MoveCursor(Movement::LineStart),
+            EditorAction::InsertChar('-'),
+            EditorAction::InsertChar(' '),
+            EditorAction::InsertChar('a'),
+            EditorAction::InsertChar('\n'),
+            EditorAction::InsertChar('b'),
+            EditorAction::Save,
+            EditorAction::ToggleMacroRecording,
+        ] {
+            recorder.record(&action);
+        }
+        let recorded = recorder.stop_recording().unwrap();
+        assert_eq!(
+            recorded.actions(),
+            [
+                EditorAction::MoveCursor(Movement::LineStart),
+                EditorAction::InsertText("- a".to_string()),
+                EditorAction::InsertChar('\n'),
+                EditorAction::InsertChar('b'),
+            ]
+        );
+
+        // An empty recording keeps the last macro
+        recorder.start_recording();
+        recorder.stop_recording();
+        assert_eq!(recorder.last_macro().unwrap().actions().len(), 4);
+    }
+
+    #[test]
+    fn test_play_times_undoes_run_by_run() {
+        let mut document = TextDocument::with_content("one\ntwo\nthree\nfour".to_string());
+        document.set_cursor_position(0);
+
+        assert_eq!(bullet_macro().play(&mut document, MacroRepeat::Times(2)), 2);
+        assert_eq!(document.content(), "- one\n- two\nthree\nfour");
+
+        document.perform_undo();
+        assert_eq!(document.content(), "- one\ntwo\nthree\nfour");
+        document.perform_undo();
+        assert_eq!(document.content(), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn test_play_to_end() {
+        let mut document = TextDocument::with_content("one\ntwo\nthree\n".to_string());
+        document.set_cursor_position(0);
+        let runs = bullet_macro().play(&mut document, MacroRepeat::ToEnd);
+        assert_eq!(document.content(), "- one\n- two\n- three\n");
+        assert_eq!(runs, 3);
+
+        // A macro that gets no closer to the end runs once
+        let mut document = TextDocument::with_content("one two".to_string());
+        document.set_cursor_position(4);
+        let prefix = Macro::new(vec![EditorAction::MoveCursor(Movement::LineStart), EditorAction::InsertChar('>')]);
+        assert_eq!(prefix.play(&mut document, MacroRepeat::ToEnd), 1);
+        assert_eq!(document.content(), ">one two");
+
+        assert_eq!(Macro::default().play(&mut document, MacroRepeat::ToEnd), 0);
+    }
+
+    #[test]
+    fn test_play_times_is_limited() {
+        let mut document = TextDocument::with_content(String::new());
+        let typing = Macro::new(vec![EditorAction::InsertChar('x')]);
+        assert_eq!(typing.play(&mut document, MacroRepeat::Times(usize::MAX)), MACRO_RUN_LIMIT);
+        assert_eq!(document.content().len(), MACRO_RUN_LIMIT);
+    }
+
+    #[test]
+    fn test_macros_serialize_as_action_lists() {
+        let json = serde_json::to_string(&bullet_macro()).unwrap();
+        assert_eq!(json, r#"[{"MoveCursor":"LineStart"},{"InsertText":"- "},{"MoveCursor":"Down"}]"#);
+        assert_eq!(serde_json::from_str::<Macro>(&json).unwrap(), bullet_macro());
+    }
+}
@@ -1,6 +1,13 @@
 pub mod actions;
+pub mod command_registry;
+pub mod config;
+pub mod context;
 pub mod keymap;
+pub mod macros;
 pub mod router;
+pub mod vim;
 
-pub use actions::{EditorAction, Movement, FormatType, ActionHandler};
+pub use actions::{EditorAction, Movement, FormatType, TableEdit, ColumnAlignment, CaseChange, SearchDirection, MacroRepeat, ActionHandler};
+pub use command_registry::{Command, CommandCategory, CommandPalette, CommandRegistry, PalettePrompt};
+pub use macros::{Macro, MacroRecorder};
 pub use router::InputRouter;
\ No newline at end of file
@@ -4,23 +4,49 @@
 //! for keyboard events, converting them to actions and routing them to handlers.
 
 use super::actions::{EditorAction, ActionHandler};
-use super::keymap::{Keymap, KeyBinding, Modifiers};
+use super::context::KeyContext;
+use super::keymap::{Keymap, KeyBinding, KeyLookup, Modifiers};
+use super::macros::MacroRecorder;
 use gpui::KeyDownEvent;
+use std::time::{Duration, Instant};
+
+/// How long the start of a chord waits for its next key by default
+const CHORD_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// What a key press did
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyResolution {
+    /// The key is bound, or completed a chord
+    Action(EditorAction),
+    /// The key started or continued a chord, which waits for more keys
+    Pending,
+    /// The key didn't continue the pending chord, which was dropped
+    Cancelled,
+    Unbound,
+}
+
+/// The keys of a chord typed so far
+#[derive(Debug)]
+struct PendingChord {
+    keys: Vec<KeyBinding>,
+    since: Instant,
+}
 
 /// Central router for handling keyboard input events
 #[derive(Debug)]
 pub struct InputRouter {
     keymap: Keymap,
     debug_mode: bool,
+    pending: Option<PendingChord>,
+    chord_timeout: Duration,
+    /// Keeps the actions keys resolve to while a macro is recorded
+    macros: MacroRecorder,
 }
 
 impl InputRouter {
     /// Create a new input router with the default keymap
     pub fn new() -> Self {
-        Self {
-            keymap: Keymap::default(),
-            debug_mode: false, // Debug mode off by default
-        }
+        Self::with_keymap(Keymap::default())
     }
 
     /// Create a new input router with a custom keymap
@@ -28,6 +54,9 @@ impl InputRouter {
         Self {
             keymap,
             debug_mode: false, // Debug mode off by default
+            pending: None,
+            chord_timeout: CHORD_TIMEOUT,
+            macros: MacroRecorder::new(),
         }
     }
 
@@ -38,7 +67,7 @@ impl InputRouter {
 
     /// Handle a keyboard event and route it to the appropriate action handler
     pub fn handle_key_event<T: ActionHandler>(
-        &self,
+        &mut self,
         event: &KeyDownEvent,
         target: &mut T,
     ) -> bool {
@@ -46,34 +75,125 @@ impl InputRouter {
             key: event.keystroke.key.clone(),
             modifiers: Modifiers::from_gpui(&event.keystroke.modifiers),
         };
+        self.handle_key_binding(key_binding, target)
+    }
 
+    /// Route a key press to `target`, in the context `target` is in. Keys
+    /// that start, continue or cancel a chord count as handled.
+    pub fn handle_key_binding<T: ActionHandler>(&mut self, key_binding: KeyBinding, target: &mut T) -> bool {
         if self.debug_mode {
             println!("InputRouter: Key event: {:?}", key_binding);
         }
 
-        if let Some(action) = self.keymap.get(&key_binding) {
-            if self.debug_mode {
-                println!("InputRouter: Executing action: {:?}", action);
+        let context = self.key_context_for(&key_binding, target);
+        match self.resolve_key(key_binding, &context) {
+            KeyResolution::Action(action) => {
+                if self.debug_mode {
+                    println!("InputRouter: Executing action: {:?}", action);
+                }
+                target.handle_action(action)
             }
-            target.handle_action(action.clone())
+            KeyResolution::Pending | KeyResolution::Cancelled => true,
+            KeyResolution::Unbound => {
+                if self.debug_mode {
+                    println!("InputRouter: No action found for key binding");
+                }
+                false
+            }
+        }
+    }
+
+    /// The context to resolve `key_binding` in, after any pending keys. Only
+    /// keys with bindings for some contexts need it, so `target` isn't asked,
+    /// which can mean parsing the document, for the rest.
+    pub fn key_context_for<T: ActionHandler>(&self, key_binding: &KeyBinding, target: &T) -> KeyContext {
+        let mut keys = self.pending_keys().map(<[KeyBinding]>::to_vec).unwrap_or_default();
+        keys.push(key_binding.clone());
+        if self.keymap.depends_on_context(&keys) {
+            target.key_context()
         } else {
-            if self.debug_mode {
-                println!("InputRouter: No action found for key binding: {:?}", key_binding);
+            KeyContext::default()
+        }
+    }
+
+    /// Add a key press to the pending chord and find what it's bound to in
+    /// `context`. The action is recorded if a macro is being recorded.
+    pub fn resolve_key(&mut self, key_binding: KeyBinding, context: &KeyContext) -> KeyResolution {
+        let resolution = self.resolve_key_at(key_binding, context, Instant::now());
+        if let KeyResolution::Action(action) = &resolution {
+            self.macros.record(action);
+        }
+        resolution
+    }
+
+    fn resolve_key_at(&mut self, key_binding: KeyBinding, context: &KeyContext, now: Instant) -> KeyResolution {
+        let mut keys = match self.pending.take() {
+            Some(pending) if now.duration_since(pending.since) <= self.chord_timeout => pending.keys,
+            _ => Vec::new(),
+        };
+        let continuing = !keys.is_empty();
+        keys.push(key_binding);
+
+        match self.keymap.lookup_in(&keys, context) {
+            KeyLookup::Action(action) => KeyResolution::Action(action.clone()),
+            KeyLookup::Prefix => {
+                self.pending = Some(PendingChord { keys, since: now });
+                KeyResolution::Pending
             }
-            false
+            KeyLookup::Unbound if continuing => KeyResolution::Cancelled,
+            KeyLookup::Unbound => KeyResolution::Unbound,
         }
     }
 
+    /// The keys of an unfinished chord, for showing while it waits
+    pub fn pending_keys(&self) -> Option<&[KeyBinding]> {
+        self.pending
+            .as_ref()
+            .filter(|pending| pending.since.elapsed() <= self.chord_timeout)
+            .map(|pending| pending.keys.as_slice())
+    }
+
+    /// Drop an unfinished chord
+    pub fn cancel_pending_keys(&mut self) {
+        self.pending = None;
+    }
+
+    /// How long the start of a chord waits for its next key
+    pub fn chord_timeout(&self) -> Duration {
+        self.chord_timeout
+    }
+
+    pub fn set_chord_timeout(&mut self, timeout: Duration) {
+        self.chord_timeout = timeout;
+    }
+
     /// Handle character input (for printable characters)
     pub fn handle_char_input<T: ActionHandler>(
-        &self,
+        &mut self,
         ch: char,
         target: &mut T,
     ) -> bool {
         if self.debug_mode {
             println!("InputRouter: Character input: '{}'", ch);
         }
-        target.handle_action(EditorAction::InsertChar(ch))
+        let action = EditorAction::InsertChar(ch);
+        self.macros.record(&action);
+        target.handle_action(action)
+    }
+
+    /// Record an action run without going through the router, such as a
+    /// movement the editor handles itself, if a macro is being recorded
+    pub fn record_action(&mut self, action: &EditorAction) {
+        self.macros.record(action);
+    }
+
+    /// The macro being recorded and the ones recorded before
+    pub fn macros(&self) -> &MacroRecorder {
+        &self.macros
+    }
+
+    pub fn macros_mut(&mut self) -> &mut MacroRecorder {
+        &mut self.macros
     }
 
     /// Get a reference to the current keymap
@@ -89,6 +209,7 @@ impl InputRouter {
     /// Replace the current keymap
     pub fn set_keymap(&mut self, keymap: Keymap) {
         self.keymap = keymap;
+        self.pending = None;
     }
 
     /// Add a single key binding
@@ -150,7 +271,7 @@ mod tests {
 
     #[test]
     fn test_char_input_handling() {
-        let router = InputRouter::new();
+        let mut router = InputRouter::new();
         let mut document = TextDocument::new();
 
         let handled = router.handle_char_input('a', &mut document);
@@ -186,6 +307,121 @@ mod tests {
         assert!(router.keymap().get(&KeyBinding::new("custom")).is_some()); // New binding present
     }
 
+    fn chord_router() -> InputRouter {
+        let mut keymap = Keymap::new();
+        keymap.bind_sequence(
+            crate::input::keymap::parse_key_sequence("ctrl-k ctrl-u").unwrap(),
+            EditorAction::SelectAll,
+        );
+        keymap.bind(KeyBinding::new("x"), EditorAction::Delete);
+        InputRouter::with_keymap(keymap)
+    }
+
+    #[test]
+    fn test_chords() {
+        let mut router = chord_router();
+        let context = KeyContext::default();
+        let ctrl_k = KeyBinding::with_modifiers("k", Modifiers::ctrl());
+        let ctrl_u = KeyBinding::with_modifiers("u", Modifiers::ctrl());
+
+        assert_eq!(router.resolve_key(ctrl_k.clone(), &context), KeyResolution::Pending);
+        assert_eq!(router.pending_keys(), Some(std::slice::from_ref(&ctrl_k)));
+        assert_eq!(router.resolve_key(ctrl_u.clone(), &context), KeyResolution::Action(EditorAction::SelectAll));
+        assert_eq!(router.pending_keys(), None);
+
+        // A key that doesn't continue the chord cancels it and does nothing else
+        assert_eq!(router.resolve_key(ctrl_k.clone(), &context), KeyResolution::Pending);
+        assert_eq!(router.resolve_key(KeyBinding::new("x"), &context), KeyResolution::Cancelled);
+        assert_eq!(router.pending_keys(), None);
+        assert_eq!(router.resolve_key(KeyBinding::new("x"), &context), KeyResolution::Action(EditorAction::Delete));
+        assert_eq!(router.resolve_key(ctrl_u.clone(), &context), KeyResolution::Unbound);
+
+        let mut document = TextDocument::with_content("text".to_string());
+        assert!(router.handle_key_binding(ctrl_k.clone(), &mut document));
+        assert!(router.handle_key_binding(ctrl_u, &mut document));
+        assert_eq!(document.selected_text().as_deref(), Some("text"));
+    }
+
+    #[test]
+    fn test_chord_timeout() {
+        let mut router = chord_router();
+        let context = KeyContext::default();
+        let ctrl_k = KeyBinding::with_modifiers("k", Modifiers::ctrl());
+        let ctrl_u = KeyBinding::with_modifiers("u", Modifiers::ctrl());
+        let start = Instant::now();
+
+        assert_eq!(router.resolve_key_at(ctrl_k.clone(), &context, start), KeyResolution::Pending);
+        let late = start + CHORD_TIMEOUT + Duration::from_millis(1);
+        assert_eq!(router.resolve_key_at(ctrl_u.clone(), &context, late), KeyResolution::Unbound);
+
+        router.set_chord_timeout(Duration::from_secs(60));
+        assert_eq!(router.resolve_key_at(ctrl_k, &context, start), KeyResolution::Pending);
+        assert_eq!(router.resolve_key_at(ctrl_u, &context, late), KeyResolution::Action(EditorAction::SelectAll));
+    }
+
+    #[test]
+    fn test_records_macros() {
+        let mut router = chord_router();
+        let mut document = TextDocument::with_content("text".to_string());
+        let ctrl_k = KeyBinding::with_modifiers("k", Modifiers::ctrl());
+        let ctrl_u = KeyBinding::with_modifiers("u", Modifiers::ctrl());
+
+        router.handle_char_input('a', &mut document);
+        router.macros_mut().start_recording();
+        router.handle_key_binding(ctrl_k, &mut document);
+        router.handle_key_binding(ctrl_u, &mut document);
+        router.handle_char_input('b', &mut document);
+        router.handle_char_input('c', &mut document);
+        router.record_action(&EditorAction::MoveCursor(Movement::Left));
+        router.handle_key_binding(KeyBinding::new("f13"), &mut document);
+
+        let recorded = router.macros_mut().stop_recording().unwrap();
+        assert_eq!(
+            recorded.actions(),
+            [
+                EditorAction::SelectAll,
+                EditorAction::InsertText("bc".to_string()),
+                EditorAction::MoveCursor(Movement::Left),
+            ]
+        );
+    }
+
+    /// A target that counts how often its context is asked for
+    #[derive(Default)]
+    struct ContextCounter {
+        requests: std::cell::Cell<usize>,
+    }
+
+    impl ActionHandler for ContextCounter {
+        fn handle_action(&mut self, _action: EditorAction) -> bool {
+            true
+        }
+
+        fn key_context(&self) -> KeyContext {
+            self.requests.set(self.requests.get() + 1);
+            KeyContext { in_table: true, ..Default::default() }
+        }
+    }
+
+    #[test]
+    fn test_context_is_only_asked_for_contextual_keys() {
+        use crate::input::context::{ContextFlag, ContextPredicate};
+
+        let mut router = chord_router();
+        let tab = KeyBinding::new("tab");
+        router.keymap_mut().bind_in(
+            ContextPredicate::when(ContextFlag::InTable),
+            vec![tab.clone()],
+            EditorAction::SelectAll,
+        );
+        let target = ContextCounter::default();
+
+        assert_eq!(router.key_context_for(&KeyBinding::new("x"), &target), KeyContext::default());
+        assert_eq!(target.requests.get(), 0);
+        assert!(router.key_context_for(&tab, &target).in_table);
+        assert_eq!(target.requests.get(), 1);
+    }
+
     // Note: Testing handle_key_event would require creating GPUI KeyDownEvent objects,
     // which is complex in a unit test environment. Integration tests would be better
     // for testing the full key event handling pipeline.
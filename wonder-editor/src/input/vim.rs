@@ -0,0 +1,1338 @@
+//! Vim-style modal editing
+//!
+//! `Vim` sees keys before the keymap. In normal and visual modes they are vim
+//! commands: motions such as `w` and `gg`, operators (`d`, `c`, `y`) with a
+//! motion or text object, counts, registers and `.` to repeat the last
+//! change. In insert mode it types text, so the change can be repeated, and
+//! leaves other keys to the `InputRouter`. Undo and redo are the document's
+//! `EditorAction::Undo` and `Redo`, and every change is a single undo step;
+//! `g-` and `g+` go to older and newer states across undo branches.
+//!
+//! Keys are `KeyBinding`s whose key is the typed character, e.g. `$` or `G`
+//! rather than Shift+4 or Shift+G. Named keys are as in the keymap, such as
+//! `escape` and `ctrl-r`.
+
+use super::actions::{ActionHandler, EditorAction};
+use super::keymap::{KeyBinding, Modifiers};
+use crate::core::TextDocument;
+use ropey::Rope;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Larger counts are treated as this, so a mistyped count can't overflow
+/// or keep a motion going for ever
+const MAX_COUNT: usize = 1_000_000;
+
+/// Most copies a count can put at once, since each is a copy of the register
+const MAX_PUT_COUNT: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VimMode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl VimMode {
+    /// The name shown while the mode is active
+    pub fn label(self) -> &'static str {
+        match self {
+            VimMode::Normal => "NORMAL",
+            VimMode::Insert => "INSERT",
+            VimMode::Visual => "VISUAL",
+            VimMode::VisualLine => "VISUAL LINE",
+        }
+    }
+}
+
+/// Text yanked or deleted into a register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines, which are put above or below the cursor's line
+    pub linewise: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Down,
+    Up,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+/// How much text an operator takes up to a motion's target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotionKind {
+    /// Up to the target
+    Exclusive,
+    /// Up to and including the target
+    Inclusive,
+    /// Every line from the cursor's to the target's
+    Linewise,
+}
+
+impl Motion {
+    fn from_key(key: &str) -> Option<Self> {
+        Some(match key {
+            "h" | "left" => Motion::Left,
+            "l" | "right" => Motion::Right,
+            "j" | "down" => Motion::Down,
+            "k" | "up" => Motion::Up,
+            "w" => Motion::WordForward,
+            "b" => Motion::WordBackward,
+            "e" => Motion::WordEnd,
+            "0" | "home" => Motion::LineStart,
+            "$" | "end" => Motion::LineEnd,
+            "G" => Motion::LastLine,
+            _ => return None,
+        })
+    }
+
+    fn kind(self) -> MotionKind {
+        match self {
+            Motion::WordEnd | Motion::LineEnd => MotionKind::Inclusive,
+            Motion::Down | Motion::Up | Motion::FirstLine | Motion::LastLine => MotionKind::Linewise,
+            _ => MotionKind::Exclusive,
+        }
+    }
+}
+
+/// The inside of a word, quotes or paragraph, after `i`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextObject {
+    Word,
+    Quote,
+    Paragraph,
+}
+
+impl TextObject {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "w" => Some(TextObject::Word),
+            "\"" => Some(TextObject::Quote),
+            "p" => Some(TextObject::Paragraph),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+impl Operator {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "d" => Some(Operator::Delete),
+            "c" => Some(Operator::Change),
+            "y" => Some(Operator::Yank),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// Lines from the cursor's, as in `dd`
+    Lines,
+    /// The visual selection
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertAt {
+    Cursor,
+    AfterCursor,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    Insert(InsertAt),
+    Put { before: bool },
+    Undo,
+    Redo,
+    /// Go to the state made before the current one, on any undo branch
+    UndoOlder,
+    /// Go to the state made after the current one, on any undo branch
+    UndoNewer,
+    /// Repeat the last change
+    Repeat,
+    /// Start visual mode, switch to another one or leave it
+    ToggleVisual(VimMode),
+    /// Select a text object in visual mode
+    SelectObject(TextObject),
+    ExitVisual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    action: Action,
+}
+
+enum Parse<T> {
+    /// More keys are needed
+    Incomplete,
+    Invalid,
+    Complete(T),
+}
+
+impl<T> Parse<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Parse<U> {
+        match self {
+            Parse::Incomplete => Parse::Incomplete,
+            Parse::Invalid => Parse::Invalid,
+            Parse::Complete(value) => Parse::Complete(f(value)),
+        }
+    }
+}
+
+/// A change `.` repeats: its command and the keys typed after it
+#[derive(Debug, Clone)]
+struct Change {
+    command: Command,
+    typed: Vec<String>,
+}
+
+/// Text an operator works on. Whole lines run from the start of the first
+/// to the end of the last, without its line break.
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    linewise: bool,
+}
+
+impl Region {
+    fn chars(start: usize, end: usize) -> Self {
+        Self { start, end, linewise: false }
+    }
+}
+
+/// Modal editing state for one document
+#[derive(Debug, Default)]
+pub struct Vim {
+    mode: VimMode,
+    /// Keys of the unfinished command
+    keys: Vec<String>,
+    registers: HashMap<char, Register>,
+    /// The end of the visual selection that stays put
+    visual_anchor: usize,
+    /// The end of the visual selection that motions move
+    visual_head: usize,
+    /// Column `j` and `k` keep to, across shorter lines
+    goal_column: Option<usize>,
+    last_change: Option<Change>,
+    /// The change being typed in insert mode, if it can be repeated
+    insert_change: Option<Change>,
+}
+
+impl Vim {
+    /// Start in normal mode
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    /// Keys of an unfinished command, e.g. `d2` while waiting for a motion
+    pub fn pending_keys(&self) -> Option<String> {
+        (!self.keys.is_empty()).then(|| self.keys.concat())
+    }
+
+    pub fn register(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Handle a key press. Returns false for keys vim doesn't use, such as
+    /// shortcuts, which should go on to the keymap.
+    pub fn handle_key(&mut self, key: &KeyBinding, document: &mut TextDocument) -> bool {
+        let key = key_name(key);
+        if self.mode == VimMode::Insert {
+            return self.handle_insert_key(&key, document);
+        }
+
+        document.clear_secondary_selections();
+        self.keys.push(key);
+        match parse(&self.keys, self.mode) {
+            Parse::Incomplete => true,
+            Parse::Invalid => {
+                let keys = std::mem::take(&mut self.keys);
+                // A lone named key such as Tab or Cmd+S isn't a vim command at all
+                !(keys.len() == 1 && single_char(&keys[0]).is_none())
+            }
+            Parse::Complete(command) => {
+                self.keys.clear();
+                self.execute(command, document);
+                true
+            }
+        }
+    }
+
+    fn handle_insert_key(&mut self, key: &str, document: &mut TextDocument) -> bool {
+        let action = match key {
+            "escape" => {
+                self.leave_insert(document);
+                return true;
+            }
+            "enter" => EditorAction::InsertChar('\n'),
+            "backspace" => EditorAction::Backspace,
+            _ => match single_char(key) {
+                Some(ch) => EditorAction::InsertChar(ch),
+                None => {
+                    // Other keys, such as arrows and shortcuts, end the change
+                    // and go to the keymap. Typing after them is a new change.
+                    document.end_undo_group();
+                    if let Some(change) = self.insert_change.take() {
+                        self.last_change = Some(change);
+                    }
+                    self.insert_change = Some(Change {
+                        command: Command { register: None, count: None, action: Action::Insert(InsertAt::Cursor) },
+                        typed: Vec::new(),
+                    });
+                    return false;
+                }
+            },
+        };
+        document.handle_action(action);
+        if let Some(change) = &mut self.insert_change {
+            change.typed.push(key.to_string());
+        }
+        true
+    }
+
+    fn leave_insert(&mut self, document: &mut TextDocument) {
+        document.end_undo_group();
+        if let Some(change) = self.insert_change.take() {
+            self.last_change = Some(change);
+        }
+        self.mode = VimMode::Normal;
+
+        // The cursor goes back onto the last typed character
+        let text = document.rope();
+        let position = document.cursor_position();
+        let position = if position > line_start(text, line_of(text, position)) { position - 1 } else { position };
+        document.set_cursor_position(clamp_normal(text, position));
+    }
+
+    fn execute(&mut self, command: Command, document: &mut TextDocument) {
+        let count = command.count.unwrap_or(1);
+        let repeatable = self.mode == VimMode::Normal;
+        if !matches!(command.action, Action::Move(Motion::Up | Motion::Down)) {
+            self.goal_column = None;
+        }
+
+        match command.action {
+            Action::Move(motion) => self.move_cursor(motion, command.count, document),
+            Action::Operate(operator, target) => {
+                let region = self.region(operator, target, command.count, document);
+                if self.mode != VimMode::Normal {
+                    self.leave_visual(document);
+                }
+                match operator {
+                    Operator::Yank => self.yank(region, command.register, document),
+                    Operator::Delete => self.edit("Delete", command, repeatable, document, |vim, document| {
+                        vim.delete(region, command.register, document)
+                    }),
+                    Operator::Change => self.edit("Change", command, repeatable, document, |vim, document| {
+                        vim.change(region, command.register, document)
+                    }),
+                }
+            }
+            Action::Insert(at) => {
+                self.edit("Insert", command, repeatable, document, |vim, document| vim.insert(at, document))
+            }
+            Action::Put { before } => self.edit("Put", command, repeatable, document, |vim, document| {
+                vim.put(before, command, document)
+            }),
+            Action::Undo | Action::Redo | Action::UndoOlder | Action::UndoNewer => {
+                let action = match command.action {
+                    Action::Undo => EditorAction::Undo,
+                    Action::Redo => EditorAction::Redo,
+                    Action::UndoOlder => EditorAction::UndoOlder,
+                    _ => EditorAction::UndoNewer,
+                };
+                for _ in 0..count {
+                    if !document.handle_action(action.clone()) {
+                        break;
+                    }
+                }
+                document.clear_selection();
+                let position = clamp_normal(document.rope(), document.cursor_position());
+                document.set_cursor_position(position);
+            }
+            Action::Repeat => self.repeat(command.count, document),
+            Action::ToggleVisual(mode) => {
+                if self.mode == mode {
+                    self.leave_visual(document);
+                } else {
+                    if self.mode == VimMode::Normal {
+                        self.visual_anchor = document.cursor_position();
+                        self.visual_head = self.visual_anchor;
+                    }
+                    self.mode = mode;
+                    self.show_visual(document);
+                }
+            }
+            Action::SelectObject(object) => {
+                if let Some(region) = object_region(document.rope(), self.visual_head, object) {
+                    self.visual_anchor = region.start;
+                    self.visual_head = region.end.saturating_sub(1).max(region.start);
+                    self.mode = if region.linewise { VimMode::VisualLine } else { VimMode::Visual };
+                    self.show_visual(document);
+                }
+            }
+            Action::ExitVisual => self.leave_visual(document),
+        }
+    }
+
+    /// Run an edit as one undo step, which stays open for the text typed
+    /// after it in insert mode. Edits from normal mode are kept for `.`.
+    fn edit(
+        &mut self,
+        description: &str,
+        command: Command,
+        repeatable: bool,
+        document: &mut TextDocument,
+        edit: impl FnOnce(&mut Self, &mut TextDocument),
+    ) {
+        document.begin_undo_group(description);
+        edit(self, document);
+        let change = repeatable.then(|| Change { command, typed: Vec::new() });
+        if self.mode == VimMode::Insert {
+            self.insert_change = change;
+        } else {
+            document.end_undo_group();
+            if change.is_some() {
+                self.last_change = change;
+            }
+        }
+    }
+
+    fn repeat(&mut self, count: Option<usize>, document: &mut TextDocument) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        let command = Command { count: count.or(change.command.count), ..change.command };
+        self.execute(command, document);
+        if self.mode == VimMode::Insert {
+            for key in &change.typed {
+                self.handle_insert_key(key, document);
+            }
+            self.leave_insert(document);
+        }
+    }
+
+    /// The cursor, which in visual mode is the head of the selection
+    fn cursor(&self, document: &TextDocument) -> usize {
+        match self.mode {
+            VimMode::Visual | VimMode::VisualLine => self.visual_head,
+            _ => document.cursor_position(),
+        }
+    }
+
+    fn move_cursor(&mut self, motion: Motion, count: Option<usize>, document: &mut TextDocument) {
+        let text = document.rope();
+        let target = clamp_normal(text, self.motion_target(text, self.cursor(document), motion, count, false));
+        if self.mode == VimMode::Normal {
+            document.clear_selection();
+            document.set_cursor_position(target);
+        } else {
+            self.visual_head = target;
+            self.show_visual(document);
+        }
+    }
+
+    /// Where a motion from `from` goes. With an operator it can reach the
+    /// end of a line rather than stopping on its last character.
+    fn motion_target(&mut self, text: &Rope, from: usize, motion: Motion, count: Option<usize>, operator: bool) -> usize {
+        let times = count.unwrap_or(1);
+        let line = line_of(text, from);
+        let repeat = |step: fn(&Rope, usize) -> usize| repeat_step(text, from, times, step);
+
+        match motion {
+            Motion::Left => from.saturating_sub(times).max(line_start(text, line)),
+            Motion::Right => {
+                let end = line_end(text, line);
+                let limit = if operator { end } else { end.saturating_sub(1).max(line_start(text, line)) };
+                from.saturating_add(times).min(limit)
+            }
+            Motion::Down | Motion::Up => {
+                let column = *self.goal_column.get_or_insert(from - line_start(text, line));
+                let target = match motion {
+                    Motion::Down => line.saturating_add(times).min(last_line(text)),
+                    _ => line.saturating_sub(times),
+                };
+                line_start(text, target).saturating_add(column).min(line_end(text, target))
+            }
+            Motion::WordForward => {
+                let mut position = from;
+                for step in 0..times {
+                    let next = word_forward(text, position);
+                    if next == position {
+                        break;
+                    }
+                    // An operator stops at the end of the last word's line,
+                    // so `dw` doesn't join lines
+                    if operator && step + 1 == times && line_of(text, next) > line_of(text, position) {
+                        return line_end(text, line_of(text, position)).max(position);
+                    }
+                    position = next;
+                }
+                position
+            }
+            Motion::WordBackward => repeat(word_backward),
+            Motion::WordEnd => repeat(word_end),
+            Motion::LineStart => line_start(text, line),
+            Motion::LineEnd => {
+                // `$` then `j` stays at the ends of lines
+                self.goal_column = Some(usize::MAX);
+                let target = line.saturating_add(times - 1).min(last_line(text));
+                line_end(text, target).saturating_sub(1).max(line_start(text, target))
+            }
+            Motion::FirstLine | Motion::LastLine => {
+                let default = if motion == Motion::FirstLine { 0 } else { last_line(text) };
+                let target = count.map_or(default, |count| count - 1).min(last_line(text));
+                first_non_blank(text, target)
+            }
+        }
+    }
+
+    fn region(&mut self, operator: Operator, target: Target, count: Option<usize>, document: &TextDocument) -> Option<Region> {
+        let text = document.rope();
+        let cursor = self.cursor(document);
+        match target {
+            Target::Motion(motion) => {
+                // `cw` on a word changes to its end, like `ce` but without
+                // going on to the next word from a one-letter one
+                let on_word = text.get_char(cursor).filter(|&ch| !ch.is_whitespace());
+                if let (Operator::Change, Motion::WordForward, Some(ch)) = (operator, motion, on_word) {
+                    let mut end = cursor;
+                    while text.get_char(end + 1).is_some_and(|other| class(other) == class(ch)) {
+                        end += 1;
+                    }
+                    let end = repeat_step(text, end, count.unwrap_or(1) - 1, word_end);
+                    return Some(Region::chars(cursor, end + 1));
+                }
+                let target = self.motion_target(text, cursor, motion, count, true);
+                let (start, end) = (cursor.min(target), cursor.max(target));
+                Some(match motion.kind() {
+                    MotionKind::Exclusive => Region::chars(start, end),
+                    MotionKind::Inclusive => Region::chars(start, inclusive_end(text, end)),
+                    MotionKind::Linewise => lines_region(text, line_of(text, start), line_of(text, end)),
+                })
+            }
+            Target::Object(object) => object_region(text, cursor, object),
+            Target::Lines => {
+                let first = line_of(text, cursor);
+                let last = first.saturating_add(count.unwrap_or(1) - 1).min(last_line(text));
+                Some(lines_region(text, first, last))
+            }
+            Target::Selection => Some(self.visual_region(text)),
+        }
+    }
+
+    fn yank(&mut self, region: Option<Region>, register: Option<char>, document: &mut TextDocument) {
+        let Some(region) = region else {
+            return;
+        };
+        let text = document.rope();
+        self.store(register, region_text(text, region), region.linewise, true);
+
+        // The cursor moves to the start of what was yanked, e.g. back a word
+        // for `yb`, keeping its column for lines above
+        let cursor = document.cursor_position();
+        let first_line = line_of(text, region.start);
+        let position = if !region.linewise {
+            region.start
+        } else if line_of(text, cursor) > first_line {
+            let column = cursor - line_start(text, line_of(text, cursor));
+            clamp_normal(text, (line_start(text, first_line) + column).min(line_end(text, first_line)))
+        } else {
+            cursor
+        };
+        document.set_cursor_position(position);
+    }
+
+    fn delete(&mut self, region: Option<Region>, register: Option<char>, document: &mut TextDocument) {
+        let Some(region) = region else {
+            return;
+        };
+        let text = document.rope();
+        self.store(register, region_text(text, region), region.linewise, false);
+        let line = line_of(text, region.start);
+        replace(document, deletion_range(text, region), "");
+
+        let text = document.rope();
+        let position = if region.linewise {
+            clamp_normal(text, first_non_blank(text, line.min(last_line(text))))
+        } else {
+            clamp_normal(text, region.start)
+        };
+        document.set_cursor_position(position);
+    }
+
+    /// Delete the region and type in its place. For lines, an empty line is
+    /// left to type on.
+    fn change(&mut self, region: Option<Region>, register: Option<char>, document: &mut TextDocument) {
+        let Some(region) = region else {
+            return;
+        };
+        self.store(register, region_text(document.rope(), region), region.linewise, false);
+        replace(document, region.start..region.end, "");
+        self.mode = VimMode::Insert;
+    }
+
+    fn insert(&mut self, at: InsertAt, document: &mut TextDocument) {
+        let text = document.rope();
+        let cursor = document.cursor_position();
+        let line = line_of(text, cursor);
+        let position = match at {
+            InsertAt::Cursor => cursor,
+            InsertAt::AfterCursor => (cursor + 1).min(line_end(text, line)),
+            InsertAt::LineStart => first_non_blank(text, line),
+            InsertAt::LineEnd => line_end(text, line),
+            InsertAt::LineBelow => {
+                let end = line_end(text, line);
+                replace(document, end..end, "\n");
+                end + 1
+            }
+            InsertAt::LineAbove => {
+                let start = line_start(text, line);
+                replace(document, start..start, "\n");
+                start
+            }
+        };
+        document.set_cursor_position(position);
+        self.mode = VimMode::Insert;
+    }
+
+    /// Put a register after the cursor, or before it with `P`. Lines go
+    /// below or above the cursor's line.
+    fn put(&mut self, before: bool, command: Command, document: &mut TextDocument) {
+        let Some(register) = self.register(command.register.unwrap_or('"')).cloned() else {
+            return;
+        };
+        let contents = register.text.repeat(command.count.unwrap_or(1).min(MAX_PUT_COUNT));
+        let text = document.rope();
+        let cursor = document.cursor_position();
+        let line = line_of(text, cursor);
+
+        if register.linewise {
+            let (position, contents, new_line) = if before {
+                (line_start(text, line), contents, line)
+            } else if line_end(text, line) < text.len_chars() {
+                (line_end(text, line) + 1, contents, line + 1)
+            } else {
+                // Below the last line, which has no line break to put them after
+                let lines = contents.strip_suffix('\n').unwrap_or(&contents);
+                (text.len_chars(), format!("\n{}", lines), line + 1)
+            };
+            replace(document, position..position, &contents);
+            let text = document.rope();
+            document.set_cursor_position(clamp_normal(text, first_non_blank(text, new_line)));
+        } else {
+            let on_char = text.get_char(cursor).is_some_and(|ch| ch != '\n');
+            let position = if before || !on_char { cursor } else { cursor + 1 };
+            replace(document, position..position, &contents);
+            document.set_cursor_position(position + contents.chars().count().saturating_sub(1));
+        }
+    }
+
+    /// Save yanked or deleted text. It goes in the named register if there
+    /// is one (appending for a capital letter), otherwise in `0` for a yank
+    /// or `1` for a delete, moving older deletes up to `9`. The unnamed
+    /// register `"` always gets it, except with the black hole register `_`.
+    fn store(&mut self, register: Option<char>, text: String, linewise: bool, yank: bool) {
+        let mut value = Register { text, linewise };
+        match register {
+            Some('_') => return,
+            Some(name) if name.is_ascii_uppercase() => {
+                let named = self.registers.entry(name.to_ascii_lowercase()).or_insert(Register {
+                    text: String::new(),
+                    linewise,
+                });
+                named.text.push_str(&value.text);
+                named.linewise |= linewise;
+                value = named.clone();
+            }
+            Some(name) if name != '"' => {
+                self.registers.insert(name, value.clone());
+            }
+            _ if yank => {
+                self.registers.insert('0', value.clone());
+            }
+            _ => {
+                for number in (1..9).rev() {
+                    if let Some(older) = self.registers.remove(&digit(number)) {
+                        self.registers.insert(digit(number + 1), older);
+                    }
+                }
+                self.registers.insert('1', value.clone());
+            }
+        }
+        self.registers.insert('"', value);
+    }
+
+    fn visual_region(&self, text: &Rope) -> Region {
+        let start = self.visual_anchor.min(self.visual_head);
+        let end = self.visual_anchor.max(self.visual_head);
+        if self.mode == VimMode::VisualLine {
+            lines_region(text, line_of(text, start), line_of(text, end))
+        } else {
+            Region::chars(start, inclusive_end(text, end))
+        }
+    }
+
+    /// Select the visual region in the document, with its cursor at the
+    /// head's end
+    fn show_visual(&self, document: &mut TextDocument) {
+        let text = document.rope();
+        let region = self.visual_region(text);
+        let end = if region.linewise { (region.end + 1).min(text.len_chars()) } else { region.end };
+        let (anchor, head) = if self.visual_head < self.visual_anchor {
+            (end, region.start)
+        } else {
+            (region.start, end)
+        };
+        document.clear_selection();
+        document.set_cursor_position(anchor);
+        document.start_selection();
+        document.set_cursor_position(head);
+    }
+
+    fn leave_visual(&mut self, document: &mut TextDocument) {
+        self.mode = VimMode::Normal;
+        document.clear_selection();
+        let position = clamp_normal(document.rope(), self.visual_head);
+        document.set_cursor_position(position);
+    }
+}
+
+/// Read the keys of a command: an optional register (`"a`), count, then an
+/// operator with its own count and target, or any other command
+fn parse(keys: &[String], mode: VimMode) -> Parse<Command> {
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let mut rest = keys.as_slice();
+    let mut register = None;
+    if let Some((&"\"", tail)) = rest.split_first() {
+        let Some((name, tail)) = tail.split_first() else {
+            return Parse::Incomplete;
+        };
+        match single_char(name).filter(|&ch| ch.is_ascii_alphanumeric() || ch == '"' || ch == '_') {
+            Some(name) => register = Some(name),
+            None => return Parse::Invalid,
+        }
+        rest = tail;
+    }
+
+    let (count, command_keys) = take_count(rest);
+    let Some((&key, rest)) = command_keys.split_first() else {
+        return Parse::Incomplete;
+    };
+    let visual = mode != VimMode::Normal;
+    let command = |count, action| Command { register, count, action };
+
+    if let Some(operator) = Operator::from_key(key) {
+        if visual {
+            return Parse::Complete(command(count, Action::Operate(operator, Target::Selection)));
+        }
+        let (motion_count, rest) = take_count(rest);
+        let count = match (count, motion_count) {
+            (None, None) => None,
+            _ => Some(count.unwrap_or(1).saturating_mul(motion_count.unwrap_or(1)).min(MAX_COUNT)),
+        };
+        let target = match rest {
+            [next] if *next == key => Parse::Complete(Target::Lines),
+            ["i"] => Parse::Incomplete,
+            ["i", object] => TextObject::from_key(object).map_or(Parse::Invalid, |object| Parse::Complete(Target::Object(object))),
+            _ => parse_motion(rest).map(Target::Motion),
+        };
+        return target.map(|target| command(count, Action::Operate(operator, target)));
+    }
+
+    if visual && key == "i" {
+        return match rest {
+            [] => Parse::Incomplete,
+            [object] => TextObject::from_key(object)
+                .map_or(Parse::Invalid, |object| Parse::Complete(command(count, Action::SelectObject(object)))),
+            _ => Parse::Invalid,
+        };
+    }
+
+    let action = match (key, visual) {
+        ("g", false) if rest == ["-"] => Action::UndoOlder,
+        ("g", false) if rest == ["+"] => Action::UndoNewer,
+        ("x", false) => Action::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+        ("X", false) => Action::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+        ("s", false) => Action::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        ("D", false) => Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        ("C", false) => Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        ("Y", false) => Action::Operate(Operator::Yank, Target::Lines),
+        ("x", true) => Action::Operate(Operator::Delete, Target::Selection),
+        ("s", true) => Action::Operate(Operator::Change, Target::Selection),
+        ("i", false) => Action::Insert(InsertAt::Cursor),
+        ("a", false) => Action::Insert(InsertAt::AfterCursor),
+        ("I", false) => Action::Insert(InsertAt::LineStart),
+        ("A", false) => Action::Insert(InsertAt::LineEnd),
+        ("o", false) => Action::Insert(InsertAt::LineBelow),
+        ("O", false) => Action::Insert(InsertAt::LineAbove),
+        ("p", false) => Action::Put { before: false },
+        ("P", false) => Action::Put { before: true },
+        ("u", false) => Action::Undo,
+        ("ctrl-r", false) => Action::Redo,
+        (".", false) => Action::Repeat,
+        ("v", _) => Action::ToggleVisual(VimMode::Visual),
+        ("V", _) => Action::ToggleVisual(VimMode::VisualLine),
+        ("escape", true) => Action::ExitVisual,
+        _ => return parse_motion(command_keys).map(|motion| command(count, Action::Move(motion))),
+    };
+    Parse::Complete(command(count, action))
+}
+
+fn parse_motion(keys: &[&str]) -> Parse<Motion> {
+    match keys {
+        [] | ["g"] => Parse::Incomplete,
+        ["g", "g"] => Parse::Complete(Motion::FirstLine),
+        [key] => Motion::from_key(key).map_or(Parse::Invalid, Parse::Complete),
+        _ => Parse::Invalid,
+    }
+}
+
+/// Split a count off the front of `keys`. A leading `0` is a motion, and
+/// counts stop growing at `MAX_COUNT`.
+fn take_count<'a, 'b>(keys: &'a [&'b str]) -> (Option<usize>, &'a [&'b str]) {
+    let digits = match keys.first() {
+        Some(&"0") => 0,
+        _ => keys.iter().take_while(|key| key.len() == 1 && key.as_bytes()[0].is_ascii_digit()).count(),
+    };
+    let count = keys[..digits].iter().fold(None, |count: Option<usize>, key| {
+        let digit = usize::from(key.as_bytes()[0] - b'0');
+        Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit).min(MAX_COUNT))
+    });
+    (count, &keys[digits..])
+}
+
+/// Take `step` from `from` up to `times` times, stopping early once it no
+/// longer moves
+fn repeat_step(text: &Rope, from: usize, times: usize, step: fn(&Rope, usize) -> usize) -> usize {
+    let mut position = from;
+    for _ in 0..times {
+        let next = step(text, position);
+        if next == position {
+            break;
+        }
+        position = next;
+    }
+    position
+}
+
+/// The name a key goes by in commands: the typed character, or the
+/// keymap's name for other keys
+fn key_name(key: &KeyBinding) -> String {
+    match single_char(&key.key) {
+        Some(ch) if key.modifiers == Modifiers::shift() => ch.to_uppercase().to_string(),
+        _ if key.key == "space" && key.modifiers == Modifiers::none() => " ".to_string(),
+        _ => key.to_string(),
+    }
+}
+
+fn single_char(key: &str) -> Option<char> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => None,
+    }
+}
+
+fn digit(number: u32) -> char {
+    char::from_digit(number, 10).unwrap_or('9')
+}
+
+/// Replace a range of the document with `text`, leaving the cursor after it
+fn replace(document: &mut TextDocument, range: Range<usize>, text: &str) {
+    document.clear_selection();
+    document.set_cursor_position(range.start);
+    if range.start < range.end {
+        document.start_selection();
+        document.set_cursor_position(range.end);
+        document.delete_selection();
+    }
+    if !text.is_empty() {
+        document.insert_text(text);
+    }
+}
+
+fn region_text(text: &Rope, region: Region) -> String {
+    let mut contents = text.slice(region.start..region.end).to_string();
+    if region.linewise {
+        contents.push('\n');
+    }
+    contents
+}
+
+/// The text deleted for a region: whole lines take a line break with them
+fn deletion_range(text: &Rope, region: Region) -> Range<usize> {
+    if !region.linewise {
+        region.start..region.end
+    } else if region.end < text.len_chars() {
+        region.start..region.end + 1
+    } else {
+        region.start.saturating_sub(1)..region.end
+    }
+}
+
+fn lines_region(text: &Rope, first: usize, last: usize) -> Region {
+    Region { start: line_start(text, first), end: line_end(text, last), linewise: true }
+}
+
+fn object_region(text: &Rope, position: usize, object: TextObject) -> Option<Region> {
+    let line = line_of(text, position);
+    match object {
+        TextObject::Word => {
+            let ch = text.get_char(position).filter(|&ch| ch != '\n')?;
+            let same = |other: char| other != '\n' && class(other) == class(ch);
+            let mut start = position;
+            while start > 0 && same(text.char(start - 1)) {
+                start -= 1;
+            }
+            let mut end = position + 1;
+            while text.get_char(end).is_some_and(&same) {
+                end += 1;
+            }
+            Some(Region::chars(start, end))
+        }
+        TextObject::Quote => {
+            let start = line_start(text, line);
+            let quotes: Vec<usize> = (start..line_end(text, line))
+                .filter(|&index| text.char(index) == '"' && (index == start || text.char(index - 1) != '\\'))
+                .collect();
+            // The pair around the cursor, or the next one on the line
+            let pair = quotes.chunks_exact(2).find(|pair| position <= pair[1])?;
+            Some(Region::chars(pair[0] + 1, pair[1]))
+        }
+        TextObject::Paragraph => {
+            let blank = |line: usize| text.line(line).chars().all(char::is_whitespace);
+            let kind = blank(line);
+            let mut first = line;
+            while first > 0 && blank(first - 1) == kind {
+                first -= 1;
+            }
+            let mut last = line;
+            while last < last_line(text) && blank(last + 1) == kind {
+                last += 1;
+            }
+            Some(lines_region(text, first, last))
+        }
+    }
+}
+
+/// Whitespace, word characters and punctuation are separate kinds of word
+fn class(ch: char) -> u8 {
+    if ch.is_whitespace() {
+        0
+    } else if ch.is_alphanumeric() || ch == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// The start of the next word. An empty line counts as a word.
+fn word_forward(text: &Rope, position: usize) -> usize {
+    let mut index = position;
+    if let Some(ch) = text.get_char(index).filter(|&ch| class(ch) != 0) {
+        while text.get_char(index).is_some_and(|other| class(other) == class(ch)) {
+            index += 1;
+        }
+    }
+    while let Some(ch) = text.get_char(index) {
+        if ch == '\n' {
+            index += 1;
+            if text.get_char(index) == Some('\n') {
+                return index;
+            }
+        } else if ch.is_whitespace() {
+            index += 1;
+        } else {
+            break;
+        }
+    }
+    index.min(text.len_chars())
+}
+
+/// The start of this word, or of the previous one if already there
+fn word_backward(text: &Rope, position: usize) -> usize {
+    if position == 0 {
+        return 0;
+    }
+    let mut index = position - 1;
+    loop {
+        let ch = text.char(index);
+        if ch == '\n' && (index == 0 || text.char(index - 1) == '\n') {
+            return index;
+        }
+        if !ch.is_whitespace() {
+            break;
+        }
+        if index == 0 {
+            return 0;
+        }
+        index -= 1;
+    }
+    let kind = class(text.char(index));
+    while index > 0 && class(text.char(index - 1)) == kind {
+        index -= 1;
+    }
+    index
+}
+
+/// The last character of this word, or of the next one if already there
+fn word_end(text: &Rope, position: usize) -> usize {
+    let mut index = position + 1;
+    while text.get_char(index).is_some_and(char::is_whitespace) {
+        index += 1;
+    }
+    let Some(ch) = text.get_char(index) else {
+        return text.len_chars().saturating_sub(1);
+    };
+    while text.get_char(index + 1).is_some_and(|other| class(other) == class(ch)) {
+        index += 1;
+    }
+    index
+}
+
+fn line_of(text: &Rope, position: usize) -> usize {
+    text.char_to_line(position.min(text.len_chars()))
+}
+
+fn line_start(text: &Rope, line: usize) -> usize {
+    text.line_to_char(line)
+}
+
+/// The end of a line's text, before its line break
+fn line_end(text: &Rope, line: usize) -> usize {
+    let contents = text.line(line);
+    let length = contents.len_chars();
+    let length = if length > 0 && contents.char(length - 1) == '\n' { length - 1 } else { length };
+    line_start(text, line) + length
+}
+
+/// The last line, not counting the empty one after a final line break
+fn last_line(text: &Rope) -> usize {
+    let lines = text.len_lines();
+    let ends_with_break = text.len_chars() > 0 && text.char(text.len_chars() - 1) == '\n';
+    if ends_with_break { lines - 2 } else { lines - 1 }
+}
+
+fn first_non_blank(text: &Rope, line: usize) -> usize {
+    let end = line_end(text, line);
+    (line_start(text, line)..end)
+        .find(|&index| !matches!(text.char(index), ' ' | '\t'))
+        .unwrap_or(end)
+}
+
+/// Where the cursor can be in normal mode: on a character of its line
+/// rather than after the last one, unless the line is empty
+fn clamp_normal(text: &Rope, position: usize) -> usize {
+    let line = line_of(text, position);
+    let start = line_start(text, line);
+    position.min(line_end(text, line).saturating_sub(1)).max(start)
+}
+
+/// The end of a range that includes the character at `position`
+fn inclusive_end(text: &Rope, position: usize) -> usize {
+    if text.get_char(position).is_some_and(|ch| ch != '\n') {
+        position + 1
+    } else {
+        position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys from vim notation: each character is a key, and `<esc>`,
+    /// `<cr>`, `<bs>`, `<c-r>` and `<tab>` are named keys
+    fn keys(notation: &str) -> Vec<KeyBinding> {
+        let mut keys = Vec::new();
+        let mut rest = notation;
+        while let Some(ch) = rest.chars().next() {
+            let named = ["<esc>", "<cr>", "<bs>", "<c-r>", "<tab>"].into_iter().find(|name| rest.starts_with(name));
+            let key = match named {
+                Some("<esc>") => KeyBinding::new("escape"),
+                Some("<cr>") => KeyBinding::new("enter"),
+                Some("<bs>") => KeyBinding::new("backspace"),
+                Some("<c-r>") => KeyBinding::with_modifiers("r", Modifiers::ctrl()),
+                Some(_) => KeyBinding::new("tab"),
+                None => KeyBinding::new(&ch.to_string()),
+            };
+            rest = &rest[named.map_or(ch.len_utf8(), str::len)..];
+            keys.push(key);
+        }
+        keys
+    }
+
+    /// A document from text with `|` at the cursor
+    fn document(text: &str) -> TextDocument {
+        let cursor = text.find('|').expect("cursor marker");
+        let mut document = TextDocument::with_content(text.replacen('|', "", 1));
+        document.set_cursor_position(text[..cursor].chars().count());
+        document
+    }
+
+    fn with_cursor(document: &TextDocument) -> String {
+        let mut content = document.content();
+        let cursor = content.char_indices().nth(document.cursor_position()).map_or(content.len(), |(index, _)| index);
+        content.insert(cursor, '|');
+        content
+    }
+
+    fn type_keys(vim: &mut Vim, document: &mut TextDocument, notation: &str) {
+        for key in keys(notation) {
+            vim.handle_key(&key, document);
+        }
+    }
+
+    /// Run keys in normal mode and show the result with `|` at the cursor
+    fn run(text: &str, notation: &str) -> String {
+        let mut document = document(text);
+        type_keys(&mut Vim::new(), &mut document, notation);
+        with_cursor(&document)
+    }
+
+    #[test]
+    fn test_motions_and_counts() {
+        assert_eq!(run("|hello world", "l"), "h|ello world");
+        assert_eq!(run("|hello world", "3l"), "hel|lo world");
+        assert_eq!(run("hell|o", "l"), "hell|o");
+        assert_eq!(run("|one two three", "w"), "one |two three");
+        assert_eq!(run("|one two three", "2w"), "one two |three");
+        assert_eq!(run("one two thr|ee", "b"), "one two |three");
+        assert_eq!(run("|foo.bar baz", "w"), "foo|.bar baz");
+        assert_eq!(run("|one two", "e"), "on|e two");
+        assert_eq!(run("one\n\n|two", "b"), "one\n|\ntwo");
+        assert_eq!(run("  one tw|o", "0"), "|  one two");
+        assert_eq!(run("|one two", "$"), "one tw|o");
+        assert_eq!(run("|one\ntwo\nthree", "2$"), "one\ntw|o\nthree");
+
+        // j and k keep the column across shorter lines
+        assert_eq!(run("abc|def\nx\nabcdef", "j"), "abcdef\n|x\nabcdef");
+        assert_eq!(run("abc|def\nx\nabcdef", "jj"), "abcdef\nx\nabc|def");
+        assert_eq!(run("abcdef\nabcdef\nab|c", "2k"), "ab|cdef\nabcdef\nabc");
+
+        assert_eq!(run("one\n  two\nthr|ee\n", "gg"), "|one\n  two\nthree\n");
+        assert_eq!(run("|one\n  two\nthree\n", "G"), "one\n  two\n|three\n");
+        assert_eq!(run("|one\n  two\nthree\n", "2G"), "one\n  |two\nthree\n");
+
+        // Huge counts stop at the ends of lines and the document
+        assert_eq!(run("|one\ntwo", "18446744073709551615$"), "one\ntw|o");
+        assert_eq!(run("|one\ntwo", "99999999999999999999999j"), "one\n|two");
+        assert_eq!(run("|one two", "18446744073709551615l"), "one tw|o");
+        assert_eq!(run("|one two", "18446744073709551615w"), "one tw|o");
+        assert_eq!(run("|one\ntwo", "99999999999999999999999G"), "one\n|two");
+    }
+
+    #[test]
+    fn test_huge_counts_are_clamped() {
+        assert_eq!(run("|one two three", "9999999999d9999999999w"), "|");
+        assert_eq!(run("|one\ntwo", "18446744073709551615dd"), "|");
+        assert_eq!(run("|one two", "9999999999cw"), "|");
+
+        let mut document = document("|x");
+        type_keys(&mut Vim::new(), &mut document, "yl1000000000p");
+        assert_eq!(document.content().len(), 1 + MAX_PUT_COUNT);
+    }
+
+    #[test]
+    fn test_operators_with_motions() {
+        assert_eq!(run("|one two three", "dw"), "|two three");
+        assert_eq!(run("|one two three", "d2w"), "|three");
+        assert_eq!(run("|one two three", "2dw"), "|three");
+        assert_eq!(run("one |two\nthree", "dw"), "one| \nthree");
+        assert_eq!(run("|one two", "de"), "| two");
+        assert_eq!(run("one |two three", "d$"), "one| ");
+        assert_eq!(run("one two th|ree", "db"), "one two |ree");
+        assert_eq!(run("|abc", "2x"), "|c");
+        assert_eq!(run("one\n|two\nthree", "dd"), "one\n|three");
+        assert_eq!(run("one\ntwo\n|three", "dd"), "one\n|two");
+        assert_eq!(run("|one\ntwo\nthree", "2dd"), "|three");
+        assert_eq!(run("|one\ntwo\nthree", "dj"), "|three");
+        assert_eq!(run("one\ntwo\n|three", "dgg"), "|");
+
+        // c deletes and types, and cw stops at the end of the word
+        assert_eq!(run("|one two", "cwsix<esc>"), "si|x two");
+        assert_eq!(run("one |two three", "c$end<esc>"), "one en|d");
+        assert_eq!(run("one\n  |two\nthree", "ccnew<esc>"), "one\nne|w\nthree");
+        assert_eq!(run("one |two", "Dx"), "on|e");
+    }
+
+    #[test]
+    fn test_text_objects() {
+        assert_eq!(run("one t|wo three", "diw"), "one | three");
+        assert_eq!(run("say \"hel|lo there\" now", "ci\"bye<esc>"), "say \"by|e\" now");
+        assert_eq!(run("|x = \"a\" + \"b\"", "di\""), "x = \"|\" + \"b\"");
+        assert_eq!(run("one\ntw|o\n\nthree", "dip"), "|\nthree");
+        assert_eq!(run("one\ntw|o\n\nthree", "yipGp"), "one\ntwo\n\nthree\n|one\ntwo");
+    }
+
+    #[test]
+    fn test_insert_commands() {
+        assert_eq!(run("one |two", "ix <esc>"), "one x| two");
+        assert_eq!(run("on|e", "a!<esc>"), "one|!");
+        assert_eq!(run("  one t|wo", "I- <esc>"), "  -| one two");
+        assert_eq!(run("o|ne", "A two<esc>"), "one tw|o");
+        assert_eq!(run("o|ne\nthree", "otwo<esc>"), "one\ntw|o\nthree");
+        assert_eq!(run("two|", "Oone<esc>"), "on|e\ntwo");
+        assert_eq!(run("|one", "Aa<bs>b<cr>c<esc>"), "oneb\n|c");
+
+        // Keys that aren't typed text go to the keymap
+        let mut vim = Vim::new();
+        let mut doc = document("|one");
+        assert!(vim.handle_key(&KeyBinding::new("i"), &mut doc));
+        assert_eq!(vim.mode(), VimMode::Insert);
+        assert!(!vim.handle_key(&KeyBinding::new("tab"), &mut doc));
+        assert!(!vim.handle_key(&KeyBinding::with_modifiers("s", Modifiers::cmd()), &mut doc));
+        assert!(vim.handle_key(&KeyBinding::new("escape"), &mut doc));
+        assert_eq!(vim.mode(), VimMode::Normal);
+        assert!(!vim.handle_key(&KeyBinding::new("tab"), &mut doc));
+        // Shift+letter keystrokes are capitals
+        assert!(vim.handle_key(&KeyBinding::with_modifiers("a", Modifiers::shift()), &mut doc));
+        assert_eq!(vim.mode(), VimMode::Insert);
+    }
+
+    #[test]
+    fn test_visual_modes() {
+        assert_eq!(run("|one two three", "vey"), "|one two three");
+        assert_eq!(run("|one two three", "ved"), "| two three");
+        assert_eq!(run("one t|wo three", "vbd"), "one |o three");
+        assert_eq!(run("one\n|two\nthree\nfour", "Vjd"), "one\n|four");
+        assert_eq!(run("one t|wo three", "viwcsix<esc>"), "one si|x three");
+        assert_eq!(run("|one two", "vlvx"), "o|e two");
+
+        let mut vim = Vim::new();
+        let mut doc = document("one t|wo three");
+        type_keys(&mut vim, &mut doc, "vl");
+        assert_eq!(vim.mode(), VimMode::Visual);
+        // The document selects what visual mode covers
+        assert_eq!(doc.selected_text().as_deref(), Some("wo"));
+        type_keys(&mut vim, &mut doc, "V");
+        assert_eq!(doc.selected_text().as_deref(), Some("one two three"));
+        type_keys(&mut vim, &mut doc, "<esc>");
+        assert_eq!(vim.mode(), VimMode::Normal);
+        assert!(!doc.has_selection());
+        assert_eq!(with_cursor(&doc), "one tw|o three");
+    }
+
+    #[test]
+    fn test_registers_and_put() {
+        assert_eq!(run("|one two", "yep"), "oon|ene two");
+        assert_eq!(run("|one\ntwo", "yyp"), "one\n|one\ntwo");
+        assert_eq!(run("one\n|two", "yyP"), "one\n|two\ntwo");
+        assert_eq!(run("|one\ntwo", "ddp"), "two\n|one");
+        assert_eq!(run("|one\ntwo", "yy2p"), "one\n|one\none\ntwo");
+        assert_eq!(run("|ab", "xp"), "b|a");
+
+        let mut vim = Vim::new();
+        let mut doc = document("|one\ntwo\nthree");
+        type_keys(&mut vim, &mut doc, "\"ayyj\"Ayy\"_ddG\"ap");
+        assert_eq!(doc.content(), "one\nthree\none\ntwo");
+        assert_eq!(vim.register('a').map(|register| register.text.as_str()), Some("one\ntwo\n"));
+        // The black hole delete left the unnamed register alone
+        assert_eq!(vim.register('"'), vim.register('a'));
+
+        // Deletes go to 1 and move older ones up, yanks go to 0
+        let mut vim = Vim::new();
+        let mut doc = document("|one two three");
+        type_keys(&mut vim, &mut doc, "dwdwyw");
+        let text = |name: char| vim.register(name).map(|register| register.text.clone());
+        assert_eq!(text('1').as_deref(), Some("two "));
+        assert_eq!(text('2').as_deref(), Some("one "));
+        assert_eq!(text('0').as_deref(), Some("three"));
+        assert_eq!(text('"').as_deref(), Some("three"));
+    }
+
+    #[test]
+    fn test_dot_repeats_last_change() {
+        assert_eq!(run("|one two three four", "dw."), "|three four");
+        assert_eq!(run("|one two three four", "dw2."), "|four");
+        assert_eq!(run("|a b c", "cwx<esc>w.w."), "x x |x");
+        assert_eq!(run("|one\ntwo", "A;<esc>j."), "one;\ntwo|;");
+        assert_eq!(run("|a\nb\nc\nd", "dd.j"), "c\n|d");
+        // Motions and yanks don't replace the change to repeat
+        assert_eq!(run("|one two three four", "dwwyw."), "two |four");
+    }
+
+    #[test]
+    fn test_undo_and_redo_whole_changes() {
+        let mut vim = Vim::new();
+        let mut doc = document("|one two\nthree");
+        type_keys(&mut vim, &mut doc, "cwsix<cr>seven<esc>");
+        assert_eq!(doc.content(), "six\nseven two\nthree");
+        type_keys(&mut vim, &mut doc, "u");
+        assert_eq!(doc.content(), "one two\nthree");
+        type_keys(&mut vim, &mut doc, "<c-r>");
+        assert_eq!(doc.content(), "six\nseven two\nthree");
+
+        type_keys(&mut vim, &mut doc, "Gdd3x");
+        assert_eq!(doc.content(), "six\nen two");
+        type_keys(&mut vim, &mut doc, "2u");
+        assert_eq!(doc.content(), "six\nseven two\nthree");
+        assert!(!doc.has_selection());
+    }
+
+    #[test]
+    fn test_older_and_newer_states_cross_undo_branches() {
+        let mut vim = Vim::new();
+        let mut doc = document("|one");
+        type_keys(&mut vim, &mut doc, "xu$x");
+        assert_eq!(doc.content(), "on");
+
+        // The undone "x" is still there, as the state before this one
+        type_keys(&mut vim, &mut doc, "g-");
+        assert_eq!(doc.content(), "ne");
+        type_keys(&mut vim, &mut doc, "g-");
+        assert_eq!(doc.content(), "one");
+        type_keys(&mut vim, &mut doc, "2g+");
+        assert_eq!(doc.content(), "on");
+    }
+
+    #[test]
+    fn test_pending_keys_and_invalid_commands() {
+        let mut vim = Vim::new();
+        let mut doc = document("|one two");
+        type_keys(&mut vim, &mut doc, "\"a2d");
+        assert_eq!(vim.pending_keys().as_deref(), Some("\"a2d"));
+        // Escape cancels the command
+        type_keys(&mut vim, &mut doc, "<esc>");
+        assert_eq!(vim.pending_keys(), None);
+        type_keys(&mut vim, &mut doc, "dqgxw");
+        assert_eq!(with_cursor(&doc), "one |two");
+        assert_eq!(doc.content(), "one two");
+    }
+}
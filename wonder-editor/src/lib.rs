@@ -1,9 +1,14 @@
 pub mod app;
 pub mod editor;
 pub mod markdown_parser;
+pub mod markdown_ast;
 pub mod hybrid_renderer;
 pub mod rendering;
 pub mod core;
+pub mod analysis;
+pub mod export;
+pub mod file;
+pub mod format;
 pub mod input;
 pub mod benchmarks;
 
@@ -1,9 +1,12 @@
 mod app;
 mod editor;
 mod markdown_parser;
+mod markdown_ast;
 mod hybrid_renderer;
 mod rendering;
 mod core;
+mod file;
+mod format;
 mod input;
 mod benchmarks;
 
@@ -16,9 +19,11 @@ use crate::app::WonderApp;
 fn main() {
     let args: Vec<String> = env::args().collect();
     let debug_mode = args.contains(&"--debug".to_string());
+    // Any other argument is a file to open
+    let file_path = args.iter().skip(1).find(|arg| !arg.starts_with("--")).map(std::path::PathBuf::from);
     
     Application::new().run(move |cx: &mut App| {
-        cx.open_window(WindowOptions::default(), move |_, cx| {
+        cx.open_window(WindowOptions::default(), move |window, cx| {
             if debug_mode {
                 // Load example markdown content for debugging
                 let example_content = match fs::read_to_string("example_markdown.md") {
@@ -28,9 +33,11 @@ fn main() {
                         include_str!("../example_markdown.md").to_string()
                     }
                 };
-                cx.new(|cx| WonderApp::new_with_content(example_content, cx))
+                cx.new(|cx| WonderApp::new_with_content(example_content, window, cx))
+            } else if let Some(path) = file_path.clone() {
+                cx.new(|cx| WonderApp::new_with_file(path, window, cx))
             } else {
-                cx.new(|cx| WonderApp::new(cx))
+                cx.new(|cx| WonderApp::new(window, cx))
             }
         })
         .unwrap();
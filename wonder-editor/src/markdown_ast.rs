@@ -0,0 +1,745 @@
+//! Hierarchical Markdown syntax tree
+//!
+//! `MarkdownParser::parse_ast` builds a `MarkdownAst`: the document node holds
+//! block nodes, which hold nested blocks or inline nodes. Every node keeps its
+//! byte range in the source and links to its parent and children, so callers
+//! can tell that a bold span sits inside a list item inside a block quote.
+//!
+//! The flat `ParsedToken` list is still available through `MarkdownAst::tokens`,
+//! which walks the tree and produces exactly what `parse_with_positions` returns.
+
+use std::ops::Range;
+use pulldown_cmark::Alignment;
+use crate::markdown_parser::{MarkdownToken, ParsedToken};
+
+/// Index of a node inside its `MarkdownAst`
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    Document,
+
+    // Blocks
+    Paragraph,
+    Heading(u32),
+    BlockQuote,
+    CodeBlock(Option<String>), // language
+    HtmlBlock,
+    List(Option<u64>), // start number for ordered lists
+    Item,
+    FootnoteDefinition(String), // label
+    Table(Vec<Alignment>),
+    TableHead,
+    TableRow,
+    TableCell,
+    MetadataBlock,
+    Rule,
+
+    // Inline containers
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link { url: String, title: String },
+    Image { url: String, title: String },
+    Subscript, // <sub>...</sub>, children include the HTML tags
+    Superscript, // <sup>...</sup>, children include the HTML tags
+
+    // Inline leaves
+    Text(String),
+    Code(String),
+    Html(String),
+    FootnoteReference(String), // label
+    TaskListMarker(bool), // checked
+    SoftBreak,
+    HardBreak,
+
+    // Custom spans found inside a Text node; they are the Text node's children
+    Highlight(String),
+    Tag(String),
+    Emoji(String),
+}
+
+impl NodeKind {
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self,
+            NodeKind::Paragraph
+                | NodeKind::Heading(_)
+                | NodeKind::BlockQuote
+                | NodeKind::CodeBlock(_)
+                | NodeKind::HtmlBlock
+                | NodeKind::List(_)
+                | NodeKind::Item
+                | NodeKind::FootnoteDefinition(_)
+                | NodeKind::Table(_)
+                | NodeKind::TableHead
+                | NodeKind::TableRow
+                | NodeKind::TableCell
+                | NodeKind::MetadataBlock
+                | NodeKind::Rule
+        )
+    }
+
+    pub fn is_inline(&self) -> bool {
+        !self.is_block() && *self != NodeKind::Document
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstNode {
+    pub kind: NodeKind,
+    pub range: Range<usize>, // Byte range in the source
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownAst {
+    nodes: Vec<AstNode>,
+}
+
+impl MarkdownAst {
+    pub fn root(&self) -> NodeId {
+        0
+    }
+
+    pub fn node(&self, id: NodeId) -> &AstNode {
+        &self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> &[AstNode] {
+        &self.nodes
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id].children
+    }
+
+    /// Top-level blocks of the document
+    pub fn blocks(&self) -> &[NodeId] {
+        self.children(self.root())
+    }
+
+    /// Ancestors of `id`, nearest first, ending with the document node
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.nodes[id].parent, move |&parent| self.nodes[parent].parent)
+    }
+
+    /// All nodes below `id` in document order, not including `id` itself
+    pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack: Vec<NodeId> = self.nodes[id].children.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let next = stack.pop()?;
+            stack.extend(self.nodes[next].children.iter().rev());
+            Some(next)
+        })
+    }
+
+    /// Nodes containing `position`, from the document node down to the innermost one
+    pub fn path_at(&self, position: usize) -> Vec<NodeId> {
+        let mut path = vec![self.root()];
+        let mut current = self.root();
+        while let Some(&child) = self.nodes[current]
+            .children
+            .iter()
+            .find(|&&child| self.nodes[child].range.contains(&position))
+        {
+            path.push(child);
+            current = child;
+        }
+        path
+    }
+
+    /// Innermost node containing `position`, if any node other than the document does
+    pub fn node_at(&self, position: usize) -> Option<NodeId> {
+        self.path_at(position).pop().filter(|&id| id != self.root())
+    }
+
+    /// Top-level block containing `position`
+    pub fn block_at(&self, position: usize) -> Option<NodeId> {
+        self.path_at(position).get(1).copied()
+    }
+
+    /// Rendered text of a node: the text of all descendant text and code nodes
+    pub fn text(&self, id: NodeId) -> String {
+        let mut text = String::new();
+        for descendant in std::iter::once(id).chain(self.descendants(id)) {
+            match &self.nodes[descendant].kind {
+                NodeKind::Text(content) | NodeKind::Code(content) => text.push_str(content),
+                NodeKind::SoftBreak | NodeKind::HardBreak => text.push('\n'),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// Flat token view of the tree, identical to `MarkdownParser::parse_with_positions`
+    pub fn tokens(&self) -> Vec<ParsedToken> {
+        self.token_nodes().into_iter().map(|(_, token)| token).collect()
+    }
+
+    /// Flat token view paired with the node each token came from
+    pub fn token_nodes(&self) -> Vec<(NodeId, ParsedToken)> {
+        let mut collector = TokenCollector::default();
+        self.collect(self.root(), &mut collector);
+        collector.tokens
+    }
+
+    fn collect(&self, id: NodeId, collector: &mut TokenCollector) {
+        let node = &self.nodes[id];
+        match &node.kind {
+            // Transparent wrappers: their tags are replayed as the HTML leaves they contain
+            NodeKind::Document | NodeKind::Subscript | NodeKind::Superscript => {
+                for &child in &node.children {
+                    self.collect(child, collector);
+                }
+            }
+            NodeKind::Text(_) | NodeKind::Code(_) | NodeKind::Html(_) | NodeKind::FootnoteReference(_) => {
+                collector.leaf(self, id);
+            }
+            _ => {
+                collector.open(id, node);
+                for &child in &node.children {
+                    self.collect(child, collector);
+                }
+                collector.close(id, node);
+            }
+        }
+    }
+}
+
+/// Builds a `MarkdownAst` from a stream of start/end/leaf events
+pub(crate) struct AstBuilder {
+    nodes: Vec<AstNode>,
+    stack: Vec<NodeId>,
+}
+
+impl AstBuilder {
+    pub(crate) fn new(source_len: usize) -> Self {
+        Self {
+            nodes: vec![AstNode {
+                kind: NodeKind::Document,
+                range: 0..source_len,
+                parent: None,
+                children: Vec::new(),
+            }],
+            stack: vec![0],
+        }
+    }
+
+    fn add(&mut self, kind: NodeKind, range: Range<usize>) -> NodeId {
+        let parent = *self.stack.last().unwrap();
+        let id = self.nodes.len();
+        self.nodes.push(AstNode { kind, range, parent: Some(parent), children: Vec::new() });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    pub(crate) fn open(&mut self, kind: NodeKind, range: Range<usize>) {
+        let id = self.add(kind, range);
+        self.stack.push(id);
+    }
+
+    /// Close the innermost open tag, along with any `<sub>`/`<sup>` left open inside it
+    pub(crate) fn close(&mut self) {
+        while self.stack.len() > 1 {
+            let id = self.stack.pop().unwrap();
+            if !self.close_wrapper(id) {
+                break;
+            }
+        }
+    }
+
+    pub(crate) fn leaf(&mut self, kind: NodeKind, range: Range<usize>) -> NodeId {
+        self.add(kind, range)
+    }
+
+    /// Add a custom span (highlight, tag, emoji) under a text node
+    pub(crate) fn span(&mut self, text_node: NodeId, kind: NodeKind, range: Range<usize>) {
+        let id = self.nodes.len();
+        self.nodes.push(AstNode { kind, range, parent: Some(text_node), children: Vec::new() });
+        self.nodes[text_node].children.push(id);
+    }
+
+    /// Add an HTML leaf, grouping `<sub>`/`<sup>` tag pairs into wrapper nodes
+    pub(crate) fn html(&mut self, html: String, range: Range<usize>) {
+        let wrapper = match html.as_str() {
+            "<sub>" => Some(NodeKind::Subscript),
+            "<sup>" => Some(NodeKind::Superscript),
+            _ => None,
+        };
+        if let Some(kind) = wrapper {
+            self.open(kind, range.clone());
+            self.leaf(NodeKind::Html(html), range);
+            return;
+        }
+
+        let closes = match html.as_str() {
+            "</sub>" => Some(NodeKind::Subscript),
+            "</sup>" => Some(NodeKind::Superscript),
+            _ if html.starts_with("<sub>") && html.ends_with("</sub>") => {
+                self.open(NodeKind::Subscript, range.clone());
+                Some(NodeKind::Subscript)
+            }
+            _ if html.starts_with("<sup>") && html.ends_with("</sup>") => {
+                self.open(NodeKind::Superscript, range.clone());
+                Some(NodeKind::Superscript)
+            }
+            _ => None,
+        };
+        let open_wrapper = closes.and_then(|kind| {
+            self.stack
+                .iter()
+                .rev()
+                .take_while(|&&id| self.is_wrapper(id))
+                .position(|&id| self.nodes[id].kind == kind)
+        });
+        match open_wrapper {
+            Some(depth) => {
+                // Close wrappers opened after the matching one, then the matching one itself
+                for _ in 0..depth {
+                    let id = self.stack.pop().unwrap();
+                    self.close_wrapper(id);
+                }
+                self.leaf(NodeKind::Html(html), range);
+                let id = self.stack.pop().unwrap();
+                self.close_wrapper(id);
+            }
+            None => {
+                self.leaf(NodeKind::Html(html), range);
+            }
+        }
+    }
+
+    fn is_wrapper(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id].kind, NodeKind::Subscript | NodeKind::Superscript)
+    }
+
+    /// Extend a closed wrapper to its last child; returns false for regular nodes
+    fn close_wrapper(&mut self, id: NodeId) -> bool {
+        if !self.is_wrapper(id) {
+            return false;
+        }
+        if let Some(&last) = self.nodes[id].children.last() {
+            let end = self.nodes[last].range.end.max(self.nodes[id].range.end);
+            self.nodes[id].range.end = end;
+        }
+        true
+    }
+
+    pub(crate) fn finish(mut self) -> MarkdownAst {
+        while self.stack.len() > 1 {
+            let id = self.stack.pop().unwrap();
+            self.close_wrapper(id);
+        }
+        MarkdownAst { nodes: self.nodes }
+    }
+}
+
+/// Replays the tree in document order and produces the flat token list.
+///
+/// This is the state machine `parse_with_positions` has always used: text inside
+/// a container accumulates into that container's token, and only text outside
+/// containers is split into text, tag, highlight and emoji tokens.
+#[derive(Default)]
+struct TokenCollector {
+    tokens: Vec<(NodeId, ParsedToken)>,
+    current_text: String,
+    in_heading: Option<u32>,
+    heading_start: usize,
+    in_strong: bool,
+    strong_start: usize,
+    in_emphasis: bool,
+    emphasis_start: usize,
+    in_strikethrough: bool,
+    strikethrough_start: usize,
+    in_link: bool,
+    link_start: usize,
+    link_url: String,
+    in_code_block: bool,
+    code_block_start: usize,
+    code_block_lang: Option<String>,
+    in_list_item: bool,
+    list_item_start: usize,
+    in_block_quote: bool,
+    block_quote_start: usize,
+    in_table_cell: bool,
+    table_cell_start: usize,
+    in_subscript: bool,
+    subscript_start: usize,
+    in_superscript: bool,
+    superscript_start: usize,
+}
+
+impl TokenCollector {
+    fn push(&mut self, node: NodeId, token_type: MarkdownToken, start: usize, end: usize) {
+        self.tokens.push((node, ParsedToken { token_type, start, end }));
+    }
+
+    fn open(&mut self, id: NodeId, node: &AstNode) {
+        let start = node.range.start;
+        match &node.kind {
+            NodeKind::Heading(level) => {
+                self.in_heading = Some(*level);
+                self.heading_start = start;
+            }
+            NodeKind::Strong => {
+                self.in_strong = true;
+                self.strong_start = start;
+            }
+            NodeKind::Emphasis => {
+                self.in_emphasis = true;
+                self.emphasis_start = start;
+            }
+            NodeKind::Strikethrough => {
+                self.in_strikethrough = true;
+                self.strikethrough_start = start;
+            }
+            NodeKind::Link { url, .. } => {
+                self.in_link = true;
+                self.link_start = start;
+                self.link_url = url.clone();
+            }
+            NodeKind::CodeBlock(lang) => {
+                self.in_code_block = true;
+                self.code_block_start = start;
+                self.code_block_lang = lang.clone();
+            }
+            NodeKind::Item => {
+                self.in_list_item = true;
+                self.list_item_start = start;
+                self.current_text.clear();
+            }
+            NodeKind::BlockQuote => {
+                self.in_block_quote = true;
+                self.block_quote_start = start;
+                self.current_text.clear();
+            }
+            NodeKind::Table(_) => self.push(id, MarkdownToken::Table, start, node.range.end),
+            NodeKind::TableHead => self.push(id, MarkdownToken::TableHeader, start, node.range.end),
+            NodeKind::TableRow => self.push(id, MarkdownToken::TableRow, start, node.range.end),
+            NodeKind::TableCell => {
+                self.in_table_cell = true;
+                self.table_cell_start = start;
+                self.current_text.clear();
+            }
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, id: NodeId, node: &AstNode) {
+        let end = node.range.end;
+        match &node.kind {
+            NodeKind::Heading(_) => {
+                if let Some(level) = self.in_heading.take() {
+                    let text = std::mem::take(&mut self.current_text);
+                    self.push(id, MarkdownToken::Heading(level, text), self.heading_start, end);
+                }
+            }
+            NodeKind::Strong if self.in_strong => {
+                let text = std::mem::take(&mut self.current_text);
+                self.push(id, MarkdownToken::Bold(text), self.strong_start, end);
+                self.in_strong = false;
+            }
+            NodeKind::Emphasis if self.in_emphasis => {
+                let text = std::mem::take(&mut self.current_text);
+                self.push(id, MarkdownToken::Italic(text), self.emphasis_start, end);
+                self.in_emphasis = false;
+            }
+            NodeKind::Strikethrough if self.in_strikethrough => {
+                let text = std::mem::take(&mut self.current_text);
+                self.push(id, MarkdownToken::Strikethrough(text), self.strikethrough_start, end);
+                self.in_strikethrough = false;
+            }
+            NodeKind::Link { .. } if self.in_link => {
+                let text = std::mem::take(&mut self.current_text);
+                let url = std::mem::take(&mut self.link_url);
+                self.push(id, MarkdownToken::Link(text, url), self.link_start, end);
+                self.in_link = false;
+            }
+            NodeKind::CodeBlock(_) if self.in_code_block => {
+                let text = std::mem::take(&mut self.current_text);
+                let lang = self.code_block_lang.take();
+                self.push(id, MarkdownToken::CodeBlock(lang, text), self.code_block_start, end);
+                self.in_code_block = false;
+            }
+            NodeKind::Item if self.in_list_item => {
+                let text = std::mem::take(&mut self.current_text);
+                self.push(id, MarkdownToken::ListItem(text), self.list_item_start, end);
+                self.in_list_item = false;
+            }
+            NodeKind::BlockQuote if self.in_block_quote => {
+                let text = std::mem::take(&mut self.current_text);
+                self.push(id, MarkdownToken::BlockQuote(text), self.block_quote_start, end);
+                self.in_block_quote = false;
+            }
+            NodeKind::TableCell if self.in_table_cell => {
+                let text = std::mem::take(&mut self.current_text);
+                self.push(id, MarkdownToken::TableCell(text), self.table_cell_start, end);
+                self.in_table_cell = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn in_container(&self) -> bool {
+        self.in_heading.is_some()
+            || self.in_strong
+            || self.in_emphasis
+            || self.in_strikethrough
+            || self.in_link
+            || self.in_code_block
+            || self.in_list_item
+            || self.in_block_quote
+            || self.in_table_cell
+            || self.in_subscript
+            || self.in_superscript
+    }
+
+    fn leaf(&mut self, ast: &MarkdownAst, id: NodeId) {
+        let node = ast.node(id);
+        let range = node.range.clone();
+        match &node.kind {
+            NodeKind::Text(text) => {
+                if self.in_container() {
+                    self.current_text.push_str(text);
+                } else if text.len() != range.len() {
+                    // Text was converted (e.g., ASCII quotes to smart quotes), use original range directly
+                    self.push(id, MarkdownToken::Text(text.clone()), range.start, range.end);
+                } else {
+                    self.push_text_spans(ast, id, text);
+                }
+            }
+            NodeKind::FootnoteReference(label) => {
+                self.push(id, MarkdownToken::FootnoteReference(label.clone()), range.start, range.end);
+            }
+            NodeKind::Code(code) => {
+                self.push(id, MarkdownToken::Code(code.clone()), range.start, range.end);
+            }
+            NodeKind::Html(html) => self.html(ast, id, html, range),
+            _ => {}
+        }
+    }
+
+    /// Split top-level text into plain text runs around its highlight, tag and emoji spans
+    fn push_text_spans(&mut self, ast: &MarkdownAst, id: NodeId, text: &str) {
+        let text_start = ast.node(id).range.start;
+        let mut last_end = 0;
+        for &span in ast.children(id) {
+            let span_node = ast.node(span);
+            let span_start = span_node.range.start - text_start;
+            if span_start > last_end {
+                let before = text[last_end..span_start].to_string();
+                self.push(id, MarkdownToken::Text(before), text_start + last_end, text_start + span_start);
+            }
+            let token_type = match &span_node.kind {
+                NodeKind::Highlight(content) => MarkdownToken::Highlight(content.clone()),
+                NodeKind::Tag(content) => MarkdownToken::Tag(content.clone()),
+                NodeKind::Emoji(content) => MarkdownToken::Emoji(content.clone()),
+                _ => continue,
+            };
+            self.push(span, token_type, span_node.range.start, span_node.range.end);
+            last_end = span_node.range.end - text_start;
+        }
+        if last_end < text.len() || last_end == 0 {
+            let remaining = text[last_end..].to_string();
+            self.push(id, MarkdownToken::Text(remaining), text_start + last_end, text_start + text.len());
+        }
+    }
+
+    fn html(&mut self, ast: &MarkdownAst, id: NodeId, html: &str, range: Range<usize>) {
+        // Sub/superscript tokens belong to the wrapper node grouping their tags
+        let owner = match ast.parent(id) {
+            Some(parent) if matches!(ast.node(parent).kind, NodeKind::Subscript | NodeKind::Superscript) => parent,
+            _ => id,
+        };
+        if html == "<sub>" {
+            self.in_subscript = true;
+            self.subscript_start = range.start;
+            self.current_text.clear();
+        } else if html == "</sub>" && self.in_subscript {
+            let text = std::mem::take(&mut self.current_text);
+            self.push(owner, MarkdownToken::Subscript(text), self.subscript_start, range.end);
+            self.in_subscript = false;
+        } else if html == "<sup>" {
+            self.in_superscript = true;
+            self.superscript_start = range.start;
+            self.current_text.clear();
+        } else if html == "</sup>" && self.in_superscript {
+            let text = std::mem::take(&mut self.current_text);
+            self.push(owner, MarkdownToken::Superscript(text), self.superscript_start, range.end);
+            self.in_superscript = false;
+        } else if html.starts_with("<sub>") && html.ends_with("</sub>") {
+            // Handle complete subscript in one event (fallback case)
+            let content = html.trim_start_matches("<sub>").trim_end_matches("</sub>");
+            self.push(owner, MarkdownToken::Subscript(content.to_string()), range.start, range.end);
+        } else if html.starts_with("<sup>") && html.ends_with("</sup>") {
+            // Handle complete superscript in one event (fallback case)
+            let content = html.trim_start_matches("<sup>").trim_end_matches("</sup>");
+            self.push(owner, MarkdownToken::Superscript(content.to_string()), range.start, range.end);
+        } else {
+            // General HTML token for other HTML content
+            self.push(id, MarkdownToken::Html(html.to_string()), range.start, range.end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown_parser::MarkdownParser;
+
+    fn kinds_on_path(ast: &MarkdownAst, position: usize) -> Vec<NodeKind> {
+        ast.path_at(position).into_iter().map(|id| ast.node(id).kind.clone()).collect()
+    }
+
+    #[test]
+    fn test_bold_inside_list_item_inside_block_quote() {
+        let parser = MarkdownParser::new();
+        let markdown = "> - item with **bold** text\n";
+        let ast = parser.parse_ast(markdown);
+
+        let bold_pos = markdown.find("bold").unwrap();
+        let path = kinds_on_path(&ast, bold_pos);
+        assert_eq!(path[0], NodeKind::Document);
+        assert_eq!(path[1], NodeKind::BlockQuote);
+        assert_eq!(path[2], NodeKind::List(None));
+        assert_eq!(path[3], NodeKind::Item);
+        assert!(path.contains(&NodeKind::Strong));
+        assert_eq!(path.last(), Some(&NodeKind::Text("bold".to_string())));
+
+        let strong = ast.path_at(bold_pos).into_iter().find(|&id| ast.node(id).kind == NodeKind::Strong).unwrap();
+        assert_eq!(&markdown[ast.node(strong).range.clone()], "**bold**");
+        assert_eq!(ast.text(strong), "bold");
+    }
+
+    #[test]
+    fn test_parent_child_links_are_consistent() {
+        let parser = MarkdownParser::new();
+        let ast = parser.parse_ast("# Title\n\n- a\n- b *c*\n\n| x | y |\n|---|---|\n| 1 | 2 |\n");
+
+        for (id, node) in ast.nodes().iter().enumerate() {
+            for &child in &node.children {
+                assert_eq!(ast.parent(child), Some(id));
+            }
+            if let Some(parent) = node.parent {
+                assert!(ast.children(parent).contains(&id));
+            }
+        }
+        assert_eq!(ast.parent(ast.root()), None);
+        assert_eq!(ast.descendants(ast.root()).count(), ast.nodes().len() - 1);
+    }
+
+    #[test]
+    fn test_blocks_and_navigation() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Title\n\nSome *text* here\n\n```rust\nfn main() {}\n```\n";
+        let ast = parser.parse_ast(markdown);
+
+        let blocks: Vec<NodeKind> = ast.blocks().iter().map(|&id| ast.node(id).kind.clone()).collect();
+        assert_eq!(blocks, vec![
+            NodeKind::Heading(1),
+            NodeKind::Paragraph,
+            NodeKind::CodeBlock(Some("rust".to_string())),
+        ]);
+
+        let italic_pos = markdown.find("text").unwrap();
+        let text_node = ast.node_at(italic_pos).unwrap();
+        let ancestors: Vec<NodeKind> = ast.ancestors(text_node).map(|id| ast.node(id).kind.clone()).collect();
+        assert_eq!(ancestors, vec![NodeKind::Emphasis, NodeKind::Paragraph, NodeKind::Document]);
+        assert_eq!(ast.block_at(italic_pos), Some(ast.blocks()[1]));
+        assert_eq!(ast.node_at(markdown.len() + 10), None);
+    }
+
+    #[test]
+    fn test_custom_spans_are_children_of_text() {
+        let parser = MarkdownParser::new();
+        let markdown = "See #project and ==this== now";
+        let ast = parser.parse_ast(markdown);
+
+        let tag = ast.node_at(markdown.find("#project").unwrap() + 1).unwrap();
+        assert_eq!(ast.node(tag).kind, NodeKind::Tag("project".to_string()));
+        assert!(matches!(ast.node(ast.parent(tag).unwrap()).kind, NodeKind::Text(_)));
+
+        let highlight = ast.node_at(markdown.find("==this").unwrap()).unwrap();
+        assert_eq!(ast.node(highlight).kind, NodeKind::Highlight("this".to_string()));
+    }
+
+    #[test]
+    fn test_subscript_tags_are_grouped() {
+        let parser = MarkdownParser::new();
+        let markdown = "H<sub>2</sub>O and x<sup>2</sup>";
+        let ast = parser.parse_ast(markdown);
+
+        let sub = ast.node_at(markdown.find("2</sub>").unwrap()).and_then(|id| ast.parent(id)).unwrap();
+        assert_eq!(ast.node(sub).kind, NodeKind::Subscript);
+        assert_eq!(&markdown[ast.node(sub).range.clone()], "<sub>2</sub>");
+
+        let sup = ast.node_at(markdown.find("2</sup>").unwrap()).and_then(|id| ast.parent(id)).unwrap();
+        assert_eq!(ast.node(sup).kind, NodeKind::Superscript);
+        assert_eq!(&markdown[ast.node(sup).range.clone()], "<sup>2</sup>");
+    }
+
+    fn token(token_type: MarkdownToken, start: usize, end: usize) -> ParsedToken {
+        ParsedToken { token_type, start, end }
+    }
+
+    // Golden lists below were captured from the flat event walker that
+    // parse_with_positions used before the AST existed
+
+    #[test]
+    fn test_tokens_view_nested_list() {
+        let parser = MarkdownParser::new();
+        let tokens = parser.parse_ast("- a\n  - b **c**\n- d\n").tokens();
+        assert_eq!(tokens, vec![
+            token(MarkdownToken::Bold("b c".to_string()), 10, 15),
+            token(MarkdownToken::ListItem(String::new()), 6, 16),
+            token(MarkdownToken::ListItem("d".to_string()), 16, 20),
+        ]);
+    }
+
+    #[test]
+    fn test_tokens_view_table_with_inline_cells() {
+        let parser = MarkdownParser::new();
+        let tokens = parser.parse_ast("| a | `b` |\n|---|:-:|\n| *c* | d |\n").tokens();
+        assert_eq!(tokens, vec![
+            token(MarkdownToken::Table, 0, 34),
+            token(MarkdownToken::TableHeader, 0, 12),
+            token(MarkdownToken::TableCell("a".to_string()), 1, 4),
+            token(MarkdownToken::Code("b".to_string()), 6, 9),
+            token(MarkdownToken::TableCell(String::new()), 5, 10),
+            token(MarkdownToken::TableRow, 22, 34),
+            token(MarkdownToken::Italic("c".to_string()), 24, 27),
+            token(MarkdownToken::TableCell(String::new()), 23, 28),
+            token(MarkdownToken::TableCell("d".to_string()), 29, 32),
+        ]);
+    }
+
+    #[test]
+    fn test_tokens_view_code_inside_emphasis() {
+        let parser = MarkdownParser::new();
+        let tokens = parser.parse_ast("*x `code` y* and **`z`**\n").tokens();
+        assert_eq!(tokens, vec![
+            token(MarkdownToken::Code("code".to_string()), 3, 9),
+            token(MarkdownToken::Italic("x  y".to_string()), 0, 12),
+            token(MarkdownToken::Text(" and ".to_string()), 12, 17),
+            token(MarkdownToken::Code("z".to_string()), 19, 22),
+            token(MarkdownToken::Bold(String::new()), 17, 24),
+        ]);
+    }
+
+    #[test]
+    fn test_token_nodes_cover_their_tokens() {
+        let parser = MarkdownParser::new();
+        let ast = parser.parse_ast(include_str!("../example_markdown.md"));
+
+        for (id, token) in ast.token_nodes() {
+            let range = &ast.node(id).range;
+            assert!(range.start <= token.start && token.end <= range.end, "{:?} outside {:?}", token, range);
+        }
+    }
+}
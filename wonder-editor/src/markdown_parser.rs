@@ -1,13 +1,28 @@
 use pulldown_cmark::{Parser, Event, Tag, TagEnd, Options};
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+use crate::markdown_ast::{AstBuilder, MarkdownAst, NodeKind};
+
+// Compiled once: text tokens are scanned for these on every parse
+fn highlight_regex() -> &'static Regex {
+    static HIGHLIGHT: OnceLock<Regex> = OnceLock::new();
+    HIGHLIGHT.get_or_init(|| Regex::new(r"==([^=]+)==").unwrap())
+}
+
+fn tag_regex() -> &'static Regex {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    TAG.get_or_init(|| Regex::new(r"#([a-zA-Z0-9_\-/.]+)").unwrap())
+}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ParsedToken {
     pub token_type: MarkdownToken,
     pub start: usize,
     pub end: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum MarkdownToken {
     Heading(u32, String),
     Paragraph(String),
@@ -318,374 +333,91 @@ impl MarkdownParser {
     }
 
     pub fn parse_with_positions(&self, markdown: &str) -> Vec<ParsedToken> {
+        self.parse_ast(markdown).tokens()
+    }
+
+    /// Parse into a tree of block and inline nodes with source ranges
+    pub fn parse_ast(&self, markdown: &str) -> MarkdownAst {
         let parser = Parser::new_ext(markdown, self.options);
-        let offset_iter = parser.into_offset_iter();
-        let mut tokens = Vec::new();
-        let mut current_text = String::new();
-        let mut in_heading = None;
-        let mut heading_start = 0;
-        let mut in_strong = false;
-        let mut strong_start = 0;
-        let mut in_emphasis = false;
-        let mut emphasis_start = 0;
-        let mut in_strikethrough = false;
-        let mut strikethrough_start = 0;
-        let mut in_link = false;
-        let mut link_start = 0;
-        let mut link_url = String::new();
-        let mut in_code_block = false;
-        let mut code_block_start = 0;
-        let mut code_block_lang = None;
-        let mut in_list_item = false;
-        let mut list_item_start = 0;
-        let mut in_block_quote = false;
-        let mut block_quote_start = 0;
-        let mut in_table = false;
-        let mut table_start = 0;
-        let mut in_table_head = false;
-        let mut table_head_start = 0;
-        let mut in_table_row = false;
-        let mut table_row_start = 0;
-        let mut in_table_cell = false;
-        let mut table_cell_start = 0;
-        let mut in_subscript = false;
-        let mut subscript_start = 0;
-        let mut in_superscript = false;
-        let mut superscript_start = 0;
-        
-        for (event, range) in offset_iter {
+        let mut builder = AstBuilder::new(markdown.len());
+
+        for (event, range) in parser.into_offset_iter() {
             match event {
-                Event::Start(Tag::Heading { level, .. }) => {
-                    in_heading = Some(level as u32);
-                    heading_start = range.start;
-                }
-                Event::Start(Tag::Strong) => {
-                    in_strong = true;
-                    strong_start = range.start;
-                }
-                Event::Start(Tag::Emphasis) => {
-                    in_emphasis = true;
-                    emphasis_start = range.start;
-                }
-                Event::Start(Tag::Strikethrough) => {
-                    in_strikethrough = true;
-                    strikethrough_start = range.start;
-                }
-                Event::Start(Tag::Link { dest_url, .. }) => {
-                    in_link = true;
-                    link_start = range.start;
-                    link_url = dest_url.to_string();
-                }
-                Event::Start(Tag::CodeBlock(kind)) => {
-                    in_code_block = true;
-                    code_block_start = range.start;
-                    code_block_lang = match kind {
-                        pulldown_cmark::CodeBlockKind::Fenced(lang) => {
-                            if lang.is_empty() { None } else { Some(lang.to_string()) }
-                        }
-                        _ => None,
-                    };
-                }
-                Event::Start(Tag::Item) => {
-                    in_list_item = true;
-                    list_item_start = range.start;
-                    current_text.clear();
-                }
-                Event::Start(Tag::BlockQuote) => {
-                    in_block_quote = true;
-                    block_quote_start = range.start;
-                    current_text.clear();
-                }
-                Event::Start(Tag::Table(_)) => {
-                    in_table = true;
-                    table_start = range.start;
-                    tokens.push(ParsedToken {
-                        token_type: MarkdownToken::Table,
-                        start: range.start,
-                        end: range.end,
-                    });
-                }
-                Event::Start(Tag::TableHead) => {
-                    in_table_head = true;
-                    table_head_start = range.start;
-                    tokens.push(ParsedToken {
-                        token_type: MarkdownToken::TableHeader,
-                        start: range.start,
-                        end: range.end,
-                    });
-                }
-                Event::Start(Tag::TableRow) => {
-                    in_table_row = true;
-                    table_row_start = range.start;
-                    tokens.push(ParsedToken {
-                        token_type: MarkdownToken::TableRow,
-                        start: range.start,
-                        end: range.end,
-                    });
-                }
-                Event::Start(Tag::TableCell) => {
-                    in_table_cell = true;
-                    table_cell_start = range.start;
-                    current_text.clear();
-                }
-                Event::End(TagEnd::Heading(_)) => {
-                    if let Some(level) = in_heading.take() {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Heading(level, current_text.clone()),
-                            start: heading_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                    }
-                }
-                Event::End(TagEnd::Strong) => {
-                    if in_strong {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Bold(current_text.clone()),
-                            start: strong_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_strong = false;
-                    }
-                }
-                Event::End(TagEnd::Emphasis) => {
-                    if in_emphasis {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Italic(current_text.clone()),
-                            start: emphasis_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_emphasis = false;
-                    }
-                }
-                Event::End(TagEnd::Strikethrough) => {
-                    if in_strikethrough {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Strikethrough(current_text.clone()),
-                            start: strikethrough_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_strikethrough = false;
-                    }
-                }
-                Event::End(TagEnd::Link) => {
-                    if in_link {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Link(current_text.clone(), link_url.clone()),
-                            start: link_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        link_url.clear();
-                        in_link = false;
-                    }
-                }
-                Event::End(TagEnd::CodeBlock) => {
-                    if in_code_block {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::CodeBlock(code_block_lang.clone(), current_text.clone()),
-                            start: code_block_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        code_block_lang = None;
-                        in_code_block = false;
-                    }
-                }
-                Event::End(TagEnd::Item) => {
-                    if in_list_item {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::ListItem(current_text.clone()),
-                            start: list_item_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_list_item = false;
-                    }
-                }
-                Event::End(TagEnd::BlockQuote) => {
-                    if in_block_quote {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::BlockQuote(current_text.clone()),
-                            start: block_quote_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_block_quote = false;
-                    }
-                }
-                Event::End(TagEnd::Table) => {
-                    in_table = false;
-                }
-                Event::End(TagEnd::TableHead) => {
-                    in_table_head = false;
-                }
-                Event::End(TagEnd::TableRow) => {
-                    in_table_row = false;
-                }
-                Event::End(TagEnd::TableCell) => {
-                    if in_table_cell {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::TableCell(current_text.clone()),
-                            start: table_cell_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_table_cell = false;
-                    }
-                }
+                Event::Start(tag) => builder.open(Self::node_kind_for_tag(tag), range),
+                Event::End(_) => builder.close(),
                 Event::Text(text) => {
-                    if in_heading.is_some() || in_strong || in_emphasis || in_strikethrough || in_link || in_code_block || in_list_item || in_block_quote || in_table_cell || in_subscript || in_superscript {
-                        current_text.push_str(&text);
-                    } else {
-                        // Check if pulldown-cmark converted the text (e.g., ASCII quotes to smart quotes)
-                        let expected_length = range.end - range.start;
-                        let actual_length = text.len();
-                        
-                        if actual_length != expected_length {
-                            // Text was converted, use original range directly to avoid position mismatches
-                            tokens.push(ParsedToken {
-                                token_type: MarkdownToken::Text(text.to_string()),
-                                start: range.start,
-                                end: range.end,
-                            });
+                    let converted = text.len() != range.len();
+                    let text_start = range.start;
+                    let text_node = builder.leaf(NodeKind::Text(text.to_string()), range);
+                    // Converted text (e.g. smart quotes) no longer lines up with the source.
+                    // Tags need '#', highlights need "==" and emoji are never ASCII.
+                    let may_have_spans = text.contains('#') || text.contains("==") || !text.is_ascii();
+                    if !converted && may_have_spans {
+                        let spans = if text.contains("==") {
+                            self.parse_special_tokens_in_text_with_positions(&text, text_start)
                         } else {
-                            // Text was not converted, safe to parse special tokens
-                            let text_with_special = if text.contains("==") {
-                                self.parse_special_tokens_in_text_with_positions(&text, range.start)
-                            } else {
-                                self.parse_tags_and_emojis_in_text_with_positions(&text, range.start)
+                            self.parse_tags_and_emojis_in_text_with_positions(&text, text_start)
+                        };
+                        for span in spans {
+                            let kind = match span.token_type {
+                                MarkdownToken::Highlight(content) => NodeKind::Highlight(content),
+                                MarkdownToken::Tag(content) => NodeKind::Tag(content),
+                                MarkdownToken::Emoji(content) => NodeKind::Emoji(content),
+                                _ => continue,
                             };
-                            tokens.extend(text_with_special);
+                            builder.span(text_node, kind, span.start..span.end);
                         }
                     }
                 }
+                Event::Code(code) => {
+                    builder.leaf(NodeKind::Code(code.to_string()), range);
+                }
+                Event::Html(html) | Event::InlineHtml(html) => builder.html(html.to_string(), range),
                 Event::FootnoteReference(label) => {
-                    tokens.push(ParsedToken {
-                        token_type: MarkdownToken::FootnoteReference(label.to_string()),
-                        start: range.start,
-                        end: range.end,
-                    });
+                    builder.leaf(NodeKind::FootnoteReference(label.to_string()), range);
                 }
-                Event::Code(code) => {
-                    tokens.push(ParsedToken {
-                        token_type: MarkdownToken::Code(code.to_string()),
-                        start: range.start,
-                        end: range.end,
-                    });
+                Event::SoftBreak => {
+                    builder.leaf(NodeKind::SoftBreak, range);
                 }
-                Event::Html(html) => {
-                    let html_str = html.to_string();
-                    // Check for specific HTML tags and handle state transitions
-                    if html_str == "<sub>" {
-                        in_subscript = true;
-                        subscript_start = range.start;
-                        current_text.clear();
-                    } else if html_str == "</sub>" && in_subscript {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Subscript(current_text.clone()),
-                            start: subscript_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_subscript = false;
-                    } else if html_str == "<sup>" {
-                        in_superscript = true;
-                        superscript_start = range.start;
-                        current_text.clear();
-                    } else if html_str == "</sup>" && in_superscript {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Superscript(current_text.clone()),
-                            start: superscript_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_superscript = false;
-                    } else if html_str.starts_with("<sub>") && html_str.ends_with("</sub>") {
-                        // Handle complete subscript in one event (fallback case)
-                        let content = html_str.trim_start_matches("<sub>").trim_end_matches("</sub>");
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Subscript(content.to_string()),
-                            start: range.start,
-                            end: range.end,
-                        });
-                    } else if html_str.starts_with("<sup>") && html_str.ends_with("</sup>") {
-                        // Handle complete superscript in one event (fallback case)
-                        let content = html_str.trim_start_matches("<sup>").trim_end_matches("</sup>");
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Superscript(content.to_string()),
-                            start: range.start,
-                            end: range.end,
-                        });
-                    } else {
-                        // General HTML token for other HTML content
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Html(html_str),
-                            start: range.start,
-                            end: range.end,
-                        });
-                    }
+                Event::HardBreak => {
+                    builder.leaf(NodeKind::HardBreak, range);
                 }
-                Event::InlineHtml(html) => {
-                    let html_str = html.to_string();
-                    // Check for specific inline HTML tags and handle state transitions
-                    if html_str == "<sub>" {
-                        in_subscript = true;
-                        subscript_start = range.start;
-                        current_text.clear();
-                    } else if html_str == "</sub>" && in_subscript {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Subscript(current_text.clone()),
-                            start: subscript_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_subscript = false;
-                    } else if html_str == "<sup>" {
-                        in_superscript = true;
-                        superscript_start = range.start;
-                        current_text.clear();
-                    } else if html_str == "</sup>" && in_superscript {
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Superscript(current_text.clone()),
-                            start: superscript_start,
-                            end: range.end,
-                        });
-                        current_text.clear();
-                        in_superscript = false;
-                    } else if html_str.starts_with("<sub>") && html_str.ends_with("</sub>") {
-                        // Handle complete subscript in one event (fallback case)
-                        let content = html_str.trim_start_matches("<sub>").trim_end_matches("</sub>");
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Subscript(content.to_string()),
-                            start: range.start,
-                            end: range.end,
-                        });
-                    } else if html_str.starts_with("<sup>") && html_str.ends_with("</sup>") {
-                        // Handle complete superscript in one event (fallback case)
-                        let content = html_str.trim_start_matches("<sup>").trim_end_matches("</sup>");
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Superscript(content.to_string()),
-                            start: range.start,
-                            end: range.end,
-                        });
-                    } else {
-                        // General HTML token for other inline HTML content
-                        tokens.push(ParsedToken {
-                            token_type: MarkdownToken::Html(html_str),
-                            start: range.start,
-                            end: range.end,
-                        });
-                    }
+                Event::Rule => {
+                    builder.leaf(NodeKind::Rule, range);
+                }
+                Event::TaskListMarker(checked) => {
+                    builder.leaf(NodeKind::TaskListMarker(checked), range);
                 }
-                _ => {}
             }
         }
-        
-        tokens
+
+        builder.finish()
+    }
+
+    fn node_kind_for_tag(tag: Tag) -> NodeKind {
+        match tag {
+            Tag::Paragraph => NodeKind::Paragraph,
+            Tag::Heading { level, .. } => NodeKind::Heading(level as u32),
+            Tag::BlockQuote => NodeKind::BlockQuote,
+            Tag::CodeBlock(pulldown_cmark::CodeBlockKind::Fenced(lang)) if !lang.is_empty() => {
+                NodeKind::CodeBlock(Some(lang.to_string()))
+            }
+            Tag::CodeBlock(_) => NodeKind::CodeBlock(None),
+            Tag::HtmlBlock => NodeKind::HtmlBlock,
+            Tag::List(start) => NodeKind::List(start),
+            Tag::Item => NodeKind::Item,
+            Tag::FootnoteDefinition(label) => NodeKind::FootnoteDefinition(label.to_string()),
+            Tag::Table(alignments) => NodeKind::Table(alignments),
+            Tag::TableHead => NodeKind::TableHead,
+            Tag::TableRow => NodeKind::TableRow,
+            Tag::TableCell => NodeKind::TableCell,
+            Tag::Emphasis => NodeKind::Emphasis,
+            Tag::Strong => NodeKind::Strong,
+            Tag::Strikethrough => NodeKind::Strikethrough,
+            Tag::Link { dest_url, title, .. } => NodeKind::Link { url: dest_url.to_string(), title: title.to_string() },
+            Tag::Image { dest_url, title, .. } => NodeKind::Image { url: dest_url.to_string(), title: title.to_string() },
+            Tag::MetadataBlock(_) => NodeKind::MetadataBlock,
+        }
     }
 
     pub fn find_token_at_position<'a>(&self, tokens: &'a [ParsedToken], position: usize) -> Option<&'a ParsedToken> {
@@ -720,7 +452,7 @@ impl MarkdownParser {
         let mut current_pos = 0;
         
         // First pass: parse highlights (avoid escaped ones)
-        let highlight_regex = regex::Regex::new(r"==([^=]+)==").unwrap();
+        let highlight_regex = highlight_regex();
         let mut processed_ranges = Vec::new();
         
         for cap in highlight_regex.captures_iter(text) {
@@ -756,7 +488,7 @@ impl MarkdownParser {
 
     fn parse_tags_in_text(&self, text: &str) -> Vec<MarkdownToken> {
         let mut tokens = Vec::new();
-        let tag_regex = regex::Regex::new(r"#([a-zA-Z0-9_\-/.]+)").unwrap();
+        let tag_regex = tag_regex();
         let mut last_end = 0;
         
         for cap in tag_regex.captures_iter(text) {
@@ -790,7 +522,7 @@ impl MarkdownParser {
 
     fn parse_tags_in_text_with_positions(&self, text: &str, text_start: usize) -> Vec<ParsedToken> {
         let mut tokens = Vec::new();
-        let tag_regex = regex::Regex::new(r"#([a-zA-Z0-9_\-/.]+)").unwrap();
+        let tag_regex = tag_regex();
         let mut last_end = 0;
         
         for cap in tag_regex.captures_iter(text) {
@@ -843,7 +575,7 @@ impl MarkdownParser {
         let mut current_pos = 0;
         
         // First pass: parse highlights (avoid escaped ones)
-        let highlight_regex = regex::Regex::new(r"==([^=]+)==").unwrap();
+        let highlight_regex = highlight_regex();
         let mut processed_ranges = Vec::new();
         
         for cap in highlight_regex.captures_iter(text) {
@@ -1046,19 +778,23 @@ impl Default for MarkdownParser {
 // ENG-116: Incremental parsing structures for performance optimization
 #[derive(Debug, Clone)]
 pub struct TextChange {
-    pub start: usize,      // Character position where change starts
-    pub deleted_len: usize, // Number of characters deleted
+    pub start: usize,      // Byte offset where change starts (same units as ParsedToken)
+    pub deleted_len: usize, // Number of bytes deleted
     pub inserted_text: String, // New text inserted
 }
 
+/// A top-level block of the last parsed document.
+///
+/// `start..end` is the block's span: it begins at the start of the block's first
+/// line and runs up to the next block's span, so spans tile the whole document.
+/// Tokens are stored relative to `block_start` (the parser's own range start) so
+/// edits before the block only need to shift the span, not every token.
 #[derive(Debug, Clone)]
 struct CachedBlock {
     tokens: Vec<ParsedToken>,
-    content_hash: u64,
-    line_start: usize,
-    line_end: usize,
-    char_start: usize,
-    char_end: usize,
+    block_start: usize,
+    start: usize,
+    end: usize,
 }
 
 pub struct IncrementalParser {
@@ -1076,13 +812,13 @@ impl IncrementalParser {
         }
     }
 
+    /// Parse `content`, which must be the previously parsed content with `changes`
+    /// applied in order. Only the top-level blocks touched by the changes are
+    /// reparsed; tokens of all other blocks are reused with shifted offsets.
     pub fn parse_incremental(&mut self, content: &str, changes: Vec<TextChange>) -> Vec<ParsedToken> {
         // If no previous content or changes, do full parse
         if self.last_content.is_empty() || changes.is_empty() {
-            self.last_content = content.to_string();
-            let tokens = self.base_parser.parse_with_positions(content);
-            self.update_cache(&tokens, content);
-            return tokens;
+            return self.full_parse(content);
         }
 
         // Check if changes are small enough for incremental parsing
@@ -1092,63 +828,240 @@ impl IncrementalParser {
 
         // If changes are substantial (>10% of document), just do full reparse
         if total_change_size > content.len() / 10 {
-            self.last_content = content.to_string();
-            let tokens = self.base_parser.parse_with_positions(content);
-            self.update_cache(&tokens, content);
-            return tokens;
+            return self.full_parse(content);
         }
 
-        // Determine affected regions based on changes
-        let affected_blocks = self.find_affected_blocks(&changes);
-        
-        // If no cached blocks or too many affected, fall back to full parse
-        if self.cached_blocks.is_empty() || affected_blocks.len() > self.cached_blocks.len() / 2 {
-            self.last_content = content.to_string();
-            let tokens = self.base_parser.parse_with_positions(content);
-            self.update_cache(&tokens, content);
-            return tokens;
+        // Link reference / footnote definitions and open <sub>/<sup> tags make
+        // blocks depend on each other, so blocks can't be reparsed in isolation
+        if self.cached_blocks.is_empty() || Self::has_cross_block_dependencies(content) {
+            return self.full_parse(content);
         }
 
-        // Perform incremental parsing (simplified for now)
+        // Determine affected regions based on changes
+        let Some((old_dirty_start, old_dirty_end)) = self.dirty_range(content, &changes) else {
+            return self.full_parse(content);
+        };
+        let (first, mut last) = self.find_affected_blocks(old_dirty_start, old_dirty_end);
+        let delta = content.len() as isize - self.last_content.len() as isize;
+        let region_start = self.cached_blocks[first].start;
+
+        // Grow the region until the block after it still starts where it used to;
+        // an unclosed fence or a lazy continuation line can swallow following blocks
+        let region_blocks = loop {
+            let region_end = Self::shift(self.cached_blocks[last].end, delta);
+
+            // If too much of the document is affected, fall back to full parse
+            if (region_end - region_start) * 2 > content.len() {
+                return self.full_parse(content);
+            }
+
+            let Some(next) = self.cached_blocks.get(last + 1) else {
+                break self.parse_region(content, region_start, content.len());
+            };
+
+            let lookahead_end = Self::shift(next.end, delta);
+            let next_block_start = Self::shift(next.block_start, delta);
+            let mut blocks = self.parse_region(content, region_start, lookahead_end);
+            if blocks.iter().any(|block| block.block_start == next_block_start) {
+                blocks.retain(|block| block.block_start < region_end);
+                break blocks;
+            }
+            last += 1;
+        };
+
+        self.splice_cache(content, first, last, delta, region_blocks);
+        self.last_content = content.to_string();
+        self.collect_tokens()
+    }
+
+    fn full_parse(&mut self, content: &str) -> Vec<ParsedToken> {
         self.last_content = content.to_string();
         let tokens = self.base_parser.parse_with_positions(content);
         self.update_cache(&tokens, content);
         tokens
     }
 
-    fn find_affected_blocks(&self, _changes: &[TextChange]) -> Vec<usize> {
-        // For now, return all blocks as potentially affected
-        // A more sophisticated implementation would analyze change positions
-        (0..self.cached_blocks.len()).collect()
+    fn has_cross_block_dependencies(content: &str) -> bool {
+        content.contains("]:") || content.contains("<sub") || content.contains("<sup")
     }
 
-    fn update_cache(&mut self, tokens: &[ParsedToken], content: &str) {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        
-        self.cached_blocks.clear();
-        
-        if tokens.is_empty() {
-            return;
+    fn shift(position: usize, delta: isize) -> usize {
+        (position as isize + delta) as usize
+    }
+
+    /// Byte range of the old content that the changes touched, or None if the
+    /// changes don't describe how the old content became `content`.
+    fn dirty_range(&self, content: &str, changes: &[TextChange]) -> Option<(usize, usize)> {
+        let old_len = self.last_content.len();
+        let mut len = old_len;
+        let mut dirty_start = usize::MAX;
+        // Length of the unchanged suffix shared by the old and new content
+        let mut unchanged_suffix = usize::MAX;
+
+        for change in changes {
+            let change_end = change.start.checked_add(change.deleted_len)?;
+            if change_end > len {
+                return None;
+            }
+            dirty_start = dirty_start.min(change.start);
+            unchanged_suffix = unchanged_suffix.min(len - change_end);
+            len = len - change.deleted_len + change.inserted_text.len();
+        }
+
+        if len != content.len() || unchanged_suffix > old_len - dirty_start.min(old_len) {
+            return None;
+        }
+        let dirty_end = old_len - unchanged_suffix;
+        if !content.is_char_boundary(dirty_start) || !content.is_char_boundary(content.len() - unchanged_suffix) {
+            return None;
+        }
+        Some((dirty_start, dirty_end))
+    }
+
+    /// Indices of the first and last cached blocks to reparse for a dirty byte
+    /// range of the old content. The block before the edit is included because
+    /// the edit can turn the start of its block into a continuation of it.
+    fn find_affected_blocks(&self, dirty_start: usize, dirty_end: usize) -> (usize, usize) {
+        let block_at = |position: usize| {
+            self.cached_blocks
+                .partition_point(|block| block.start <= position)
+                .saturating_sub(1)
+        };
+        (block_at(dirty_start).saturating_sub(1), block_at(dirty_end))
+    }
+
+    /// Parse `content[start..end]` in isolation into top-level blocks, with
+    /// spans and tokens as absolute offsets.
+    fn parse_region(&self, content: &str, start: usize, end: usize) -> Vec<CachedBlock> {
+        let slice = &content[start..end];
+        let tokens = self.base_parser.parse_with_positions(slice);
+        let mut blocks = self.split_into_blocks(slice, tokens);
+        for block in &mut blocks {
+            block.start += start;
+            block.end += start;
+            block.block_start += start;
+            for token in &mut block.tokens {
+                token.start += start;
+                token.end += start;
+            }
+        }
+        blocks
+    }
+
+    /// Group tokens by the top-level block that emitted them. Tokens are emitted
+    /// block by block, but can't be assigned by start offset alone: a table pads
+    /// short rows with empty cells that sit at the very end of the table, which
+    /// may be where the next block starts.
+    fn split_into_blocks(&self, content: &str, tokens: Vec<ParsedToken>) -> Vec<CachedBlock> {
+        let parser = Parser::new_ext(content, self.base_parser.options);
+        let mut blocks: Vec<CachedBlock> = Vec::new();
+        let mut block_ends: Vec<usize> = Vec::new();
+        let mut depth = 0usize;
+
+        for (event, range) in parser.into_offset_iter() {
+            let is_top_level = depth == 0 && !matches!(event, Event::End(_));
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if !is_top_level {
+                continue;
+            }
+
+            let line_start = content[..range.start].rfind('\n').map_or(0, |i| i + 1);
+            if blocks.last().is_some_and(|block| block.start == line_start) {
+                // Blocks sharing a line are cached together
+                if let Some(end) = block_ends.last_mut() {
+                    *end = range.end;
+                }
+                continue;
+            }
+            blocks.push(CachedBlock {
+                tokens: Vec::new(),
+                block_start: range.start,
+                start: if blocks.is_empty() { 0 } else { line_start },
+                end: content.len(),
+            });
+            block_ends.push(range.end);
         }
 
-        // Group tokens into logical blocks (simplified approach)
-        // For now, treat each token as its own block
+        for index in 1..blocks.len() {
+            blocks[index - 1].end = blocks[index].start;
+        }
+
+        let mut index = 0;
         for token in tokens {
-            let block_content = &content[token.start..token.end.min(content.len())];
-            let mut hasher = DefaultHasher::new();
-            block_content.hash(&mut hasher);
-            
-            let cached_block = CachedBlock {
-                tokens: vec![token.clone()],
-                content_hash: hasher.finish(),
-                line_start: token.start,  // Simplified
-                line_end: token.end,      // Simplified  
-                char_start: token.start,
-                char_end: token.end,
-            };
-            
-            self.cached_blocks.push(cached_block);
+            let in_current = block_ends.get(index).is_some_and(|&end| token.end <= end);
+            while !in_current && index + 1 < blocks.len() && token.start >= blocks[index + 1].block_start {
+                index += 1;
+                if token.end <= block_ends[index] {
+                    break;
+                }
+            }
+            if let Some(block) = blocks.get_mut(index) {
+                block.tokens.push(token);
+            }
+        }
+
+        blocks
+    }
+
+    /// Replace cached blocks `first..=last` with freshly parsed ones and shift
+    /// the blocks after them by `delta`.
+    fn splice_cache(&mut self, content: &str, first: usize, last: usize, delta: isize, region_blocks: Vec<CachedBlock>) {
+        let region_start = self.cached_blocks[first].start;
+        let mut new_blocks: Vec<CachedBlock> = region_blocks
+            .into_iter()
+            .map(|mut block| {
+                for token in &mut block.tokens {
+                    token.start -= block.block_start;
+                    token.end -= block.block_start;
+                }
+                block
+            })
+            .collect();
+
+        let mut following: Vec<CachedBlock> = self.cached_blocks.drain(last + 1..).collect();
+        for block in &mut following {
+            block.block_start = Self::shift(block.block_start, delta);
+            block.start = Self::shift(block.start, delta);
+        }
+
+        // Keep the spans tiling the document from the start of the region
+        if let Some(block) = new_blocks.first_mut().or(following.first_mut()) {
+            block.start = region_start;
+        }
+
+        self.cached_blocks.truncate(first);
+        self.cached_blocks.append(&mut new_blocks);
+        self.cached_blocks.append(&mut following);
+        for index in 0..self.cached_blocks.len() {
+            let end = self.cached_blocks.get(index + 1).map_or(content.len(), |block| block.start);
+            self.cached_blocks[index].end = end;
+        }
+    }
+
+    fn collect_tokens(&self) -> Vec<ParsedToken> {
+        let token_count = self.cached_blocks.iter().map(|block| block.tokens.len()).sum();
+        let mut tokens = Vec::with_capacity(token_count);
+        for block in &self.cached_blocks {
+            tokens.extend(block.tokens.iter().map(|token| ParsedToken {
+                token_type: token.token_type.clone(),
+                start: token.start + block.block_start,
+                end: token.end + block.block_start,
+            }));
+        }
+        tokens
+    }
+
+    fn update_cache(&mut self, tokens: &[ParsedToken], content: &str) {
+        self.cached_blocks = self.split_into_blocks(content, tokens.to_vec());
+        for block in &mut self.cached_blocks {
+            for token in &mut block.tokens {
+                token.start -= block.block_start;
+                token.end -= block.block_start;
+            }
         }
     }
 }
@@ -1541,6 +1454,187 @@ mod tests {
         assert!(tokens.len() > 5, "Should have multiple tokens from expanded document");
     }
 
+    /// Small deterministic PRNG so the equivalence tests are reproducible without extra deps
+    struct EditRng(u64);
+
+    impl EditRng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound.max(1) as u64) as usize
+        }
+    }
+
+    fn equivalence_test_document() -> String {
+        let mut markdown = String::new();
+        for i in 0..40 {
+            markdown.push_str(&format!("# Heading {}\n\n", i));
+            markdown.push_str(&format!("Paragraph {} with **bold**, *italic*, `code` and #tag{}.\n", i, i));
+            markdown.push_str("A second line with ==highlight== and [a link](https://example.com).\n\n");
+            markdown.push_str("- item one\n- item **two**\n  - nested item\n- [ ] task\n\n");
+            markdown.push_str("> quoted *text*\n> more quote\n\n");
+            if i % 5 == 0 {
+                markdown.push_str("```rust\nfn main() {}\n```\n\n");
+                markdown.push_str("| A | B |\n|---|---|\n| 1 | 2 |\n\n---\n\n");
+            }
+        }
+        markdown
+    }
+
+    fn random_edit(rng: &mut EditRng, content: &str) -> TextChange {
+        const SNIPPETS: &[&str] = &[
+            "a", "word ", "\n", "\n\n", "# ", "## ", "- ", "1. ", "> ", "**", "*", "`", "```", "~~",
+            "==", "#tag", "|", "---", "- [ ] ", "    ", "[x](y)", "é", "😀",
+        ];
+
+        let mut start = rng.below(content.len() + 1);
+        while !content.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = (start + rng.below(6)).min(content.len());
+        while !content.is_char_boundary(end) {
+            end += 1;
+        }
+        let inserted_text = if rng.below(4) == 0 {
+            String::new()
+        } else {
+            SNIPPETS[rng.below(SNIPPETS.len())].to_string()
+        };
+
+        TextChange { start, deleted_len: end - start, inserted_text }
+    }
+
+    fn apply_change(content: &str, change: &TextChange) -> String {
+        let mut result = content.to_string();
+        result.replace_range(change.start..change.start + change.deleted_len, &change.inserted_text);
+        result
+    }
+
+    #[test]
+    fn test_incremental_parser_matches_full_parse_for_random_edits() {
+        let parser = MarkdownParser::new();
+
+        for seed in 1..=4u64 {
+            let mut rng = EditRng(0x9E37_79B9_7F4A_7C15 ^ seed);
+            let mut incremental_parser = IncrementalParser::new();
+            let mut content = equivalence_test_document();
+            incremental_parser.parse_incremental(&content, vec![]);
+
+            for step in 0..150 {
+                let change = random_edit(&mut rng, &content);
+                content = apply_change(&content, &change);
+
+                let incremental = incremental_parser.parse_incremental(&content, vec![change.clone()]);
+                let full = parser.parse_with_positions(&content);
+                assert_eq!(incremental, full, "seed {} step {} diverged after {:?}", seed, step, change);
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_parser_matches_full_parse_for_batched_edits() {
+        let parser = MarkdownParser::new();
+        let mut rng = EditRng(0xDEAD_BEEF);
+        let mut incremental_parser = IncrementalParser::new();
+        let mut content = equivalence_test_document();
+        incremental_parser.parse_incremental(&content, vec![]);
+
+        for step in 0..60 {
+            // Each change is relative to the content produced by the previous one
+            let mut changes = Vec::new();
+            for _ in 0..3 {
+                let change = random_edit(&mut rng, &content);
+                content = apply_change(&content, &change);
+                changes.push(change);
+            }
+
+            let incremental = incremental_parser.parse_incremental(&content, changes);
+            assert_eq!(incremental, parser.parse_with_positions(&content), "diverged at step {}", step);
+        }
+    }
+
+    #[test]
+    fn test_incremental_parser_unclosed_fence_swallows_following_blocks() {
+        let parser = MarkdownParser::new();
+        let mut incremental_parser = IncrementalParser::new();
+        let original = equivalence_test_document();
+        incremental_parser.parse_incremental(&original, vec![]);
+
+        // Opening a fence near the end turns everything after it into code
+        let start = original.rfind("# Heading 38").unwrap();
+        let change = TextChange { start, deleted_len: 0, inserted_text: "```\n".to_string() };
+        let modified = apply_change(&original, &change);
+
+        let tokens = incremental_parser.parse_incremental(&modified, vec![change]);
+        assert_eq!(tokens, parser.parse_with_positions(&modified));
+        assert!(tokens.iter().any(|t| t.start == start && matches!(t.token_type, MarkdownToken::CodeBlock(..))));
+    }
+
+    #[test]
+    fn test_incremental_parser_shifts_tokens_after_edit() {
+        let mut incremental_parser = IncrementalParser::new();
+        let original = equivalence_test_document();
+        let initial_tokens = incremental_parser.parse_incremental(&original, vec![]);
+
+        let change = TextChange { start: 2, deleted_len: 0, inserted_text: "Big ".to_string() };
+        let modified = apply_change(&original, &change);
+        let tokens = incremental_parser.parse_incremental(&modified, vec![change]);
+
+        // Every token after the edited heading keeps its type and moves by the inserted length
+        assert_eq!(tokens.len(), initial_tokens.len());
+        for (before, after) in initial_tokens.iter().zip(&tokens).skip(1) {
+            assert_eq!(before.token_type, after.token_type);
+            assert_eq!(before.start + 4, after.start);
+            assert_eq!(before.end + 4, after.end);
+        }
+    }
+
+    #[test]
+    fn test_incremental_parser_falls_back_for_reference_definitions() {
+        let parser = MarkdownParser::new();
+        let mut incremental_parser = IncrementalParser::new();
+        let mut original = equivalence_test_document();
+        original.push_str("See [the docs][docs].\n\n[docs]: https://example.com\n");
+        incremental_parser.parse_incremental(&original, vec![]);
+
+        // Renaming the definition changes how the earlier reference parses
+        let start = original.rfind("[docs]:").unwrap() + 1;
+        let change = TextChange { start, deleted_len: 4, inserted_text: "other".to_string() };
+        let modified = apply_change(&original, &change);
+
+        let tokens = incremental_parser.parse_incremental(&modified, vec![change]);
+        assert_eq!(tokens, parser.parse_with_positions(&modified));
+    }
+
+    #[test]
+    fn test_incremental_parser_large_document_edit() {
+        use std::time::Instant;
+
+        let content = include_str!("../test_large_1mb.md");
+        let mut incremental_parser = IncrementalParser::new();
+        incremental_parser.parse_incremental(content, vec![]);
+
+        let start = content.len() / 2;
+        let start = (start..content.len()).find(|&i| content.is_char_boundary(i)).unwrap();
+        let change = TextChange { start, deleted_len: 0, inserted_text: "x".to_string() };
+        let modified = apply_change(content, &change);
+
+        let timer = Instant::now();
+        let tokens = incremental_parser.parse_incremental(&modified, vec![change]);
+        let duration = timer.elapsed();
+        println!("Incremental parse of 1MB document: {:?}", duration);
+
+        assert_eq!(tokens, MarkdownParser::new().parse_with_positions(&modified));
+        // Generous bound for unoptimized test builds; release builds are well under 16ms
+        assert!(duration < std::time::Duration::from_millis(100),
+                "Incremental parsing of a 1MB document took too long: {:?}", duration);
+    }
+
     // TDD RED: First failing test for table parsing (ENG-153)
     #[test]
     fn test_parse_basic_table() {
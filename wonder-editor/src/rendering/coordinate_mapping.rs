@@ -1,6 +1,6 @@
 use super::text_content::TextContent;
-use super::token_mode::TokenRenderMode;
-use crate::markdown_parser::{MarkdownParser, MarkdownToken, ParsedToken};
+use super::token_mode::{resolve_token_modes, TokenRenderMode};
+use crate::markdown_parser::{MarkdownParser, MarkdownToken};
 use std::ops::Range;
 
 // ENG-173: Unified Coordinate System Data Structures
@@ -105,29 +105,17 @@ impl CoordinateMapper {
         let content_str = content.text_to_string();
         let selection_range = selection;
 
-        // Get tokens and their render modes
-        let tokens = self.parser.parse_with_positions(&content_str);
+        // Get tokens and their render modes; the syntax tree keeps nested tokens from overlapping
+        let ast = self.parser.parse_ast(&content_str);
+        let token_modes = resolve_token_modes(&ast, cursor_position, selection_range);
         eprintln!("DEBUG COORD: Parsing content: {:?}", content_str);
-        eprintln!("DEBUG COORD: Found {} tokens", tokens.len());
-        let token_modes: Vec<(ParsedToken, TokenRenderMode)> = tokens
-            .into_iter()
-            .map(|token| {
-                eprintln!(
-                    "DEBUG COORD: Token {:?} at [{}, {}]",
-                    token.token_type, token.start, token.end
-                );
-                let mode = TokenRenderMode::get_for_token(
-                    &token,
-                    cursor_position,
-                    selection_range.clone(),
-                );
-                eprintln!(
-                    "DEBUG COORD: Cursor at {}, token mode: {:?}",
-                    cursor_position, mode
-                );
-                (token, mode)
-            })
-            .collect();
+        eprintln!("DEBUG COORD: Found {} tokens", token_modes.len());
+        for (token, mode) in &token_modes {
+            eprintln!(
+                "DEBUG COORD: Token {:?} at [{}, {}], mode: {:?}",
+                token.token_type, token.start, token.end, mode
+            );
+        }
 
         // Initialize coordinate mapping structures
         let mut original_to_display = Vec::new();
@@ -6,6 +6,7 @@ pub struct StyleContext {
     pub text_color: Hsla,
     pub code_color: Hsla,
     pub border_color: Hsla,
+    pub background_color: Hsla,
 }
 
 impl StyleContext {
@@ -14,6 +15,7 @@ impl StyleContext {
             text_color: Hsla { h: 0.0, s: 0.0, l: 0.85, a: 1.0 }, // Light gray instead of black
             code_color: Hsla { h: 120.0, s: 0.5, l: 0.7, a: 1.0 }, // Green-ish instead of black
             border_color: Hsla { h: 0.0, s: 0.0, l: 0.5, a: 1.0 }, // Medium gray instead of black
+            background_color: Hsla { h: 240.0, s: 0.21, l: 0.15, a: 1.0 },
         }
     }
     
@@ -22,6 +24,7 @@ impl StyleContext {
             text_color: Hsla { h: 0.0, s: 0.0, l: 0.9, a: 1.0 },
             code_color: Hsla { h: 120.0, s: 0.5, l: 0.7, a: 1.0 },
             border_color: Hsla { h: 0.0, s: 0.0, l: 0.3, a: 1.0 },
+            background_color: Hsla { h: 240.0, s: 0.21, l: 0.15, a: 1.0 }, // Editor background (#1e1e2e)
         }
     }
     
@@ -39,6 +42,11 @@ impl StyleContext {
         self.border_color = color;
         self
     }
+    
+    pub fn with_background_color(mut self, color: Hsla) -> Self {
+        self.background_color = color;
+        self
+    }
 }
 
 #[cfg(test)]
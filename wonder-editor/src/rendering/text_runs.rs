@@ -2,7 +2,7 @@ use std::ops::Range;
 use gpui::{TextRun, Font, FontFeatures, FontWeight, FontStyle, rgb};
 use crate::markdown_parser::{ParsedToken, MarkdownParser, MarkdownToken};
 use super::text_content::TextContent;
-use super::token_mode::TokenRenderMode;
+use super::token_mode::{resolve_token_modes, TokenRenderMode};
 use super::style_context::StyleContext;
 use super::typography::Typography;
 
@@ -38,13 +38,8 @@ impl TextRunGenerator {
         }
         
         let content_str = content.text_to_string();
-        let tokens = self.parser.parse_with_positions(&content_str);
-        let token_modes: Vec<(ParsedToken, TokenRenderMode)> = tokens.into_iter()
-            .map(|token| {
-                let mode = TokenRenderMode::get_for_token(&token, cursor_position, selection.clone());
-                (token, mode)
-            })
-            .collect();
+        let ast = self.parser.parse_ast(&content_str);
+        let token_modes = resolve_token_modes(&ast, cursor_position, selection.clone());
         
         self.build_text_segments(&content_str, &token_modes, style_context, buffer_font_size)
     }
@@ -60,13 +55,8 @@ impl TextRunGenerator {
         }
         
         let content_str = content.text_to_string();
-        let tokens = self.parser.parse_with_positions(&content_str);
-        let token_modes: Vec<(ParsedToken, TokenRenderMode)> = tokens.into_iter()
-            .map(|token| {
-                let mode = TokenRenderMode::get_for_token(&token, cursor_position, selection.clone());
-                (token, mode)
-            })
-            .collect();
+        let ast = self.parser.parse_ast(&content_str);
+        let token_modes = resolve_token_modes(&ast, cursor_position, selection.clone());
         
         // Use default style context for backward compatibility
         let default_style_context = StyleContext::default();
@@ -306,13 +296,8 @@ impl TextRunGenerator {
         }
         
         let content_str = content.text_to_string();
-        let tokens = self.parser.parse_with_positions(&content_str);
-        let token_modes: Vec<(ParsedToken, TokenRenderMode)> = tokens.into_iter()
-            .map(|token| {
-                let mode = TokenRenderMode::get_for_token(&token, cursor_position, selection.clone());
-                (token, mode)
-            })
-            .collect();
+        let ast = self.parser.parse_ast(&content_str);
+        let token_modes = resolve_token_modes(&ast, cursor_position, selection.clone());
         
         // Build the transformed content and corresponding TextRuns
         let (_transformed_content, mut text_runs) = self.build_transformed_content_with_proper_runs(&content_str, &token_modes);
@@ -337,13 +322,8 @@ impl TextRunGenerator {
         }
         
         let content_str = content.text_to_string();
-        let tokens = self.parser.parse_with_positions(&content_str);
-        let token_modes: Vec<(ParsedToken, TokenRenderMode)> = tokens.into_iter()
-            .map(|token| {
-                let mode = TokenRenderMode::get_for_token(&token, cursor_position, selection.clone());
-                (token, mode)
-            })
-            .collect();
+        let ast = self.parser.parse_ast(&content_str);
+        let token_modes = resolve_token_modes(&ast, cursor_position, selection.clone());
         
         let (transformed_content, _) = self.build_transformed_content_with_proper_runs(&content_str, &token_modes);
         transformed_content
@@ -575,6 +555,20 @@ mod tests {
         assert_eq!(segments[1].text_run.color, style_context.text_color.into());
     }
     
+    #[test]
+    fn test_nested_tokens_are_not_rendered_twice() {
+        let generator = TextRunGenerator::new();
+
+        // Bold inside a list item: the list item gives way to the bold span
+        let display_content = generator.get_display_content("- item **bold**", 100, None);
+        assert_eq!(display_content, "- item bold");
+
+        // Bold inside a heading: the heading keeps all of its text
+        let segments = generator.generate_styled_text_segments("# Hello **bold** world", 100, None);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].text, "Hello bold world");
+    }
+
     #[test]
     fn test_get_display_content() {
         let generator = TextRunGenerator::new();
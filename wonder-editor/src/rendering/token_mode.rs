@@ -1,5 +1,7 @@
+use std::collections::HashSet;
 use std::ops::Range;
-use crate::markdown_parser::ParsedToken;
+use crate::markdown_ast::{MarkdownAst, NodeId, NodeKind};
+use crate::markdown_parser::{MarkdownToken, ParsedToken};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenRenderMode {
@@ -33,12 +35,109 @@ impl TokenRenderMode {
         // 3. Default to Preview mode for better visual representation
         TokenRenderMode::Preview
     }
+
+    /// Same rules as `get_for_token`, for a node of the syntax tree. A node inside an inline
+    /// span that is shown raw is raw too, since the span's source is displayed verbatim.
+    pub fn get_for_node(ast: &MarkdownAst, node: NodeId, cursor_position: usize, selection: Option<Range<usize>>) -> TokenRenderMode {
+        let range = &ast.node(node).range;
+        if touches(range.start, range.end, cursor_position, &selection)
+            || in_raw_span(ast, node, cursor_position, &selection)
+        {
+            TokenRenderMode::Raw
+        } else {
+            TokenRenderMode::Preview
+        }
+    }
+}
+
+fn touches(start: usize, end: usize, cursor_position: usize, selection: &Option<Range<usize>>) -> bool {
+    (cursor_position >= start && cursor_position <= end)
+        || selection.as_ref().is_some_and(|sel| ranges_intersect(sel.start, sel.end, start, end))
+}
+
+fn in_raw_span(ast: &MarkdownAst, node: NodeId, cursor_position: usize, selection: &Option<Range<usize>>) -> bool {
+    ast.ancestors(node)
+        .map(|ancestor| ast.node(ancestor))
+        .filter(|ancestor| ancestor.kind.is_inline())
+        .any(|ancestor| touches(ancestor.range.start, ancestor.range.end, cursor_position, selection))
+}
+
+/// Headings and inline spans are rendered as a single unit, hiding any tokens nested inside
+fn renders_as_unit(kind: &NodeKind) -> bool {
+    matches!(
+        kind,
+        NodeKind::Heading(_)
+            | NodeKind::CodeBlock(_)
+            | NodeKind::Strong
+            | NodeKind::Emphasis
+            | NodeKind::Strikethrough
+            | NodeKind::Link { .. }
+            | NodeKind::Subscript
+            | NodeKind::Superscript
+    )
+}
+
+/// List items, quotes and tables only show their source, so they give way to nested tokens
+fn gives_way_to_children(kind: &NodeKind) -> bool {
+    matches!(
+        kind,
+        NodeKind::Item
+            | NodeKind::BlockQuote
+            | NodeKind::Table(_)
+            | NodeKind::TableHead
+            | NodeKind::TableRow
+            | NodeKind::TableCell
+    )
+}
+
+/// Resolve the tokens to render for a whole document along with their render modes.
+///
+/// The flat token list contains overlapping tokens for nested markdown (a bold span
+/// inside a list item yields both). The syntax tree decides which one is drawn, so
+/// the result is sorted by start and free of overlaps. Text of headings and spans is
+/// taken from the tree, so nested formatting doesn't drop part of it.
+pub fn resolve_token_modes(ast: &MarkdownAst, cursor_position: usize, selection: Option<Range<usize>>) -> Vec<(ParsedToken, TokenRenderMode)> {
+    let token_nodes = ast.token_nodes();
+    let emitting: HashSet<NodeId> = token_nodes.iter().map(|(node, _)| *node).collect();
+
+    let mut resolved: Vec<(ParsedToken, TokenRenderMode)> = token_nodes
+        .into_iter()
+        .filter(|(node, _)| {
+            !ast.ancestors(*node)
+                .any(|ancestor| emitting.contains(&ancestor) && renders_as_unit(&ast.node(ancestor).kind))
+        })
+        .filter(|(node, _)| {
+            !gives_way_to_children(&ast.node(*node).kind)
+                || !ast.descendants(*node).any(|descendant| emitting.contains(&descendant))
+        })
+        .map(|(node, mut token)| {
+            match &mut token.token_type {
+                MarkdownToken::Bold(text)
+                | MarkdownToken::Italic(text)
+                | MarkdownToken::Strikethrough(text)
+                | MarkdownToken::Heading(_, text)
+                | MarkdownToken::Link(text, _) => *text = ast.text(node),
+                _ => {}
+            }
+            let mode = if touches(token.start, token.end, cursor_position, &selection)
+                || in_raw_span(ast, node, cursor_position, &selection)
+            {
+                TokenRenderMode::Raw
+            } else {
+                TokenRenderMode::Preview
+            };
+            (token, mode)
+        })
+        .collect();
+
+    resolved.sort_by_key(|(token, _)| token.start);
+    resolved
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::markdown_parser::MarkdownToken;
+    use crate::markdown_parser::MarkdownParser;
     
     #[test]
     fn test_cursor_inside_token_should_be_raw() {
@@ -117,6 +216,69 @@ mod tests {
         assert_eq!(mode_after, TokenRenderMode::Preview);
     }
     
+    fn find_node(ast: &MarkdownAst, kind: NodeKind) -> NodeId {
+        ast.descendants(ast.root()).find(|&id| ast.node(id).kind == kind).unwrap()
+    }
+
+    #[test]
+    fn test_node_inside_raw_span_is_raw() {
+        let parser = MarkdownParser::new();
+        let ast = parser.parse_ast("Text **bold _nested_ end** after");
+        let italic = find_node(&ast, NodeKind::Emphasis);
+
+        // Cursor inside the bold span but outside the italic one
+        assert_eq!(TokenRenderMode::get_for_node(&ast, italic, 8, None), TokenRenderMode::Raw);
+        // Cursor outside both spans
+        assert_eq!(TokenRenderMode::get_for_node(&ast, italic, 0, None), TokenRenderMode::Preview);
+    }
+
+    #[test]
+    fn test_block_containers_do_not_force_raw() {
+        let parser = MarkdownParser::new();
+        let markdown = "- item with **bold**";
+        let ast = parser.parse_ast(markdown);
+        let strong = find_node(&ast, NodeKind::Strong);
+
+        // Cursor inside the list item, before the bold span
+        assert_eq!(TokenRenderMode::get_for_node(&ast, strong, 3, None), TokenRenderMode::Preview);
+    }
+
+    #[test]
+    fn test_resolve_token_modes_prefers_nested_tokens_in_list_items() {
+        let parser = MarkdownParser::new();
+        let markdown = "- item with **bold**";
+        let ast = parser.parse_ast(markdown);
+
+        let resolved = resolve_token_modes(&ast, 0, None);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].0.token_type, MarkdownToken::Bold("bold".to_string()));
+        assert_eq!(resolved[0].1, TokenRenderMode::Preview);
+    }
+
+    #[test]
+    fn test_resolve_token_modes_keeps_heading_as_one_unit() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Hello **bold** world";
+        let ast = parser.parse_ast(markdown);
+
+        let resolved = resolve_token_modes(&ast, 100, None);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].0.token_type, MarkdownToken::Heading(1, "Hello bold world".to_string()));
+        assert_eq!((resolved[0].0.start, resolved[0].0.end), (0, markdown.len()));
+    }
+
+    #[test]
+    fn test_resolve_token_modes_does_not_overlap() {
+        let parser = MarkdownParser::new();
+        let markdown = include_str!("../../example_markdown.md");
+        let ast = parser.parse_ast(markdown);
+
+        let resolved = resolve_token_modes(&ast, 0, None);
+        for pair in resolved.windows(2) {
+            assert!(pair[0].0.end <= pair[1].0.start, "{:?} overlaps {:?}", pair[0].0, pair[1].0);
+        }
+    }
+
     #[test]
     fn test_ranges_intersect() {
         // Test various intersection scenarios